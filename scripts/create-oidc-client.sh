@@ -0,0 +1,106 @@
+#!/bin/bash
+
+# Create OIDC Client
+# This script registers a relying party (Forgejo, Matrix, ...) that signs in
+# members of a territory through the OpenID provider. Confidential clients get
+# a secret; only its hash is stored. Public clients (--public) rely on PKCE.
+
+set -e
+
+# Colors for output
+GREEN='\033[0;32m'
+BLUE='\033[0;34m'
+YELLOW='\033[1;33m'
+RED='\033[0;31m'
+NC='\033[0m' # No Color
+
+# Configuration
+TERRITORY_CODE="${1:-dk}"
+CLIENT_ID="${2}"
+CLIENT_NAME="${3}"
+REDIRECT_URIS="${4}" # Comma-separated
+CLIENT_TYPE="${5}"
+
+# Validate inputs
+if [ -z "$CLIENT_ID" ] || [ -z "$CLIENT_NAME" ] || [ -z "$REDIRECT_URIS" ]; then
+    echo -e "${RED}Error: Client ID, name and redirect URI are required${NC}"
+    echo "Usage: $0 <territory_code> <client_id> <name> <redirect_uri[,redirect_uri...]> [--public]"
+    echo "Example: $0 dk forgejo \"Forgejo\" https://git.example.org/user/oauth2/unityplan/callback"
+    exit 1
+fi
+
+if ! [[ "$CLIENT_ID" =~ ^[a-z0-9_-]+$ ]]; then
+    echo -e "${RED}Error: Client ID may only contain a-z, 0-9, '-' and '_'${NC}"
+    exit 1
+fi
+
+if [ -n "$CLIENT_TYPE" ] && [ "$CLIENT_TYPE" != "--public" ]; then
+    echo -e "${RED}Error: Unknown option ${CLIENT_TYPE}${NC}"
+    exit 1
+fi
+
+# Database connection details
+DB_CONTAINER="service-postgres-${TERRITORY_CODE}"
+DB_NAME="unityplan_${TERRITORY_CODE}"
+DB_USER="unityplan"
+
+echo -e "${BLUE}Creating OIDC client...${NC}"
+echo "Territory: ${TERRITORY_CODE}"
+echo "Client ID: ${CLIENT_ID}"
+echo "Name: ${CLIENT_NAME}"
+echo "Redirect URIs: ${REDIRECT_URIS}"
+echo ""
+
+# Generate random secret and hash it (SHA-256, like the Rust implementation)
+CLIENT_SECRET=""
+SECRET_HASH=""
+if [ "$CLIENT_TYPE" != "--public" ]; then
+    CLIENT_SECRET="$(openssl rand -hex 32)"
+    SECRET_HASH="$(printf '%s' "${CLIENT_SECRET}" | sha256sum | cut -d' ' -f1)"
+fi
+
+echo -e "${YELLOW}Inserting client into database...${NC}"
+
+docker exec -i "${DB_CONTAINER}" psql -U "${DB_USER}" -d "${DB_NAME}" -v ON_ERROR_STOP=1 \
+    -v territory_code="${TERRITORY_CODE}" -v client_id="${CLIENT_ID}" \
+    -v client_name="${CLIENT_NAME}" -v secret_hash="${SECRET_HASH}" \
+    -v redirect_uris="${REDIRECT_URIS}" <<'SQL'
+INSERT INTO global.oidc_clients (client_id, territory_code, name, secret_hash, redirect_uris)
+VALUES (
+    :'client_id',
+    :'territory_code',
+    :'client_name',
+    NULLIF(:'secret_hash', ''),
+    string_to_array(:'redirect_uris', ',')
+);
+
+-- Verify insertion
+SELECT client_id, territory_code, name, redirect_uris, allowed_scopes, is_active
+FROM global.oidc_clients
+WHERE client_id = :'client_id';
+SQL
+
+if [ $? -eq 0 ]; then
+    echo ""
+    echo -e "${GREEN}✓ OIDC client created successfully!${NC}"
+    echo ""
+    echo -e "${YELLOW}═══════════════════════════════════════════════════════════${NC}"
+    echo -e "${GREEN}Client ID:     ${CLIENT_ID}${NC}"
+    if [ -n "$CLIENT_SECRET" ]; then
+        echo -e "${GREEN}Client secret: ${CLIENT_SECRET}${NC}"
+    else
+        echo -e "${GREEN}Public client: no secret, PKCE only${NC}"
+    fi
+    echo -e "${YELLOW}═══════════════════════════════════════════════════════════${NC}"
+    echo ""
+    if [ -n "$CLIENT_SECRET" ]; then
+        echo -e "${YELLOW}⚠️  Save this secret securely - it will not be shown again!${NC}"
+        echo ""
+    fi
+    echo "Point the client at the discovery document:"
+    echo "  http://localhost:8001/api/auth/.well-known/openid-configuration"
+    echo ""
+else
+    echo -e "${RED}✗ Failed to create OIDC client${NC}"
+    exit 1
+fi
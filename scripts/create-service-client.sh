@@ -0,0 +1,80 @@
+#!/bin/bash
+
+# Create Service Client
+# This script registers an internal service that may introspect access tokens
+# (POST /api/auth/introspect). Only a hash of the client secret is stored.
+
+set -e
+
+# Colors for output
+GREEN='\033[0;32m'
+BLUE='\033[0;34m'
+YELLOW='\033[1;33m'
+RED='\033[0;31m'
+NC='\033[0m' # No Color
+
+# Configuration
+TERRITORY_CODE="${1:-dk}"
+CLIENT_ID="${2}"
+CLIENT_NAME="${3:-$2}"
+
+# Validate inputs
+if [ -z "$CLIENT_ID" ]; then
+    echo -e "${RED}Error: Client ID is required${NC}"
+    echo "Usage: $0 <territory_code> <client_id> [name]"
+    echo "Example: $0 dk user-service \"User service\""
+    exit 1
+fi
+
+if ! [[ "$CLIENT_ID" =~ ^[a-z0-9_-]+$ ]]; then
+    echo -e "${RED}Error: Client ID may only contain a-z, 0-9, '-' and '_'${NC}"
+    exit 1
+fi
+
+# Database connection details
+DB_CONTAINER="service-postgres-${TERRITORY_CODE}"
+DB_NAME="unityplan_${TERRITORY_CODE}"
+DB_USER="unityplan"
+
+echo -e "${BLUE}Creating service client...${NC}"
+echo "Territory pod: ${TERRITORY_CODE}"
+echo "Client ID: ${CLIENT_ID}"
+echo "Name: ${CLIENT_NAME}"
+echo ""
+
+# Generate random secret and hash it (SHA-256, like the Rust implementation)
+CLIENT_SECRET="$(openssl rand -hex 32)"
+SECRET_HASH="$(printf '%s' "${CLIENT_SECRET}" | sha256sum | cut -d' ' -f1)"
+
+echo -e "${YELLOW}Inserting client into database...${NC}"
+
+docker exec -i "${DB_CONTAINER}" psql -U "${DB_USER}" -d "${DB_NAME}" -v ON_ERROR_STOP=1 \
+    -v client_id="${CLIENT_ID}" -v client_name="${CLIENT_NAME}" -v secret_hash="${SECRET_HASH}" <<'SQL'
+INSERT INTO global.service_clients (client_id, name, secret_hash)
+VALUES (:'client_id', :'client_name', :'secret_hash');
+
+-- Verify insertion
+SELECT client_id, name, is_active, created_at
+FROM global.service_clients
+WHERE client_id = :'client_id';
+SQL
+
+if [ $? -eq 0 ]; then
+    echo ""
+    echo -e "${GREEN}✓ Service client created successfully!${NC}"
+    echo ""
+    echo -e "${YELLOW}═══════════════════════════════════════════════════════════${NC}"
+    echo -e "${GREEN}Client ID:     ${CLIENT_ID}${NC}"
+    echo -e "${GREEN}Client secret: ${CLIENT_SECRET}${NC}"
+    echo -e "${YELLOW}═══════════════════════════════════════════════════════════${NC}"
+    echo ""
+    echo -e "${YELLOW}⚠️  Save this secret securely - it will not be shown again!${NC}"
+    echo ""
+    echo "Introspect an access token with:"
+    echo "  curl -u ${CLIENT_ID}:<secret> -d token=<access_token> \\"
+    echo "    http://localhost:8001/api/auth/introspect"
+    echo ""
+else
+    echo -e "${RED}✗ Failed to create service client${NC}"
+    exit 1
+fi
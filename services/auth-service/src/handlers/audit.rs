@@ -0,0 +1,78 @@
+use crate::{
+    middleware::get_authenticated_user,
+    models::{AuditLogQuery, AuditVerifyQuery, DEFAULT_AUDIT_PAGE_SIZE, MAX_AUDIT_PAGE_SIZE},
+    services::{list_audit_log, verify_audit_log, AuditLogFilter, TokenService},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+/// Browse the audit log, newest entries first
+/// GET /api/audit?user_id=&action=&from=&to=&page=&per_page=
+///
+/// Territory admins see their own territory; platform admins see every
+/// territory unless they filter by `territory_code`.
+pub async fn list_audit_entries(
+    req: HttpRequest,
+    query: web::Query<AuditLogQuery>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_AUDIT_PAGE_SIZE);
+    if page < 1 || !(1..=MAX_AUDIT_PAGE_SIZE).contains(&per_page) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "page must be at least 1 and per_page between 1 and {}",
+            MAX_AUDIT_PAGE_SIZE
+        )));
+    }
+
+    let territory_code = if auth_user.roles.is_platform_admin() {
+        query.territory_code.as_deref().map(str::to_lowercase)
+    } else {
+        Some(auth_user.territory_code.clone())
+    };
+
+    let filter = AuditLogFilter {
+        territory_code,
+        user_id: query.user_id,
+        action: query.action.clone(),
+        from: query.from,
+        to: query.to,
+    };
+
+    let entries = list_audit_log(pool.get_ref(), &filter, page, per_page)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// Walk a territory's audit hash chain and report the first broken link
+/// GET /api/audit/verify?territory_code=
+///
+/// Territory admins verify their own territory; platform admins may pick any
+/// chain, including "global".
+pub async fn verify_audit_entries(
+    req: HttpRequest,
+    query: web::Query<AuditVerifyQuery>,
+    pool: web::Data<PgPool>,
+    token_service: web::Data<TokenService>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let chain_id = match query.territory_code.as_deref() {
+        Some(territory_code) if auth_user.roles.is_platform_admin() => {
+            territory_code.to_lowercase()
+        }
+        _ => auth_user.territory_code.clone(),
+    };
+
+    let report = verify_audit_log(pool.get_ref(), token_service.get_ref(), &chain_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
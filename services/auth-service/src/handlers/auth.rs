@@ -1,10 +1,24 @@
 use crate::{
-    models::{user::User, AuthResponse, AuthUserInfo, LoginRequest, RegisterRequest},
-    services::{use_invitation_token, validate_invitation_token, PasswordService, TokenService},
+    models::{
+        user::User, AuthResponse, AuthUserInfo, LoginRequest, MfaChallengeResponse,
+        MfaLoginRequest, RegisterRequest,
+    },
+    services::{
+        add_community_member, clear_login_failures, create_email_verification_token,
+        create_session, end_session, is_mfa_enabled, issue_recovery_codes, lock_invitation_token,
+        login_throttle_keys, record_login_failure, release_login_attempt, reserve_login_attempt,
+        resolve_invitation_token, revoke_access_token, rotate_session, use_invitation_token,
+        verify_mfa_code, EmailService, LoginAttempt, LoginReservation, PasswordService,
+        TokenService, DEFAULT_COMMUNITY_ROLE,
+    },
+    utils::ClientInfo,
 };
-use actix_web::{web, HttpResponse};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
 use chrono::Utc;
-use sha2::Digest;
+use shared_lib::{
+    enqueue_event, error::AppError, record_audit, AuditEntry, InvitationRedeemed,
+    TerritoryResolver, UserLoggedIn, UserRegistered,
+};
 use sqlx::{FromRow, PgPool};
 use uuid::Uuid;
 use validator::Validate;
@@ -15,23 +29,14 @@ struct TerritoryCode {
     code: String,
 }
 
-/// Get schema name for a territory
-/// For single-territory pods: returns "territory"
-/// For multi-territory pods: returns "territory_XX" (e.g., "territory_de")
-fn get_schema_name(_territory_code: &str) -> String {
-    // TODO: Make this configurable via environment variable
-    // For now, use single-territory approach (generic "territory" schema)
-    "territory".to_string()
-
-    // For multi-territory pods, use:
-    // format!("territory_{}", territory_code.to_lowercase())
-}
-
 /// Register a new user
 pub async fn register(
+    http_req: HttpRequest,
     req: web::Json<RegisterRequest>,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
     token_service: web::Data<TokenService>,
+    email_service: web::Data<EmailService>,
 ) -> actix_web::Result<HttpResponse> {
     eprintln!("DEBUG: Register handler called");
 
@@ -61,15 +66,37 @@ pub async fn register(
 
     eprintln!("DEBUG: Territory found: {}", territory.code);
 
-    // Set schema context to territory (dynamic based on territory_code)
-    let schema_name = get_schema_name(&territory.code);
+    // Resolve the schema holding this territory's data on this pod
+    let schema_name = territories
+        .schema_for(&territory.code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
 
-    // Validate invitation token
-    let invitation = validate_invitation_token(
-        pool.get_ref(),
-        &schema_name,
-        &req.invitation_token,
+    // Signed invitations are checked offline, before touching the database
+    let invitation_token =
+        resolve_invitation_token(&token_service, &req.invitation_token, &territory.code)
+            .map_err(actix_web::error::ErrorBadRequest)?;
+
+    // Hash password (before the transaction, so the invitation is not locked while hashing)
+    let password_hash = PasswordService::hash_password(&req.password)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let client = ClientInfo::from_request(&http_req);
+
+    // Everything below runs in one transaction: a failure at any step leaves
+    // neither a user nor a consumed invitation behind
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Validate invitation token and lock it until commit, so concurrent
+    // registrations cannot both take its last use
+    let invitation = lock_invitation_token(
+        &mut tx,
+        schema_name,
+        &invitation_token,
         req.email.as_deref(), // Pass Option<&str>
+        Some(&req.username),
     )
     .await
     .map_err(actix_web::error::ErrorBadRequest)?;
@@ -78,8 +105,8 @@ pub async fn register(
     let username_exists = sqlx::query_scalar::<_, bool>(
         "SELECT EXISTS(SELECT 1 FROM global.user_identities WHERE LOWER(username) = LOWER($1))",
     )
-    .bind(&req.username.to_lowercase())
-    .fetch_one(pool.get_ref())
+    .bind(req.username.to_lowercase())
+    .fetch_one(&mut *tx)
     .await
     .map_err(actix_web::error::ErrorInternalServerError)?;
 
@@ -96,7 +123,7 @@ pub async fn register(
             schema_name
         ))
         .bind(email)
-        .fetch_optional(pool.get_ref())
+        .fetch_optional(&mut *tx)
         .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
@@ -107,17 +134,13 @@ pub async fn register(
         }
     }
 
-    // Hash password
-    let password_hash = PasswordService::hash_password(&req.password)
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-
     // Create user in territory schema
     // Note: Database trigger will automatically create global.user_identities entry
     let user = sqlx::query_as::<_, User>(&format!(
         r#"
         INSERT INTO {}.users (
-            username, email, password_hash, full_name, invitation_by_token_id
-        ) VALUES ($1, $2, $3, $4, $5)
+            username, email, password_hash, full_name, invitation_by_token_id, invited_by_user_id
+        ) VALUES ($1, $2, $3, $4, $5, $6)
         RETURNING 
             id, email, password_hash, username, 
             full_name, display_name, avatar_url, bio, date_of_birth, phone,
@@ -133,11 +156,18 @@ pub async fn register(
     .bind(&password_hash)
     .bind(&req.full_name)
     .bind(invitation.id)
-    .fetch_one(pool.get_ref())
+    .bind(invitation.created_by_user_id)
+    .fetch_one(&mut *tx)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to create user: {:?}", e);
-        actix_web::error::ErrorInternalServerError(format!("Failed to create user: {}", e))
+    .map_err(|e| match e {
+        // A concurrent registration took the username or email after our checks
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            actix_web::error::ErrorBadRequest("Username or email already registered")
+        }
+        _ => {
+            tracing::error!("Failed to create user: {:?}", e);
+            actix_web::error::ErrorInternalServerError(format!("Failed to create user: {}", e))
+        }
     })?;
 
     eprintln!(
@@ -155,7 +185,7 @@ pub async fn register(
     )
     .bind(&req.territory_code)
     .bind(user.id)
-    .fetch_one(pool.get_ref())
+    .fetch_one(&mut *tx)
     .await
     .map_err(|e| {
         eprintln!("DEBUG: Failed to fetch global identity: {:?}", e);
@@ -173,49 +203,133 @@ pub async fn register(
     );
 
     // Mark invitation as used
-    use_invitation_token(pool.get_ref(), &schema_name, invitation.id, user.id, None)
+    use_invitation_token(&mut tx, schema_name, invitation.id, user.id, &client)
         .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    // Generate tokens
-    let access_token = token_service
-        .generate_access_token(
-            &public_key_hash,
-            &req.territory_code,
+    for entry in [
+        AuditEntry::new("auth.register")
+            .resource("user", user.id)
+            .changes(serde_json::json!({ "invitation_id": invitation.id })),
+        AuditEntry::new("invitation.used").resource("invitation", invitation.id),
+    ] {
+        record_audit(
+            &mut *tx,
+            &entry
+                .actor(global_identity_id)
+                .territory(&territory.code)
+                .client(&client),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    // Community invitations drop the newcomer straight into their community
+    if let Some(community_id) = invitation.community_id {
+        add_community_member(
+            &mut *tx,
+            schema_name,
             user.id,
-            &user.username,
+            community_id,
+            invitation.role.as_deref().unwrap_or(DEFAULT_COMMUNITY_ROLE),
         )
+        .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
 
-    let refresh_token = token_service.generate_refresh_token();
+    // Start a new session family in global.sessions (using global identity ID)
+    let session = create_session(&mut *tx, &token_service, global_identity_id, &client, false)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    // Store refresh token in global.sessions table (using global identity ID)
-    let refresh_token_hash = format!("{:x}", sha2::Sha256::digest(refresh_token.as_bytes()));
-    let expires_at = Utc::now() + chrono::Duration::days(7);
+    // Issue recovery codes - the only way back in for accounts without email
+    let recovery_codes = issue_recovery_codes(&mut *tx, schema_name, user.id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    sqlx::query(
-        "INSERT INTO global.sessions (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+    // Ask the user to verify the email they registered with
+    let email_verification_token = match user.email {
+        Some(ref email) => Some(
+            create_email_verification_token(&mut *tx, schema_name, user.id, email)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?,
+        ),
+        None => None,
+    };
+
+    // Announce the registration with the change itself (published by the outbox relay)
+    enqueue_event(
+        &mut *tx,
+        &territory.code,
+        UserRegistered {
+            user_id: user.id,
+            public_key_hash: public_key_hash.clone(),
+            username: user.username.clone(),
+            invitation_id: invitation.id,
+            invited_by: invitation.created_by_user_id,
+        },
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    enqueue_event(
+        &mut *tx,
+        &territory.code,
+        InvitationRedeemed {
+            invitation_id: invitation.id,
+            user_id: user.id,
+            invited_by: invitation.created_by_user_id,
+            community_id: invitation.community_id,
+        },
     )
-    .bind(global_identity_id)
-    .bind(&refresh_token_hash)
-    .bind(expires_at)
-    .execute(pool.get_ref())
     .await
     .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    // Return tokens and user info
+    tx.commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Generate access token bound to the session
+    let access_token = token_service
+        .generate_access_token(
+            &public_key_hash,
+            &req.territory_code,
+            user.id,
+            &user.username,
+            Some(session.session_id),
+            false,
+        )
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if let (Some(email), Some(token)) = (&user.email, &email_verification_token) {
+        // The account works without a verified email, so a delivery failure is not fatal
+        if let Err(e) = email_service
+            .send_email_verification(email, &user.username, &req.territory_code, token)
+            .await
+        {
+            tracing::error!(
+                "Failed to send verification email for user {}: {}",
+                user.id,
+                e
+            );
+        }
+    }
+
+    // Return tokens and user info (recovery codes are shown only this once)
     Ok(HttpResponse::Created().json(serde_json::json!({
         "user": AuthUserInfo::from(user),
         "access_token": access_token,
-        "refresh_token": refresh_token,
+        "refresh_token": session.refresh_token,
         "expires_in": token_service.get_access_token_ttl(),
+        "recovery_codes": recovery_codes,
     })))
 }
 
 /// Login user
 pub async fn login(
+    http_req: HttpRequest,
     req: web::Json<LoginRequest>,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
     token_service: web::Data<TokenService>,
 ) -> actix_web::Result<HttpResponse> {
     // Validate request
@@ -232,8 +346,25 @@ pub async fn login(
     .map_err(actix_web::error::ErrorInternalServerError)?
     .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
 
-    // Set schema context to territory (dynamic based on territory_code)
-    let schema_name = get_schema_name(&territory.code);
+    // Resolve the schema holding this territory's data on this pod
+    let schema_name = territories
+        .schema_for(&territory.code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
+
+    let client = ClientInfo::from_request(&http_req);
+
+    // Count the attempt up front, refusing it while the username or client IP is locked out
+    let throttle_keys = login_throttle_keys(&territory.code, &req.username, &client);
+    let attempt = match reserve_login_attempt(pool.get_ref(), &throttle_keys)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        LoginReservation::Reserved(attempt) => attempt,
+        // Only the start of a lockout is audited (see `fail_login`), not each refused attempt
+        LoginReservation::Throttled { retry_after } => {
+            return Err(too_many_login_attempts(retry_after));
+        }
+    };
 
     // Find user by username (not email - privacy-first)
     let user = sqlx::query_as::<_, User>(&format!(
@@ -252,24 +383,209 @@ pub async fn login(
     .bind(&req.username)
     .fetch_optional(pool.get_ref())
     .await
-    .map_err(actix_web::error::ErrorInternalServerError)?
-    .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid credentials"))?;
+    .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    // Check if user is active
-    if !user.is_active {
-        return Err(actix_web::error::ErrorUnauthorized("Account is inactive"));
-    }
+    let user = match user {
+        Some(user) => user,
+        None => {
+            // Unknown username: spend the same time as a real verification
+            PasswordService::verify_dummy(&req.password);
+
+            fail_login(
+                pool.get_ref(),
+                &attempt,
+                &territory.code,
+                &req.username,
+                None,
+                "unknown_user",
+                &client,
+            )
+            .await?;
+
+            return Err(actix_web::error::ErrorUnauthorized("Invalid credentials"));
+        }
+    };
 
-    // Verify password
+    // Verify password, then refuse inactive accounts the same way, so neither
+    // the response nor its timing tells whether an account is deactivated
     let password_hash = &user.password_hash;
 
     let is_valid = PasswordService::verify_password(&req.password, password_hash)
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    if !is_valid {
+    if !is_valid || !user.is_active {
+        fail_login(
+            pool.get_ref(),
+            &attempt,
+            &territory.code,
+            &user.username,
+            Some(user.id),
+            if is_valid {
+                "inactive"
+            } else {
+                "invalid_password"
+            },
+            &client,
+        )
+        .await?;
+
         return Err(actix_web::error::ErrorUnauthorized("Invalid credentials"));
     }
 
+    // Accounts with 2FA log in in two steps: hand out a challenge token instead of tokens
+    let mfa_enabled = is_mfa_enabled(pool.get_ref(), schema_name, user.id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if mfa_enabled {
+        // The password was right, so this attempt does not count until the code is checked
+        release_login_attempt(pool.get_ref(), &attempt)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        let mfa_token = token_service
+            .generate_mfa_challenge_token(user.id, &req.territory_code)
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        return Ok(HttpResponse::Ok().json(MfaChallengeResponse {
+            mfa_required: true,
+            mfa_token,
+            expires_in: token_service.get_mfa_challenge_ttl(),
+        }));
+    }
+
+    // Counters are only reset once the login is complete (after 2FA, if enabled)
+    clear_login_failures(pool.get_ref(), &attempt)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let response = complete_login(
+        pool.get_ref(),
+        &token_service,
+        schema_name,
+        &req.territory_code,
+        user,
+        &client,
+        false,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Complete a 2FA login with a TOTP or backup code
+/// POST /api/auth/login/mfa
+pub async fn login_mfa(
+    http_req: HttpRequest,
+    req: web::Json<MfaLoginRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    req.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Verify challenge token from the password step
+    let claims = token_service
+        .validate_mfa_challenge_token(&req.mfa_token)
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or expired MFA token"))?;
+
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or expired MFA token"))?;
+
+    let schema_name = territories
+        .schema_for(&claims.territory_code)
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or expired MFA token"))?;
+
+    // Load user from territory database
+    let user = sqlx::query_as::<_, User>(&format!(
+        r#"
+        SELECT 
+            id, email, password_hash, username, 
+            full_name, display_name, avatar_url, bio, date_of_birth, phone,
+            profile_visibility, email_notifications, push_notifications,
+            is_verified, is_active, last_login_at,
+            invited_by_user_id, invitation_by_token_id,
+            created_at, updated_at
+        FROM {}.users 
+        WHERE id = $1 AND is_active = true
+        "#,
+        schema_name
+    ))
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?
+    .ok_or_else(|| actix_web::error::ErrorUnauthorized("User not found or inactive"))?;
+
+    // Codes are throttled with the same counters as passwords
+    let client = ClientInfo::from_request(&http_req);
+    let throttle_keys = login_throttle_keys(&claims.territory_code, &user.username, &client);
+    let attempt = match reserve_login_attempt(pool.get_ref(), &throttle_keys)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        LoginReservation::Reserved(attempt) => attempt,
+        // Only the start of a lockout is audited (see `fail_login`), not each refused attempt
+        LoginReservation::Throttled { retry_after } => {
+            return Err(too_many_login_attempts(retry_after));
+        }
+    };
+
+    // Verify second factor (TOTP code or one-time backup code)
+    match verify_mfa_code(pool.get_ref(), schema_name, user.id, &req.code).await {
+        Ok(()) => {}
+        Err(AppError::Unauthorized(msg)) => {
+            fail_login(
+                pool.get_ref(),
+                &attempt,
+                &claims.territory_code,
+                &user.username,
+                Some(user.id),
+                "invalid_mfa_code",
+                &client,
+            )
+            .await?;
+
+            return Err(actix_web::error::ErrorUnauthorized(msg));
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+
+    clear_login_failures(pool.get_ref(), &attempt)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let response = complete_login(
+        pool.get_ref(),
+        &token_service,
+        schema_name,
+        &claims.territory_code,
+        user,
+        &client,
+        true,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Finish a successful login: record it, start a session and issue tokens
+async fn complete_login(
+    pool: &PgPool,
+    token_service: &TokenService,
+    schema_name: &str,
+    territory_code: &str,
+    user: User,
+    client: &ClientInfo,
+    mfa_verified: bool,
+) -> actix_web::Result<AuthResponse> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
     // Update last login (dynamic schema)
     sqlx::query(&format!(
         "UPDATE {}.users SET last_login_at = $1 WHERE id = $2",
@@ -277,7 +593,7 @@ pub async fn login(
     ))
     .bind(Utc::now())
     .bind(user.id)
-    .execute(pool.get_ref())
+    .execute(&mut *tx)
     .await
     .map_err(actix_web::error::ErrorInternalServerError)?;
 
@@ -285,57 +601,172 @@ pub async fn login(
     let (public_key_hash, global_identity_id): (String, Uuid) = sqlx::query_as(
         "SELECT public_key_hash, id FROM global.user_identities WHERE territory_code = $1 AND territory_user_id = $2"
     )
-    .bind(&req.territory_code)
+    .bind(territory_code)
     .bind(user.id)
-    .fetch_one(pool.get_ref())
+    .fetch_one(&mut *tx)
     .await
     .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    // Generate tokens
+    // Start a new session family in global.sessions (using global identity ID)
+    let session = create_session(
+        &mut *tx,
+        token_service,
+        global_identity_id,
+        client,
+        mfa_verified,
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("auth.login")
+            .actor(global_identity_id)
+            .territory(territory_code)
+            .resource("session_family", session.session_id)
+            .changes(serde_json::json!({ "mfa_verified": mfa_verified }))
+            .client(client),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    enqueue_event(
+        &mut *tx,
+        territory_code,
+        UserLoggedIn {
+            user_id: user.id,
+            public_key_hash: public_key_hash.clone(),
+            session_id: session.session_id,
+            mfa_verified,
+        },
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Generate access token bound to the session
     let access_token = token_service
         .generate_access_token(
             &public_key_hash,
-            &req.territory_code,
+            territory_code,
             user.id,
             &user.username,
+            Some(session.session_id),
+            mfa_verified,
         )
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let refresh_token = token_service.generate_refresh_token();
-
-    // Store refresh token in global.sessions (using global identity ID)
-    let refresh_token_hash = format!("{:x}", sha2::Sha256::digest(refresh_token.as_bytes()));
-    let expires_at = Utc::now() + chrono::Duration::days(7);
-
-    sqlx::query(
-        "INSERT INTO global.sessions (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
-    )
-    .bind(global_identity_id)
-    .bind(&refresh_token_hash)
-    .bind(expires_at)
-    .execute(pool.get_ref())
-    .await
-    .map_err(actix_web::error::ErrorInternalServerError)?;
-
-    // Return response
-    Ok(HttpResponse::Ok().json(AuthResponse {
+    Ok(AuthResponse {
         user: AuthUserInfo::from(user),
         access_token,
-        refresh_token,
+        refresh_token: session.refresh_token,
         expires_in: token_service.get_access_token_ttl(),
-    }))
+    })
+}
+
+/// Finish a failed login: count it, and audit it along with the lockout it started, if any
+async fn fail_login(
+    pool: &PgPool,
+    attempt: &LoginAttempt,
+    territory_code: &str,
+    username: &str,
+    user_id: Option<Uuid>,
+    reason: &str,
+    client: &ClientInfo,
+) -> actix_web::Result<()> {
+    let locked_out = record_login_failure(pool, attempt)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    audit_login_failure(pool, territory_code, username, user_id, reason, client).await?;
+
+    if locked_out {
+        audit_login_failure(
+            pool,
+            territory_code,
+            username,
+            user_id,
+            "locked_out",
+            client,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Record a failed login in the audit log
+///
+/// `user_id` is the territory user, if the username exists; the audit entry
+/// is attributed to their global identity.
+async fn audit_login_failure(
+    pool: &PgPool,
+    territory_code: &str,
+    username: &str,
+    user_id: Option<Uuid>,
+    reason: &str,
+    client: &ClientInfo,
+) -> actix_web::Result<()> {
+    let identity_id: Option<Uuid> = match user_id {
+        Some(user_id) => sqlx::query_scalar(
+            "SELECT id FROM global.user_identities WHERE territory_code = $1 AND territory_user_id = $2",
+        )
+        .bind(territory_code)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?,
+        None => None,
+    };
+
+    let mut entry = AuditEntry::new("auth.login_failed")
+        .territory(territory_code)
+        .changes(serde_json::json!({ "username": username, "reason": reason }))
+        .client(client);
+    if let Some(identity_id) = identity_id {
+        entry = entry.actor(identity_id);
+    }
+    if let Some(user_id) = user_id {
+        entry = entry.resource("user", user_id);
+    }
+
+    record_audit(pool, &entry)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+/// 429 error for a locked-out login, telling the client when to retry
+pub(crate) fn too_many_login_attempts(retry_after: i64) -> actix_web::Error {
+    let response = HttpResponse::TooManyRequests()
+        .insert_header((
+            actix_web::http::header::RETRY_AFTER,
+            retry_after.to_string(),
+        ))
+        .json(serde_json::json!({
+            "error": "Too many failed login attempts",
+            "retry_after": retry_after
+        }));
+
+    actix_web::error::InternalError::from_response("Too many failed login attempts", response)
+        .into()
 }
 
 /// Get current authenticated user info
 pub async fn me(
     req: actix_web::HttpRequest,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
 ) -> actix_web::Result<HttpResponse> {
     // Get authenticated user from JWT middleware
     let auth_user = crate::middleware::get_authenticated_user(&req)?;
 
-    // Set schema context to territory
-    let schema_name = get_schema_name(&auth_user.territory_code);
+    // Resolve the schema holding the user's territory data
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
 
     // Load full user profile from database
     let user = sqlx::query_as::<_, User>(&format!(
@@ -379,8 +810,10 @@ pub async fn me(
 
 /// Refresh access token
 pub async fn refresh(
+    http_req: HttpRequest,
     req: web::Json<crate::models::RefreshTokenRequest>,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
     token_service: web::Data<TokenService>,
 ) -> actix_web::Result<HttpResponse> {
     // Validate request
@@ -397,55 +830,25 @@ pub async fn refresh(
     .map_err(actix_web::error::ErrorInternalServerError)?
     .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
 
-    let schema_name = get_schema_name(&territory.code);
-
-    // Hash the provided refresh token
-    let token_hash = format!("{:x}", sha2::Sha256::digest(req.refresh_token.as_bytes()));
-
-    // Query session from global.sessions
-    #[derive(FromRow)]
-    struct SessionRecord {
-        user_id: uuid::Uuid,
-        expires_at: chrono::DateTime<Utc>,
-    }
-
-    let session_record = sqlx::query_as::<_, SessionRecord>(
-        "SELECT user_id, expires_at FROM global.sessions WHERE token_hash = $1",
-    )
-    .bind(&token_hash)
-    .fetch_optional(pool.get_ref())
-    .await
-    .map_err(actix_web::error::ErrorInternalServerError)?
-    .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid refresh token"))?;
-
-    // Check if token is expired
-    if session_record.expires_at < Utc::now() {
-        // Delete expired session
-        sqlx::query("DELETE FROM global.sessions WHERE token_hash = $1")
-            .bind(&token_hash)
-            .execute(pool.get_ref())
-            .await
-            .map_err(actix_web::error::ErrorInternalServerError)?;
-
-        return Err(actix_web::error::ErrorUnauthorized("Refresh token expired"));
-    }
-
-    // Get territory user ID and public_key_hash from global.user_identities
-    #[derive(FromRow)]
-    struct GlobalIdentity {
-        territory_user_id: uuid::Uuid,
-        public_key_hash: String,
-    }
+    let schema_name = territories
+        .schema_for(&territory.code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
 
-    let identity = sqlx::query_as::<_, GlobalIdentity>(
-        "SELECT territory_user_id, public_key_hash FROM global.user_identities WHERE id = $1 AND territory_code = $2"
+    // Rotate the refresh token within its session family (detects token reuse,
+    // and ends the family of a deactivated member)
+    let session = rotate_session(
+        pool.get_ref(),
+        &token_service,
+        schema_name,
+        &req.refresh_token,
+        &req.territory_code,
+        &ClientInfo::from_request(&http_req),
     )
-    .bind(session_record.user_id)
-    .bind(&req.territory_code)
-    .fetch_optional(pool.get_ref())
     .await
-    .map_err(actix_web::error::ErrorInternalServerError)?
-    .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid session"))?;
+    .map_err(|e| match e {
+        AppError::Unauthorized(msg) => actix_web::error::ErrorUnauthorized(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
 
     // Load user from territory database
     let user = sqlx::query_as::<_, User>(&format!(
@@ -462,69 +865,66 @@ pub async fn refresh(
         "#,
         schema_name
     ))
-    .bind(identity.territory_user_id)
+    .bind(session.territory_user_id)
     .fetch_optional(pool.get_ref())
     .await
     .map_err(actix_web::error::ErrorInternalServerError)?
     .ok_or_else(|| actix_web::error::ErrorUnauthorized("User not found or inactive"))?;
 
-    // Generate new tokens
+    // Generate new access token
     let new_access_token = token_service
         .generate_access_token(
-            &identity.public_key_hash,
+            &session.public_key_hash,
             &req.territory_code,
             user.id,
             &user.username,
+            Some(session.session_id),
+            session.mfa_verified,
         )
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let new_refresh_token = token_service.generate_refresh_token();
-    let new_token_hash = format!("{:x}", sha2::Sha256::digest(new_refresh_token.as_bytes()));
-    let new_expires_at = Utc::now() + chrono::Duration::days(7);
-
-    // Delete old session and insert new one (token rotation) in global.sessions
-    sqlx::query("DELETE FROM global.sessions WHERE token_hash = $1")
-        .bind(&token_hash)
-        .execute(pool.get_ref())
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
-
-    sqlx::query(
-        "INSERT INTO global.sessions (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
-    )
-    .bind(session_record.user_id) // Use the global identity ID from the session
-    .bind(&new_token_hash)
-    .bind(new_expires_at)
-    .execute(pool.get_ref())
-    .await
-    .map_err(actix_web::error::ErrorInternalServerError)?;
-
     // Return new tokens
     Ok(HttpResponse::Ok().json(AuthResponse {
         user: AuthUserInfo::from(user),
         access_token: new_access_token,
-        refresh_token: new_refresh_token,
+        refresh_token: session.refresh_token,
         expires_in: token_service.get_access_token_ttl(),
     }))
 }
 
 /// Logout user
+///
+/// An access token sent along (`Authorization: Bearer`) is revoked too;
+/// otherwise it would stay valid until it expires.
 pub async fn logout(
+    http_req: HttpRequest,
     req: web::Json<crate::models::LogoutRequest>,
     pool: web::Data<PgPool>,
+    token_service: web::Data<TokenService>,
 ) -> actix_web::Result<HttpResponse> {
-    // Hash the provided refresh token
-    let token_hash = format!("{:x}", sha2::Sha256::digest(req.refresh_token.as_bytes()));
+    // End the whole session family in global.sessions
+    end_session(
+        pool.get_ref(),
+        &req.refresh_token,
+        &ClientInfo::from_request(&http_req),
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
 
-    // Delete session from global.sessions
-    let result = sqlx::query("DELETE FROM global.sessions WHERE token_hash = $1")
-        .bind(&token_hash)
-        .execute(pool.get_ref())
-        .await
-        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let access_claims = http_req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .and_then(|token| token_service.validate_token(token).ok());
 
-    if result.rows_affected() == 0 {
-        return Err(actix_web::error::ErrorNotFound("Refresh token not found"));
+    if let Some(claims) = access_claims {
+        revoke_access_token(pool.get_ref(), &claims, "logout")
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
     }
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
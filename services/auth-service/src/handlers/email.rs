@@ -0,0 +1,171 @@
+use crate::{
+    handlers::password::verify_current_password,
+    middleware::get_authenticated_user,
+    models::{ChangeEmailRequest, VerifyEmailQuery},
+    services::{create_email_verification_token, verify_email_token, EmailService},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared_lib::{error::AppError, TerritoryResolver};
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Verify an email address (link from the verification email)
+/// GET /api/auth/verify-email/{token}?territory_code=dk
+pub async fn verify_email(
+    path: web::Path<String>,
+    query: web::Query<VerifyEmailQuery>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    let token = path.into_inner();
+
+    let territory_code = query
+        .territory_code
+        .as_deref()
+        .or_else(|| territories.default_territory())
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Territory code is required"))?;
+
+    let schema_name = territories
+        .schema_for(territory_code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
+
+    verify_email_token(pool.get_ref(), schema_name, &token)
+        .await
+        .map_err(|e| match e {
+            AppError::NotFound(msg) => actix_web::error::ErrorBadRequest(msg),
+            AppError::Validation(msg) => actix_web::error::ErrorConflict(msg),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Email verified successfully"
+    })))
+}
+
+/// Change the authenticated user's email
+/// PUT /api/auth/email
+///
+/// The new address replaces the current one only once it has been verified.
+pub async fn change_email(
+    req: HttpRequest,
+    body: web::Json<ChangeEmailRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    email_service: web::Data<EmailService>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    verify_current_password(
+        pool.get_ref(),
+        schema_name,
+        &req,
+        &auth_user.territory_code,
+        &auth_user.username,
+        auth_user.user_id,
+        &body.current_password,
+    )
+    .await?;
+
+    // Check if email already exists in territory
+    let existing_email: Option<Uuid> = sqlx::query_scalar(&format!(
+        "SELECT id FROM {}.users WHERE email = $1 AND id <> $2",
+        schema_name
+    ))
+    .bind(&body.email)
+    .bind(auth_user.user_id)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if existing_email.is_some() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Email already registered in this territory",
+        ));
+    }
+
+    let token = create_email_verification_token(
+        pool.get_ref(),
+        schema_name,
+        auth_user.user_id,
+        &body.email,
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    email_service
+        .send_email_verification(
+            &body.email,
+            &auth_user.username,
+            &auth_user.territory_code,
+            &token,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "Verification email sent to the new address"
+    })))
+}
+
+/// Send a new verification link for the authenticated user's current email
+/// POST /api/auth/email/resend-verification
+pub async fn resend_email_verification(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    email_service: web::Data<EmailService>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let (email, is_verified): (Option<String>, bool) = sqlx::query_as(&format!(
+        "SELECT email, is_verified FROM {}.users WHERE id = $1",
+        schema_name
+    ))
+    .bind(auth_user.user_id)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let email = email
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("No email address on this account"))?;
+
+    if is_verified {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Email is already verified",
+        ));
+    }
+
+    let token =
+        create_email_verification_token(pool.get_ref(), schema_name, auth_user.user_id, &email)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    email_service
+        .send_email_verification(
+            &email,
+            &auth_user.username,
+            &auth_user.territory_code,
+            &token,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "Verification email sent"
+    })))
+}
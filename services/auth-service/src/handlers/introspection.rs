@@ -0,0 +1,62 @@
+use crate::{
+    models::IntrospectionRequest,
+    services::{authenticate_service_client, introspect_token, TokenService},
+};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use shared_lib::TerritoryResolver;
+use sqlx::PgPool;
+
+/// Introspect an access token for an internal service (RFC 7662)
+/// POST /api/auth/introspect
+///
+/// The calling service authenticates with its client credentials (HTTP Basic,
+/// see `global.service_clients`) instead of holding the JWT signing key.
+pub async fn introspect(
+    http_req: HttpRequest,
+    form: web::Form<IntrospectionRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+) -> actix_web::Result<HttpResponse> {
+    let client = match basic_credentials(&http_req) {
+        Some((client_id, client_secret)) => {
+            authenticate_service_client(pool.get_ref(), &client_id, &client_secret)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+        }
+        None => None,
+    };
+    let Some(client) = client else {
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"unityplan\""))
+            .json(serde_json::json!({ "error": "invalid_client" })));
+    };
+    tracing::debug!(
+        "Token introspection by service client {} ({})",
+        client.client_id,
+        client.name
+    );
+
+    let response = introspect_token(pool.get_ref(), &token_service, &territories, &form.token)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(response))
+}
+
+/// Client ID and secret from an `Authorization: Basic` header
+pub(crate) fn basic_credentials(req: &HttpRequest) -> Option<(String, String)> {
+    let encoded = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (client_id, client_secret) = decoded.split_once(':')?;
+
+    Some((client_id.to_string(), client_secret.to_string()))
+}
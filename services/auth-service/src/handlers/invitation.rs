@@ -1,34 +1,36 @@
 use crate::{
-    middleware::get_authenticated_user,
-    models::invitation::{CreateInvitationRequest, InvitationResponse},
+    middleware::{get_authenticated_user, AuthenticatedUser},
+    models::invitation::{
+        CreateInvitationBatchRequest, CreateInvitationRequest, InvitationBatchResponse,
+        InvitationResponse, InvitationToken,
+    },
     services::{
-        create_invitation_token, get_invitation_uses, list_user_invitations,
-        revoke_invitation_token, validate_invitation_token,
+        self, check_invitation_quota, community_role_of, community_role_rank,
+        create_invitation_token, get_invitation_uses, get_user_invitation,
+        has_community_permission, is_community_active, is_community_role, is_mfa_required,
+        is_signed_invitation_token, is_verified_email_required, list_batch_invitations,
+        list_user_invitations, lock_invitation_token, permissions, resolve_invitation_token,
+        revoke_invitation_token, set_community_role, use_invitation_token,
+        validate_invitation_token, InvitationCardService, TokenService, DEFAULT_COMMUNITY_ROLE,
     },
+    utils::ClientInfo,
 };
 use actix_web::{web, HttpRequest, HttpResponse};
+use shared_lib::{
+    enqueue_event, error::AppError, record_audit, AuditEntry, InvitationRedeemed, TerritoryResolver,
+};
 use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
 
-/// Get schema name for a territory
-/// For single-territory pods: returns "territory"
-/// For multi-territory pods: returns "territory_XX" (e.g., "territory_de")
-fn get_schema_name(_territory_code: &str) -> String {
-    // TODO: Make this configurable via environment variable
-    // For now, use single-territory approach (generic "territory" schema)
-    "territory".to_string()
-
-    // For multi-territory pods, use:
-    // format!("territory_{}", territory_code.to_lowercase())
-}
-
 /// Create a new invitation token
 /// POST /api/auth/invitations
 pub async fn create_invitation(
     req: HttpRequest,
     body: web::Json<CreateInvitationRequest>,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
 ) -> actix_web::Result<HttpResponse> {
     // Get authenticated user
     let auth_user = get_authenticated_user(&req)?;
@@ -39,27 +41,87 @@ pub async fn create_invitation(
 
     // Validate business rules
     body.validate_business_rules()
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    // Multi-use invitations are reserved for roles granting the permission
+    if body.token_type == "group"
+        && !auth_user.has_permission(permissions::INVITATIONS_CREATE_GROUP)
+    {
+        return Err(actix_web::error::ErrorForbidden(format!(
+            "Missing permission: {}",
+            permissions::INVITATIONS_CREATE_GROUP
+        )));
+    }
 
     // Get territory schema
-    let schema_name = get_schema_name(&auth_user.territory_code);
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    authorize_invitation(
+        &auth_user,
+        pool.get_ref(),
+        schema_name,
+        body.community_id,
+        body.role.as_deref(),
+    )
+    .await?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Creators are held to the territory's invitation quota
+    check_invitation_quota(&mut tx, schema_name, auth_user.user_id, 1)
+        .await
+        .map_err(map_quota_error)?;
 
     // Create invitation token
     let token = create_invitation_token(
-        pool.get_ref(),
-        &schema_name,
+        &mut *tx,
+        schema_name,
         &body.token_type,
         body.email.clone(),
+        body.username.clone(),
         body.max_uses,
         body.expires_in_days,
         body.purpose.clone(),
         Some(auth_user.user_id),
+        body.community_id,
+        body.role.as_deref(),
+        None,
     )
     .await
     .map_err(actix_web::error::ErrorInternalServerError)?;
 
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("invitation.created")
+            .actor(auth_user.identity_id)
+            .territory(&auth_user.territory_code)
+            .resource("invitation", token.id)
+            .changes(serde_json::json!({
+                "token_type": token.token_type,
+                "max_uses": token.max_uses,
+                "community_id": token.community_id,
+                "role": token.role,
+            }))
+            .client(&ClientInfo::from_request(&req)),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
     // Return response
-    Ok(HttpResponse::Created().json(InvitationResponse::from(token)))
+    Ok(HttpResponse::Created().json(invitation_response(
+        &token_service,
+        &auth_user.territory_code,
+        token,
+    )?))
 }
 
 /// List invitation tokens created by the authenticated user
@@ -67,21 +129,27 @@ pub async fn create_invitation(
 pub async fn list_invitations(
     req: HttpRequest,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
 ) -> actix_web::Result<HttpResponse> {
     // Get authenticated user
     let auth_user = get_authenticated_user(&req)?;
 
     // Get territory schema
-    let schema_name = get_schema_name(&auth_user.territory_code);
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
 
     // List user's invitations
-    let tokens = list_user_invitations(pool.get_ref(), &schema_name, auth_user.user_id)
+    let tokens = list_user_invitations(pool.get_ref(), schema_name, auth_user.user_id)
         .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
     // Convert to response format
-    let responses: Vec<InvitationResponse> =
-        tokens.into_iter().map(InvitationResponse::from).collect();
+    let responses = tokens
+        .into_iter()
+        .map(|token| invitation_response(&token_service, &auth_user.territory_code, token))
+        .collect::<actix_web::Result<Vec<_>>>()?;
 
     Ok(HttpResponse::Ok().json(responses))
 }
@@ -92,23 +160,37 @@ pub async fn revoke_invitation(
     req: HttpRequest,
     path: web::Path<Uuid>,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
 ) -> actix_web::Result<HttpResponse> {
     // Get authenticated user
     let auth_user = get_authenticated_user(&req)?;
 
     // Get territory schema
-    let schema_name = get_schema_name(&auth_user.territory_code);
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
 
     let token_id = path.into_inner();
 
     // Revoke token (only if created by this user)
-    revoke_invitation_token(pool.get_ref(), &schema_name, token_id, auth_user.user_id)
+    revoke_invitation_token(pool.get_ref(), schema_name, token_id, auth_user.user_id)
         .await
         .map_err(|e| match e {
-            shared_lib::error::AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+            AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
             _ => actix_web::error::ErrorInternalServerError(e),
         })?;
 
+    record_audit(
+        pool.get_ref(),
+        &AuditEntry::new("invitation.revoked")
+            .actor(auth_user.identity_id)
+            .territory(&auth_user.territory_code)
+            .resource("invitation", token_id)
+            .client(&ClientInfo::from_request(&req)),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "Invitation token revoked successfully"
     })))
@@ -120,56 +202,703 @@ pub async fn get_invitation_usage(
     req: HttpRequest,
     path: web::Path<Uuid>,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
 ) -> actix_web::Result<HttpResponse> {
     // Get authenticated user
     let auth_user = get_authenticated_user(&req)?;
 
     // Get territory schema
-    let schema_name = get_schema_name(&auth_user.territory_code);
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
 
     let token_id = path.into_inner();
 
     // Get usage statistics
-    let uses = get_invitation_uses(pool.get_ref(), &schema_name, token_id)
+    let uses = get_invitation_uses(pool.get_ref(), schema_name, token_id)
         .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
     Ok(HttpResponse::Ok().json(uses))
 }
 
+/// Create a batch of single-use invitation tokens
+/// POST /api/auth/invitations/batch?format=json|csv
+///
+/// Creates one token per email and username, plus `count` untargeted tokens,
+/// all sharing a `batch_id`. Batches outside a community need the
+/// `invitations.create_group` permission.
+pub async fn create_invitation_batch(
+    req: HttpRequest,
+    body: web::Json<CreateInvitationBatchRequest>,
+    query: web::Query<BatchFormatQuery>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+    cards: web::Data<InvitationCardService>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Validate business rules
+    body.validate_business_rules()
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let format = query.format()?;
+
+    // Handing out many invitations at once is a group invitation in disguise
+    if body.community_id.is_none()
+        && !auth_user.has_permission(permissions::INVITATIONS_CREATE_GROUP)
+    {
+        return Err(actix_web::error::ErrorForbidden(format!(
+            "Missing permission: {}",
+            permissions::INVITATIONS_CREATE_GROUP
+        )));
+    }
+
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    authorize_invitation(
+        &auth_user,
+        pool.get_ref(),
+        schema_name,
+        body.community_id,
+        body.role.as_deref(),
+    )
+    .await?;
+
+    // Create all tokens in one transaction
+    let (batch_id, tokens) = services::create_invitation_batch(
+        pool.get_ref(),
+        schema_name,
+        auth_user.user_id,
+        body.recipients(),
+        body.expires_in_days,
+        body.community_id,
+        body.role.as_deref(),
+    )
+    .await
+    .map_err(map_quota_error)?;
+
+    record_audit(
+        pool.get_ref(),
+        &AuditEntry::new("invitation.batch_created")
+            .actor(auth_user.identity_id)
+            .territory(&auth_user.territory_code)
+            .resource("invitation_batch", batch_id)
+            .changes(serde_json::json!({
+                "count": tokens.len(),
+                "community_id": body.community_id,
+                "role": body.role,
+            }))
+            .client(&ClientInfo::from_request(&req)),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    batch_response(
+        HttpResponse::Created(),
+        format,
+        &cards,
+        &token_service,
+        &auth_user.territory_code,
+        batch_id,
+        tokens,
+    )
+}
+
+/// Report on an invitation batch (tokens, uses and status)
+/// GET /api/auth/invitations/batch/{batch_id}?format=json|csv
+pub async fn get_invitation_batch(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<BatchFormatQuery>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+    cards: web::Data<InvitationCardService>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let format = query.format()?;
+
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let batch_id = path.into_inner();
+
+    // List the batch (only if created by this user)
+    let tokens = list_batch_invitations(pool.get_ref(), schema_name, batch_id, auth_user.user_id)
+        .await
+        .map_err(|e| match e {
+            AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        })?;
+
+    batch_response(
+        HttpResponse::Ok(),
+        format,
+        &cards,
+        &token_service,
+        &auth_user.territory_code,
+        batch_id,
+        tokens,
+    )
+}
+
+/// Revoke every invitation of a batch
+/// DELETE /api/auth/invitations/batch/{batch_id}
+pub async fn revoke_invitation_batch(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let batch_id = path.into_inner();
+
+    // Revoke batch (only if created by this user)
+    let revoked =
+        services::revoke_invitation_batch(pool.get_ref(), schema_name, batch_id, auth_user.user_id)
+            .await
+            .map_err(|e| match e {
+                AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+                _ => actix_web::error::ErrorInternalServerError(e),
+            })?;
+
+    record_audit(
+        pool.get_ref(),
+        &AuditEntry::new("invitation.batch_revoked")
+            .actor(auth_user.identity_id)
+            .territory(&auth_user.territory_code)
+            .resource("invitation_batch", batch_id)
+            .changes(serde_json::json!({ "revoked": revoked }))
+            .client(&ClientInfo::from_request(&req)),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Invitation batch revoked successfully",
+        "batch_id": batch_id,
+        "revoked": revoked
+    })))
+}
+
+/// Answer with a batch as JSON or as a CSV file for mail merges and printing
+fn batch_response(
+    mut response: actix_web::HttpResponseBuilder,
+    format: BatchFormat,
+    cards: &InvitationCardService,
+    token_service: &TokenService,
+    territory_code: &str,
+    batch_id: Uuid,
+    tokens: Vec<InvitationToken>,
+) -> actix_web::Result<HttpResponse> {
+    match format {
+        BatchFormat::Json => Ok(response.json(InvitationBatchResponse {
+            batch_id,
+            invitations: tokens
+                .into_iter()
+                .map(|token| invitation_response(token_service, territory_code, token))
+                .collect::<actix_web::Result<_>>()?,
+        })),
+        BatchFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record([
+                    "token",
+                    "email",
+                    "username",
+                    "registration_link",
+                    "expires_at",
+                    "current_uses",
+                    "is_active",
+                ])
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            for token in &tokens {
+                let link_token = printable_token(token_service, territory_code, token)?;
+                writer
+                    .write_record([
+                        token.token.clone(),
+                        token.invited_email.clone().unwrap_or_default(),
+                        token.invited_username.clone().unwrap_or_default(),
+                        cards.registration_link(territory_code, &link_token),
+                        token
+                            .expires_at
+                            .map(|expires_at| expires_at.to_rfc3339())
+                            .unwrap_or_default(),
+                        token.current_uses.to_string(),
+                        token.is_active.to_string(),
+                    ])
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+
+            let csv = writer
+                .into_inner()
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            Ok(response
+                .content_type("text/csv; charset=utf-8")
+                .insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"invitations-{}.csv\"", batch_id),
+                ))
+                .body(csv))
+        }
+    }
+}
+
+/// Render an invitation as a QR code of its registration link
+/// GET /api/auth/invitations/{id}/qr?format=svg|png
+pub async fn get_invitation_qr(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<QrQuery>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+    cards: web::Data<InvitationCardService>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let token =
+        load_printable_invitation(&auth_user, path.into_inner(), &pool, &territories).await?;
+    let link_token = printable_token(&token_service, &auth_user.territory_code, &token)?;
+
+    match query.format.as_deref().unwrap_or("svg") {
+        "svg" => {
+            let svg = cards
+                .qr_svg(&auth_user.territory_code, &link_token)
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            Ok(HttpResponse::Ok().content_type("image/svg+xml").body(svg))
+        }
+        "png" => {
+            let png = cards
+                .qr_png(&auth_user.territory_code, &link_token)
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            Ok(HttpResponse::Ok().content_type("image/png").body(png))
+        }
+        _ => Err(actix_web::error::ErrorBadRequest(
+            "Format must be svg or png",
+        )),
+    }
+}
+
+/// Render a printable A5 flyer (SVG) for an invitation
+/// GET /api/auth/invitations/{id}/flyer
+pub async fn get_invitation_flyer(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+    cards: web::Data<InvitationCardService>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let token =
+        load_printable_invitation(&auth_user, path.into_inner(), &pool, &territories).await?;
+
+    let link_token = printable_token(&token_service, &auth_user.territory_code, &token)?;
+
+    let svg = cards
+        .flyer_svg(&auth_user.territory_code, &token, &link_token)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header((
+            "Content-Disposition",
+            format!("inline; filename=\"invitation-{}.svg\"", token.id),
+        ))
+        .body(svg))
+}
+
+/// Invitation response including the signed form of the token
+fn invitation_response(
+    token_service: &TokenService,
+    territory_code: &str,
+    token: InvitationToken,
+) -> actix_web::Result<InvitationResponse> {
+    let signed_token = signed_invitation_token(token_service, territory_code, &token)?;
+
+    let mut response = InvitationResponse::from(token);
+    response.signed_token = signed_token;
+    Ok(response)
+}
+
+/// Signed, offline-verifiable form of an invitation (None if it never expires)
+fn signed_invitation_token(
+    token_service: &TokenService,
+    territory_code: &str,
+    token: &InvitationToken,
+) -> actix_web::Result<Option<String>> {
+    token
+        .expires_at
+        .map(|expires_at| {
+            token_service.sign_invitation(
+                territory_code,
+                &token.token_type,
+                expires_at,
+                &token.token,
+            )
+        })
+        .transpose()
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+/// Token put into printed links and QR codes: the signed form where possible,
+/// so kiosks can check it offline
+fn printable_token(
+    token_service: &TokenService,
+    territory_code: &str,
+    token: &InvitationToken,
+) -> actix_web::Result<String> {
+    Ok(
+        signed_invitation_token(token_service, territory_code, token)?
+            .unwrap_or_else(|| token.token.clone()),
+    )
+}
+
+/// Check that the authenticated user may create invitations, optionally into
+/// a community with a community role
+async fn authorize_invitation(
+    auth_user: &AuthenticatedUser,
+    pool: &PgPool,
+    schema_name: &str,
+    community_id: Option<Uuid>,
+    role: Option<&str>,
+) -> actix_web::Result<()> {
+    // Territories may require managers to sign in with 2FA before inviting
+    if !auth_user.mfa_verified {
+        let required = is_mfa_required(
+            pool,
+            schema_name,
+            auth_user.identity_id,
+            &auth_user.territory_code,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        if required {
+            return Err(actix_web::error::ErrorForbidden(
+                "Two-factor authentication is required for territory managers",
+            ));
+        }
+    }
+
+    // Territories may reserve invitations for users with a verified email
+    if !auth_user.is_verified {
+        let required = is_verified_email_required(pool, schema_name, "invitations")
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        if required {
+            return Err(actix_web::error::ErrorForbidden(
+                "A verified email address is required to create invitations",
+            ));
+        }
+    }
+
+    // Community invitations need the right permission in that community
+    if let Some(community_id) = community_id {
+        let role = role.unwrap_or(DEFAULT_COMMUNITY_ROLE);
+        if !is_community_role(role) {
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "Unknown community role: {}",
+                role
+            )));
+        }
+
+        let active = is_community_active(pool, schema_name, community_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        if !active {
+            return Err(actix_web::error::ErrorNotFound("Community not found"));
+        }
+
+        // Inviting as a plain member vs. handing out a community role
+        let permission = if role == DEFAULT_COMMUNITY_ROLE {
+            permissions::COMMUNITIES_INVITE
+        } else {
+            permissions::COMMUNITIES_MANAGE_ROLES
+        };
+
+        let allowed = has_community_permission(
+            pool,
+            schema_name,
+            &auth_user.roles,
+            auth_user.user_id,
+            community_id,
+            permission,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        if !allowed {
+            return Err(actix_web::error::ErrorForbidden(format!(
+                "Missing permission in this community: {}",
+                permission
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn map_quota_error(e: AppError) -> actix_web::Error {
+    match e {
+        AppError::Forbidden(msg) => actix_web::error::ErrorForbidden(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    }
+}
+
+/// Load an invitation of the authenticated user that can still be handed out
+async fn load_printable_invitation(
+    auth_user: &AuthenticatedUser,
+    token_id: Uuid,
+    pool: &PgPool,
+    territories: &TerritoryResolver,
+) -> actix_web::Result<InvitationToken> {
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    // Only the creator may print an invitation
+    let token = get_user_invitation(pool, schema_name, token_id, auth_user.user_id)
+        .await
+        .map_err(|e| match e {
+            AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        })?;
+
+    let is_expired = token
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= chrono::Utc::now());
+    let is_used_up = token
+        .max_uses
+        .is_some_and(|max_uses| token.current_uses >= max_uses);
+    if !token.is_active || is_expired || is_used_up {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Invitation is no longer valid",
+        ));
+    }
+
+    Ok(token)
+}
+
+/// Redeem a community invitation as an existing member
+/// POST /api/auth/invitations/{token}/accept
+///
+/// Joins the invitation's community (or takes on its role there) and uses up
+/// the invitation the same way a registration would.
+pub async fn accept_invitation(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&http_req)?;
+
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    // Signed invitations are checked offline, before touching the database
+    let token = resolve_invitation_token(
+        &token_service,
+        &path.into_inner(),
+        &auth_user.territory_code,
+    )
+    .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let email: Option<String> = sqlx::query_scalar(&format!(
+        "SELECT email FROM {}.users WHERE id = $1",
+        schema_name
+    ))
+    .bind(auth_user.user_id)
+    .fetch_one(pool.get_ref())
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Validate invitation token and lock it until commit
+    let invitation = lock_invitation_token(
+        &mut tx,
+        schema_name,
+        &token,
+        email.as_deref(),
+        Some(&auth_user.username),
+    )
+    .await
+    .map_err(actix_web::error::ErrorBadRequest)?;
+
+    // Plain invitations are only good for signing up
+    let community_id = invitation.community_id.ok_or_else(|| {
+        actix_web::error::ErrorBadRequest("This invitation is for new members only")
+    })?;
+
+    let role = invitation.role.as_deref().unwrap_or(DEFAULT_COMMUNITY_ROLE);
+
+    let current_role = community_role_of(&mut *tx, schema_name, auth_user.user_id, community_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Invitations can only promote: never replace a role ranking the same or higher
+    if let Some(current_role) = current_role {
+        let current_rank = community_role_rank(&current_role);
+        if current_rank.is_none() || community_role_rank(role) <= current_rank {
+            return Err(actix_web::error::ErrorConflict(
+                "You already hold this role or a higher one in this community",
+            ));
+        }
+    }
+
+    set_community_role(&mut *tx, schema_name, auth_user.user_id, community_id, role)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let client = ClientInfo::from_request(&http_req);
+    use_invitation_token(
+        &mut tx,
+        schema_name,
+        invitation.id,
+        auth_user.user_id,
+        &client,
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("invitation.used")
+            .actor(auth_user.identity_id)
+            .territory(&auth_user.territory_code)
+            .resource("invitation", invitation.id)
+            .changes(serde_json::json!({ "community_id": community_id, "role": role }))
+            .client(&client),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    enqueue_event(
+        &mut *tx,
+        &auth_user.territory_code,
+        InvitationRedeemed {
+            invitation_id: invitation.id,
+            user_id: auth_user.user_id,
+            invited_by: invitation.created_by_user_id,
+            community_id: Some(community_id),
+        },
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Invitation accepted",
+        "community_id": community_id,
+        "role": role,
+    })))
+}
+
 /// Validate an invitation token (public endpoint - no auth required)
 /// GET /api/auth/invitations/validate/{token}
 pub async fn validate_invitation(
     path: web::Path<String>,
     query: web::Query<ValidationQuery>,
     pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
 ) -> actix_web::Result<HttpResponse> {
-    let token = path.into_inner();
+    let presented = path.into_inner();
+    let signed = is_signed_invitation_token(&presented);
 
     // For validation, we need to know which territory to check
-    // This should come from query parameter
-    let territory_code = query.territory_code.as_deref().ok_or_else(|| {
-        actix_web::error::ErrorBadRequest("territory_code query parameter is required")
-    })?;
+    // This should come from query parameter (signed tokens carry their own)
+    let territory_code = match &query.territory_code {
+        Some(territory_code) => territory_code.clone(),
+        None if signed => {
+            token_service
+                .verify_signed_invitation(&presented)
+                .map_err(|_| {
+                    actix_web::error::ErrorBadRequest("Invalid or expired invitation token")
+                })?
+                .territory_code
+        }
+        None => {
+            return Err(actix_web::error::ErrorBadRequest(
+                "territory_code query parameter is required",
+            ))
+        }
+    };
+
+    // Signed tokens are checked offline, before touching the database
+    let token = resolve_invitation_token(&token_service, &presented, &territory_code).map_err(
+        |e| match e {
+            AppError::Validation(msg) => actix_web::error::ErrorBadRequest(msg),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        },
+    )?;
 
-    let schema_name = get_schema_name(&territory_code);
+    let schema_name = territories
+        .schema_for(&territory_code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
 
     // Validate token (without consuming it)
-    let invitation =
-        validate_invitation_token(pool.get_ref(), &schema_name, &token, query.email.as_deref())
-            .await
-            .map_err(|e| match e {
-                shared_lib::error::AppError::Validation(msg) => {
-                    actix_web::error::ErrorBadRequest(msg)
-                }
-                _ => actix_web::error::ErrorInternalServerError(e),
-            })?;
+    let invitation = validate_invitation_token(
+        pool.get_ref(),
+        schema_name,
+        &token,
+        query.email.as_deref(),
+        query.username.as_deref(),
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::Validation(msg) => actix_web::error::ErrorBadRequest(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
 
     // Return validation response
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "valid": true,
+        "signed": signed,
         "token_type": invitation.token_type,
         "email": invitation.invited_email,
+        "username": invitation.invited_username,
         "expires_at": invitation.expires_at,
         "remaining_uses": invitation.max_uses.map(|max| max - invitation.current_uses),
     })))
@@ -179,4 +908,32 @@ pub async fn validate_invitation(
 pub struct ValidationQuery {
     pub territory_code: Option<String>,
     pub email: Option<String>,
+    pub username: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct QrQuery {
+    pub format: Option<String>, // "svg" (default) or "png"
+}
+
+#[derive(serde::Deserialize)]
+pub struct BatchFormatQuery {
+    pub format: Option<String>, // "json" (default) or "csv"
+}
+
+enum BatchFormat {
+    Json,
+    Csv,
+}
+
+impl BatchFormatQuery {
+    fn format(&self) -> actix_web::Result<BatchFormat> {
+        match self.format.as_deref().unwrap_or("json") {
+            "json" => Ok(BatchFormat::Json),
+            "csv" => Ok(BatchFormat::Csv),
+            _ => Err(actix_web::error::ErrorBadRequest(
+                "Format must be json or csv",
+            )),
+        }
+    }
 }
@@ -0,0 +1,102 @@
+use crate::{
+    middleware::get_authenticated_user,
+    models::DeactivateSubtreeRequest,
+    services::{deactivate_invitation_subtree, get_invitation_lineage},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use shared_lib::{error::AppError, TerritoryResolver};
+use sqlx::PgPool;
+use validator::Validate;
+
+#[derive(Debug, Deserialize)]
+pub struct LineageQuery {
+    pub max_depth: Option<i32>, // Levels of descendants to return (default: all)
+}
+
+/// Show who invited a user and everyone they brought in
+/// GET /api/auth/lineage/{username}
+pub async fn get_lineage(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<LineageQuery>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    if query.max_depth.is_some_and(|depth| depth < 0) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "max_depth must not be negative",
+        ));
+    }
+
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let lineage = get_invitation_lineage(pool.get_ref(), schema_name, &path, query.max_depth)
+        .await
+        .map_err(|e| match e {
+            AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        })?;
+
+    Ok(HttpResponse::Ok().json(lineage))
+}
+
+/// Deactivate a user and their whole invitation subtree
+/// POST /api/auth/lineage/{username}/deactivate
+///
+/// Also revokes the subtree's outstanding invitation tokens and sessions.
+pub async fn deactivate_lineage(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<DeactivateSubtreeRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let username = path.into_inner();
+
+    let outcome = deactivate_invitation_subtree(
+        pool.get_ref(),
+        schema_name,
+        &auth_user.territory_code,
+        &username,
+        &body.reason,
+        auth_user.user_id,
+        auth_user.identity_id,
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+        AppError::Validation(msg) => actix_web::error::ErrorBadRequest(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
+
+    tracing::warn!(
+        "User {} deactivated the invitation tree of {} in {} ({} users, {} tokens): {}",
+        auth_user.username,
+        username,
+        auth_user.territory_code,
+        outcome.users_deactivated,
+        outcome.tokens_revoked,
+        body.reason
+    );
+
+    Ok(HttpResponse::Ok().json(outcome))
+}
@@ -0,0 +1,150 @@
+use crate::{
+    handlers::auth::too_many_login_attempts,
+    middleware::get_authenticated_user,
+    models::{BackupCodesResponse, TotpCodeRequest, TotpEnrolmentResponse},
+    services::{
+        begin_totp_enrolment, clear_login_failures, confirm_totp_enrolment,
+        disable_totp as disable_user_totp, is_mfa_required, login_throttle_keys,
+        record_login_failure, reserve_login_attempt, totp_uri, verify_mfa_code, LoginReservation,
+    },
+    utils::ClientInfo,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared_lib::{error::AppError, TerritoryResolver};
+use sqlx::PgPool;
+use validator::Validate;
+
+/// Start TOTP enrolment for the authenticated user
+/// POST /api/auth/mfa/totp/enrol
+pub async fn enrol_totp(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let secret = begin_totp_enrolment(pool.get_ref(), schema_name, auth_user.user_id)
+        .await
+        .map_err(|e| match e {
+            AppError::Validation(msg) => actix_web::error::ErrorConflict(msg),
+            _ => actix_web::error::ErrorInternalServerError(e),
+        })?;
+
+    let otpauth_uri = totp_uri(&secret, &auth_user.username, &auth_user.territory_code);
+
+    Ok(HttpResponse::Ok().json(TotpEnrolmentResponse {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+/// Confirm TOTP enrolment with a code from the authenticator app
+/// POST /api/auth/mfa/totp/confirm
+pub async fn confirm_totp(
+    req: HttpRequest,
+    body: web::Json<TotpCodeRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let backup_codes =
+        confirm_totp_enrolment(pool.get_ref(), schema_name, auth_user.user_id, &body.code)
+            .await
+            .map_err(|e| match e {
+                AppError::Validation(msg) => actix_web::error::ErrorBadRequest(msg),
+                _ => actix_web::error::ErrorInternalServerError(e),
+            })?;
+
+    Ok(HttpResponse::Ok().json(BackupCodesResponse { backup_codes }))
+}
+
+/// Disable TOTP for the authenticated user (requires a current code)
+/// DELETE /api/auth/mfa/totp
+pub async fn disable_totp(
+    req: HttpRequest,
+    body: web::Json<TotpCodeRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    // Managers cannot opt out where the territory requires 2FA
+    let required = is_mfa_required(
+        pool.get_ref(),
+        schema_name,
+        auth_user.identity_id,
+        &auth_user.territory_code,
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if required {
+        return Err(actix_web::error::ErrorForbidden(
+            "Two-factor authentication is required for territory managers",
+        ));
+    }
+
+    // Codes are throttled with the same counters as logins
+    let throttle_keys = login_throttle_keys(
+        &auth_user.territory_code,
+        &auth_user.username,
+        &ClientInfo::from_request(&req),
+    );
+    let attempt = match reserve_login_attempt(pool.get_ref(), &throttle_keys)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        LoginReservation::Reserved(attempt) => attempt,
+        LoginReservation::Throttled { retry_after } => {
+            return Err(too_many_login_attempts(retry_after))
+        }
+    };
+
+    match verify_mfa_code(pool.get_ref(), schema_name, auth_user.user_id, &body.code).await {
+        Ok(()) => {}
+        Err(AppError::Unauthorized(msg)) => {
+            record_login_failure(pool.get_ref(), &attempt)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Err(actix_web::error::ErrorUnauthorized(msg));
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+
+    clear_login_failures(pool.get_ref(), &attempt)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    disable_user_totp(pool.get_ref(), schema_name, auth_user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Two-factor authentication disabled"
+    })))
+}
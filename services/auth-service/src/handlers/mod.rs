@@ -1,5 +1,27 @@
+pub mod audit;
 pub mod auth;
+pub mod email;
+pub mod introspection;
 pub mod invitation;
+pub mod lineage;
+pub mod mfa;
+pub mod oidc;
+pub mod password;
+pub mod personal_access_token;
+pub mod role;
+pub mod session;
+pub mod well_known;
 
+pub use audit::*;
 pub use auth::*;
+pub use email::*;
+pub use introspection::*;
 pub use invitation::*;
+pub use lineage::*;
+pub use mfa::*;
+pub use oidc::*;
+pub use password::*;
+pub use personal_access_token::*;
+pub use role::*;
+pub use session::*;
+pub use well_known::*;
@@ -0,0 +1,139 @@
+use super::introspection::basic_credentials;
+use crate::{
+    middleware::get_authenticated_user,
+    models::{AuthorizationDecision, AuthorizationRequest, OidcError, TokenRequest},
+    services::{
+        complete_authorization, consent_screen, exchange_authorization_code, userinfo,
+        OidcProvider, TokenService,
+    },
+};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use shared_lib::TerritoryResolver;
+use sqlx::PgPool;
+
+/// Consent screen data for a relying party's authorization request
+/// GET /api/auth/oidc/authorize
+///
+/// Called by the front-end authorize page with the request's query string.
+/// Errors carry `redirect_to` when the client should be told about them.
+pub async fn oidc_consent(
+    req: HttpRequest,
+    query: web::Query<AuthorizationRequest>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let auth_user = get_authenticated_user(&req)?;
+
+    match consent_screen(
+        pool.get_ref(),
+        auth_user.identity_id,
+        &auth_user.territory_code,
+        &query,
+    )
+    .await
+    {
+        Ok(screen) => Ok(HttpResponse::Ok().json(screen)),
+        Err(error) => Ok(authorization_error(error)),
+    }
+}
+
+/// Approve or decline an authorization request
+/// POST /api/auth/oidc/authorize
+///
+/// Returns where to send the browser: the client's redirect URI with a code,
+/// or with `access_denied` when declined.
+pub async fn oidc_authorize(
+    req: HttpRequest,
+    body: web::Json<AuthorizationDecision>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let auth_user = get_authenticated_user(&req)?;
+
+    match complete_authorization(
+        pool.get_ref(),
+        auth_user.identity_id,
+        &auth_user.territory_code,
+        auth_user.session_id,
+        auth_user.mfa_verified,
+        &body,
+    )
+    .await
+    {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(error) => Ok(authorization_error(error)),
+    }
+}
+
+/// Exchange an authorization code for an access token and an ID token
+/// POST /api/auth/oidc/token
+pub async fn oidc_token(
+    http_req: HttpRequest,
+    form: web::Form<TokenRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+    provider: web::Data<OidcProvider>,
+) -> actix_web::Result<HttpResponse> {
+    let result = exchange_authorization_code(
+        pool.get_ref(),
+        &token_service,
+        &territories,
+        &provider,
+        basic_credentials(&http_req),
+        &form,
+    )
+    .await;
+
+    match result {
+        Ok(tokens) => Ok(HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(tokens)),
+        Err(error) if error.error == "invalid_client" => Ok(HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"unityplan\""))
+            .json(error)),
+        Err(error) if error.error == "server_error" => {
+            Ok(HttpResponse::InternalServerError().json(error))
+        }
+        Err(error) => Ok(HttpResponse::BadRequest().json(error)),
+    }
+}
+
+/// Claims about the member a client access token was issued for
+/// GET /api/auth/oidc/userinfo
+pub async fn oidc_userinfo(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+) -> actix_web::Result<HttpResponse> {
+    let token = http_req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    let info = match token {
+        Some(token) => userinfo(pool.get_ref(), &token_service, &territories, token)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        None => None,
+    };
+
+    match info {
+        Some(info) => Ok(HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(info)),
+        None => Ok(HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\""))
+            .json(serde_json::json!({ "error": "invalid_token" }))),
+    }
+}
+
+/// Authorization endpoint errors are for the front-end (never 401, which would
+/// read as an expired login)
+fn authorization_error(error: OidcError) -> HttpResponse {
+    if error.error == "server_error" {
+        HttpResponse::InternalServerError().json(error)
+    } else {
+        HttpResponse::BadRequest().json(error)
+    }
+}
@@ -0,0 +1,437 @@
+use crate::{
+    handlers::auth::too_many_login_attempts,
+    middleware::get_authenticated_user,
+    models::{
+        ChangePasswordRequest, CurrentPasswordRequest, PasswordResetRequest, RecoverAccountRequest,
+        RecoveryCodesResponse, ResetPasswordRequest,
+    },
+    services::{
+        clear_login_failures, create_password_reset_token, issue_recovery_codes,
+        login_throttle_keys, record_login_failure, recover_with_code, remaining_recovery_codes,
+        reserve_login_attempt, reset_password_with_token, revoke_access_tokens_issued_before_now,
+        revoke_other_sessions, update_password, EmailService, LoginReservation, PasswordService,
+        TokenService,
+    },
+    utils::ClientInfo,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared_lib::{error::AppError, TerritoryResolver};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Change the authenticated user's password
+/// POST /api/auth/password
+///
+/// Requires the current password. Every other session is revoked, and so is
+/// every access token issued so far; the response carries a new access token
+/// for the current session.
+pub async fn change_password(
+    req: HttpRequest,
+    body: web::Json<ChangePasswordRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    token_service: web::Data<TokenService>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    verify_current_password(
+        pool.get_ref(),
+        schema_name,
+        &req,
+        &auth_user.territory_code,
+        &auth_user.username,
+        auth_user.user_id,
+        &body.current_password,
+    )
+    .await?;
+
+    let password_hash = PasswordService::hash_password(&body.new_password)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Sign out everywhere else - the old password may have been compromised.
+    // One transaction, so the new password never applies while the old
+    // credentials' sessions and access tokens live on.
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    update_password(&mut tx, schema_name, auth_user.user_id, &password_hash)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let revoked = revoke_other_sessions(
+        &mut *tx,
+        auth_user.identity_id,
+        auth_user.session_id,
+        "password_changed",
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Access tokens outlive their sessions
+    revoke_access_tokens_issued_before_now(&mut *tx, &[auth_user.identity_id], "password_changed")
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let access_token = token_service
+        .generate_access_token(
+            &auth_user.public_key_hash,
+            &auth_user.territory_code,
+            auth_user.user_id,
+            &auth_user.username,
+            auth_user.session_id,
+            auth_user.mfa_verified,
+        )
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Password changed successfully",
+        "revoked_sessions": revoked,
+        "access_token": access_token,
+        "expires_in": token_service.get_access_token_ttl()
+    })))
+}
+
+/// Replace the authenticated user's recovery codes
+/// POST /api/auth/recovery-codes
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    body: web::Json<CurrentPasswordRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    verify_current_password(
+        pool.get_ref(),
+        schema_name,
+        &req,
+        &auth_user.territory_code,
+        &auth_user.username,
+        auth_user.user_id,
+        &body.current_password,
+    )
+    .await?;
+
+    let recovery_codes = issue_recovery_codes(pool.get_ref(), schema_name, auth_user.user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// Reset a forgotten password with a recovery code
+/// POST /api/auth/password/recover
+pub async fn recover_account(
+    http_req: HttpRequest,
+    req: web::Json<RecoverAccountRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    req.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    let schema_name = territories
+        .schema_for(&req.territory_code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
+
+    // Recovery codes are guessable secrets too - share the login counters
+    let client = ClientInfo::from_request(&http_req);
+    let throttle_keys = login_throttle_keys(&req.territory_code, &req.username, &client);
+    let attempt = match reserve_login_attempt(pool.get_ref(), &throttle_keys)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        LoginReservation::Reserved(attempt) => attempt,
+        LoginReservation::Throttled { retry_after } => {
+            return Err(too_many_login_attempts(retry_after))
+        }
+    };
+
+    // Hash before looking the user up, so unknown usernames cost the same time
+    let password_hash = PasswordService::hash_password(&req.new_password)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let user_id: Option<Uuid> = sqlx::query_scalar(&format!(
+        "SELECT id FROM {}.users WHERE username = $1 AND is_active = true",
+        schema_name
+    ))
+    .bind(&req.username)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let result = match user_id {
+        Some(user_id) => {
+            recover_and_sign_out(pool.get_ref(), schema_name, &req, user_id, &password_hash)
+                .await
+                .map(|_| user_id)
+        }
+        None => Err(AppError::Unauthorized("Invalid recovery code".to_string())),
+    };
+
+    let user_id = match result {
+        Ok(user_id) => user_id,
+        Err(AppError::Unauthorized(msg)) => {
+            record_login_failure(pool.get_ref(), &attempt)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Err(actix_web::error::ErrorUnauthorized(msg));
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    };
+
+    clear_login_failures(pool.get_ref(), &attempt)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let remaining = remaining_recovery_codes(pool.get_ref(), schema_name, user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Password reset successfully",
+        "remaining_recovery_codes": remaining
+    })))
+}
+
+/// Request a password reset link for a verified email
+/// POST /api/auth/password/reset-request
+///
+/// Always answers 202, so the endpoint cannot be used to discover accounts.
+pub async fn request_password_reset(
+    req: web::Json<PasswordResetRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+    email_service: web::Data<EmailService>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    req.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    let schema_name = territories
+        .schema_for(&req.territory_code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
+
+    // Only verified addresses can receive reset links
+    let user: Option<(Uuid, String)> = sqlx::query_as(&format!(
+        "SELECT id, username FROM {}.users WHERE email = $1 AND is_verified = true AND is_active = true",
+        schema_name
+    ))
+    .bind(&req.email)
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if let Some((user_id, username)) = user {
+        let token = create_password_reset_token(pool.get_ref(), schema_name, user_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        // Delivery problems are logged, not reported - the answer must not depend on the account
+        if let Err(e) = email_service
+            .send_password_reset(&req.email, &username, &req.territory_code, &token)
+            .await
+        {
+            tracing::error!(
+                "Failed to send password reset email for user {}: {}",
+                user_id,
+                e
+            );
+        }
+    }
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "If the email belongs to a verified account, a reset link has been sent"
+    })))
+}
+
+/// Set a new password with an emailed reset token
+/// POST /api/auth/password/reset
+pub async fn reset_password(
+    req: web::Json<ResetPasswordRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    req.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    let schema_name = territories
+        .schema_for(&req.territory_code)
+        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid territory code"))?;
+
+    let password_hash = PasswordService::hash_password(&req.new_password)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let user_id = reset_password_with_token(&mut tx, schema_name, &req.token, &password_hash)
+        .await
+        .map_err(|e| match e {
+            AppError::Unauthorized(msg) => actix_web::error::ErrorUnauthorized(msg),
+            AppError::NotFound(_) => {
+                actix_web::error::ErrorUnauthorized("Invalid or expired reset token")
+            }
+            _ => actix_web::error::ErrorInternalServerError(e),
+        })?;
+
+    // Whoever knew the old password is signed out everywhere, in the same transaction
+    let identity_id = global_identity_id(&mut *tx, &req.territory_code, user_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    sign_out_everywhere(&mut tx, identity_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Password reset successfully"
+    })))
+}
+
+/// Check the authenticated user's current password (throttled like logins)
+pub(crate) async fn verify_current_password(
+    pool: &PgPool,
+    schema_name: &str,
+    req: &HttpRequest,
+    territory_code: &str,
+    username: &str,
+    user_id: Uuid,
+    password: &str,
+) -> actix_web::Result<()> {
+    let throttle_keys =
+        login_throttle_keys(territory_code, username, &ClientInfo::from_request(req));
+    let attempt = match reserve_login_attempt(pool, &throttle_keys)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        LoginReservation::Reserved(attempt) => attempt,
+        LoginReservation::Throttled { retry_after } => {
+            return Err(too_many_login_attempts(retry_after))
+        }
+    };
+
+    let password_hash: String = sqlx::query_scalar(&format!(
+        "SELECT password_hash FROM {}.users WHERE id = $1 AND is_active = true",
+        schema_name
+    ))
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("User not found"))?;
+
+    let is_valid = PasswordService::verify_password(password, &password_hash)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if !is_valid {
+        record_login_failure(pool, &attempt)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Current password is incorrect",
+        ));
+    }
+
+    clear_login_failures(pool, &attempt)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(())
+}
+
+/// Consume a recovery code, set the new password and sign the user out
+/// everywhere, all in one transaction
+async fn recover_and_sign_out(
+    pool: &PgPool,
+    schema_name: &str,
+    req: &RecoverAccountRequest,
+    user_id: Uuid,
+    password_hash: &str,
+) -> Result<(), AppError> {
+    // Resolved before anything changes, so a missing identity changes nothing
+    let identity_id = global_identity_id(pool, &req.territory_code, user_id).await?;
+
+    let mut tx = pool.begin().await?;
+    recover_with_code(
+        &mut tx,
+        schema_name,
+        user_id,
+        &req.recovery_code,
+        password_hash,
+    )
+    .await?;
+    sign_out_everywhere(&mut tx, identity_id).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Revoke every session and access token of a user whose password was reset
+async fn sign_out_everywhere(
+    tx: &mut Transaction<'_, Postgres>,
+    identity_id: Uuid,
+) -> Result<(), AppError> {
+    revoke_other_sessions(&mut **tx, identity_id, None, "password_reset").await?;
+    revoke_access_tokens_issued_before_now(&mut **tx, &[identity_id], "password_reset").await
+}
+
+/// Look up the global identity of a territory user
+///
+/// Territory codes in requests are matched case-insensitively, like the
+/// territory resolver does.
+async fn global_identity_id<'e, E>(
+    executor: E,
+    territory_code: &str,
+    user_id: Uuid,
+) -> Result<Uuid, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let identity_id: Uuid = sqlx::query_scalar(
+        r#"
+        SELECT id FROM global.user_identities
+        WHERE territory_user_id = $2 AND LOWER(territory_code) = LOWER(TRIM($1))
+        "#,
+    )
+    .bind(territory_code)
+    .bind(user_id)
+    .fetch_one(executor)
+    .await?;
+
+    Ok(identity_id)
+}
@@ -0,0 +1,91 @@
+use crate::{
+    middleware::get_authenticated_user,
+    models::CreatePersonalAccessTokenRequest,
+    services::{
+        create_personal_access_token, list_personal_access_tokens, revoke_personal_access_token,
+    },
+    utils::ClientInfo,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared_lib::error::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Create a personal access token for scripts and integrations
+/// POST /api/auth/tokens
+///
+/// The token is only returned in this response.
+pub async fn create_personal_token(
+    req: HttpRequest,
+    body: web::Json<CreatePersonalAccessTokenRequest>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let created = create_personal_access_token(
+        pool.get_ref(),
+        auth_user.identity_id,
+        &auth_user.territory_code,
+        &body,
+        &ClientInfo::from_request(&req),
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::Validation(msg) => {
+            actix_web::error::ErrorBadRequest(format!("Validation error: {}", msg))
+        }
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
+
+    Ok(HttpResponse::Created().json(created))
+}
+
+/// List the authenticated user's personal access tokens
+/// GET /api/auth/tokens
+pub async fn list_personal_tokens(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let tokens = list_personal_access_tokens(pool.get_ref(), auth_user.identity_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Revoke one of the authenticated user's personal access tokens
+/// DELETE /api/auth/tokens/{id}
+pub async fn revoke_personal_token(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    revoke_personal_access_token(
+        pool.get_ref(),
+        auth_user.identity_id,
+        &auth_user.territory_code,
+        path.into_inner(),
+        &ClientInfo::from_request(&req),
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Personal access token revoked successfully"
+    })))
+}
@@ -0,0 +1,112 @@
+use crate::{
+    middleware::get_authenticated_user,
+    models::GrantRoleRequest,
+    services::{grant_territory_role, list_territory_roles, revoke_territory_role},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared_lib::error::AppError;
+use sqlx::PgPool;
+use validator::Validate;
+
+/// List the territory roles granted in the admin's territory
+/// GET /api/auth/roles
+pub async fn list_roles(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let grants = list_territory_roles(pool.get_ref(), &auth_user.territory_code)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(grants))
+}
+
+/// Grant a territory role to a user of the admin's territory
+/// POST /api/auth/roles
+pub async fn grant_role(
+    req: HttpRequest,
+    body: web::Json<GrantRoleRequest>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let grant = grant_territory_role(
+        pool.get_ref(),
+        &auth_user.territory_code,
+        &body.username,
+        &body.role,
+        auth_user.identity_id,
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+        AppError::Validation(msg) if msg.starts_with("Unknown role") => {
+            actix_web::error::ErrorBadRequest(msg)
+        }
+        AppError::Validation(msg) => actix_web::error::ErrorConflict(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
+
+    tracing::info!(
+        "User {} granted role {} to {} in {}",
+        auth_user.username,
+        grant.role,
+        grant.username,
+        auth_user.territory_code
+    );
+
+    Ok(HttpResponse::Created().json(grant))
+}
+
+/// Revoke a territory role from a user of the admin's territory
+/// DELETE /api/auth/roles/{username}/{role}
+pub async fn revoke_role(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let (username, role) = path.into_inner();
+
+    // Admins cannot lock themselves out - another admin has to do it
+    if username.eq_ignore_ascii_case(&auth_user.username) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "You cannot revoke your own role",
+        ));
+    }
+
+    revoke_territory_role(
+        pool.get_ref(),
+        &auth_user.territory_code,
+        &username,
+        &role,
+        auth_user.identity_id,
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
+
+    tracing::info!(
+        "User {} revoked role {} from {} in {}",
+        auth_user.username,
+        role,
+        username,
+        auth_user.territory_code
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Role revoked successfully"
+    })))
+}
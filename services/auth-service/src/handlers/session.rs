@@ -0,0 +1,137 @@
+use crate::{
+    middleware::get_authenticated_user,
+    models::ForceLogoutRequest,
+    services::{force_logout, list_sessions, revoke_other_sessions, revoke_session},
+    utils::ClientInfo,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use shared_lib::{error::AppError, TerritoryResolver};
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+/// List the authenticated user's active sessions (devices)
+/// GET /api/auth/sessions
+pub async fn get_sessions(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let sessions = list_sessions(pool.get_ref(), auth_user.identity_id, auth_user.session_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Revoke one of the authenticated user's sessions (remote logout)
+/// DELETE /api/auth/sessions/{id}
+pub async fn delete_session(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    let session_id = path.into_inner();
+
+    // Revoke session (only if it belongs to this user)
+    revoke_session(
+        pool.get_ref(),
+        auth_user.identity_id,
+        &auth_user.territory_code,
+        session_id,
+        &ClientInfo::from_request(&req),
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Session revoked successfully"
+    })))
+}
+
+/// Revoke all of the authenticated user's sessions except the current one
+/// POST /api/auth/sessions/revoke-others
+pub async fn revoke_others(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    // Without a session-bound token we cannot tell which session to keep
+    let current_session_id = auth_user.session_id.ok_or_else(|| {
+        actix_web::error::ErrorBadRequest("Access token is not bound to a session")
+    })?;
+
+    let revoked = revoke_other_sessions(
+        pool.get_ref(),
+        auth_user.identity_id,
+        Some(current_session_id),
+        "user_revoked",
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Other sessions revoked successfully",
+        "revoked": revoked
+    })))
+}
+
+/// Sign a member out of every session, and revoke their access tokens
+/// POST /api/auth/users/{username}/logout
+pub async fn force_logout_user(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ForceLogoutRequest>,
+    pool: web::Data<PgPool>,
+    territories: web::Data<TerritoryResolver>,
+) -> actix_web::Result<HttpResponse> {
+    // Validate request
+    body.validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Validation error: {}", e)))?;
+
+    // Get authenticated user
+    let auth_user = get_authenticated_user(&req)?;
+
+    // Get territory schema
+    let schema_name = territories
+        .schema_for(&auth_user.territory_code)
+        .map_err(actix_web::error::ErrorNotFound)?;
+
+    let username = path.into_inner();
+
+    let outcome = force_logout(
+        pool.get_ref(),
+        schema_name,
+        &auth_user.territory_code,
+        &username,
+        &body.reason,
+        auth_user.identity_id,
+    )
+    .await
+    .map_err(|e| match e {
+        AppError::NotFound(msg) => actix_web::error::ErrorNotFound(msg),
+        _ => actix_web::error::ErrorInternalServerError(e),
+    })?;
+
+    tracing::warn!(
+        "User {} signed out {} in {} ({} sessions, {} personal access tokens): {}",
+        auth_user.username,
+        username,
+        auth_user.territory_code,
+        outcome.sessions_revoked,
+        outcome.personal_access_tokens_revoked,
+        body.reason
+    );
+
+    Ok(HttpResponse::Ok().json(outcome))
+}
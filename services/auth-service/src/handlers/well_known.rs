@@ -0,0 +1,23 @@
+use crate::services::{OidcProvider, TokenService};
+use actix_web::{http::header, web, HttpResponse};
+
+/// Publish the public token verification keys as a JWK Set
+///
+/// Other services fetch this to verify access tokens locally. The set is empty
+/// when the service signs with an HS256 shared secret.
+pub async fn jwks(token_service: web::Data<TokenService>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(token_service.jwks())
+}
+
+/// OpenID provider metadata (discovery document)
+/// GET /api/auth/.well-known/openid-configuration
+pub async fn openid_configuration(
+    provider: web::Data<OidcProvider>,
+    token_service: web::Data<TokenService>,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(provider.metadata(&token_service))
+}
@@ -6,7 +6,15 @@ mod utils;
 
 use actix_web::{middleware::Logger, web, App, HttpServer};
 use anyhow::Result;
-use services::TokenService;
+use services::{
+    permissions, schemas_without_member_triggers, scopes, AuthStateCache, AuthStateListener,
+    EmailService, InvitationCardService, OidcProvider, TokenRevocations, TokenService,
+};
+use shared_lib::{
+    EventPublisher, FileMailer, JetStreamPublisher, LogEventPublisher, Mailer, NatsClient,
+    OutboxMetrics, OutboxRelay, SchemaLayout, SmtpConfig, SmtpMailer, TerritoryResolver,
+    TrustedProxies,
+};
 use sqlx::postgres::PgPoolOptions;
 use std::sync::Arc;
 use tracing_subscriber::EnvFilter;
@@ -15,10 +23,28 @@ use tracing_subscriber::EnvFilter;
 struct Config {
     database_url: String,
     jwt_secret: String,
-    access_token_ttl: i64,  // seconds (default: 15 minutes)
-    refresh_token_ttl: i64, // seconds (default: 7 days)
+    // PKCS#8 PEM (Ed25519 or P-256); falls back to the HS256 secret when unset
+    jwt_signing_key_file: Option<String>,
+    // Public keys of retired signing keys, still accepted during rotation
+    jwt_verification_key_files: Vec<String>,
+    access_token_ttl: i64,   // seconds (default: 15 minutes)
+    refresh_token_ttl: i64,  // seconds (default: 7 days)
+    refresh_family_ttl: i64, // seconds (default: 30 days, absolute session lifetime)
     server_host: String,
     server_port: u16,
+    pod_id: String,
+    schema_layout: SchemaLayout, // single: "territory" schema, multi: "territory_XX"
+    smtp: Option<SmtpConfig>,    // From SMTP_*; emails go to mail_dir when unset
+    mail_dir: String,
+    nats_url: Option<String>, // Domain events are only logged when unset
+    nats_cluster_name: String,
+    public_url: String,                 // Public origin used in emailed links
+    audit_checkpoint_interval: u64, // seconds between signed audit checkpoints (default: 1 hour)
+    outbox_relay_interval: u64,     // seconds between outbox relay passes (default: 1 second)
+    oidc_issuer: Option<String>,    // OpenID provider issuer (default: {public_url}/api/auth)
+    oidc_authorize_url: Option<String>, // Front-end authorize page (default: {public_url}/oidc/authorize)
+    auth_cache_ttl: u64, // seconds JwtAuth caches auth state (default: 60, 0 = database on every request)
+    trusted_proxies: TrustedProxies, // Proxies whose X-Forwarded-For/Forwarded is trusted (default: none)
 }
 
 impl Config {
@@ -30,6 +56,16 @@ impl Config {
             }),
             jwt_secret: std::env::var("JWT_SECRET")
                 .unwrap_or_else(|_| "dev_secret_change_in_production".to_string()),
+            jwt_signing_key_file: std::env::var("JWT_SIGNING_KEY_FILE").ok(),
+            jwt_verification_key_files: std::env::var("JWT_VERIFICATION_KEY_FILES")
+                .map(|s| {
+                    s.split(',')
+                        .map(str::trim)
+                        .filter(|path| !path.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
             access_token_ttl: std::env::var("ACCESS_TOKEN_TTL")
                 .ok()
                 .and_then(|s| s.parse().ok())
@@ -38,11 +74,47 @@ impl Config {
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(604800), // 7 days
+            refresh_family_ttl: std::env::var("REFRESH_TOKEN_FAMILY_TTL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(2592000), // 30 days
             server_host: std::env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
             server_port: std::env::var("SERVER_PORT")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(8001),
+            pod_id: std::env::var("POD_ID").unwrap_or_else(|_| "dk".to_string()),
+            schema_layout: std::env::var("TERRITORY_SCHEMA_LAYOUT")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            smtp: SmtpConfig::from_env(),
+            mail_dir: std::env::var("MAIL_DIR").unwrap_or_else(|_| "mail-outbox".to_string()),
+            nats_url: std::env::var("NATS_URL").ok().filter(|url| !url.is_empty()),
+            nats_cluster_name: std::env::var("NATS_CLUSTER_NAME")
+                .unwrap_or_else(|_| "unityplan-global".to_string()),
+            public_url: std::env::var("PUBLIC_URL")
+                .unwrap_or_else(|_| "http://localhost:8000".to_string()),
+            audit_checkpoint_interval: std::env::var("AUDIT_CHECKPOINT_INTERVAL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(3600), // 1 hour
+            outbox_relay_interval: std::env::var("OUTBOX_RELAY_INTERVAL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1),
+            oidc_issuer: std::env::var("OIDC_ISSUER").ok(),
+            oidc_authorize_url: std::env::var("OIDC_AUTHORIZE_URL").ok(),
+            auth_cache_ttl: std::env::var("AUTH_CACHE_TTL")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60),
+            trusted_proxies: std::env::var("TRUSTED_PROXIES")
+                .ok()
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or_default(),
         })
     }
 }
@@ -83,13 +155,184 @@ async fn main() -> Result<()> {
     sqlx::query("SELECT 1").execute(&pool).await?;
     tracing::info!("Database health check passed");
 
+    // Resolve which territories (and schemas) this pod serves
+    let territories =
+        Arc::new(TerritoryResolver::load(&pool, &config.pod_id, config.schema_layout).await?);
+
+    // Listen for auth state changes before loading anything they change
+    let mut auth_state_listener = AuthStateListener::connect(&pool).await?;
+
+    // Revoked access tokens, checked whenever a token is validated
+    let revocations = Arc::new(TokenRevocations::new(config.access_token_ttl));
+    revocations.reload(&pool).await?;
+    auth_state_listener = auth_state_listener.revocations(revocations.clone());
+
+    // Cache the auth state JwtAuth checks, invalidated by Postgres notifications
+    let auth_state = if config.auth_cache_ttl > 0 {
+        // Members of schemas without the member state triggers would go stale
+        let mut schemas: Vec<String> = territories
+            .territories()
+            .filter_map(|code| territories.schema_for(code).ok())
+            .map(str::to_string)
+            .collect();
+        schemas.sort();
+        schemas.dedup();
+        let uncached_schemas = schemas_without_member_triggers(&pool, &schemas).await?;
+        for schema in &uncached_schemas {
+            tracing::warn!(
+                "{}.users has no member state triggers - members are not cached (run SELECT install_member_state_triggers('{}'))",
+                schema,
+                schema
+            );
+        }
+
+        let cache = Arc::new(
+            AuthStateCache::new(std::time::Duration::from_secs(config.auth_cache_ttl))
+                .uncached_schemas(uncached_schemas),
+        );
+        auth_state_listener = auth_state_listener.cache(cache.clone());
+        tracing::info!("Auth state cache enabled (TTL: {}s)", config.auth_cache_ttl);
+        Some(cache)
+    } else {
+        tracing::warn!("AUTH_CACHE_TTL is 0 - JwtAuth reads the database on every request");
+        None
+    };
+    tokio::spawn(auth_state_listener.run());
+
     // Create token service
-    let token_service = Arc::new(TokenService::new(
-        &config.jwt_secret,
-        config.access_token_ttl,
-        config.refresh_token_ttl,
+    let token_service = match &config.jwt_signing_key_file {
+        Some(path) => TokenService::from_private_key_pem(
+            &std::fs::read_to_string(path)?,
+            config.access_token_ttl,
+            config.refresh_token_ttl,
+        )?,
+        None => {
+            tracing::warn!("JWT_SIGNING_KEY_FILE not set, signing tokens with HS256 shared secret");
+            TokenService::new(
+                &config.jwt_secret,
+                config.access_token_ttl,
+                config.refresh_token_ttl,
+            )
+        }
+    };
+    let mut token_service = token_service
+        .with_refresh_family_ttl(config.refresh_family_ttl)
+        .with_revocations(revocations.clone());
+    for path in &config.jwt_verification_key_files {
+        let kid = token_service.add_verification_key(&std::fs::read_to_string(path)?)?;
+        tracing::info!("Accepting tokens signed by retired key {}", kid);
+    }
+    let token_service = Arc::new(token_service);
+    tracing::info!(
+        "Token service initialized (kid: {})",
+        token_service.signing_key_id().unwrap_or("none")
+    );
+
+    // OpenID provider for relying parties (Forgejo, Matrix, ...)
+    let oidc_provider = Arc::new(OidcProvider::new(
+        &config
+            .oidc_issuer
+            .clone()
+            .unwrap_or_else(|| format!("{}/api/auth", config.public_url)),
+        &config
+            .oidc_authorize_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/oidc/authorize", config.public_url)),
     ));
-    tracing::info!("Token service initialized");
+    if token_service.signing_key_id().is_none() {
+        tracing::warn!("ID tokens signed with the HS256 secret cannot be verified by OIDC clients");
+    }
+    tracing::info!("OpenID provider issuer: {}", oidc_provider.issuer());
+
+    // Create email service (SMTP, or files in MAIL_DIR without an SMTP server)
+    let mailer: Arc<dyn Mailer> = match &config.smtp {
+        Some(smtp) => Arc::new(SmtpMailer::new(smtp)?),
+        None => {
+            tracing::warn!(
+                "SMTP_HOST not set - emails are written to {}",
+                config.mail_dir
+            );
+            Arc::new(FileMailer::new(&config.mail_dir)?)
+        }
+    };
+    let email_service = Arc::new(EmailService::new(mailer, &config.public_url));
+    let invitation_cards = Arc::new(InvitationCardService::new(&config.public_url));
+
+    let nats = match &config.nats_url {
+        Some(url) => Some(NatsClient::new(url, config.nats_cluster_name.clone()).await?),
+        None => None,
+    };
+
+    // Create domain event publisher (JetStream, or the log without a NATS server)
+    let events: Arc<dyn EventPublisher> = match &nats {
+        Some(nats) => Arc::new(JetStreamPublisher::new(nats)),
+        None => {
+            tracing::warn!("NATS_URL not set - domain events are logged, not published");
+            Arc::new(LogEventPublisher)
+        }
+    };
+
+    // Publish the domain events handlers write to the outbox
+    let relay = OutboxRelay::new(pool.clone(), events).metrics(OutboxMetrics::register_default()?);
+    tokio::spawn(relay.run(std::time::Duration::from_secs(
+        config.outbox_relay_interval.max(1),
+    )));
+
+    // Answer token introspection requests from other services over NATS
+    if let Some(nats) = nats {
+        let introspection = services::serve_introspection(
+            nats,
+            pool.clone(),
+            token_service.clone(),
+            territories.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = introspection.await {
+                tracing::error!("Token introspection over NATS stopped: {}", e);
+            }
+        });
+    }
+
+    // Periodically sign the heads of the audit hash chains
+    let checkpoint_pool = pool.clone();
+    let checkpoint_tokens = token_service.clone();
+    let checkpoint_interval =
+        std::time::Duration::from_secs(config.audit_checkpoint_interval.max(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(checkpoint_interval);
+        loop {
+            interval.tick().await;
+            match services::create_audit_checkpoints(&checkpoint_pool, &checkpoint_tokens).await {
+                Ok(checkpoints) if !checkpoints.is_empty() => {
+                    tracing::info!("Signed {} audit checkpoint(s)", checkpoints.len())
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to sign audit checkpoints: {}", e),
+            }
+        }
+    });
+
+    // Periodically delete login throttle counters that no longer count
+    let prune_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            services::LOGIN_ATTEMPTS_PRUNE_INTERVAL_SECS,
+        ));
+        loop {
+            interval.tick().await;
+            match services::prune_login_attempts(&prune_pool).await {
+                Ok(pruned) if pruned > 0 => {
+                    tracing::info!("Pruned {} stale login throttle counter(s)", pruned)
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Failed to prune login throttle counters: {}", e),
+            }
+        }
+    });
+
+    // Client IPs (login throttling, sessions, audit log) come from forwarding
+    // headers only when the peer is one of these proxies
+    let trusted_proxies = web::Data::new(config.trusted_proxies.clone());
 
     let bind_addr = format!("{}:{}", config.server_host, config.server_port);
     tracing::info!("Starting HTTP server on {}", bind_addr);
@@ -99,36 +342,230 @@ async fn main() -> Result<()> {
         App::new()
             .wrap(Logger::default())
             .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(territories.clone()))
             .app_data(web::Data::from(token_service.clone()))
+            .app_data(web::Data::from(email_service.clone()))
+            .app_data(web::Data::from(invitation_cards.clone()))
+            .app_data(web::Data::from(oidc_provider.clone()))
+            .app_data(trusted_proxies.clone())
+            .configure(|cfg| {
+                if let Some(auth_state) = &auth_state {
+                    cfg.app_data(web::Data::from(auth_state.clone()));
+                }
+            })
             .service(
                 web::scope("/api/auth")
                     // Public auth endpoints
                     .route("/register", web::post().to(handlers::register))
                     .route("/login", web::post().to(handlers::login))
+                    .route("/login/mfa", web::post().to(handlers::login_mfa))
                     .route("/refresh", web::post().to(handlers::refresh))
                     .route("/logout", web::post().to(handlers::logout))
+                    // Public account recovery endpoints
+                    .route(
+                        "/password/recover",
+                        web::post().to(handlers::recover_account),
+                    )
+                    .route(
+                        "/password/reset-request",
+                        web::post().to(handlers::request_password_reset),
+                    )
+                    .route("/password/reset", web::post().to(handlers::reset_password))
+                    // Public email verification link
+                    .route(
+                        "/verify-email/{token}",
+                        web::get().to(handlers::verify_email),
+                    )
+                    // Public token verification keys
+                    .route("/.well-known/jwks.json", web::get().to(handlers::jwks))
+                    // Token introspection for internal services (service client credentials)
+                    .route("/introspect", web::post().to(handlers::introspect))
+                    // OpenID provider for relying parties (client credentials or PKCE)
+                    .route(
+                        "/.well-known/openid-configuration",
+                        web::get().to(handlers::openid_configuration),
+                    )
+                    .route("/oidc/token", web::post().to(handlers::oidc_token))
+                    .route("/oidc/userinfo", web::get().to(handlers::oidc_userinfo))
+                    .route("/oidc/userinfo", web::post().to(handlers::oidc_userinfo))
                     // Public invitation validation
                     .route(
                         "/invitations/validate/{token}",
                         web::get().to(handlers::validate_invitation),
                     )
-                    // Protected endpoints (require JWT)
+                    // Protected session (device) management endpoints
                     .service(
-                        web::scope("")
+                        web::scope("/sessions")
+                            .wrap(middleware::JwtAuth)
+                            .route("", web::get().to(handlers::get_sessions))
+                            .route("/revoke-others", web::post().to(handlers::revoke_others))
+                            .route("/{id}", web::delete().to(handlers::delete_session)),
+                    )
+                    // Protected OpenID consent (the member is logged in to the front-end)
+                    .service(
+                        web::scope("/oidc/authorize")
+                            .wrap(middleware::JwtAuth)
+                            .route("", web::get().to(handlers::oidc_consent))
+                            .route("", web::post().to(handlers::oidc_authorize)),
+                    )
+                    // Protected two-factor authentication endpoints
+                    .service(
+                        web::scope("/mfa")
+                            .wrap(middleware::JwtAuth)
+                            .route("/totp/enrol", web::post().to(handlers::enrol_totp))
+                            .route("/totp/confirm", web::post().to(handlers::confirm_totp))
+                            .route("/totp", web::delete().to(handlers::disable_totp)),
+                    )
+                    // Territory role administration
+                    .service(
+                        web::scope("/roles")
+                            .wrap(middleware::RequirePermission(permissions::ROLES_MANAGE))
                             .wrap(middleware::JwtAuth)
-                            .route("/me", web::get().to(handlers::me)),
+                            .route("", web::get().to(handlers::list_roles))
+                            .route("", web::post().to(handlers::grant_role))
+                            .route(
+                                "/{username}/{role}",
+                                web::delete().to(handlers::revoke_role),
+                            ),
+                    )
+                    // Invitation lineage moderation
+                    .service(
+                        web::scope("/lineage")
+                            .wrap(middleware::RequirePermission(permissions::USERS_MODERATE))
+                            .wrap(middleware::JwtAuth)
+                            .route("/{username}", web::get().to(handlers::get_lineage))
+                            .route(
+                                "/{username}/deactivate",
+                                web::post().to(handlers::deactivate_lineage),
+                            ),
+                    )
+                    // Forced logout by moderators
+                    .service(
+                        web::scope("/users")
+                            .wrap(middleware::RequirePermission(permissions::USERS_MODERATE))
+                            .wrap(middleware::JwtAuth)
+                            .route(
+                                "/{username}/logout",
+                                web::post().to(handlers::force_logout_user),
+                            ),
+                    )
+                    // Protected personal access token management (not usable with one)
+                    .service(
+                        web::scope("/tokens")
+                            .wrap(middleware::JwtAuth)
+                            .route("", web::post().to(handlers::create_personal_token))
+                            .route("", web::get().to(handlers::list_personal_tokens))
+                            .route("/{id}", web::delete().to(handlers::revoke_personal_token)),
+                    )
+                    // Protected endpoints (require JWT; personal access tokens where scoped)
+                    .service(
+                        web::scope("")
+                            .route(
+                                "/me",
+                                web::get()
+                                    .to(handlers::me)
+                                    .wrap(middleware::ScopedAuth(scopes::PROFILE_READ)),
+                            )
+                            .route(
+                                "/email",
+                                web::put()
+                                    .to(handlers::change_email)
+                                    .wrap(middleware::JwtAuth),
+                            )
+                            .route(
+                                "/email/resend-verification",
+                                web::post()
+                                    .to(handlers::resend_email_verification)
+                                    .wrap(middleware::JwtAuth),
+                            )
+                            .route(
+                                "/password",
+                                web::post()
+                                    .to(handlers::change_password)
+                                    .wrap(middleware::JwtAuth),
+                            )
+                            .route(
+                                "/recovery-codes",
+                                web::post()
+                                    .to(handlers::regenerate_recovery_codes)
+                                    .wrap(middleware::JwtAuth),
+                            ),
                     )
                     // Protected invitation endpoints
                     .service(
                         web::scope("/invitations")
-                            .wrap(middleware::JwtAuth)
-                            .route("", web::post().to(handlers::create_invitation))
-                            .route("", web::get().to(handlers::list_invitations))
-                            .route("/{id}", web::delete().to(handlers::revoke_invitation))
-                            .route("/{id}/uses", web::get().to(handlers::get_invitation_usage)),
+                            .route(
+                                "",
+                                web::post()
+                                    .to(handlers::create_invitation)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_WRITE)),
+                            )
+                            .route(
+                                "",
+                                web::get()
+                                    .to(handlers::list_invitations)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_READ)),
+                            )
+                            .route(
+                                "/batch",
+                                web::post()
+                                    .to(handlers::create_invitation_batch)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_WRITE)),
+                            )
+                            .route(
+                                "/batch/{batch_id}",
+                                web::get()
+                                    .to(handlers::get_invitation_batch)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_READ)),
+                            )
+                            .route(
+                                "/batch/{batch_id}",
+                                web::delete()
+                                    .to(handlers::revoke_invitation_batch)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_WRITE)),
+                            )
+                            .route(
+                                "/{id}",
+                                web::delete()
+                                    .to(handlers::revoke_invitation)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_WRITE)),
+                            )
+                            .route(
+                                "/{id}/uses",
+                                web::get()
+                                    .to(handlers::get_invitation_usage)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_READ)),
+                            )
+                            .route(
+                                "/{id}/qr",
+                                web::get()
+                                    .to(handlers::get_invitation_qr)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_READ)),
+                            )
+                            .route(
+                                "/{id}/flyer",
+                                web::get()
+                                    .to(handlers::get_invitation_flyer)
+                                    .wrap(middleware::ScopedAuth(scopes::INVITATIONS_READ)),
+                            )
+                            .route(
+                                "/{token}/accept",
+                                web::post()
+                                    .to(handlers::accept_invitation)
+                                    .wrap(middleware::JwtAuth),
+                            ),
                     ),
             )
+            // Audit log
+            .service(
+                web::scope("/api/audit")
+                    .wrap(middleware::RequirePermission(permissions::AUDIT_READ))
+                    .wrap(middleware::ScopedAuth(scopes::AUDIT_READ))
+                    .route("", web::get().to(handlers::list_audit_entries))
+                    .route("/verify", web::get().to(handlers::verify_audit_entries)),
+            )
             .route("/health", web::get().to(handlers::health))
+            .route("/metrics", web::get().to(shared_lib::metrics::metrics))
     })
     .bind(&bind_addr)?
     .run()
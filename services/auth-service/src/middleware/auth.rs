@@ -1,40 +1,50 @@
-use crate::{models::user::User, services::TokenService};
+use crate::{
+    services::{
+        authenticate_personal_access_token, is_personal_access_token, is_session_active,
+        load_member_state, AuthStateCache, MemberState, TokenService, UserRoles,
+    },
+    utils::ClientInfo,
+};
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
+    error::{ErrorForbidden, ErrorUnauthorized},
     Error, HttpMessage,
 };
 use futures_util::future::LocalBoxFuture;
+use shared_lib::TerritoryResolver;
 use sqlx::PgPool;
 use std::{
     future::{ready, Ready},
     rc::Rc,
 };
 
-/// Get schema name for a territory
-/// For single-territory pods: returns "territory"
-/// For multi-territory pods: returns "territory_XX" (e.g., "territory_de")
-fn get_schema_name(_territory_code: &str) -> String {
-    // TODO: Make this configurable via environment variable
-    // For now, use single-territory approach (generic "territory" schema)
-    "territory".to_string()
-
-    // For multi-territory pods, use:
-    // format!("territory_{}", territory_code.to_lowercase())
-}
-
 /// Authenticated user information extracted from JWT
 #[derive(Debug, Clone)]
 pub struct AuthenticatedUser {
     pub user_id: uuid::Uuid,
-    #[allow(dead_code)] // Will be used when implementing user profile endpoints
     pub username: String,
     pub territory_code: String,
-    #[allow(dead_code)] // Will be used when implementing Holochain integration
     pub public_key_hash: String,
+    pub identity_id: uuid::Uuid,        // global.user_identities.id
+    pub session_id: Option<uuid::Uuid>, // None for tokens issued without a session
+    pub mfa_verified: bool,             // Second factor completed at login
+    pub is_verified: bool,              // Email address verified
+    pub roles: UserRoles,               // Territory and global roles
+}
+
+impl AuthenticatedUser {
+    /// Check whether the user's roles grant `permission`
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.roles.has_permission(permission)
+    }
 }
 
 /// Middleware factory for JWT authentication
+///
+/// Trusts the signed claims of short-lived access tokens for who the user is.
+/// Session revocation, deactivation, email verification and roles are checked
+/// against the `AuthStateCache` app data, or against the database when no
+/// cache is configured.
 pub struct JwtAuth;
 
 impl<S, B> Transform<S, ServiceRequest> for JwtAuth
@@ -76,83 +86,11 @@ where
         let service = Rc::clone(&self.service);
 
         Box::pin(async move {
-            // Extract Authorization header
-            let auth_header = req
-                .headers()
-                .get("Authorization")
-                .and_then(|h| h.to_str().ok());
-
-            let token = match auth_header {
-                Some(header) if header.starts_with("Bearer ") => &header[7..],
-                _ => return Err(ErrorUnauthorized("Missing or invalid Authorization header")),
-            };
-
-            // Get TokenService from app data
-            let token_service = req
-                .app_data::<actix_web::web::Data<TokenService>>()
-                .ok_or_else(|| ErrorUnauthorized("TokenService not configured"))?;
-
-            // Validate and decode JWT
-            let claims = token_service
-                .validate_token(token)
-                .map_err(|_| ErrorUnauthorized("Invalid or expired token"))?;
-
-            // Parse user_id from string to UUID
-            let user_id = uuid::Uuid::parse_str(&claims.user_id)
-                .map_err(|_| ErrorUnauthorized("Invalid user ID in token"))?;
-
-            // Get database pool
-            let pool = req
-                .app_data::<actix_web::web::Data<PgPool>>()
-                .ok_or_else(|| ErrorUnauthorized("Database pool not configured"))?;
-
-            // Load user from database (using territory schema)
-            let schema_name = get_schema_name(&claims.territory_code);
-            let user_query = format!(
-                r#"
-                SELECT 
-                    id, email, password_hash, username, 
-                    full_name, display_name, avatar_url, bio, date_of_birth, phone,
-                    profile_visibility, email_notifications, push_notifications,
-                    is_verified, is_active, last_login_at,
-                    invited_by_user_id, invitation_by_token_id,
-                    created_at, updated_at
-                FROM {}.users 
-                WHERE id = $1 AND is_active = true
-                "#,
-                schema_name
-            );
-
-            let user = sqlx::query_as::<_, User>(&user_query)
-                .bind(user_id)
-                .fetch_optional(pool.get_ref())
-                .await
-                .map_err(|e| {
-                    tracing::error!("Database error loading user: {:?}", e);
-                    ErrorUnauthorized("Failed to load user")
-                })?
-                .ok_or_else(|| ErrorUnauthorized("User not found or inactive"))?;
-
-            // Get public_key_hash from global.user_identities
-            let public_key_hash: String = sqlx::query_scalar(
-                "SELECT public_key_hash FROM global.user_identities WHERE territory_code = $1 AND territory_user_id = $2"
-            )
-            .bind(&claims.territory_code)
-            .bind(user.id)
-            .fetch_one(pool.get_ref())
-            .await
-            .map_err(|e| {
-                tracing::error!("Failed to fetch public_key_hash: {:?}", e);
-                ErrorUnauthorized("Public key not found")
-            })?;
+            let token = bearer_token(&req)?;
+            let user = authenticate_access_token(&req, &token).await?;
 
             // Store authenticated user in request extensions
-            req.extensions_mut().insert(AuthenticatedUser {
-                user_id: user.id,
-                username: user.username,
-                territory_code: claims.territory_code,
-                public_key_hash,
-            });
+            req.extensions_mut().insert(user);
 
             // Continue with request
             let res = service.call(req).await?;
@@ -161,6 +99,223 @@ where
     }
 }
 
+/// Middleware factory that accepts access tokens and personal access tokens
+///
+/// Session access tokens are checked like [`JwtAuth`] does. Personal access
+/// tokens are only accepted if they were created with the scope, e.g.
+/// `.wrap(ScopedAuth(scopes::INVITATIONS_READ))`. Routes wrapped in plain
+/// `JwtAuth` never accept personal access tokens.
+pub struct ScopedAuth(pub &'static str);
+
+impl<S, B> Transform<S, ServiceRequest> for ScopedAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ScopedAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ScopedAuthMiddleware {
+            service: Rc::new(service),
+            scope: self.0,
+        }))
+    }
+}
+
+pub struct ScopedAuthMiddleware<S> {
+    service: Rc<S>,
+    scope: &'static str,
+}
+
+impl<S, B> Service<ServiceRequest> for ScopedAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let scope = self.scope;
+
+        Box::pin(async move {
+            let token = bearer_token(&req)?;
+            let user = if is_personal_access_token(&token) {
+                authenticate_scoped_token(&req, &token, scope).await?
+            } else {
+                authenticate_access_token(&req, &token).await?
+            };
+
+            req.extensions_mut().insert(user);
+
+            service.call(req).await
+        })
+    }
+}
+
+/// Extract the bearer token from the Authorization header
+fn bearer_token(req: &ServiceRequest) -> Result<String, Error> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::to_string)
+        .ok_or_else(|| ErrorUnauthorized("Missing or invalid Authorization header"))
+}
+
+/// Authenticate a session access token (JWT)
+async fn authenticate_access_token(
+    req: &ServiceRequest,
+    token: &str,
+) -> Result<AuthenticatedUser, Error> {
+    // Get TokenService from app data
+    let token_service = req
+        .app_data::<actix_web::web::Data<TokenService>>()
+        .ok_or_else(|| ErrorUnauthorized("TokenService not configured"))?;
+
+    // Validate and decode JWT
+    let claims = token_service
+        .validate_token(token)
+        .map_err(|_| ErrorUnauthorized("Invalid or expired token"))?;
+
+    // Parse user_id from string to UUID
+    let user_id = uuid::Uuid::parse_str(&claims.user_id)
+        .map_err(|_| ErrorUnauthorized("Invalid user ID in token"))?;
+
+    // Parse session ID (tokens are bound to the session that issued them)
+    let session_id = claims
+        .sid
+        .as_deref()
+        .map(uuid::Uuid::parse_str)
+        .transpose()
+        .map_err(|_| ErrorUnauthorized("Invalid session ID in token"))?;
+
+    let member = member_state(req, &claims.territory_code, user_id).await?;
+
+    // Reject tokens whose session was revoked (remote logout)
+    if let Some(session_id) = session_id {
+        let pool = database_pool(req)?;
+        let active = match auth_state_cache(req) {
+            Some(cache) => cache.is_session_active(pool, session_id).await,
+            None => is_session_active(pool, session_id).await,
+        }
+        .map_err(|e| {
+            tracing::error!("Failed to check session: {:?}", e);
+            ErrorUnauthorized("Failed to check session")
+        })?;
+
+        if !active {
+            return Err(ErrorUnauthorized("Session has been revoked"));
+        }
+    }
+
+    // Identity comes from the signed claims
+    Ok(AuthenticatedUser {
+        user_id,
+        is_verified: member.is_verified,
+        username: claims.username,
+        territory_code: claims.territory_code,
+        public_key_hash: claims.sub,
+        identity_id: member.identity_id,
+        session_id,
+        mfa_verified: claims.amr.iter().any(|method| method == "otp"),
+        roles: member.roles,
+    })
+}
+
+/// Authenticate a personal access token that must hold `scope`
+async fn authenticate_scoped_token(
+    req: &ServiceRequest,
+    token: &str,
+    scope: &str,
+) -> Result<AuthenticatedUser, Error> {
+    let client = ClientInfo::from_request(req.request());
+
+    let owner = authenticate_personal_access_token(database_pool(req)?, token, &client)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to check personal access token: {:?}", e);
+            ErrorUnauthorized("Failed to check token")
+        })?
+        .ok_or_else(|| ErrorUnauthorized("Invalid, revoked or expired token"))?;
+
+    if !owner.scopes.iter().any(|granted| granted == scope) {
+        return Err(ErrorForbidden(format!("Token lacks scope: {}", scope)));
+    }
+
+    let member = member_state(req, &owner.territory_code, owner.user_id).await?;
+
+    // No session, and no second factor: the token stands in for neither
+    Ok(AuthenticatedUser {
+        user_id: owner.user_id,
+        is_verified: member.is_verified,
+        username: owner.username,
+        territory_code: owner.territory_code,
+        public_key_hash: owner.public_key_hash,
+        identity_id: owner.identity_id,
+        session_id: None,
+        mfa_verified: false,
+        roles: member.roles,
+    })
+}
+
+/// Load an active member's state, from the auth state cache when configured
+///
+/// The token must belong to a territory served by this pod.
+async fn member_state(
+    req: &ServiceRequest,
+    territory_code: &str,
+    user_id: uuid::Uuid,
+) -> Result<MemberState, Error> {
+    let pool = database_pool(req)?;
+
+    // Resolve the territory schema (token must belong to a territory served here)
+    let territories = req
+        .app_data::<actix_web::web::Data<TerritoryResolver>>()
+        .ok_or_else(|| ErrorUnauthorized("Territory resolver not configured"))?;
+
+    let schema_name = territories
+        .schema_for(territory_code)
+        .map_err(|_| ErrorUnauthorized("Territory not served by this pod"))?;
+
+    // Revocation and deactivation come from the auth state cache; the
+    // database is only read on a miss, or on every request without a cache
+    match auth_state_cache(req) {
+        Some(cache) => {
+            cache
+                .member_state(pool, schema_name, territory_code, user_id)
+                .await
+        }
+        None => load_member_state(pool, schema_name, territory_code, user_id).await,
+    }
+    .map_err(|e| {
+        tracing::error!("Failed to load member state: {:?}", e);
+        ErrorUnauthorized("Failed to load user")
+    })?
+    .ok_or_else(|| ErrorUnauthorized("User not found or inactive"))
+}
+
+pub(super) fn database_pool(req: &ServiceRequest) -> Result<&PgPool, Error> {
+    req.app_data::<actix_web::web::Data<PgPool>>()
+        .map(|pool| pool.get_ref())
+        .ok_or_else(|| ErrorUnauthorized("Database pool not configured"))
+}
+
+fn auth_state_cache(req: &ServiceRequest) -> Option<&AuthStateCache> {
+    req.app_data::<actix_web::web::Data<AuthStateCache>>()
+        .map(|cache| cache.get_ref())
+}
+
 /// Helper function to extract authenticated user from request
 pub fn get_authenticated_user(req: &actix_web::HttpRequest) -> Result<AuthenticatedUser, Error> {
     req.extensions()
@@ -1,3 +1,5 @@
 pub mod auth;
+pub mod permission;
 
-pub use auth::{get_authenticated_user, JwtAuth};
+pub use auth::{get_authenticated_user, AuthenticatedUser, JwtAuth, ScopedAuth};
+pub use permission::RequirePermission;
@@ -0,0 +1,109 @@
+use crate::{
+    middleware::{auth::database_pool, AuthenticatedUser},
+    services::is_mfa_required_for_managers,
+};
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
+    web, Error, HttpMessage,
+};
+use futures_util::future::LocalBoxFuture;
+use shared_lib::TerritoryResolver;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+/// Middleware factory that requires a permission on every route it wraps
+///
+/// Where the territory requires 2FA for managers (`require_mfa_for_managers`),
+/// the permission is only honoured for sessions that completed a second factor.
+///
+/// Must run after [`crate::middleware::JwtAuth`], so wrap it before `JwtAuth`:
+/// `.wrap(RequirePermission("roles.manage")).wrap(JwtAuth)`.
+pub struct RequirePermission(pub &'static str);
+
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequirePermissionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequirePermissionMiddleware {
+            service: Rc::new(service),
+            permission: self.0,
+        }))
+    }
+}
+
+pub struct RequirePermissionMiddleware<S> {
+    service: Rc<S>,
+    permission: &'static str,
+}
+
+impl<S, B> Service<ServiceRequest> for RequirePermissionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let permission = self.permission;
+
+        Box::pin(async move {
+            let (allowed, mfa_verified, territory_code) = req
+                .extensions()
+                .get::<AuthenticatedUser>()
+                .map(|user| {
+                    (
+                        user.has_permission(permission),
+                        user.mfa_verified,
+                        user.territory_code.clone(),
+                    )
+                })
+                .ok_or_else(|| ErrorUnauthorized("User not authenticated"))?;
+
+            if !allowed {
+                return Err(ErrorForbidden(format!(
+                    "Missing permission: {}",
+                    permission
+                )));
+            }
+
+            if !mfa_verified && is_mfa_required(&req, &territory_code).await? {
+                return Err(ErrorForbidden(
+                    "Two-factor authentication is required for territory managers",
+                ));
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+/// Check whether the user's territory requires 2FA for permission holders
+async fn is_mfa_required(req: &ServiceRequest, territory_code: &str) -> Result<bool, Error> {
+    let schema_name = req
+        .app_data::<web::Data<TerritoryResolver>>()
+        .ok_or_else(|| ErrorUnauthorized("Territory resolver not configured"))?
+        .schema_for(territory_code)
+        .map_err(|_| ErrorUnauthorized("Territory not served by this pod"))?;
+
+    is_mfa_required_for_managers(database_pool(req)?, schema_name)
+        .await
+        .map_err(ErrorInternalServerError)
+}
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// Entries returned per page unless `per_page` says otherwise
+pub const DEFAULT_AUDIT_PAGE_SIZE: i64 = 50;
+
+/// Largest page of audit entries served at once
+pub const MAX_AUDIT_PAGE_SIZE: i64 = 200;
+
+/// Row of `global.audit_log`
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>, // Actor: global.user_identities.id
+    pub username: Option<String>,
+    pub territory_code: Option<String>,
+    pub action: String,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub changes: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub seq: Option<i64>,     // Position in the territory's hash chain
+    pub hash: Option<String>, // None for entries written before chaining
+}
+
+/// Filters for GET /api/audit
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditLogQuery {
+    pub user_id: Option<Uuid>,          // Actor's global identity
+    pub action: Option<String>,         // Exact action, or a prefix ending in ".*" (e.g. "auth.*")
+    pub from: Option<DateTime<Utc>>,    // Inclusive
+    pub to: Option<DateTime<Utc>>,      // Exclusive
+    pub territory_code: Option<String>, // Platform admins only; others see their own territory
+    pub page: Option<i64>,              // 1-based (default: 1)
+    pub per_page: Option<i64>,          // Default: 50, at most 200
+}
+
+/// One page of audit entries, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditLogPage {
+    pub entries: Vec<AuditLogEntry>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+/// Query for GET /api/audit/verify
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditVerifyQuery {
+    pub territory_code: Option<String>, // Platform admins only ("global" for entries without a territory)
+}
+
+/// Row of `global.audit_checkpoints`: a signed chain head
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AuditCheckpoint {
+    pub id: Uuid,
+    pub chain_id: String,
+    pub seq: i64,
+    pub hash: String,
+    pub signature: String, // JWT over the chain, seq and hash
+    pub created_at: DateTime<Utc>,
+}
+
+/// Claims of a signed audit checkpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditCheckpointClaims {
+    pub aud: String,
+    pub chain: String,
+    pub seq: i64,
+    pub hash: String,
+    pub iat: i64,
+}
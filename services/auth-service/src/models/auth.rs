@@ -18,7 +18,7 @@ pub struct RegisterRequest {
     #[validate(length(min = 2, max = 10, message = "Territory code must be 2-10 characters"))]
     pub territory_code: String,
 
-    #[validate(length(min = 10, max = 100, message = "Invitation token is required"))]
+    #[validate(length(min = 10, max = 1024, message = "Invitation token is required"))]
     pub invitation_token: String, // ⭐ REQUIRED
 }
 
@@ -68,4 +68,12 @@ pub struct Claims {
     pub username: String,
     pub exp: i64, // Expiration time (Unix timestamp)
     pub iat: i64, // Issued at (Unix timestamp)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>, // Issued at in milliseconds, for access token cut-offs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>, // Session (family) ID, UUID as string
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>, // Authentication methods: "pwd", "otp"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>, // Token ID (UUID as string), for revocation
 }
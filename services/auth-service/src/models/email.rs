@@ -0,0 +1,18 @@
+use serde::Deserialize;
+use validator::Validate;
+
+/// Change the authenticated user's email (takes effect once verified)
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+}
+
+/// Query of an email verification link
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailQuery {
+    pub territory_code: Option<String>, // Defaults to the pod's only territory
+}
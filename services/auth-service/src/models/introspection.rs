@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Body of POST /api/auth/introspect (RFC 7662, form-encoded)
+///
+/// Only access tokens can be introspected, so `token_type_hint` is ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IntrospectionRequest {
+    pub token: String,
+}
+
+/// Answer to an introspection request
+///
+/// Inactive tokens only say `"active": false`, whatever the reason (expired,
+/// revoked, unknown user), as RFC 7662 recommends.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>, // "access_token"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>, // public_key_hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub territory_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<Uuid>, // Territory user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>, // Territory and global roles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>, // Session family
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mfa_verified: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+}
+
+impl IntrospectionResponse {
+    pub fn inactive() -> Self {
+        Self::default()
+    }
+}
+
+/// Row of `global.service_clients`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ServiceClient {
+    pub client_id: String,
+    pub name: String,
+}
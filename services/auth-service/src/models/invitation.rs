@@ -2,7 +2,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidateEmail};
 
 /// Invitation token model from territory schema
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -21,6 +21,7 @@ pub struct InvitationToken {
     pub role: Option<String>,
     pub max_uses: Option<i32>,
     pub current_uses: i32,
+    pub batch_id: Option<Uuid>, // Set for tokens created together in one batch
 
     // Lifecycle
     pub expires_at: Option<DateTime<Utc>>,
@@ -31,6 +32,22 @@ pub struct InvitationToken {
     pub updated_at: DateTime<Utc>,
 }
 
+/// JWT claims of a signed invitation token
+///
+/// Signed with the pod key so kiosks can check printed invitations offline
+/// against the published JWKS. Short claim names keep the QR codes small;
+/// `nonce` is the stored invitation token the signature stands for.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InvitationClaims {
+    pub aud: String,
+    #[serde(rename = "ter")]
+    pub territory_code: String,
+    #[serde(rename = "typ")]
+    pub token_type: String, // "single_use" or "group"
+    pub exp: i64,
+    pub nonce: String,
+}
+
 /// Record of invitation token usage
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct InvitationUse {
@@ -49,7 +66,10 @@ pub struct CreateInvitationRequest {
     pub token_type: String, // "single_use" or "group"
 
     #[validate(email(message = "Invalid email format"))]
-    pub email: Option<String>, // Required for single_use, null for group
+    pub email: Option<String>, // Single_use only (email or username required)
+
+    #[validate(length(min = 3, max = 50, message = "Username must be 3-50 characters"))]
+    pub username: Option<String>, // Single_use only - invite a specific person by username
 
     #[validate(range(min = 1, max = 1000, message = "Max uses must be between 1 and 1000"))]
     pub max_uses: i32,
@@ -63,6 +83,106 @@ pub struct CreateInvitationRequest {
 
     #[validate(length(max = 500, message = "Purpose must be 500 characters or less"))]
     pub purpose: Option<String>,
+
+    pub community_id: Option<Uuid>, // Community the newcomer joins on registration
+
+    #[validate(length(min = 1, max = 50, message = "Role must be 1-50 characters"))]
+    pub role: Option<String>, // Community role (default: member)
+}
+
+/// Largest number of invitations created in one batch
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// Request to create a batch of single-use invitation tokens
+///
+/// One token is created per email and per username, plus `count` tokens
+/// that are not targeted at anyone.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateInvitationBatchRequest {
+    pub count: Option<usize>,
+
+    pub emails: Option<Vec<String>>,
+
+    pub usernames: Option<Vec<String>>,
+
+    #[validate(range(
+        min = 1,
+        max = 365,
+        message = "Expiration must be between 1 and 365 days"
+    ))]
+    pub expires_in_days: Option<i64>, // Default: 7 days
+
+    pub community_id: Option<Uuid>, // Community the newcomers join on registration
+
+    #[validate(length(min = 1, max = 50, message = "Role must be 1-50 characters"))]
+    pub role: Option<String>, // Community role (default: member)
+}
+
+/// Invitee of one token in a batch (both `None` for an untargeted token)
+#[derive(Debug, Clone, Default)]
+pub struct BatchRecipient {
+    pub email: Option<String>,
+    pub username: Option<String>,
+}
+
+impl CreateInvitationBatchRequest {
+    /// Validate business logic rules
+    pub fn validate_business_rules(&self) -> Result<(), String> {
+        for email in self.emails.iter().flatten() {
+            if !email.validate_email() {
+                return Err(format!("Invalid email format: {}", email));
+            }
+        }
+
+        for username in self.usernames.iter().flatten() {
+            if !(3..=50).contains(&username.chars().count()) {
+                return Err(format!("Username must be 3-50 characters: {}", username));
+            }
+        }
+
+        let size = self.recipients().len();
+        if size == 0 || size > MAX_BATCH_SIZE {
+            return Err(format!(
+                "A batch must contain between 1 and {} invitations",
+                MAX_BATCH_SIZE
+            ));
+        }
+
+        // Roles are community roles
+        if self.role.is_some() && self.community_id.is_none() {
+            return Err("A role can only be given together with a community".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// One recipient per token to create
+    pub fn recipients(&self) -> Vec<BatchRecipient> {
+        let emails = self.emails.iter().flatten().map(|email| BatchRecipient {
+            email: Some(email.clone()),
+            username: None,
+        });
+        let usernames = self
+            .usernames
+            .iter()
+            .flatten()
+            .map(|username| BatchRecipient {
+                email: None,
+                username: Some(username.clone()),
+            });
+        // Capped so an oversized count is rejected without being allocated
+        let untargeted =
+            (0..self.count.unwrap_or(0).min(MAX_BATCH_SIZE + 1)).map(|_| BatchRecipient::default());
+
+        emails.chain(usernames).chain(untargeted).collect()
+    }
+}
+
+/// Invitation tokens created together in one batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitationBatchResponse {
+    pub batch_id: Uuid,
+    pub invitations: Vec<InvitationResponse>,
 }
 
 /// Response containing invitation token details
@@ -72,10 +192,15 @@ pub struct InvitationResponse {
     pub token: String,
     pub token_type: String,
     pub email: Option<String>,
+    pub username: Option<String>,
     pub max_uses: Option<i32>,
     pub current_uses: i32,
     pub expires_at: Option<DateTime<Utc>>,
     pub is_active: bool,
+    pub community_id: Option<Uuid>,
+    pub role: Option<String>, // Community role given on registration
+    pub batch_id: Option<Uuid>,
+    pub signed_token: Option<String>, // Offline-verifiable form of `token` (set by handlers)
     pub created_at: DateTime<Utc>,
 }
 
@@ -86,10 +211,15 @@ impl From<InvitationToken> for InvitationResponse {
             token: token.token,
             token_type: token.token_type,
             email: token.invited_email,
+            username: token.invited_username,
             max_uses: token.max_uses,
             current_uses: token.current_uses,
             expires_at: token.expires_at,
             is_active: token.is_active,
+            role: token.community_id.and(token.role),
+            community_id: token.community_id,
+            batch_id: token.batch_id,
+            signed_token: None,
             created_at: token.created_at,
         }
     }
@@ -121,10 +251,12 @@ fn validate_token_type(token_type: &str) -> Result<(), validator::ValidationErro
 impl CreateInvitationRequest {
     /// Validate business logic rules
     pub fn validate_business_rules(&self) -> Result<(), String> {
-        // Single-use tokens must have email
+        // Single-use tokens must target someone (email and/or username)
         if self.token_type == "single_use" {
-            if self.email.is_none() {
-                return Err("Email is required for single_use invitation tokens".to_string());
+            if self.email.is_none() && self.username.is_none() {
+                return Err(
+                    "Email or username is required for single_use invitation tokens".to_string(),
+                );
             }
             if self.max_uses != 1 {
                 return Err("Single-use tokens must have max_uses = 1".to_string());
@@ -136,11 +268,19 @@ impl CreateInvitationRequest {
             if self.email.is_some() {
                 return Err("Email must not be set for group invitation tokens".to_string());
             }
+            if self.username.is_some() {
+                return Err("Username must not be set for group invitation tokens".to_string());
+            }
             if self.max_uses <= 1 {
                 return Err("Group tokens must have max_uses > 1".to_string());
             }
         }
 
+        // Roles are community roles
+        if self.role.is_some() && self.community_id.is_none() {
+            return Err("A role can only be given together with a community".to_string());
+        }
+
         Ok(())
     }
 }
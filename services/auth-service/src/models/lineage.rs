@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// User in an invitation tree
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LineageMember {
+    pub user_id: Uuid,
+    pub username: String,
+    pub invited_by: Option<String>, // Username of the inviter
+    pub depth: i32, // 0 = the user asked about, -1 = their inviter, 1 = their invitees
+    pub is_active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Who brought a user in (`ancestors`, closest first) and who they brought in
+#[derive(Debug, Clone, Serialize)]
+pub struct LineageResponse {
+    pub user: LineageMember,
+    pub ancestors: Vec<LineageMember>,
+    pub descendants: Vec<LineageMember>,
+    pub descendant_count: usize,
+    pub max_depth: i32, // Deepest level below the user (0 without invitees)
+}
+
+/// Deactivate a user and everyone they (indirectly) invited
+#[derive(Debug, Deserialize, Validate)]
+pub struct DeactivateSubtreeRequest {
+    #[validate(length(min = 1, max = 500, message = "Reason must be 1-500 characters"))]
+    pub reason: String,
+}
+
+/// Outcome of a subtree deactivation
+#[derive(Debug, Clone, Serialize)]
+pub struct SubtreeDeactivation {
+    pub users_deactivated: i64,
+    pub tokens_revoked: i64,
+    pub sessions_revoked: i64,
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Second login step: MFA challenge token plus a TOTP or backup code
+#[derive(Debug, Deserialize, Validate)]
+pub struct MfaLoginRequest {
+    #[validate(length(min = 1, message = "MFA token is required"))]
+    pub mfa_token: String,
+
+    #[validate(length(min = 6, max = 20, message = "Invalid authentication code"))]
+    pub code: String, // 6-digit TOTP code or backup code
+}
+
+/// Login response when the account has 2FA enabled
+#[derive(Debug, Serialize)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: i64, // seconds
+}
+
+/// Request carrying a TOTP (or backup) code
+#[derive(Debug, Deserialize, Validate)]
+pub struct TotpCodeRequest {
+    #[validate(length(min = 6, max = 20, message = "Invalid authentication code"))]
+    pub code: String,
+}
+
+/// TOTP enrolment details for the authenticator app
+#[derive(Debug, Serialize)]
+pub struct TotpEnrolmentResponse {
+    pub secret: String,      // Base32, for manual entry
+    pub otpauth_uri: String, // For QR code rendering
+}
+
+/// Backup codes, shown to the user exactly once
+#[derive(Debug, Serialize)]
+pub struct BackupCodesResponse {
+    pub backup_codes: Vec<String>,
+}
+
+/// JWT claims of an MFA challenge token (issued after the password step)
+///
+/// Deliberately incompatible with [`super::Claims`]: it has an audience and no
+/// username, so it can never be accepted as an access token.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MfaChallengeClaims {
+    pub sub: String, // Territory user ID, UUID as string
+    pub territory_code: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+}
@@ -1,7 +1,27 @@
+pub mod audit;
 pub mod auth;
+pub mod email;
+pub mod introspection;
 pub mod invitation;
+pub mod lineage;
+pub mod mfa;
+pub mod oidc;
+pub mod personal_access_token;
+pub mod recovery;
+pub mod role;
+pub mod session;
 pub mod user;
 
+pub use audit::*;
 pub use auth::*;
+pub use email::*;
+pub use introspection::*;
 // pub use invitation::* - unused, comment out
+pub use lineage::*;
+pub use mfa::*;
+pub use oidc::*;
+pub use personal_access_token::*;
+pub use recovery::*;
+pub use role::*;
+pub use session::*;
 pub use user::*;
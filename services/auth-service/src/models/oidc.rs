@@ -0,0 +1,219 @@
+use super::Claims;
+use serde::{Deserialize, Serialize};
+use shared_lib::error::AppError;
+
+/// Row of `global.oidc_clients`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OidcClient {
+    pub client_id: String,
+    pub territory_code: String,
+    pub name: String,
+    pub secret_hash: Option<String>, // None for public clients (PKCE only)
+    pub redirect_uris: Vec<String>,
+    pub allowed_scopes: Vec<String>,
+}
+
+/// Authorization request of a relying party (OIDC Core 3.1.2.1)
+///
+/// The front-end receives it as query parameters on its authorize page and
+/// forwards it unchanged to the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationRequest {
+    pub response_type: String, // Only "code"
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: String, // Space-separated, must include "openid"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_challenge: Option<String>, // PKCE, required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_challenge_method: Option<String>, // Only "S256"
+}
+
+/// Body of POST /api/auth/oidc/authorize: the request and the member's answer
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthorizationDecision {
+    #[serde(flatten)]
+    pub request: AuthorizationRequest,
+    pub approved: bool,
+}
+
+/// A requested scope as shown on the consent screen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScopeDescription {
+    pub scope: String,
+    pub description: String,
+}
+
+/// Client shown on the consent screen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsentClient {
+    pub client_id: String,
+    pub name: String,
+}
+
+/// What the front-end needs to render the consent screen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsentScreen {
+    pub client: ConsentClient,
+    pub scopes: Vec<ScopeDescription>, // Granted scopes, unsupported ones dropped
+    pub consent_required: bool,        // false when the member already agreed to these scopes
+}
+
+/// Where the front-end sends the browser after the member decided
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationResponse {
+    pub redirect_to: String,
+}
+
+/// OAuth 2.0 error (RFC 6749 4.1.2.1 and 5.2)
+///
+/// `redirect_to` is set when the error may be reported to the client's
+/// redirect URI; errors about the client or redirect URI themselves never are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcError {
+    pub error: String,
+    pub error_description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect_to: Option<String>,
+}
+
+impl OidcError {
+    pub fn new(error: &str, error_description: impl Into<String>) -> Self {
+        Self {
+            error: error.to_string(),
+            error_description: error_description.into(),
+            redirect_to: None,
+        }
+    }
+}
+
+impl From<AppError> for OidcError {
+    fn from(e: AppError) -> Self {
+        tracing::error!("OpenID provider error: {}", e);
+        Self::new("server_error", "Internal error")
+    }
+}
+
+impl From<sqlx::Error> for OidcError {
+    fn from(e: sqlx::Error) -> Self {
+        AppError::from(e).into()
+    }
+}
+
+/// Body of POST /api/auth/oidc/token (form-encoded)
+///
+/// Confidential clients authenticate with HTTP Basic or `client_secret`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String, // Only "authorization_code"
+    pub code: String,
+    pub redirect_uri: String,
+    pub code_verifier: String,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+}
+
+/// Tokens issued for an authorization code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String, // "Bearer"
+    pub expires_in: i64,
+    pub id_token: String,
+    pub scope: String,
+}
+
+/// ID token claims (OIDC Core 2), built from the member's access token [`Claims`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String, // public_key_hash
+    pub aud: String, // client_id
+    pub exp: i64,
+    pub iat: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+impl IdTokenClaims {
+    pub fn from_claims(
+        claims: &Claims,
+        issuer: &str,
+        client_id: &str,
+        nonce: Option<String>,
+    ) -> Self {
+        Self {
+            iss: issuer.to_string(),
+            sub: claims.sub.clone(),
+            aud: client_id.to_string(),
+            exp: claims.exp,
+            iat: claims.iat,
+            nonce,
+            amr: claims.amr.clone(),
+            sid: claims.sid.clone(),
+        }
+    }
+}
+
+/// Claims of an access token issued to a relying party
+///
+/// The audience keeps these tokens out of [`crate::middleware::JwtAuth`]: they
+/// are only accepted by the userinfo endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientAccessClaims {
+    #[serde(flatten)]
+    pub claims: Claims,
+    pub aud: String,
+    pub client_id: String,
+    pub scope: String, // Space-separated granted scopes
+}
+
+/// Userinfo response (OIDC Core 5.3.2)
+///
+/// Profile claims follow the member's profile privacy settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub picture: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+}
+
+/// OpenID Provider metadata (OIDC Discovery 3)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub scopes_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+}
@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Personal access token as shown to its owner (never the token itself)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PersonalAccessToken {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+}
+
+/// Request to create a personal access token
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct CreatePersonalAccessTokenRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be 1-100 characters"))]
+    pub name: String,
+
+    #[validate(length(min = 1, message = "At least one scope is required"))]
+    pub scopes: Vec<String>, // e.g. ["profile:read", "invitations:write"]
+
+    #[validate(range(
+        min = 1,
+        max = 365,
+        message = "Expiration must be between 1 and 365 days"
+    ))]
+    pub expires_in_days: Option<i64>, // Default: 90 days
+}
+
+/// Newly created personal access token, the only time `token` is returned
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedPersonalAccessToken {
+    #[serde(flatten)]
+    pub details: PersonalAccessToken,
+    pub token: String,
+}
+
+/// Owner of a personal access token presented with a request
+#[derive(Debug, Clone, FromRow)]
+pub struct PersonalAccessTokenOwner {
+    pub scopes: Vec<String>,
+    pub identity_id: Uuid, // global.user_identities.id
+    pub territory_code: String,
+    pub user_id: Uuid, // territory_user_id
+    pub username: String,
+    pub public_key_hash: String,
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Change the password of the authenticated user
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+
+    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    pub new_password: String,
+}
+
+/// Re-authenticate with the current password (e.g. to issue new recovery codes)
+#[derive(Debug, Deserialize, Validate)]
+pub struct CurrentPasswordRequest {
+    #[validate(length(min = 1, message = "Current password is required"))]
+    pub current_password: String,
+}
+
+/// Reset a forgotten password with a recovery code (no email needed)
+#[derive(Debug, Deserialize, Validate)]
+pub struct RecoverAccountRequest {
+    #[validate(length(min = 3, max = 50, message = "Username must be 3-50 characters"))]
+    pub username: String,
+
+    #[validate(length(min = 2, max = 10))]
+    pub territory_code: String,
+
+    #[validate(length(min = 10, max = 20, message = "Invalid recovery code"))]
+    pub recovery_code: String,
+
+    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    pub new_password: String,
+}
+
+/// Ask for a password reset link by email
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+
+    #[validate(length(min = 2, max = 10))]
+    pub territory_code: String,
+}
+
+/// Set a new password with an emailed reset token
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 10, max = 100, message = "Reset token is required"))]
+    pub token: String,
+
+    #[validate(length(min = 2, max = 10))]
+    pub territory_code: String,
+
+    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    pub new_password: String,
+}
+
+/// Recovery codes, shown to the user exactly once
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
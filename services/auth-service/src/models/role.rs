@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+/// Territory role held by a user (`global.territory_managers`)
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RoleGrant {
+    pub username: String,
+    pub role: String,
+    pub granted_at: DateTime<Utc>,
+    pub granted_by: Option<String>, // Username of the granting admin
+}
+
+/// Grant a territory role to a user
+#[derive(Debug, Deserialize, Validate)]
+pub struct GrantRoleRequest {
+    #[validate(length(min = 3, max = 50, message = "Username must be 3-50 characters"))]
+    pub username: String,
+
+    #[validate(length(min = 1, max = 50, message = "Role must be 1-50 characters"))]
+    pub role: String,
+}
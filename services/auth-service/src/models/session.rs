@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Active session (one device/login) as shown to its owner
+///
+/// `id` is the session family ID, which stays the same across refresh token rotations.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,     // Login time
+    pub last_active_at: DateTime<Utc>, // Last refresh
+    pub expires_at: DateTime<Utc>,
+    pub current: bool, // Session of the access token making the request
+}
+
+/// Sign a member out of every session and access token
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForceLogoutRequest {
+    #[validate(length(min = 1, max = 500, message = "Reason must be 1-500 characters"))]
+    pub reason: String,
+}
+
+/// Outcome of a forced logout
+#[derive(Debug, Clone, Serialize)]
+pub struct ForcedLogout {
+    pub sessions_revoked: i64,
+    pub personal_access_tokens_revoked: i64,
+}
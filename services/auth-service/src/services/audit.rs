@@ -0,0 +1,199 @@
+use crate::models::{AuditCheckpoint, AuditLogEntry, AuditLogPage};
+use crate::services::TokenService;
+use chrono::{DateTime, Utc};
+use shared_lib::{
+    error::AppError, verify_audit_chain, AuditChainBreak, AuditChainReport, ChainCheckpoint,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Filters for [`list_audit_log`]; `None` matches everything
+#[derive(Debug, Default)]
+pub struct AuditLogFilter {
+    pub territory_code: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub action: Option<String>, // Exact action, or a prefix ending in ".*"
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Page through `global.audit_log`, newest entries first
+pub async fn list_audit_log(
+    pool: &PgPool,
+    filter: &AuditLogFilter,
+    page: i64,
+    per_page: i64,
+) -> Result<AuditLogPage, AppError> {
+    let (action, action_prefix) = match filter.action.as_deref() {
+        Some(action) => match action.strip_suffix('*') {
+            Some(prefix) => (None, Some(prefix)),
+            None => (Some(action), None),
+        },
+        None => (None, None),
+    };
+
+    let conditions = r#"
+        ($1::varchar IS NULL OR a.territory_code = $1)
+        AND ($2::uuid IS NULL OR a.user_id = $2)
+        AND ($3::varchar IS NULL OR a.action = $3)
+        AND ($4::text IS NULL OR starts_with(a.action, $4))
+        AND ($5::timestamptz IS NULL OR a.created_at >= $5)
+        AND ($6::timestamptz IS NULL OR a.created_at < $6)
+    "#;
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM global.audit_log a WHERE {}",
+        conditions
+    ))
+    .bind(&filter.territory_code)
+    .bind(filter.user_id)
+    .bind(action)
+    .bind(action_prefix)
+    .bind(filter.from)
+    .bind(filter.to)
+    .fetch_one(pool)
+    .await?;
+
+    let entries = sqlx::query_as::<_, AuditLogEntry>(&format!(
+        r#"
+        SELECT
+            a.id, a.user_id, ui.username, a.territory_code, a.action,
+            a.resource_type, a.resource_id, a.changes,
+            host(a.ip_address) AS ip_address, a.user_agent, a.created_at, a.seq, a.hash
+        FROM global.audit_log a
+        LEFT JOIN global.user_identities ui ON ui.id = a.user_id
+        WHERE {}
+        ORDER BY a.created_at DESC, a.id
+        LIMIT $7 OFFSET $8
+        "#,
+        conditions
+    ))
+    .bind(&filter.territory_code)
+    .bind(filter.user_id)
+    .bind(action)
+    .bind(action_prefix)
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(per_page)
+    .bind((page - 1) * per_page)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(AuditLogPage {
+        entries,
+        page,
+        per_page,
+        total,
+    })
+}
+
+/// Sign the head of every audit chain that grew since its last checkpoint
+///
+/// Returns the new checkpoints.
+pub async fn create_audit_checkpoints(
+    pool: &PgPool,
+    token_service: &TokenService,
+) -> Result<Vec<AuditCheckpoint>, AppError> {
+    let heads: Vec<(String, i64, String)> = sqlx::query_as(
+        r#"
+        SELECT DISTINCT ON (a.chain_id) a.chain_id, a.seq, a.hash
+        FROM global.audit_log a
+        WHERE a.seq IS NOT NULL
+        ORDER BY a.chain_id, a.seq DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut checkpoints = Vec::new();
+    for (chain_id, seq, hash) in heads {
+        let signature = token_service
+            .sign_audit_checkpoint(&chain_id, seq, &hash)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        let checkpoint = sqlx::query_as::<_, AuditCheckpoint>(
+            r#"
+            INSERT INTO global.audit_checkpoints (chain_id, seq, hash, signature)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (chain_id, seq) DO NOTHING
+            RETURNING id, chain_id, seq, hash, signature, created_at
+            "#,
+        )
+        .bind(&chain_id)
+        .bind(seq)
+        .bind(&hash)
+        .bind(&signature)
+        .fetch_optional(pool)
+        .await?;
+
+        checkpoints.extend(checkpoint);
+    }
+
+    Ok(checkpoints)
+}
+
+/// Verify an audit chain against its signed checkpoints
+///
+/// A checkpoint whose signature does not hold (or does not match its row) is
+/// reported as the broken link at its position.
+pub async fn verify_audit_log(
+    pool: &PgPool,
+    token_service: &TokenService,
+    chain_id: &str,
+) -> Result<AuditChainReport, AppError> {
+    let checkpoints = sqlx::query_as::<_, AuditCheckpoint>(
+        r#"
+        SELECT id, chain_id, seq, hash, signature, created_at
+        FROM global.audit_checkpoints
+        WHERE chain_id = $1
+        ORDER BY seq
+        "#,
+    )
+    .bind(chain_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut trusted = Vec::with_capacity(checkpoints.len());
+    let mut forged = None;
+    for checkpoint in &checkpoints {
+        let signed = token_service
+            .verify_audit_checkpoint(&checkpoint.signature)
+            .ok()
+            .filter(|claims| {
+                claims.chain == checkpoint.chain_id
+                    && claims.seq == checkpoint.seq
+                    && claims.hash == checkpoint.hash
+            });
+
+        match signed {
+            Some(_) => trusted.push(ChainCheckpoint {
+                seq: checkpoint.seq,
+                hash: checkpoint.hash.clone(),
+            }),
+            None => {
+                forged = Some(checkpoint.seq);
+                break;
+            }
+        }
+    }
+
+    let mut report = verify_audit_chain(pool, chain_id, &trusted).await?;
+
+    // Report whichever problem comes first in the chain
+    if let Some(seq) = forged {
+        let first = match &report.broken_link {
+            Some(broken) => seq < broken.seq,
+            None => true,
+        };
+        if first {
+            report.valid = false;
+            report.broken_link = Some(AuditChainBreak {
+                seq,
+                entry_id: None,
+                reason: "Checkpoint signature is invalid".to_string(),
+            });
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,482 @@
+use super::{resolve_user_roles, TokenRevocations, UserRoles};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use shared_lib::error::AppError;
+use sqlx::{postgres::PgListener, PgPool};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Postgres channel the auth state triggers notify on (see the
+/// `auth_state_notifications` migration)
+pub const AUTH_STATE_CHANNEL: &str = "auth_state";
+
+/// Delay before listening again after the listener connection failed
+const LISTENER_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// State of a member that can change while their access tokens are still valid
+#[derive(Debug, Clone)]
+pub struct MemberState {
+    pub identity_id: Uuid, // global.user_identities.id
+    pub is_verified: bool,
+    pub roles: UserRoles,
+}
+
+/// Load the state of an active member (`None` when deactivated or unknown)
+pub async fn load_member_state(
+    pool: &PgPool,
+    schema_name: &str,
+    territory_code: &str,
+    user_id: Uuid,
+) -> Result<Option<MemberState>, AppError> {
+    let query = format!(
+        r#"
+        SELECT ui.id, u.is_verified
+        FROM {}.users u
+        JOIN global.user_identities ui
+            ON ui.territory_code = $1 AND ui.territory_user_id = u.id
+        WHERE u.id = $2 AND u.is_active = true
+        "#,
+        schema_name
+    );
+
+    let member: Option<(Uuid, bool)> = sqlx::query_as(&query)
+        .bind(territory_code)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    let Some((identity_id, is_verified)) = member else {
+        return Ok(None);
+    };
+
+    Ok(Some(MemberState {
+        identity_id,
+        is_verified,
+        roles: resolve_user_roles(pool, identity_id, territory_code).await?,
+    }))
+}
+
+/// When the live refresh token of a session family expires (`None` once the
+/// family has ended)
+pub async fn session_active_until(
+    pool: &PgPool,
+    session_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, AppError> {
+    let until: Option<DateTime<Utc>> = sqlx::query_scalar(
+        r#"
+        SELECT MAX(LEAST(expires_at, family_expires_at))
+        FROM global.sessions
+        WHERE family_id = $1
+          AND rotated_at IS NULL
+          AND revoked_at IS NULL
+          AND expires_at > NOW()
+          AND family_expires_at > NOW()
+        "#,
+    )
+    .bind(session_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(until)
+}
+
+/// The schemas among `schemas` whose users table lacks the member state
+/// triggers (see `install_member_state_triggers`), so member changes there are
+/// not announced
+pub async fn schemas_without_member_triggers(
+    pool: &PgPool,
+    schemas: &[String],
+) -> Result<Vec<String>, AppError> {
+    let missing: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT s.schema_name
+        FROM UNNEST($1::TEXT[]) AS s(schema_name)
+        WHERE (
+            SELECT COUNT(*)
+            FROM pg_trigger t
+            JOIN pg_class c ON c.oid = t.tgrelid
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE n.nspname = s.schema_name
+              AND c.relname = 'users'
+              AND t.tgname IN ('trg_notify_member_changed', 'trg_notify_member_deleted')
+        ) < 2
+        ORDER BY s.schema_name
+        "#,
+    )
+    .bind(schemas)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(missing)
+}
+
+/// A change announced on [`AUTH_STATE_CHANNEL`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuthStateChange {
+    /// A session family was revoked or ended
+    Session { id: Uuid },
+    /// A member was deactivated, reactivated, verified or deleted
+    Member { schema: String, id: Uuid },
+    /// A global identity's territory or global roles changed
+    Identity { id: Uuid },
+    /// An access token was revoked (`expires_at` is its `exp`)
+    AccessToken { jti: String, expires_at: i64 },
+    /// Access tokens issued to a user (`sub`) up to `not_before_ms` were revoked
+    AccessTokenCutoff { sub: String, not_before_ms: i64 },
+}
+
+struct CachedEntry<T> {
+    value: T,
+    loaded_at: Instant,
+}
+
+type CachedEntries<K, T> = RwLock<HashMap<K, CachedEntry<T>>>;
+
+/// In-memory cache of the state `JwtAuth` checks on every request
+///
+/// Entries are loaded from the database on a miss and dropped when an
+/// [`AuthStateListener`] hears that they changed. The TTL bounds how stale an
+/// entry can get while the listener is reconnecting.
+pub struct AuthStateCache {
+    ttl: Duration,
+    // Bumped by every invalidation, so a load racing with one is not stored
+    generation: AtomicU64,
+    members: CachedEntries<(String, Uuid), Option<MemberState>>, // (schema, territory user id)
+    sessions: CachedEntries<Uuid, Option<DateTime<Utc>>>,        // Live until, by family id
+    uncached_schemas: HashSet<String>, // Member changes are not announced there
+    last_purge: RwLock<Instant>,
+}
+
+impl AuthStateCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            generation: AtomicU64::new(0),
+            members: RwLock::new(HashMap::new()),
+            sessions: RwLock::new(HashMap::new()),
+            uncached_schemas: HashSet::new(),
+            last_purge: RwLock::new(Instant::now()),
+        }
+    }
+
+    /// Always load members of `schemas` from the database, because no trigger
+    /// announces their changes (see [`schemas_without_member_triggers`])
+    pub fn uncached_schemas<I>(mut self, schemas: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        self.uncached_schemas.extend(schemas);
+        self
+    }
+
+    /// State of an active member, from the cache or the database
+    pub async fn member_state(
+        &self,
+        pool: &PgPool,
+        schema_name: &str,
+        territory_code: &str,
+        user_id: Uuid,
+    ) -> Result<Option<MemberState>, AppError> {
+        if self.uncached_schemas.contains(schema_name) {
+            return load_member_state(pool, schema_name, territory_code, user_id).await;
+        }
+
+        let key = (schema_name.to_string(), user_id);
+        if let Some(member) = self.cached(&self.members, &key) {
+            return Ok(member);
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let member = load_member_state(pool, schema_name, territory_code, user_id).await?;
+        self.store(&self.members, key, member.clone(), generation);
+
+        Ok(member)
+    }
+
+    /// Check whether a session family still has a live refresh token
+    pub async fn is_session_active(
+        &self,
+        pool: &PgPool,
+        session_id: Uuid,
+    ) -> Result<bool, AppError> {
+        match self.cached(&self.sessions, &session_id) {
+            Some(None) => return Ok(false), // Ended families never come back
+            Some(Some(until)) if until > Utc::now() => return Ok(true),
+            _ => {} // The refresh token may have been rotated since, look again
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let until = session_active_until(pool, session_id).await?;
+        self.store(&self.sessions, session_id, until, generation);
+
+        Ok(until.is_some_and(|until| until > Utc::now()))
+    }
+
+    /// Drop the entries a change affects
+    pub fn invalidate(&self, change: &AuthStateChange) {
+        if let AuthStateChange::AccessToken { .. } | AuthStateChange::AccessTokenCutoff { .. } =
+            change
+        {
+            return; // Checked by TokenRevocations
+        }
+
+        self.generation.fetch_add(1, Ordering::AcqRel);
+
+        match change {
+            AuthStateChange::Session { id } => {
+                write(&self.sessions).remove(id);
+            }
+            AuthStateChange::Member { schema, id } => {
+                write(&self.members).remove(&(schema.clone(), *id));
+            }
+            AuthStateChange::Identity { id } => {
+                write(&self.members).retain(
+                    |_, entry| !matches!(&entry.value, Some(member) if member.identity_id == *id),
+                );
+            }
+            AuthStateChange::AccessToken { .. } | AuthStateChange::AccessTokenCutoff { .. } => {}
+        }
+    }
+
+    /// Drop every entry (changes may have been missed)
+    pub fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        write(&self.members).clear();
+        write(&self.sessions).clear();
+    }
+
+    fn cached<K: Eq + Hash, T: Clone>(&self, entries: &CachedEntries<K, T>, key: &K) -> Option<T> {
+        read(entries)
+            .get(key)
+            .filter(|entry| entry.loaded_at.elapsed() < self.ttl)
+            .map(|entry| entry.value.clone())
+    }
+
+    fn store<K: Eq + Hash, T>(
+        &self,
+        entries: &CachedEntries<K, T>,
+        key: K,
+        value: T,
+        generation: u64,
+    ) {
+        self.purge_expired();
+
+        let mut entries = write(entries);
+        // Checked under the lock: invalidations take it after bumping the generation
+        if self.generation.load(Ordering::Acquire) == generation {
+            entries.insert(
+                key,
+                CachedEntry {
+                    value,
+                    loaded_at: Instant::now(),
+                },
+            );
+        }
+    }
+
+    /// Drop expired entries, at most once per TTL
+    fn purge_expired(&self) {
+        {
+            let mut last_purge = write(&self.last_purge);
+            if last_purge.elapsed() < self.ttl {
+                return;
+            }
+            *last_purge = Instant::now();
+        }
+
+        write(&self.members).retain(|_, entry| entry.loaded_at.elapsed() < self.ttl);
+        write(&self.sessions).retain(|_, entry| entry.loaded_at.elapsed() < self.ttl);
+    }
+}
+
+/// Keeps an [`AuthStateCache`] and [`TokenRevocations`] up to date with the
+/// auth state triggers
+pub struct AuthStateListener {
+    pool: PgPool,
+    listener: PgListener,
+    cache: Option<Arc<AuthStateCache>>,
+    revocations: Option<Arc<TokenRevocations>>,
+}
+
+impl AuthStateListener {
+    /// Start listening, before loading anything the notifications would change
+    pub async fn connect(pool: &PgPool) -> Result<Self, AppError> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(AUTH_STATE_CHANNEL).await?;
+
+        Ok(Self {
+            pool: pool.clone(),
+            listener,
+            cache: None,
+            revocations: None,
+        })
+    }
+
+    pub fn cache(mut self, cache: Arc<AuthStateCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn revocations(mut self, revocations: Arc<TokenRevocations>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// Apply changes until the pool is closed
+    pub async fn run(mut self) {
+        loop {
+            match self.listener.try_recv().await {
+                Ok(Some(notification)) => {
+                    match serde_json::from_str::<AuthStateChange>(notification.payload()) {
+                        Ok(change) => self.apply(&change),
+                        Err(e) => {
+                            tracing::warn!("Ignoring auth state notification: {}", e)
+                        }
+                    }
+                }
+                Ok(None) => {
+                    // Changes made while reconnecting are lost
+                    tracing::warn!("Auth state listener lost its connection, resynchronizing");
+                    self.resync().await;
+                }
+                Err(sqlx::Error::PoolClosed) => break,
+                Err(e) => {
+                    tracing::error!("Auth state listener failed: {}", e);
+                    tokio::time::sleep(LISTENER_RETRY_DELAY).await;
+                    self.resync().await;
+                }
+            }
+        }
+    }
+
+    fn apply(&self, change: &AuthStateChange) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(change);
+        }
+
+        if let Some(revocations) = &self.revocations {
+            match change {
+                AuthStateChange::AccessToken { jti, expires_at } => {
+                    revocations.revoke_token(jti, *expires_at)
+                }
+                AuthStateChange::AccessTokenCutoff { sub, not_before_ms } => {
+                    revocations.revoke_issued_before(sub, *not_before_ms)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Forget cached state and reload revocations (which must never be dropped)
+    async fn resync(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+
+        if let Some(revocations) = &self.revocations {
+            if let Err(e) = revocations.reload(&self.pool).await {
+                tracing::error!("Failed to reload access token revocations: {}", e);
+            }
+        }
+    }
+}
+
+pub(super) fn read<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub(super) fn write<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(identity_id: Uuid) -> Option<MemberState> {
+        Some(MemberState {
+            identity_id,
+            is_verified: true,
+            roles: UserRoles::default(),
+        })
+    }
+
+    #[test]
+    fn test_parse_auth_state_changes() {
+        let id = Uuid::new_v4();
+
+        assert_eq!(
+            serde_json::from_str::<AuthStateChange>(&format!(
+                r#"{{"kind": "member", "schema": "territory", "id": "{}"}}"#,
+                id
+            ))
+            .unwrap(),
+            AuthStateChange::Member {
+                schema: "territory".to_string(),
+                id
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<AuthStateChange>(&format!(
+                r#"{{"kind": "session", "id": "{}"}}"#,
+                id
+            ))
+            .unwrap(),
+            AuthStateChange::Session { id }
+        );
+        assert!(serde_json::from_str::<AuthStateChange>(r#"{"kind": "other"}"#).is_err());
+    }
+
+    #[test]
+    fn test_invalidate_drops_affected_entries() {
+        let cache = AuthStateCache::new(Duration::from_secs(60));
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let alice_identity = Uuid::new_v4();
+        let key = |id| ("territory".to_string(), id);
+
+        cache.store(&cache.members, key(alice), member(alice_identity), 0);
+        cache.store(&cache.members, key(bob), member(Uuid::new_v4()), 0);
+
+        cache.invalidate(&AuthStateChange::Identity { id: alice_identity });
+        assert!(cache.cached(&cache.members, &key(alice)).is_none());
+        assert!(cache.cached(&cache.members, &key(bob)).is_some());
+
+        cache.invalidate(&AuthStateChange::Member {
+            schema: "territory".to_string(),
+            id: bob,
+        });
+        assert!(cache.cached(&cache.members, &key(bob)).is_none());
+    }
+
+    #[test]
+    fn test_load_racing_an_invalidation_is_not_stored() {
+        let cache = AuthStateCache::new(Duration::from_secs(60));
+        let session_id = Uuid::new_v4();
+
+        let generation = cache.generation.load(Ordering::Acquire);
+        cache.invalidate(&AuthStateChange::Session { id: session_id });
+        cache.store(&cache.sessions, session_id, Some(Utc::now()), generation);
+
+        assert!(cache.cached(&cache.sessions, &session_id).is_none());
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let cache = AuthStateCache::new(Duration::ZERO);
+        let session_id = Uuid::new_v4();
+
+        cache.store(&cache.sessions, session_id, None, 0);
+
+        assert!(cache.cached(&cache.sessions, &session_id).is_none());
+    }
+}
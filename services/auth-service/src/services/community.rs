@@ -0,0 +1,103 @@
+use crate::services::DEFAULT_COMMUNITY_ROLE;
+use shared_lib::error::AppError;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// Check whether an active community exists in the territory
+pub async fn is_community_active<'e, E>(
+    executor: E,
+    schema_name: &str,
+    community_id: Uuid,
+) -> Result<bool, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let active: bool = sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM {}.communities WHERE id = $1 AND is_active = true)",
+        schema_name
+    ))
+    .bind(community_id)
+    .fetch_one(executor)
+    .await?;
+
+    Ok(active)
+}
+
+/// A user's role in a community, or `None` if they are not a member
+pub async fn community_role_of<'e, E>(
+    executor: E,
+    schema_name: &str,
+    user_id: Uuid,
+    community_id: Uuid,
+) -> Result<Option<String>, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let role: Option<Option<String>> = sqlx::query_scalar(&format!(
+        "SELECT role FROM {}.community_members WHERE user_id = $1 AND community_id = $2",
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(community_id)
+    .fetch_optional(executor)
+    .await?;
+
+    // A membership without a role is a plain membership
+    Ok(role.map(|role| role.unwrap_or_else(|| DEFAULT_COMMUNITY_ROLE.to_string())))
+}
+
+/// Add a user to a community with `role` (an existing membership is kept as is)
+pub async fn add_community_member<'e, E>(
+    executor: E,
+    schema_name: &str,
+    user_id: Uuid,
+    community_id: Uuid,
+    role: &str,
+) -> Result<(), AppError>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO {}.community_members (user_id, community_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, community_id) DO NOTHING
+        "#,
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(community_id)
+    .bind(role)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Add a user to a community, or change their role if they already are a member
+pub async fn set_community_role<'e, E>(
+    executor: E,
+    schema_name: &str,
+    user_id: Uuid,
+    community_id: Uuid,
+    role: &str,
+) -> Result<(), AppError>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO {}.community_members (user_id, community_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, community_id) DO UPDATE SET role = EXCLUDED.role
+        "#,
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(community_id)
+    .bind(role)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
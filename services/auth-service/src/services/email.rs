@@ -0,0 +1,93 @@
+use shared_lib::{error::AppError, Email, Mailer};
+use std::sync::Arc;
+
+/// Composes and sends the emails auth-service needs (verification, password reset)
+pub struct EmailService {
+    mailer: Arc<dyn Mailer>,
+    public_url: String, // Public origin of the platform, e.g. https://dk.unityplan.org
+}
+
+impl EmailService {
+    pub fn new(mailer: Arc<dyn Mailer>, public_url: &str) -> Self {
+        Self {
+            mailer,
+            public_url: public_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Send the link that verifies `to` for `username`
+    pub async fn send_email_verification(
+        &self,
+        to: &str,
+        username: &str,
+        territory_code: &str,
+        token: &str,
+    ) -> Result<(), AppError> {
+        let link = format!(
+            "{}/api/auth/verify-email/{}?territory_code={}",
+            self.public_url,
+            token,
+            territory_code.to_lowercase()
+        );
+
+        self.mailer
+            .send(Email {
+                to: to.to_string(),
+                subject: "Verify your email address".to_string(),
+                body: format!(
+                    "Hi {},\n\nPlease confirm this email address for your UnityPlan account:\n\n{}\n\nThe link is valid for 24 hours. If you did not ask for this, you can ignore this email.\n",
+                    username, link
+                ),
+            })
+            .await
+    }
+
+    /// Send a password reset link to `to` for `username`
+    pub async fn send_password_reset(
+        &self,
+        to: &str,
+        username: &str,
+        territory_code: &str,
+        token: &str,
+    ) -> Result<(), AppError> {
+        let link = format!(
+            "{}/reset-password?token={}&territory_code={}",
+            self.public_url,
+            token,
+            territory_code.to_lowercase()
+        );
+
+        self.mailer
+            .send(Email {
+                to: to.to_string(),
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Hi {},\n\nSomeone asked to reset the password of your UnityPlan account. To choose a new password, open:\n\n{}\n\nThe link is valid for 1 hour. If this was not you, you can ignore this email - your password has not been changed.\n",
+                    username, link
+                ),
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_lib::InMemoryMailer;
+
+    #[tokio::test]
+    async fn test_verification_email_links_to_endpoint() {
+        let mailer = InMemoryMailer::new();
+        let service = EmailService::new(Arc::new(mailer.clone()), "https://dk.unityplan.org/");
+
+        service
+            .send_email_verification("alice@test.dk", "alice", "DK", "evt_abc")
+            .await
+            .unwrap();
+
+        let email = mailer.last_sent_to("alice@test.dk").unwrap();
+        assert!(email
+            .body
+            .contains("https://dk.unityplan.org/api/auth/verify-email/evt_abc?territory_code=dk"));
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use shared_lib::error::AppError;
+use sqlx::{Acquire, PgPool, Postgres};
+use uuid::Uuid;
+
+/// Lifetime of an email verification token
+const EMAIL_VERIFICATION_TTL_SECS: i64 = 86400;
+
+/// Generate an email verification token (format: evt_ + 32 hex characters)
+pub fn generate_email_verification_token() -> String {
+    let random_bytes: [u8; 16] = rand::random();
+    format!("evt_{}", hex::encode(random_bytes))
+}
+
+/// Hash an email verification token for storage
+fn hash_verification_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Issue a verification token for `email`, invalidating the user's earlier ones
+///
+/// `db` is a pool, connection or transaction (e.g. the registration transaction).
+pub async fn create_email_verification_token<'a, A>(
+    db: A,
+    schema_name: &str,
+    user_id: Uuid,
+    email: &str,
+) -> Result<String, AppError>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let token = generate_email_verification_token();
+    let expires_at = Utc::now() + Duration::seconds(EMAIL_VERIFICATION_TTL_SECS);
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query(&format!(
+        "UPDATE {}.email_verification_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        schema_name
+    ))
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO {}.email_verification_tokens (user_id, email, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(email)
+    .bind(hash_verification_token(&token))
+    .bind(expires_at)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(token)
+}
+
+/// Consume a verification token: the token's address becomes the user's verified email
+///
+/// Returns the ID of the verified user.
+pub async fn verify_email_token(
+    pool: &PgPool,
+    schema_name: &str,
+    token: &str,
+) -> Result<Uuid, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let (user_id, email): (Uuid, String) = sqlx::query_as(&format!(
+        r#"
+        UPDATE {}.email_verification_tokens
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id, email
+        "#,
+        schema_name
+    ))
+    .bind(hash_verification_token(token))
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Invalid or expired verification token".to_string()))?;
+
+    // The address may have been claimed by someone else since the token was issued
+    let taken: bool = sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM {}.users WHERE email = $1 AND id <> $2)",
+        schema_name
+    ))
+    .bind(&email)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if taken {
+        return Err(AppError::Validation(
+            "Email already registered in this territory".to_string(),
+        ));
+    }
+
+    sqlx::query(&format!(
+        "UPDATE {}.users SET email = $2, is_verified = true WHERE id = $1",
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(&email)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(user_id)
+}
+
+/// Check whether a territory reserves `feature` for users with a verified email
+///
+/// Territories list such features in the `verified_email_required_for` setting.
+pub async fn is_verified_email_required(
+    pool: &PgPool,
+    schema_name: &str,
+    feature: &str,
+) -> Result<bool, AppError> {
+    let required: Option<bool> = sqlx::query_scalar(&format!(
+        "SELECT value ? $1 FROM {}.settings WHERE key = 'verified_email_required_for'",
+        schema_name
+    ))
+    .bind(feature)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(required.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_email_verification_token() {
+        let token = generate_email_verification_token();
+
+        assert!(token.starts_with("evt_"));
+        assert_eq!(token.len(), 36);
+        assert_ne!(
+            hash_verification_token(&token),
+            hash_verification_token(&generate_email_verification_token())
+        );
+    }
+}
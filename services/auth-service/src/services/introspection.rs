@@ -0,0 +1,191 @@
+use crate::{
+    models::{IntrospectionRequest, IntrospectionResponse, ServiceClient},
+    services::{is_session_active, resolve_user_roles, TokenService},
+};
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use shared_lib::{error::AppError, NatsClient, TerritoryResolver};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Queue group of the NATS introspection responders; each request is answered
+/// by one auth-service instance
+const INTROSPECTION_QUEUE_GROUP: &str = "auth-service";
+
+/// NATS subject answering introspection requests for a territory's tokens
+///
+/// Kept outside `territory.>` so JetStream territory streams never capture
+/// (and acknowledge) the requests.
+pub fn introspection_subject(territory_code: &str) -> String {
+    format!("auth.introspect.{}", territory_code.to_lowercase())
+}
+
+/// Hash a service client secret for storage (only the hash is persisted)
+pub fn hash_client_secret(client_secret: &str) -> String {
+    format!("{:x}", Sha256::digest(client_secret.as_bytes()))
+}
+
+/// Check service client credentials and record their use
+///
+/// Returns `None` for unknown or deactivated clients and wrong secrets.
+pub async fn authenticate_service_client(
+    pool: &PgPool,
+    client_id: &str,
+    client_secret: &str,
+) -> Result<Option<ServiceClient>, AppError> {
+    let client = sqlx::query_as::<_, ServiceClient>(
+        r#"
+        UPDATE global.service_clients
+        SET last_used_at = NOW()
+        WHERE client_id = $1 AND secret_hash = $2 AND is_active = true
+        RETURNING client_id, name
+        "#,
+    )
+    .bind(client_id)
+    .bind(hash_client_secret(client_secret))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(client)
+}
+
+/// Tell whether an access token is currently valid, and whose it is
+///
+/// A token is active when its signature and expiry check out, it belongs to a
+/// territory served by this pod, its user is still active and its session
+/// (if any) has not been revoked.
+pub async fn introspect_token(
+    pool: &PgPool,
+    token_service: &TokenService,
+    territories: &TerritoryResolver,
+    token: &str,
+) -> Result<IntrospectionResponse, AppError> {
+    let Ok(claims) = token_service.validate_token(token) else {
+        return Ok(IntrospectionResponse::inactive());
+    };
+    let Ok(user_id) = Uuid::parse_str(&claims.user_id) else {
+        return Ok(IntrospectionResponse::inactive());
+    };
+    let Ok(session_id) = claims.sid.as_deref().map(Uuid::parse_str).transpose() else {
+        return Ok(IntrospectionResponse::inactive());
+    };
+    let Ok(schema_name) = territories.schema_for(&claims.territory_code) else {
+        return Ok(IntrospectionResponse::inactive());
+    };
+
+    let identity: Option<(Uuid, String)> = sqlx::query_as(&format!(
+        r#"
+        SELECT ui.id, u.username
+        FROM global.user_identities ui
+        JOIN {}.users u ON u.id = ui.territory_user_id
+        WHERE ui.territory_code = $1 AND ui.territory_user_id = $2
+          AND ui.public_key_hash = $3 AND u.is_active = true
+        "#,
+        schema_name
+    ))
+    .bind(&claims.territory_code)
+    .bind(user_id)
+    .bind(&claims.sub)
+    .fetch_optional(pool)
+    .await?;
+    let Some((identity_id, username)) = identity else {
+        return Ok(IntrospectionResponse::inactive());
+    };
+
+    // Honour remote logout and session revocation
+    if let Some(session_id) = session_id {
+        if !is_session_active(pool, session_id).await? {
+            return Ok(IntrospectionResponse::inactive());
+        }
+    }
+
+    let roles = resolve_user_roles(pool, identity_id, &claims.territory_code).await?;
+
+    Ok(IntrospectionResponse {
+        active: true,
+        token_type: Some("access_token".to_string()),
+        sub: Some(claims.sub),
+        territory_code: Some(claims.territory_code),
+        user_id: Some(user_id),
+        username: Some(username),
+        roles: Some(roles.roles),
+        sid: session_id,
+        mfa_verified: Some(claims.amr.iter().any(|method| method == "otp")),
+        exp: Some(claims.exp),
+        iat: Some(claims.iat),
+    })
+}
+
+/// Answer one NATS introspection request (JSON `{"token": "..."}`)
+///
+/// Malformed requests and lookup failures are answered as inactive.
+pub async fn handle_introspection_message(
+    pool: &PgPool,
+    token_service: &TokenService,
+    territories: &TerritoryResolver,
+    payload: &[u8],
+) -> IntrospectionResponse {
+    let Ok(request) = serde_json::from_slice::<IntrospectionRequest>(payload) else {
+        return IntrospectionResponse::inactive();
+    };
+
+    introspect_token(pool, token_service, territories, &request.token)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to introspect token: {}", e);
+            IntrospectionResponse::inactive()
+        })
+}
+
+/// Answer introspection requests for every territory served by this pod
+///
+/// Who may send requests is governed by NATS subject permissions, not by
+/// service client credentials.
+pub async fn serve_introspection(
+    nats: NatsClient,
+    pool: PgPool,
+    token_service: std::sync::Arc<TokenService>,
+    territories: std::sync::Arc<TerritoryResolver>,
+) -> Result<(), AppError> {
+    let mut subscriptions = Vec::new();
+    for territory_code in territories.territories() {
+        let subject = introspection_subject(territory_code);
+        subscriptions.push(
+            nats.queue_subscribe(&subject, INTROSPECTION_QUEUE_GROUP)
+                .await?,
+        );
+        tracing::info!("Answering token introspection on {}", subject);
+    }
+
+    let mut requests = futures_util::stream::select_all(subscriptions);
+    while let Some(message) = requests.next().await {
+        let Some(reply) = message.reply else {
+            continue;
+        };
+
+        let response =
+            handle_introspection_message(&pool, &token_service, &territories, &message.payload)
+                .await;
+        if let Err(e) = nats.publish(&reply, serde_json::to_vec(&response)?).await {
+            tracing::warn!("Failed to answer introspection request: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_introspection_subject_is_outside_territory_streams() {
+        assert_eq!(introspection_subject("DK"), "auth.introspect.dk");
+    }
+
+    #[test]
+    fn test_inactive_response_only_says_inactive() {
+        let json = serde_json::to_value(IntrospectionResponse::inactive()).unwrap();
+        assert_eq!(json, serde_json::json!({ "active": false }));
+    }
+}
@@ -1,6 +1,10 @@
-use crate::models::invitation::{InvitationToken, InvitationUse};
+use crate::{
+    models::invitation::{BatchRecipient, InvitationToken, InvitationUse},
+    services::TokenService,
+    utils::ClientInfo,
+};
 use shared_lib::error::AppError;
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 /// Generate a cryptographically secure invitation token
@@ -15,6 +19,38 @@ pub fn generate_invitation_token() -> String {
     format!("inv_{}", hex_string)
 }
 
+/// Check whether a presented invitation token is a signed (JWT) token
+pub fn is_signed_invitation_token(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+/// Resolve a presented invitation token to the stored token
+///
+/// Legacy tokens (`inv_...`) are stored as presented. Signed tokens are
+/// checked offline first - signature, expiry and territory - and stand for
+/// the stored token in their `nonce`, so use tracking stays with the row.
+pub fn resolve_invitation_token(
+    token_service: &TokenService,
+    token: &str,
+    territory_code: &str,
+) -> Result<String, AppError> {
+    if !is_signed_invitation_token(token) {
+        return Ok(token.to_string());
+    }
+
+    let claims = token_service
+        .verify_signed_invitation(token)
+        .map_err(|_| AppError::Validation("Invalid or expired invitation token".to_string()))?;
+
+    if !claims.territory_code.eq_ignore_ascii_case(territory_code) {
+        return Err(AppError::Validation(
+            "This invitation token belongs to another territory".to_string(),
+        ));
+    }
+
+    Ok(claims.nonce)
+}
+
 /// Validate an invitation token without consuming it
 ///
 /// This checks:
@@ -22,35 +58,83 @@ pub fn generate_invitation_token() -> String {
 /// 2. Token has not expired
 /// 3. Token has available uses (used_count < max_uses)
 /// 4. For single_use tokens: email matches (if provided)
+/// 5. For username-targeted tokens: username matches (if provided)
 ///
 /// Returns the token if valid, error otherwise
-pub async fn validate_invitation_token(
-    pool: &PgPool,
+pub async fn validate_invitation_token<'e, E>(
+    executor: E,
+    schema_name: &str,
+    token: &str,
+    email: Option<&str>,
+    username: Option<&str>,
+) -> Result<InvitationToken, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let token_record = fetch_invitation_token(executor, schema_name, token, false).await?;
+
+    check_invitation_token(&token_record, email, username)?;
+
+    Ok(token_record)
+}
+
+/// Validate an invitation token and lock it for the rest of the transaction
+///
+/// Same checks as [`validate_invitation_token`], but the row stays locked
+/// (`FOR UPDATE`) until the transaction ends, so concurrent registrations
+/// cannot both take the last use of a token.
+pub async fn lock_invitation_token(
+    tx: &mut Transaction<'_, Postgres>,
     schema_name: &str,
     token: &str,
     email: Option<&str>,
+    username: Option<&str>,
 ) -> Result<InvitationToken, AppError> {
+    let token_record = fetch_invitation_token(&mut **tx, schema_name, token, true).await?;
+
+    check_invitation_token(&token_record, email, username)?;
+
+    Ok(token_record)
+}
+
+async fn fetch_invitation_token<'e, E>(
+    executor: E,
+    schema_name: &str,
+    token: &str,
+    for_update: bool,
+) -> Result<InvitationToken, AppError>
+where
+    E: PgExecutor<'e>,
+{
     // Query token from territory schema
     let query = format!(
         r#"
         SELECT 
             id, token, token_type, created_by_user_id,
             invited_email, invited_username, community_id, role,
-            max_uses, current_uses,
+            max_uses, current_uses, batch_id,
             expires_at, is_active,
             created_at, updated_at
         FROM {}.invitation_tokens
         WHERE token = $1
+        {}
         "#,
-        schema_name
+        schema_name,
+        if for_update { "FOR UPDATE" } else { "" }
     );
 
-    let token_record = sqlx::query_as::<_, InvitationToken>(&query)
+    sqlx::query_as::<_, InvitationToken>(&query)
         .bind(token)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await?
-        .ok_or_else(|| AppError::Validation("Invalid invitation token".to_string()))?;
+        .ok_or_else(|| AppError::Validation("Invalid invitation token".to_string()))
+}
 
+fn check_invitation_token(
+    token_record: &InvitationToken,
+    email: Option<&str>,
+    username: Option<&str>,
+) -> Result<(), AppError> {
     // Check if token is active
     if !token_record.is_active {
         return Err(AppError::Validation(
@@ -89,7 +173,16 @@ pub async fn validate_invitation_token(
         }
     }
 
-    Ok(token_record)
+    // For username-targeted tokens, verify username matches if provided
+    if let (Some(token_username), Some(username)) = (&token_record.invited_username, username) {
+        if !token_username.eq_ignore_ascii_case(username) {
+            return Err(AppError::Validation(
+                "This invitation token is for a different user".to_string(),
+            ));
+        }
+    }
+
+    Ok(())
 }
 
 /// Mark an invitation token as used
@@ -99,13 +192,14 @@ pub async fn validate_invitation_token(
 /// 2. Creates an audit record in invitation_uses
 /// 3. Deactivates the token if max_uses is reached
 ///
-/// Must be called within a transaction along with user creation
+/// Must run in the transaction that created the user and locked the token
+/// (see [`lock_invitation_token`]).
 pub async fn use_invitation_token(
-    pool: &PgPool,
+    tx: &mut Transaction<'_, Postgres>,
     schema_name: &str,
     token_id: Uuid,
     user_id: Uuid,
-    ip_address: Option<String>,
+    client: &ClientInfo,
 ) -> Result<(), AppError> {
     // Increment current_uses
     let update_query = format!(
@@ -126,15 +220,16 @@ pub async fn use_invitation_token(
 
     sqlx::query(&update_query)
         .bind(token_id)
-        .execute(pool)
+        .execute(&mut **tx)
         .await
         .map_err(|e| AppError::Internal(format!("Failed to update invitation token: {}", e)))?;
 
     // Create audit record
     let audit_query = format!(
         r#"
-        INSERT INTO {}.invitation_uses (id, token_id, used_by_user_id, used_at, ip_address)
-        VALUES ($1, $2, $3, CURRENT_TIMESTAMP, $4::inet)
+        INSERT INTO {}.invitation_uses
+            (id, token_id, used_by_user_id, used_at, ip_address, user_agent)
+        VALUES ($1, $2, $3, CURRENT_TIMESTAMP, $4::inet, $5)
         "#,
         schema_name
     );
@@ -143,8 +238,9 @@ pub async fn use_invitation_token(
         .bind(Uuid::new_v4())
         .bind(token_id)
         .bind(user_id)
-        .bind(ip_address)
-        .execute(pool)
+        .bind(&client.ip_address)
+        .bind(&client.user_agent)
+        .execute(&mut **tx)
         .await;
 
     match audit_result {
@@ -174,16 +270,24 @@ pub async fn use_invitation_token(
 ///
 /// This generates a new token and stores it in the database
 /// Returns the created token with all fields populated
-pub async fn create_invitation_token(
-    pool: &PgPool,
+#[allow(clippy::too_many_arguments)]
+pub async fn create_invitation_token<'e, E>(
+    executor: E,
     schema_name: &str,
     token_type: &str,
     email: Option<String>,
+    username: Option<String>, // Username-targeted invitation
     max_uses: i32,
     expires_in_days: Option<i64>,
     _purpose: Option<String>, // Deprecated - kept for API compatibility
     created_by: Option<Uuid>, // None for bootstrap tokens
-) -> Result<InvitationToken, AppError> {
+    community_id: Option<Uuid>,
+    role: Option<&str>, // Community role (default: member)
+    batch_id: Option<Uuid>,
+) -> Result<InvitationToken, AppError>
+where
+    E: PgExecutor<'e>,
+{
     // Generate token
     let token = generate_invitation_token();
     let id = Uuid::new_v4();
@@ -198,12 +302,13 @@ pub async fn create_invitation_token(
     let insert_query = format!(
         r#"
         INSERT INTO {}.invitation_tokens 
-            (id, token, token_type, invited_email, invited_username, max_uses, current_uses, expires_at, is_active, created_by_user_id)
-        VALUES ($1, $2, $3, $4, NULL, $5, 0, $6, true, $7)
+            (id, token, token_type, invited_email, invited_username, max_uses, current_uses, expires_at, is_active, created_by_user_id,
+             community_id, role, batch_id)
+        VALUES ($1, $2, $3, $4, $10, $5, 0, $6, true, $7, $8, COALESCE($9, 'member'), $11)
         RETURNING 
             id, token, token_type, created_by_user_id,
             invited_email, invited_username, community_id, role,
-            max_uses, current_uses,
+            max_uses, current_uses, batch_id,
             expires_at, is_active,
             created_at, updated_at
         "#,
@@ -218,13 +323,148 @@ pub async fn create_invitation_token(
         .bind(Some(max_uses))
         .bind(expires_at)
         .bind(created_by)
-        .fetch_one(pool)
+        .bind(community_id)
+        .bind(role)
+        .bind(username)
+        .bind(batch_id)
+        .fetch_one(executor)
         .await
         .map_err(|e| AppError::Internal(format!("Failed to create invitation token: {}", e)))?;
 
     Ok(created_token)
 }
 
+/// Make sure a creator stays within the territory's invitation quota
+///
+/// The `invitation_quota_per_creator` setting caps how many usable (active,
+/// unexpired, not used up) invitations one user may have at a time. The
+/// check locks the creator for the rest of the transaction, so concurrent
+/// requests cannot both squeeze under the quota.
+pub async fn check_invitation_quota(
+    tx: &mut Transaction<'_, Postgres>,
+    schema_name: &str,
+    created_by: Uuid,
+    requested: i64,
+) -> Result<(), AppError> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1::text, 0))")
+        .bind(created_by)
+        .execute(&mut **tx)
+        .await?;
+
+    let quota: Option<i64> = sqlx::query_scalar(&format!(
+        "SELECT (value #>> '{{}}')::bigint FROM {}.settings WHERE key = 'invitation_quota_per_creator'",
+        schema_name
+    ))
+    .fetch_optional(&mut **tx)
+    .await?
+    .flatten();
+
+    let Some(quota) = quota else {
+        return Ok(());
+    };
+
+    let in_use: i64 = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COUNT(*) FROM {}.invitation_tokens
+        WHERE created_by_user_id = $1
+          AND is_active
+          AND (expires_at IS NULL OR expires_at > NOW())
+          AND (max_uses IS NULL OR current_uses < max_uses)
+        "#,
+        schema_name
+    ))
+    .bind(created_by)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if in_use + requested > quota {
+        return Err(AppError::Forbidden(format!(
+            "Invitation quota exceeded: {} of {} invitations in use",
+            in_use, quota
+        )));
+    }
+
+    Ok(())
+}
+
+/// Create a batch of single-use invitation tokens, one per recipient
+///
+/// All tokens are created in one transaction and share a new `batch_id`.
+pub async fn create_invitation_batch(
+    pool: &PgPool,
+    schema_name: &str,
+    created_by: Uuid,
+    recipients: Vec<BatchRecipient>,
+    expires_in_days: Option<i64>,
+    community_id: Option<Uuid>,
+    role: Option<&str>,
+) -> Result<(Uuid, Vec<InvitationToken>), AppError> {
+    let batch_id = Uuid::new_v4();
+    let mut tx = pool.begin().await?;
+
+    check_invitation_quota(&mut tx, schema_name, created_by, recipients.len() as i64).await?;
+
+    let mut tokens = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let token = create_invitation_token(
+            &mut *tx,
+            schema_name,
+            "single_use",
+            recipient.email,
+            recipient.username,
+            1,
+            expires_in_days,
+            None,
+            Some(created_by),
+            community_id,
+            role,
+            Some(batch_id),
+        )
+        .await?;
+        tokens.push(token);
+    }
+
+    tx.commit().await?;
+
+    Ok((batch_id, tokens))
+}
+
+/// List the invitation tokens of a batch created by a specific user
+pub async fn list_batch_invitations(
+    pool: &PgPool,
+    schema_name: &str,
+    batch_id: Uuid,
+    user_id: Uuid,
+) -> Result<Vec<InvitationToken>, AppError> {
+    let query = format!(
+        r#"
+        SELECT 
+            id, token, token_type, created_by_user_id,
+            invited_email, invited_username, community_id, role,
+            max_uses, current_uses, batch_id,
+            expires_at, is_active,
+            created_at, updated_at
+        FROM {}.invitation_tokens
+        WHERE batch_id = $1 AND created_by_user_id = $2
+        ORDER BY created_at, id
+        "#,
+        schema_name
+    );
+
+    let tokens = sqlx::query_as::<_, InvitationToken>(&query)
+        .bind(batch_id)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to fetch invitation batch: {}", e)))?;
+
+    if tokens.is_empty() {
+        return Err(AppError::NotFound("Invitation batch not found".to_string()));
+    }
+
+    Ok(tokens)
+}
+
 /// List invitation tokens created by a specific user
 ///
 /// Returns all tokens (active and inactive) created by the user
@@ -239,7 +479,7 @@ pub async fn list_user_invitations(
         SELECT 
             id, token, token_type, created_by_user_id,
             invited_email, invited_username, community_id, role,
-            max_uses, current_uses,
+            max_uses, current_uses, batch_id,
             expires_at, is_active,
             created_at, updated_at
         FROM {}.invitation_tokens
@@ -258,6 +498,36 @@ pub async fn list_user_invitations(
     Ok(tokens)
 }
 
+/// Get an invitation token created by a specific user
+pub async fn get_user_invitation(
+    pool: &PgPool,
+    schema_name: &str,
+    token_id: Uuid,
+    user_id: Uuid,
+) -> Result<InvitationToken, AppError> {
+    let query = format!(
+        r#"
+        SELECT 
+            id, token, token_type, created_by_user_id,
+            invited_email, invited_username, community_id, role,
+            max_uses, current_uses, batch_id,
+            expires_at, is_active,
+            created_at, updated_at
+        FROM {}.invitation_tokens
+        WHERE id = $1 AND created_by_user_id = $2
+        "#,
+        schema_name
+    );
+
+    sqlx::query_as::<_, InvitationToken>(&query)
+        .bind(token_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to fetch invitation token: {}", e)))?
+        .ok_or_else(|| AppError::NotFound("Invitation token not found".to_string()))
+}
+
 /// Revoke an invitation token
 ///
 /// Deactivates the token so it cannot be used for new registrations
@@ -297,6 +567,50 @@ pub async fn revoke_invitation_token(
     Ok(())
 }
 
+/// Revoke every token of an invitation batch
+///
+/// Returns the number of tokens that were still active
+pub async fn revoke_invitation_batch(
+    pool: &PgPool,
+    schema_name: &str,
+    batch_id: Uuid,
+    user_id: Uuid,
+) -> Result<u64, AppError> {
+    let query = format!(
+        r#"
+        WITH batch AS (
+            SELECT id, is_active FROM {schema}.invitation_tokens
+            WHERE batch_id = $1 AND created_by_user_id = $2
+            FOR UPDATE
+        ), revoked AS (
+            UPDATE {schema}.invitation_tokens t
+            SET 
+                is_active = false,
+                updated_at = CURRENT_TIMESTAMP
+            FROM batch
+            WHERE t.id = batch.id AND batch.is_active
+        )
+        SELECT COUNT(*), COUNT(*) FILTER (WHERE is_active) FROM batch
+        "#,
+        schema = schema_name
+    );
+
+    let (total, revoked): (i64, i64) = sqlx::query_as(&query)
+        .bind(batch_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to revoke invitation batch: {}", e)))?;
+
+    if total == 0 {
+        return Err(AppError::NotFound(
+            "Invitation batch not found or you don't have permission to revoke it".to_string(),
+        ));
+    }
+
+    Ok(revoked as u64)
+}
+
 /// Get usage statistics for an invitation token
 ///
 /// Returns list of users who used this token
@@ -0,0 +1,170 @@
+use crate::models::invitation::InvitationToken;
+use qrcode::{render::svg, Color, QrCode};
+use shared_lib::error::AppError;
+
+/// Size of one QR module in PNG output, in pixels
+const PNG_MODULE_SIZE: usize = 8;
+
+/// Light modules around the code, as required by the QR specification
+const QUIET_ZONE: usize = 4;
+
+/// Renders invitations for hand-out at physical events: QR codes and
+/// printable flyers that open the registration page with the token filled in
+pub struct InvitationCardService {
+    public_url: String, // Public origin of the platform, e.g. https://dk.unityplan.org
+}
+
+impl InvitationCardService {
+    pub fn new(public_url: &str) -> Self {
+        Self {
+            public_url: public_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Registration deep link for an invitation token
+    pub fn registration_link(&self, territory_code: &str, token: &str) -> String {
+        format!(
+            "{}/register?territory_code={}&invitation_token={}",
+            self.public_url,
+            territory_code.to_lowercase(),
+            token
+        )
+    }
+
+    /// QR code of the registration link as an SVG document
+    pub fn qr_svg(&self, territory_code: &str, token: &str) -> Result<String, AppError> {
+        let code = encode(&self.registration_link(territory_code, token))?;
+
+        Ok(code
+            .render::<svg::Color>()
+            .min_dimensions(256, 256)
+            .quiet_zone(true)
+            .build())
+    }
+
+    /// QR code of the registration link as a grayscale PNG
+    pub fn qr_png(&self, territory_code: &str, token: &str) -> Result<Vec<u8>, AppError> {
+        let code = encode(&self.registration_link(territory_code, token))?;
+
+        let modules = code.width();
+        let colors = code.to_colors();
+        let size = (modules + 2 * QUIET_ZONE) * PNG_MODULE_SIZE;
+
+        let mut pixels = vec![0xFF_u8; size * size];
+        for (index, color) in colors.iter().enumerate() {
+            if *color != Color::Dark {
+                continue;
+            }
+            let left = (index % modules + QUIET_ZONE) * PNG_MODULE_SIZE;
+            let top = (index / modules + QUIET_ZONE) * PNG_MODULE_SIZE;
+            for y in top..top + PNG_MODULE_SIZE {
+                pixels[y * size + left..y * size + left + PNG_MODULE_SIZE].fill(0);
+            }
+        }
+
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, size as u32, size as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .map_err(|e| AppError::Internal(format!("Failed to encode QR code: {}", e)))?;
+
+        Ok(png_data)
+    }
+
+    /// Printable A5 flyer (SVG) with the QR code, the link and the raw token
+    /// for people who would rather type it in
+    ///
+    /// `link_token` goes into the link and QR code (e.g. the signed token).
+    pub fn flyer_svg(
+        &self,
+        territory_code: &str,
+        invitation: &InvitationToken,
+        link_token: &str,
+    ) -> Result<String, AppError> {
+        let link = self.registration_link(territory_code, link_token);
+        let code = encode(&link)?;
+
+        // One unit per module, scaled into a 90mm box; the nested document
+        // must not carry its own XML declaration
+        let modules = code.width() + 2 * QUIET_ZONE;
+        let qr = format!(
+            r#"<svg x="29" y="52" width="90" height="90" viewBox="0 0 {size} {size}">{code}</svg>"#,
+            size = modules,
+            code = code
+                .render::<svg::Color>()
+                .module_dimensions(1, 1)
+                .quiet_zone(true)
+                .build()
+                .replacen(r#"<?xml version="1.0" standalone="yes"?>"#, "", 1),
+        );
+
+        let uses = match invitation.max_uses {
+            Some(1) => "Valid for one person".to_string(),
+            Some(max_uses) => format!("Valid for up to {} people", max_uses),
+            None => "Valid for any number of people".to_string(),
+        };
+        let expiry = match invitation.expires_at {
+            Some(expires_at) => format!("{} - until {}", uses, expires_at.format("%Y-%m-%d")),
+            None => uses,
+        };
+
+        Ok(format!(
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="148mm" height="210mm" viewBox="0 0 148 210" font-family="Helvetica, Arial, sans-serif" text-anchor="middle">
+<rect width="148" height="210" fill="#ffffff"/>
+<text x="74" y="28" font-size="10" font-weight="bold">You're invited to UnityPlan</text>
+<text x="74" y="40" font-size="5">Territory: {territory}</text>
+{qr}
+<text x="74" y="156" font-size="5">Scan the code with your phone to sign up</text>
+<text x="74" y="168" font-size="3">{link}</text>
+<text x="74" y="180" font-size="4">Or enter this invitation code:</text>
+<text x="74" y="187" font-size="4.5" font-family="Courier, monospace" font-weight="bold">{token}</text>
+<text x="74" y="198" font-size="3.5" fill="#555555">{expiry}</text>
+</svg>
+"##,
+            territory = escape_xml(&territory_code.to_uppercase()),
+            qr = qr,
+            link = escape_xml(&link),
+            token = escape_xml(&invitation.token),
+            expiry = escape_xml(&expiry),
+        ))
+    }
+}
+
+fn encode(data: &str) -> Result<QrCode, AppError> {
+    QrCode::new(data.as_bytes())
+        .map_err(|e| AppError::Internal(format!("Failed to generate QR code: {}", e)))
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_link_includes_territory_and_token() {
+        let cards = InvitationCardService::new("https://dk.unityplan.org/");
+
+        assert_eq!(
+            cards.registration_link("DK", "inv_abc"),
+            "https://dk.unityplan.org/register?territory_code=dk&invitation_token=inv_abc"
+        );
+    }
+
+    #[test]
+    fn test_qr_png_is_png() {
+        let cards = InvitationCardService::new("https://dk.unityplan.org");
+
+        let png_data = cards.qr_png("dk", "inv_abc").unwrap();
+        assert!(png_data.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+}
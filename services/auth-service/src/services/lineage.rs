@@ -0,0 +1,243 @@
+use crate::{
+    models::{LineageMember, LineageResponse, SubtreeDeactivation},
+    services::revoke_access_tokens_issued_before_now,
+};
+use shared_lib::{error::AppError, record_audit, AuditEntry};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// Walk the invitation tree around a user
+///
+/// Ancestors follow `invited_by_user_id` up to the first user nobody invited;
+/// descendants are everyone the user brought in, directly or through their
+/// invitees, down to `max_depth` levels (unlimited if `None`).
+pub async fn get_invitation_lineage(
+    pool: &PgPool,
+    schema_name: &str,
+    username: &str,
+    max_depth: Option<i32>,
+) -> Result<LineageResponse, AppError> {
+    let user_id = find_user_id(pool, schema_name, username).await?;
+
+    let ancestors = sqlx::query_as::<_, LineageMember>(&format!(
+        r#"
+        WITH RECURSIVE chain AS (
+            SELECT id, invited_by_user_id, 0 AS depth, ARRAY[id] AS path
+            FROM {schema}.users
+            WHERE id = $1
+            UNION ALL
+            SELECT u.id, u.invited_by_user_id, c.depth - 1, c.path || u.id
+            FROM {schema}.users u
+            JOIN chain c ON u.id = c.invited_by_user_id
+            WHERE NOT u.id = ANY(c.path)
+        )
+        SELECT
+            u.id AS user_id, u.username, inviter.username AS invited_by,
+            c.depth, u.is_active, u.created_at
+        FROM chain c
+        JOIN {schema}.users u ON u.id = c.id
+        LEFT JOIN {schema}.users inviter ON inviter.id = u.invited_by_user_id
+        WHERE c.depth < 0
+        ORDER BY c.depth DESC
+        "#,
+        schema = schema_name
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut subtree = list_subtree(pool, schema_name, user_id, max_depth).await?;
+
+    // The subtree starts with the user at depth 0
+    if subtree.is_empty() {
+        return Err(AppError::NotFound(
+            "User not found in this territory".to_string(),
+        ));
+    }
+    let user = subtree.remove(0);
+    let max_depth = subtree.iter().map(|member| member.depth).max().unwrap_or(0);
+
+    Ok(LineageResponse {
+        user,
+        ancestors,
+        descendant_count: subtree.len(),
+        descendants: subtree,
+        max_depth,
+    })
+}
+
+/// Deactivate a user and everyone below them in the invitation tree
+///
+/// In one transaction: deactivates the accounts, revokes the invitation
+/// tokens they can still hand out, ends their sessions, revokes their access
+/// tokens and records the operation in `global.audit_log`. `actor_user_id`
+/// (territory user) and `actor_identity_id` (global identity) belong to the
+/// moderator, who must not be part of the subtree.
+pub async fn deactivate_invitation_subtree(
+    pool: &PgPool,
+    schema_name: &str,
+    territory_code: &str,
+    username: &str,
+    reason: &str,
+    actor_user_id: Uuid,
+    actor_identity_id: Uuid,
+) -> Result<SubtreeDeactivation, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let root_id = find_user_id(&mut *tx, schema_name, username).await?;
+
+    let user_ids: Vec<Uuid> = list_subtree(&mut *tx, schema_name, root_id, None)
+        .await?
+        .into_iter()
+        .map(|member| member.user_id)
+        .collect();
+
+    if user_ids.contains(&actor_user_id) {
+        return Err(AppError::Validation(
+            "You cannot deactivate an invitation tree you are part of".to_string(),
+        ));
+    }
+
+    let users_deactivated = sqlx::query(&format!(
+        r#"
+        UPDATE {}.users
+        SET is_active = false, updated_at = NOW()
+        WHERE id = ANY($1) AND is_active
+        "#,
+        schema_name
+    ))
+    .bind(&user_ids)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected() as i64;
+
+    let tokens_revoked = sqlx::query(&format!(
+        r#"
+        UPDATE {}.invitation_tokens
+        SET is_active = false, updated_at = CURRENT_TIMESTAMP
+        WHERE created_by_user_id = ANY($1) AND is_active
+        "#,
+        schema_name
+    ))
+    .bind(&user_ids)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected() as i64;
+
+    let sessions_revoked: i64 = sqlx::query_scalar(
+        r#"
+        WITH revoked AS (
+            UPDATE global.sessions
+            SET revoked_at = NOW(), revoked_reason = 'lineage_deactivated'
+            WHERE revoked_at IS NULL
+              AND user_id IN (
+                  SELECT id FROM global.user_identities
+                  WHERE territory_code = $1 AND territory_user_id = ANY($2)
+              )
+            RETURNING rotated_at, expires_at
+        )
+        SELECT COUNT(*) FROM revoked WHERE rotated_at IS NULL AND expires_at > NOW()
+        "#,
+    )
+    .bind(territory_code)
+    .bind(&user_ids)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Their access tokens stop working now, not when they expire
+    let identity_ids: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT id FROM global.user_identities
+        WHERE territory_code = $1 AND territory_user_id = ANY($2)
+        "#,
+    )
+    .bind(territory_code)
+    .bind(&user_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    revoke_access_tokens_issued_before_now(&mut *tx, &identity_ids, "lineage_deactivated").await?;
+
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("lineage.deactivated")
+            .actor(actor_identity_id)
+            .territory(territory_code)
+            .resource("user", root_id)
+            .changes(serde_json::json!({
+                "reason": reason,
+                "user_ids": user_ids,
+                "users_deactivated": users_deactivated,
+                "tokens_revoked": tokens_revoked,
+                "sessions_revoked": sessions_revoked,
+            })),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(SubtreeDeactivation {
+        users_deactivated,
+        tokens_revoked,
+        sessions_revoked,
+    })
+}
+
+/// The user (depth 0) followed by their descendants, level by level
+async fn list_subtree<'e, E>(
+    executor: E,
+    schema_name: &str,
+    user_id: Uuid,
+    max_depth: Option<i32>,
+) -> Result<Vec<LineageMember>, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let members = sqlx::query_as::<_, LineageMember>(&format!(
+        r#"
+        WITH RECURSIVE tree AS (
+            SELECT id, 0 AS depth, ARRAY[id] AS path
+            FROM {schema}.users
+            WHERE id = $1
+            UNION ALL
+            SELECT u.id, t.depth + 1, t.path || u.id
+            FROM {schema}.users u
+            JOIN tree t ON u.invited_by_user_id = t.id
+            WHERE NOT u.id = ANY(t.path)
+              AND ($2::int IS NULL OR t.depth < $2)
+        )
+        SELECT
+            u.id AS user_id, u.username, inviter.username AS invited_by,
+            t.depth, u.is_active, u.created_at
+        FROM tree t
+        JOIN {schema}.users u ON u.id = t.id
+        LEFT JOIN {schema}.users inviter ON inviter.id = u.invited_by_user_id
+        ORDER BY t.depth, u.created_at, u.username
+        "#,
+        schema = schema_name
+    ))
+    .bind(user_id)
+    .bind(max_depth)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(members)
+}
+
+async fn find_user_id<'e, E>(
+    executor: E,
+    schema_name: &str,
+    username: &str,
+) -> Result<Uuid, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar(&format!(
+        "SELECT id FROM {}.users WHERE LOWER(username) = LOWER($1)",
+        schema_name
+    ))
+    .bind(username)
+    .fetch_optional(executor)
+    .await?
+    .ok_or_else(|| AppError::NotFound("User not found in this territory".to_string()))
+}
@@ -0,0 +1,351 @@
+use crate::utils::ClientInfo;
+use chrono::{DateTime, Utc};
+use shared_lib::error::AppError;
+use sqlx::PgPool;
+
+/// Failed logins allowed per username before backoff starts
+const USERNAME_FREE_ATTEMPTS: i32 = 5;
+
+/// Failed logins allowed per IP before backoff starts (IPs can be shared behind NAT)
+const IP_FREE_ATTEMPTS: i32 = 20;
+
+/// Lockout after the first failure past the free attempts; doubles with each further failure
+const BASE_LOCKOUT_SECS: i64 = 1;
+
+/// Longest lockout applied to a key
+const MAX_LOCKOUT_SECS: i64 = 900;
+
+/// Failures older than this no longer count
+const FAILURE_WINDOW_SECS: i64 = 3600;
+
+/// How often counters that no longer count are deleted (see [`prune_login_attempts`])
+pub const LOGIN_ATTEMPTS_PRUNE_INTERVAL_SECS: u64 = 600;
+
+/// Counter a failed login is recorded against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThrottleKey {
+    Username(String), // "territory:username", lowercase
+    Ip(String),
+}
+
+impl ThrottleKey {
+    fn scope(&self) -> &'static str {
+        match self {
+            ThrottleKey::Username(_) => "username",
+            ThrottleKey::Ip(_) => "ip",
+        }
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            ThrottleKey::Username(key) | ThrottleKey::Ip(key) => key,
+        }
+    }
+
+    fn free_attempts(&self) -> i32 {
+        match self {
+            ThrottleKey::Username(_) => USERNAME_FREE_ATTEMPTS,
+            ThrottleKey::Ip(_) => IP_FREE_ATTEMPTS,
+        }
+    }
+}
+
+/// Counters that apply to a login attempt for `username` from `client`
+pub fn login_throttle_keys(
+    territory_code: &str,
+    username: &str,
+    client: &ClientInfo,
+) -> Vec<ThrottleKey> {
+    let mut keys = vec![ThrottleKey::Username(format!(
+        "{}:{}",
+        territory_code.trim().to_lowercase(),
+        username.trim().to_lowercase()
+    ))];
+
+    if let Some(ip) = &client.ip_address {
+        keys.push(ThrottleKey::Ip(ip.clone()));
+    }
+
+    keys
+}
+
+/// Lockout in seconds after `failure_count` failures, if any
+fn lockout_secs(failure_count: i32, free_attempts: i32) -> Option<i64> {
+    let excess = failure_count - free_attempts;
+    if excess <= 0 {
+        return None;
+    }
+
+    // Cap the exponent well before i64 overflow
+    let lockout = BASE_LOCKOUT_SECS.saturating_mul(1 << (excess - 1).min(32));
+    Some(lockout.min(MAX_LOCKOUT_SECS))
+}
+
+/// Login attempt counted against its throttle keys before the credentials are checked
+///
+/// Counting up front means parallel guesses cannot all pass the lockout check
+/// before any of them is recorded. Finish the attempt with
+/// [`record_login_failure`] or [`clear_login_failures`].
+#[derive(Debug)]
+pub struct LoginAttempt {
+    reservations: Vec<Reservation>,
+}
+
+/// Attempt counted against one key
+#[derive(Debug)]
+struct Reservation {
+    key: ThrottleKey,
+    failure_count: i32,
+    lockout: Option<i64>, // seconds, when this attempt is past the free attempts
+    locked_until: Option<DateTime<Utc>>, // lock set while the attempt is being checked
+}
+
+/// Outcome of [`reserve_login_attempt`]
+#[derive(Debug)]
+pub enum LoginReservation {
+    Reserved(LoginAttempt),
+    Throttled { retry_after: i64 }, // seconds until a login may be attempted again
+}
+
+/// Count a login attempt against every key, unless one of them is locked out
+///
+/// The counter rows are locked while they are checked and incremented, so
+/// concurrent attempts are counted one after the other. An attempt past the
+/// free attempts locks its keys straight away; the lock is restarted when the
+/// attempt fails and lifted again when it succeeds.
+pub async fn reserve_login_attempt(
+    pool: &PgPool,
+    keys: &[ThrottleKey],
+) -> Result<LoginReservation, AppError> {
+    let mut tx = pool.begin().await?;
+
+    // Lock the rows in the order of `keys` (username first), so concurrent
+    // attempts sharing a key cannot deadlock. An existing row is locked by the
+    // upsert already, so pruning cannot delete it before it is read.
+    let mut counters = Vec::with_capacity(keys.len());
+    for key in keys {
+        sqlx::query(
+            r#"
+            INSERT INTO global.login_attempts (scope, key)
+            VALUES ($1, $2)
+            ON CONFLICT (scope, key) DO UPDATE SET scope = EXCLUDED.scope
+            "#,
+        )
+        .bind(key.scope())
+        .bind(key.key())
+        .execute(&mut *tx)
+        .await?;
+
+        let counter: (i32, Option<i64>) = sqlx::query_as(
+            r#"
+            SELECT
+                CASE
+                    WHEN last_failure_at < NOW() - make_interval(secs => $3) THEN 0
+                    ELSE failure_count
+                END,
+                CASE
+                    WHEN locked_until > NOW()
+                    THEN CEIL(EXTRACT(EPOCH FROM locked_until - NOW()))::BIGINT
+                END
+            FROM global.login_attempts
+            WHERE scope = $1 AND key = $2
+            FOR UPDATE
+            "#,
+        )
+        .bind(key.scope())
+        .bind(key.key())
+        .bind(FAILURE_WINDOW_SECS as f64)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        counters.push(counter);
+    }
+
+    if let Some(retry_after) = counters.iter().filter_map(|(_, retry)| *retry).max() {
+        tx.rollback().await?;
+        return Ok(LoginReservation::Throttled {
+            retry_after: retry_after.max(1),
+        });
+    }
+
+    let mut reservations = Vec::with_capacity(keys.len());
+    for (key, (failure_count, _)) in keys.iter().zip(counters) {
+        let failure_count = failure_count.saturating_add(1);
+        let lockout = lockout_secs(failure_count, key.free_attempts());
+
+        let locked_until: Option<DateTime<Utc>> = sqlx::query_scalar(
+            r#"
+            UPDATE global.login_attempts
+            SET failure_count = $3,
+                last_failure_at = NOW(),
+                locked_until = NOW() + make_interval(secs => $4)
+            WHERE scope = $1 AND key = $2
+            RETURNING locked_until
+            "#,
+        )
+        .bind(key.scope())
+        .bind(key.key())
+        .bind(failure_count)
+        .bind(lockout.map(|secs| secs as f64))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        reservations.push(Reservation {
+            key: key.clone(),
+            failure_count,
+            lockout,
+            locked_until,
+        });
+    }
+
+    tx.commit().await?;
+
+    Ok(LoginReservation::Reserved(LoginAttempt { reservations }))
+}
+
+/// Finish a failed attempt, restarting the lockout of keys it took past their limit
+///
+/// The failure itself was counted by [`reserve_login_attempt`]. Returns whether
+/// the failure locked out any key.
+pub async fn record_login_failure(pool: &PgPool, attempt: &LoginAttempt) -> Result<bool, AppError> {
+    let mut locked_out = false;
+    for reservation in &attempt.reservations {
+        let Some(lockout) = reservation.lockout else {
+            continue;
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE global.login_attempts
+            SET locked_until = GREATEST(locked_until, NOW() + make_interval(secs => $3))
+            WHERE scope = $1 AND key = $2
+            "#,
+        )
+        .bind(reservation.key.scope())
+        .bind(reservation.key.key())
+        .bind(lockout as f64)
+        .execute(pool)
+        .await?;
+
+        tracing::warn!(
+            "Login throttled for {} '{}' after {} failures ({}s lockout)",
+            reservation.key.scope(),
+            reservation.key.key(),
+            reservation.failure_count,
+            lockout
+        );
+        locked_out = true;
+    }
+
+    Ok(locked_out)
+}
+
+/// Finish a successful attempt: reset the username counter, release the IP one
+///
+/// IP counters are only released, so one valid account cannot be used to reset
+/// the counter of an IP that is guessing passwords for others.
+pub async fn clear_login_failures(pool: &PgPool, attempt: &LoginAttempt) -> Result<(), AppError> {
+    for reservation in &attempt.reservations {
+        match &reservation.key {
+            ThrottleKey::Username(username) => {
+                sqlx::query(
+                    "DELETE FROM global.login_attempts WHERE scope = 'username' AND key = $1",
+                )
+                .bind(username)
+                .execute(pool)
+                .await?;
+            }
+            ThrottleKey::Ip(_) => release_reservation(pool, reservation).await?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Finish an attempt that neither failed nor completed the login (e.g. the
+/// password was right but a second factor is still due) by taking it off every counter
+pub async fn release_login_attempt(pool: &PgPool, attempt: &LoginAttempt) -> Result<(), AppError> {
+    for reservation in &attempt.reservations {
+        release_reservation(pool, reservation).await?;
+    }
+
+    Ok(())
+}
+
+/// Take an attempt off its counter, with the lock it set unless a later attempt replaced it
+async fn release_reservation(pool: &PgPool, reservation: &Reservation) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        UPDATE global.login_attempts
+        SET failure_count = GREATEST(failure_count - 1, 0),
+            locked_until = CASE
+                WHEN locked_until = $3 THEN NULL
+                ELSE locked_until
+            END
+        WHERE scope = $1 AND key = $2
+        "#,
+    )
+    .bind(reservation.key.scope())
+    .bind(reservation.key.key())
+    .bind(reservation.locked_until)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete counters whose failures no longer count and whose lockout has passed
+///
+/// Such a counter would start over at the next attempt anyway. Returns the
+/// number of counters deleted.
+pub async fn prune_login_attempts(pool: &PgPool) -> Result<u64, AppError> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM global.login_attempts
+        WHERE last_failure_at < NOW() - make_interval(secs => $1)
+          AND (locked_until IS NULL OR locked_until <= NOW())
+        "#,
+    )
+    .bind(FAILURE_WINDOW_SECS as f64)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockout_grows_exponentially_and_is_capped() {
+        assert_eq!(lockout_secs(0, 5), None);
+        assert_eq!(lockout_secs(5, 5), None);
+        assert_eq!(lockout_secs(6, 5), Some(1));
+        assert_eq!(lockout_secs(7, 5), Some(2));
+        assert_eq!(lockout_secs(10, 5), Some(16));
+        assert_eq!(lockout_secs(20, 5), Some(MAX_LOCKOUT_SECS));
+        assert_eq!(lockout_secs(i32::MAX, 5), Some(MAX_LOCKOUT_SECS));
+    }
+
+    #[test]
+    fn test_login_throttle_keys() {
+        let client = ClientInfo {
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: None,
+        };
+
+        assert_eq!(
+            login_throttle_keys("DK", " Alice ", &client),
+            vec![
+                ThrottleKey::Username("dk:alice".to_string()),
+                ThrottleKey::Ip("203.0.113.7".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            login_throttle_keys("dk", "alice", &ClientInfo::default()),
+            vec![ThrottleKey::Username("dk:alice".to_string())]
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use crate::services::resolve_user_roles;
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use shared_lib::error::AppError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// TOTP time step in seconds (RFC 6238 default)
+const TOTP_STEP: i64 = 30;
+
+/// Number of digits in a TOTP code
+const TOTP_DIGITS: u32 = 6;
+
+/// Accepted clock drift, in time steps either side of the current one
+const TOTP_SKEW: i64 = 1;
+
+/// Number of backup codes issued at enrolment
+const BACKUP_CODE_COUNT: usize = 10;
+
+/// Issuer shown in authenticator apps
+const TOTP_ISSUER: &str = "UnityPlan";
+
+/// Generate a random TOTP secret (160 bits, Base32 without padding)
+pub fn generate_totp_secret() -> String {
+    let bytes: [u8; 20] = rand::random();
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// Build the `otpauth://` URI that authenticator apps import (usually via QR code)
+pub fn totp_uri(secret: &str, username: &str, territory_code: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{username}%40{territory}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = TOTP_ISSUER,
+        username = username,
+        territory = territory_code.to_lowercase(),
+        secret = secret,
+        digits = TOTP_DIGITS,
+        period = TOTP_STEP,
+    )
+}
+
+/// Compute the TOTP code for a time step (RFC 4226 dynamic truncation)
+fn totp_code(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+/// Verify a TOTP code and return the time step it matched
+///
+/// Steps at or before `last_used_step` are rejected so a code cannot be replayed
+/// within its validity window.
+pub fn verify_totp(
+    secret: &str,
+    code: &str,
+    unix_time: i64,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let current = unix_time / TOTP_STEP;
+    (current - TOTP_SKEW..=current + TOTP_SKEW)
+        .filter(|step| last_used_step < Some(*step)) // None sorts before any step
+        .find(|&step| totp_code(&secret, step) == code)
+}
+
+/// Generate one-time backup codes, formatted as `XXXXX-XXXXX`
+pub fn generate_backup_codes() -> Vec<String> {
+    (0..BACKUP_CODE_COUNT)
+        .map(|_| {
+            let bytes: [u8; 10] = rand::random();
+            let encoded = BASE32_NOPAD.encode(&bytes);
+            format!("{}-{}", &encoded[..5], &encoded[5..10])
+        })
+        .collect()
+}
+
+/// Hash a backup code for storage (case and dashes are ignored)
+pub fn hash_backup_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// Check whether a user has confirmed TOTP enrolment
+pub async fn is_mfa_enabled(
+    pool: &PgPool,
+    schema_name: &str,
+    user_id: Uuid,
+) -> Result<bool, AppError> {
+    let enabled: bool = sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM {}.user_mfa WHERE user_id = $1 AND enabled_at IS NOT NULL)",
+        schema_name
+    ))
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(enabled)
+}
+
+/// Check whether the territory requires 2FA for this user
+///
+/// Applies to users holding any role that grants permissions, whether a
+/// territory role (`global.territory_managers`) or a global one
+/// (`global.role_assignments`), once the territory opts in.
+pub async fn is_mfa_required(
+    pool: &PgPool,
+    schema_name: &str,
+    identity_id: Uuid,
+    territory_code: &str,
+) -> Result<bool, AppError> {
+    if !is_mfa_required_for_managers(pool, schema_name).await? {
+        return Ok(false);
+    }
+
+    let roles = resolve_user_roles(pool, identity_id, territory_code).await?;
+
+    Ok(roles.grants_permissions())
+}
+
+/// Check whether the territory requires 2FA for users whose roles grant
+/// permissions (the `require_mfa_for_managers` setting)
+pub async fn is_mfa_required_for_managers(
+    pool: &PgPool,
+    schema_name: &str,
+) -> Result<bool, AppError> {
+    let required: bool = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COALESCE(
+            (SELECT value = 'true'::jsonb FROM {}.settings WHERE key = 'require_mfa_for_managers'),
+            false
+        )
+        "#,
+        schema_name
+    ))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(required)
+}
+
+/// Start (or restart) TOTP enrolment and return the new secret
+///
+/// The secret is not active until [`confirm_totp_enrolment`] succeeds.
+pub async fn begin_totp_enrolment(
+    pool: &PgPool,
+    schema_name: &str,
+    user_id: Uuid,
+) -> Result<String, AppError> {
+    if is_mfa_enabled(pool, schema_name, user_id).await? {
+        return Err(AppError::Validation(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let secret = generate_totp_secret();
+
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO {}.user_mfa AS m (user_id, totp_secret)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET totp_secret = EXCLUDED.totp_secret, last_used_step = NULL
+        WHERE m.enabled_at IS NULL
+        "#,
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(&secret)
+    .execute(pool)
+    .await?;
+
+    Ok(secret)
+}
+
+/// Confirm TOTP enrolment with a code from the authenticator app
+///
+/// Enables 2FA and returns a fresh set of backup codes (shown to the user once).
+pub async fn confirm_totp_enrolment(
+    pool: &PgPool,
+    schema_name: &str,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Vec<String>, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let (secret, enabled): (String, bool) = sqlx::query_as(&format!(
+        "SELECT totp_secret, enabled_at IS NOT NULL FROM {}.user_mfa WHERE user_id = $1 FOR UPDATE",
+        schema_name
+    ))
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::Validation("No two-factor enrolment in progress".to_string()))?;
+
+    if enabled {
+        return Err(AppError::Validation(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let step = verify_totp(&secret, code, Utc::now().timestamp(), None)
+        .ok_or_else(|| AppError::Validation("Invalid authentication code".to_string()))?;
+
+    sqlx::query(&format!(
+        "UPDATE {}.user_mfa SET enabled_at = NOW(), last_used_step = $2 WHERE user_id = $1",
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(step)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(&format!(
+        "DELETE FROM {}.user_mfa_backup_codes WHERE user_id = $1",
+        schema_name
+    ))
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let backup_codes = generate_backup_codes();
+    for backup_code in &backup_codes {
+        sqlx::query(&format!(
+            "INSERT INTO {}.user_mfa_backup_codes (user_id, code_hash) VALUES ($1, $2)",
+            schema_name
+        ))
+        .bind(user_id)
+        .bind(hash_backup_code(backup_code))
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(backup_codes)
+}
+
+/// Verify a second factor: a TOTP code, or an unused backup code (consumed)
+pub async fn verify_mfa_code(
+    pool: &PgPool,
+    schema_name: &str,
+    user_id: Uuid,
+    code: &str,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    // Lock the authenticator so a code can only be accepted once
+    let (secret, last_used_step): (String, Option<i64>) = sqlx::query_as(&format!(
+        r#"
+        SELECT totp_secret, last_used_step FROM {}.user_mfa
+        WHERE user_id = $1 AND enabled_at IS NOT NULL
+        FOR UPDATE
+        "#,
+        schema_name
+    ))
+    .bind(user_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| {
+        AppError::Unauthorized("Two-factor authentication is not enabled".to_string())
+    })?;
+
+    if let Some(step) = verify_totp(&secret, code, Utc::now().timestamp(), last_used_step) {
+        sqlx::query(&format!(
+            "UPDATE {}.user_mfa SET last_used_step = $2 WHERE user_id = $1",
+            schema_name
+        ))
+        .bind(user_id)
+        .bind(step)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        return Ok(());
+    }
+
+    let used_backup_code = sqlx::query(&format!(
+        r#"
+        UPDATE {}.user_mfa_backup_codes
+        SET used_at = NOW()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(hash_backup_code(code))
+    .execute(&mut *tx)
+    .await?;
+
+    if used_backup_code.rows_affected() == 0 {
+        return Err(AppError::Unauthorized(
+            "Invalid authentication code".to_string(),
+        ));
+    }
+
+    tx.commit().await?;
+
+    tracing::info!("Backup code used for user {}", user_id);
+
+    Ok(())
+}
+
+/// Disable 2FA and delete the authenticator and backup codes
+pub async fn disable_totp(pool: &PgPool, schema_name: &str, user_id: Uuid) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(&format!(
+        "DELETE FROM {}.user_mfa_backup_codes WHERE user_id = $1",
+        schema_name
+    ))
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(&format!(
+        "DELETE FROM {}.user_mfa WHERE user_id = $1",
+        schema_name
+    ))
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 Appendix B test secret ("12345678901234567890")
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_totp_rfc6238_vectors() {
+        let secret = BASE32_NOPAD.decode(RFC_SECRET.as_bytes()).unwrap();
+
+        // RFC vectors are 8 digits; a 6-digit code is the last 6 of them
+        assert_eq!(totp_code(&secret, 59 / TOTP_STEP), 287082);
+        assert_eq!(totp_code(&secret, 1111111109 / TOTP_STEP), 81804);
+        assert_eq!(totp_code(&secret, 1234567890 / TOTP_STEP), 5924);
+        assert_eq!(totp_code(&secret, 2000000000 / TOTP_STEP), 279037);
+    }
+
+    #[test]
+    fn test_verify_totp_accepts_clock_skew() {
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59, None), Some(1));
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59 + 30, None), Some(1));
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59 + 90, None), None);
+    }
+
+    #[test]
+    fn test_verify_totp_rejects_replay_and_garbage() {
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59, Some(1)), None);
+        assert_eq!(verify_totp(RFC_SECRET, "28708", 59, None), None);
+        assert_eq!(verify_totp(RFC_SECRET, "2870a2", 59, None), None);
+        assert_eq!(verify_totp("not base32!", "287082", 59, None), None);
+    }
+
+    #[test]
+    fn test_generate_totp_secret() {
+        let secret = generate_totp_secret();
+
+        assert_eq!(secret.len(), 32);
+        assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), 20);
+        assert_ne!(secret, generate_totp_secret());
+    }
+
+    #[test]
+    fn test_totp_uri() {
+        let uri = totp_uri("ABC", "alice", "DK");
+
+        assert!(uri.starts_with("otpauth://totp/UnityPlan:alice%40dk?"));
+        assert!(uri.contains("secret=ABC"));
+        assert!(uri.contains("issuer=UnityPlan"));
+    }
+
+    #[test]
+    fn test_backup_codes() {
+        let codes = generate_backup_codes();
+
+        assert_eq!(codes.len(), BACKUP_CODE_COUNT);
+        assert!(codes.iter().all(|c| c.len() == 11 && &c[5..6] == "-"));
+        assert_eq!(
+            hash_backup_code(&codes[0]),
+            hash_backup_code(&codes[0].replace('-', "").to_lowercase())
+        );
+        assert_ne!(hash_backup_code(&codes[0]), hash_backup_code(&codes[1]));
+    }
+}
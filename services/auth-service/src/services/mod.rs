@@ -1,7 +1,39 @@
+pub mod audit;
+pub mod auth_state;
+pub mod community;
+pub mod email;
+pub mod email_verification;
+pub mod introspection;
 pub mod invitation;
+pub mod invitation_card;
+pub mod lineage;
+pub mod login_throttle;
+pub mod mfa;
+pub mod oidc;
 pub mod password;
+pub mod personal_access_token;
+pub mod rbac;
+pub mod recovery;
+pub mod session;
 pub mod token;
+pub mod token_revocation;
 
+pub use audit::*;
+pub use auth_state::*;
+pub use community::*;
+pub use email::*;
+pub use email_verification::*;
+pub use introspection::*;
 pub use invitation::*;
+pub use invitation_card::*;
+pub use lineage::*;
+pub use login_throttle::*;
+pub use mfa::*;
+pub use oidc::*;
 pub use password::*;
+pub use personal_access_token::*;
+pub use rbac::*;
+pub use recovery::*;
+pub use session::*;
 pub use token::*;
+pub use token_revocation::*;
@@ -0,0 +1,626 @@
+use crate::{
+    models::{
+        AuthorizationDecision, AuthorizationRequest, AuthorizationResponse, ConsentClient,
+        ConsentScreen, IdTokenClaims, OidcClient, OidcError, ProviderMetadata, ScopeDescription,
+        TokenRequest, TokenResponse, UserInfo,
+    },
+    services::{hash_client_secret, is_session_active, TokenService},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use shared_lib::{error::AppError, TerritoryResolver};
+use sqlx::{FromRow, PgPool};
+use url::Url;
+use uuid::Uuid;
+
+/// Lifetime of an authorization code
+const AUTHORIZATION_CODE_TTL_SECS: i64 = 60;
+
+/// Scopes relying parties may request, with their consent screen description
+pub const SUPPORTED_SCOPES: &[(&str, &str)] = &[
+    ("openid", "Sign you in with your UnityPlan account"),
+    (
+        "profile",
+        "Your username, and your name and picture if your profile is public",
+    ),
+    ("email", "Your email address"),
+];
+
+/// Claims the userinfo endpoint may return
+const SUPPORTED_CLAIMS: &[&str] = &[
+    "sub",
+    "preferred_username",
+    "name",
+    "nickname",
+    "picture",
+    "website",
+    "email",
+    "email_verified",
+];
+
+/// Issuer and public endpoints of the OpenID provider
+///
+/// Authorization happens in the front-end: its authorize page reads the
+/// request, shows the consent screen (GET /api/auth/oidc/authorize) and sends
+/// the browser back to the client (POST /api/auth/oidc/authorize).
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    issuer: String,                 // e.g. https://dk.unityplan.org/api/auth
+    authorization_endpoint: String, // Front-end authorize page
+}
+
+impl OidcProvider {
+    pub fn new(issuer: &str, authorization_endpoint: &str) -> Self {
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            authorization_endpoint: authorization_endpoint.to_string(),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Discovery document, served at `{issuer}/.well-known/openid-configuration`
+    pub fn metadata(&self, token_service: &TokenService) -> ProviderMetadata {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+
+        ProviderMetadata {
+            issuer: self.issuer.clone(),
+            authorization_endpoint: self.authorization_endpoint.clone(),
+            token_endpoint: format!("{}/oidc/token", self.issuer),
+            userinfo_endpoint: format!("{}/oidc/userinfo", self.issuer),
+            jwks_uri: format!("{}/.well-known/jwks.json", self.issuer),
+            response_types_supported: strings(&["code"]),
+            grant_types_supported: strings(&["authorization_code"]),
+            subject_types_supported: strings(&["public"]),
+            id_token_signing_alg_values_supported: vec![format!(
+                "{:?}",
+                token_service.signing_algorithm()
+            )],
+            scopes_supported: SUPPORTED_SCOPES
+                .iter()
+                .map(|(scope, _)| scope.to_string())
+                .collect(),
+            claims_supported: strings(SUPPORTED_CLAIMS),
+            token_endpoint_auth_methods_supported: strings(&[
+                "client_secret_basic",
+                "client_secret_post",
+                "none",
+            ]),
+            code_challenge_methods_supported: strings(&["S256"]),
+        }
+    }
+}
+
+/// Generate an authorization code (format: oac_ + 64 hex characters)
+fn generate_authorization_code() -> String {
+    let random_bytes: [u8; 32] = rand::random();
+    format!("oac_{}", hex::encode(random_bytes))
+}
+
+/// Hash an authorization code for storage
+fn hash_authorization_code(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+/// Check a PKCE code verifier against its S256 challenge (RFC 7636 4.6)
+pub fn verify_pkce(code_verifier: &str, code_challenge: &str) -> bool {
+    (43..=128).contains(&code_verifier.len())
+        && URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())) == code_challenge
+}
+
+/// `redirect_uri` with `params` added to its query
+fn redirect_with(redirect_uri: &str, params: &[(&str, Option<&str>)]) -> String {
+    let Ok(mut url) = Url::parse(redirect_uri) else {
+        return redirect_uri.to_string();
+    };
+    {
+        let mut query = url.query_pairs_mut();
+        for (name, value) in params {
+            if let Some(value) = value {
+                query.append_pair(name, value);
+            }
+        }
+    }
+    url.to_string()
+}
+
+/// An error the client learns about through its redirect URI
+fn redirected_error(
+    request: &AuthorizationRequest,
+    error: &str,
+    error_description: &str,
+) -> OidcError {
+    OidcError {
+        redirect_to: Some(redirect_with(
+            &request.redirect_uri,
+            &[
+                ("error", Some(error)),
+                ("error_description", Some(error_description)),
+                ("state", request.state.as_deref()),
+            ],
+        )),
+        ..OidcError::new(error, error_description)
+    }
+}
+
+/// Load an active relying party
+async fn find_client(pool: &PgPool, client_id: &str) -> Result<Option<OidcClient>, AppError> {
+    let client = sqlx::query_as::<_, OidcClient>(
+        r#"
+        SELECT client_id, territory_code, name, secret_hash, redirect_uris, allowed_scopes
+        FROM global.oidc_clients
+        WHERE client_id = $1 AND is_active = true
+        "#,
+    )
+    .bind(client_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(client)
+}
+
+/// Check an authorization request from a member of `territory_code`
+///
+/// Returns the client and the scopes it gets: the supported scopes it asked
+/// for and is allowed, in a stable order. Unknown client IDs and redirect URIs
+/// are never redirected to.
+pub async fn validate_authorization_request(
+    pool: &PgPool,
+    territory_code: &str,
+    request: &AuthorizationRequest,
+) -> Result<(OidcClient, Vec<String>), OidcError> {
+    let Some(client) = find_client(pool, &request.client_id).await? else {
+        return Err(OidcError::new("invalid_client", "Unknown client"));
+    };
+    if !client.redirect_uris.contains(&request.redirect_uri) {
+        return Err(OidcError::new(
+            "invalid_request",
+            "redirect_uri is not registered for this client",
+        ));
+    }
+
+    if request.response_type != "code" {
+        return Err(redirected_error(
+            request,
+            "unsupported_response_type",
+            "Only the authorization code flow is supported",
+        ));
+    }
+    if !client.territory_code.eq_ignore_ascii_case(territory_code) {
+        return Err(redirected_error(
+            request,
+            "access_denied",
+            "The client is registered in another territory",
+        ));
+    }
+    if request.code_challenge.as_deref().unwrap_or("").is_empty()
+        || request.code_challenge_method.as_deref() != Some("S256")
+    {
+        return Err(redirected_error(
+            request,
+            "invalid_request",
+            "PKCE with code_challenge_method S256 is required",
+        ));
+    }
+
+    let requested: Vec<&str> = request.scope.split_whitespace().collect();
+    if !requested.contains(&"openid") {
+        return Err(redirected_error(
+            request,
+            "invalid_scope",
+            "The openid scope is required",
+        ));
+    }
+    let scopes = SUPPORTED_SCOPES
+        .iter()
+        .map(|(scope, _)| *scope)
+        .filter(|scope| requested.contains(scope))
+        .filter(|scope| client.allowed_scopes.iter().any(|allowed| allowed == scope))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if scopes.is_empty() {
+        return Err(redirected_error(
+            request,
+            "invalid_scope",
+            "The client may not request the openid scope",
+        ));
+    }
+
+    Ok((client, scopes))
+}
+
+/// Describe an authorization request for the consent screen
+///
+/// `consent_required` is false when the member already agreed to share these
+/// scopes with the client.
+pub async fn consent_screen(
+    pool: &PgPool,
+    identity_id: Uuid,
+    territory_code: &str,
+    request: &AuthorizationRequest,
+) -> Result<ConsentScreen, OidcError> {
+    let (client, scopes) = validate_authorization_request(pool, territory_code, request).await?;
+
+    let consented: Option<Vec<String>> = sqlx::query_scalar(
+        "SELECT scopes FROM global.oidc_consents WHERE user_id = $1 AND client_id = $2",
+    )
+    .bind(identity_id)
+    .bind(&client.client_id)
+    .fetch_optional(pool)
+    .await?;
+    let consent_required = match consented {
+        Some(consented) => !scopes.iter().all(|scope| consented.contains(scope)),
+        None => true,
+    };
+
+    Ok(ConsentScreen {
+        client: ConsentClient {
+            client_id: client.client_id,
+            name: client.name,
+        },
+        scopes: SUPPORTED_SCOPES
+            .iter()
+            .filter(|(scope, _)| scopes.iter().any(|granted| granted == scope))
+            .map(|(scope, description)| ScopeDescription {
+                scope: scope.to_string(),
+                description: description.to_string(),
+            })
+            .collect(),
+        consent_required,
+    })
+}
+
+/// Record the member's decision and issue an authorization code
+///
+/// Approving remembers the consent and redirects with a single-use code bound
+/// to the PKCE challenge; declining redirects with `access_denied`.
+pub async fn complete_authorization(
+    pool: &PgPool,
+    identity_id: Uuid,
+    territory_code: &str,
+    session_id: Option<Uuid>,
+    mfa_verified: bool,
+    decision: &AuthorizationDecision,
+) -> Result<AuthorizationResponse, OidcError> {
+    let request = &decision.request;
+    let (client, scopes) = validate_authorization_request(pool, territory_code, request).await?;
+
+    if !decision.approved {
+        let error = redirected_error(request, "access_denied", "The member declined");
+        return Ok(AuthorizationResponse {
+            redirect_to: error.redirect_to.unwrap_or_default(),
+        });
+    }
+
+    let code = generate_authorization_code();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.oidc_consents (user_id, client_id, scopes)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, client_id) DO UPDATE
+        SET scopes = ARRAY(
+                SELECT DISTINCT unnest(global.oidc_consents.scopes || EXCLUDED.scopes)
+            ),
+            granted_at = NOW()
+        "#,
+    )
+    .bind(identity_id)
+    .bind(&client.client_id)
+    .bind(&scopes)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.oidc_authorization_codes
+            (code_hash, client_id, user_id, session_id, redirect_uri, scopes, nonce,
+             code_challenge, mfa_verified, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+    )
+    .bind(hash_authorization_code(&code))
+    .bind(&client.client_id)
+    .bind(identity_id)
+    .bind(session_id)
+    .bind(&request.redirect_uri)
+    .bind(&scopes)
+    .bind(&request.nonce)
+    .bind(&request.code_challenge)
+    .bind(mfa_verified)
+    .bind(Utc::now() + Duration::seconds(AUTHORIZATION_CODE_TTL_SECS))
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(AuthorizationResponse {
+        redirect_to: redirect_with(
+            &request.redirect_uri,
+            &[("code", Some(&code)), ("state", request.state.as_deref())],
+        ),
+    })
+}
+
+/// Row of `global.oidc_authorization_codes`, as redeemed
+#[derive(Debug, FromRow)]
+struct AuthorizationCode {
+    client_id: String,
+    user_id: Uuid, // global.user_identities.id
+    session_id: Option<Uuid>,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    nonce: Option<String>,
+    code_challenge: String,
+    mfa_verified: bool,
+}
+
+/// Authenticate the client of a token request
+///
+/// `basic` holds HTTP Basic credentials, if sent. Public clients (registered
+/// without a secret) only name themselves and rely on PKCE.
+async fn authenticate_client(
+    pool: &PgPool,
+    basic: Option<(String, String)>,
+    request: &TokenRequest,
+) -> Result<OidcClient, OidcError> {
+    let (client_id, client_secret) = match basic {
+        Some((client_id, client_secret)) => (Some(client_id), Some(client_secret)),
+        None => (request.client_id.clone(), request.client_secret.clone()),
+    };
+    let invalid_client = || OidcError::new("invalid_client", "Client authentication failed");
+
+    let client_id = client_id.ok_or_else(invalid_client)?;
+    let client = find_client(pool, &client_id)
+        .await?
+        .ok_or_else(invalid_client)?;
+
+    if let Some(secret_hash) = &client.secret_hash {
+        match client_secret {
+            Some(secret) if hash_client_secret(&secret) == *secret_hash => {}
+            _ => return Err(invalid_client()),
+        }
+    }
+
+    Ok(client)
+}
+
+/// Exchange an authorization code for an access token and an ID token
+///
+/// The code is spent by the first attempt, successful or not. Both tokens
+/// carry the member's access token claims; the access token is only good for
+/// the userinfo endpoint.
+pub async fn exchange_authorization_code(
+    pool: &PgPool,
+    token_service: &TokenService,
+    territories: &TerritoryResolver,
+    provider: &OidcProvider,
+    basic: Option<(String, String)>,
+    request: &TokenRequest,
+) -> Result<TokenResponse, OidcError> {
+    if request.grant_type != "authorization_code" {
+        return Err(OidcError::new(
+            "unsupported_grant_type",
+            "Only authorization_code is supported",
+        ));
+    }
+
+    let client = authenticate_client(pool, basic, request).await?;
+
+    let invalid_grant = |description: &str| OidcError::new("invalid_grant", description);
+
+    let code = sqlx::query_as::<_, AuthorizationCode>(
+        r#"
+        UPDATE global.oidc_authorization_codes
+        SET used_at = NOW()
+        WHERE code_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING client_id, user_id, session_id, redirect_uri, scopes, nonce,
+                  code_challenge, mfa_verified
+        "#,
+    )
+    .bind(hash_authorization_code(&request.code))
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| invalid_grant("Unknown, expired or used authorization code"))?;
+
+    if code.client_id != client.client_id {
+        return Err(invalid_grant("Code was issued to another client"));
+    }
+    if code.redirect_uri != request.redirect_uri {
+        return Err(invalid_grant(
+            "redirect_uri does not match the authorization request",
+        ));
+    }
+    if !verify_pkce(&request.code_verifier, &code.code_challenge) {
+        return Err(invalid_grant("PKCE verification failed"));
+    }
+
+    let schema_name = territories
+        .schema_for(&client.territory_code)
+        .map_err(|_| invalid_grant("Territory not served by this pod"))?;
+    let member: Option<(String, String, Uuid, String)> = sqlx::query_as(&format!(
+        r#"
+        SELECT ui.public_key_hash, ui.territory_code, u.id, u.username
+        FROM global.user_identities ui
+        JOIN {}.users u ON u.id = ui.territory_user_id
+        WHERE ui.id = $1 AND u.is_active = true
+        "#,
+        schema_name
+    ))
+    .bind(code.user_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((public_key_hash, territory_code, user_id, username)) = member else {
+        return Err(invalid_grant("Member is no longer active"));
+    };
+
+    if let Some(session_id) = code.session_id {
+        if !is_session_active(pool, session_id).await? {
+            return Err(invalid_grant("The member's session has ended"));
+        }
+    }
+
+    let claims = token_service.access_claims(
+        &public_key_hash,
+        &territory_code,
+        user_id,
+        &username,
+        code.session_id,
+        code.mfa_verified,
+    );
+    let scope = code.scopes.join(" ");
+    let id_token = token_service
+        .sign_id_token(&IdTokenClaims::from_claims(
+            &claims,
+            provider.issuer(),
+            &client.client_id,
+            code.nonce,
+        ))
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let access_token = token_service
+        .sign_client_access_token(claims, &client.client_id, &scope)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: token_service.get_access_token_ttl(),
+        id_token,
+        scope,
+    })
+}
+
+/// Member data behind a userinfo response
+#[derive(Debug, FromRow)]
+struct UserInfoRow {
+    username: String,
+    email: Option<String>,
+    is_verified: bool,
+    full_name: Option<String>,
+    display_name: Option<String>,
+    avatar_url: Option<String>,
+    website_url: Option<String>,
+    profile_visibility: String,
+    show_real_name: bool,
+}
+
+/// Claims about the member a client access token was issued for
+///
+/// Returns `None` for invalid tokens, deactivated clients and members, and
+/// ended sessions. `profile` claims beyond the username are only released for
+/// public profiles, and the real name only when the member shows it;
+/// `email` is released with the `email` scope the member consented to.
+pub async fn userinfo(
+    pool: &PgPool,
+    token_service: &TokenService,
+    territories: &TerritoryResolver,
+    token: &str,
+) -> Result<Option<UserInfo>, AppError> {
+    let Ok(access) = token_service.validate_client_access_token(token) else {
+        return Ok(None);
+    };
+    let scopes: Vec<&str> = access.scope.split_whitespace().collect();
+    if !scopes.contains(&"openid") || find_client(pool, &access.client_id).await?.is_none() {
+        return Ok(None);
+    }
+
+    let claims = access.claims;
+    let Ok(user_id) = Uuid::parse_str(&claims.user_id) else {
+        return Ok(None);
+    };
+    let Ok(session_id) = claims.sid.as_deref().map(Uuid::parse_str).transpose() else {
+        return Ok(None);
+    };
+    let Ok(schema_name) = territories.schema_for(&claims.territory_code) else {
+        return Ok(None);
+    };
+
+    let row = sqlx::query_as::<_, UserInfoRow>(&format!(
+        r#"
+        SELECT u.username, u.email, u.is_verified, u.full_name, u.display_name,
+               u.avatar_url, p.website_url,
+               COALESCE(u.profile_visibility, 'public') AS profile_visibility,
+               COALESCE(p.show_real_name, true) AS show_real_name
+        FROM global.user_identities ui
+        JOIN {schema}.users u ON u.id = ui.territory_user_id
+        LEFT JOIN {schema}.user_profiles p ON p.user_id = u.id
+        WHERE ui.territory_code = $1 AND ui.territory_user_id = $2
+          AND ui.public_key_hash = $3 AND u.is_active = true
+        "#,
+        schema = schema_name
+    ))
+    .bind(&claims.territory_code)
+    .bind(user_id)
+    .bind(&claims.sub)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    if let Some(session_id) = session_id {
+        if !is_session_active(pool, session_id).await? {
+            return Ok(None);
+        }
+    }
+
+    let mut info = UserInfo {
+        sub: claims.sub,
+        ..Default::default()
+    };
+    if scopes.contains(&"profile") {
+        info.preferred_username = Some(row.username);
+        if row.profile_visibility == "public" {
+            info.name = row.full_name.filter(|_| row.show_real_name);
+            info.nickname = row.display_name;
+            info.picture = row.avatar_url;
+            info.website = row.website_url;
+        }
+    }
+    if scopes.contains(&"email") {
+        if let Some(email) = row.email {
+            info.email = Some(email);
+            info.email_verified = Some(row.is_verified);
+        }
+    }
+
+    Ok(Some(info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_pkce_s256() {
+        // RFC 7636 Appendix B
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let challenge = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+        assert!(verify_pkce(verifier, challenge));
+        assert!(!verify_pkce(&verifier.replace('d', "e"), challenge));
+        assert!(!verify_pkce("too-short", "too-short"));
+    }
+
+    #[test]
+    fn test_redirect_keeps_existing_query() {
+        assert_eq!(
+            redirect_with(
+                "https://git.example.org/callback?provider=unityplan",
+                &[("code", Some("oac_1")), ("state", None)],
+            ),
+            "https://git.example.org/callback?provider=unityplan&code=oac_1"
+        );
+        assert_eq!(
+            redirect_with(
+                "https://chat.example.org/cb",
+                &[("error", Some("access_denied")), ("state", Some("a b"))],
+            ),
+            "https://chat.example.org/cb?error=access_denied&state=a+b"
+        );
+    }
+}
@@ -3,6 +3,10 @@ use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
+use std::sync::OnceLock;
+
+/// Hash verified when a login names an unknown user, so it costs the same as a wrong password
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
 
 /// Password hashing service using Argon2
 pub struct PasswordService;
@@ -31,6 +35,18 @@ impl PasswordService {
             .verify_password(password.as_bytes(), &parsed_hash)
             .is_ok())
     }
+
+    /// Spend the time of a password verification without a real hash
+    ///
+    /// Used when the user does not exist, so response timing does not reveal
+    /// which usernames are registered.
+    pub fn verify_dummy(password: &str) {
+        let hash = DUMMY_HASH.get_or_init(|| {
+            Self::hash_password("unityplan-dummy-password").expect("Failed to hash dummy password")
+        });
+
+        let _ = Self::verify_password(password, hash);
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,408 @@
+use crate::{models::RoleGrant, services::community_role_of};
+use shared_lib::{error::AppError, record_audit, AuditEntry};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Permission names checked by handlers and [`crate::middleware::RequirePermission`]
+pub mod permissions {
+    /// Create multi-use (group) invitations
+    pub const INVITATIONS_CREATE_GROUP: &str = "invitations.create_group";
+    /// Grant and revoke territory roles
+    pub const ROLES_MANAGE: &str = "roles.manage";
+    /// Create invitations that add newcomers to a community
+    pub const COMMUNITIES_INVITE: &str = "communities.invite";
+    /// Hand out community roles other than `member` (e.g. through invitations)
+    pub const COMMUNITIES_MANAGE_ROLES: &str = "communities.manage_roles";
+    /// Inspect invitation lineage, deactivate invitation subtrees and sign users out
+    pub const USERS_MODERATE: &str = "users.moderate";
+    /// Read the audit log of the territory (every territory for platform admins)
+    pub const AUDIT_READ: &str = "audit.read";
+}
+
+/// Territory roles (`global.territory_managers.role`) and the permissions they grant
+pub const TERRITORY_ROLES: &[(&str, &[&str])] = &[
+    (
+        "territory_admin",
+        &[
+            permissions::INVITATIONS_CREATE_GROUP,
+            permissions::ROLES_MANAGE,
+            permissions::COMMUNITIES_INVITE,
+            permissions::COMMUNITIES_MANAGE_ROLES,
+            permissions::USERS_MODERATE,
+            permissions::AUDIT_READ,
+        ],
+    ),
+    (
+        "moderator",
+        &[
+            permissions::INVITATIONS_CREATE_GROUP,
+            permissions::USERS_MODERATE,
+        ],
+    ),
+];
+
+/// Community roles (`territory.community_members.role`) and the permissions
+/// they grant within that community, from lowest to highest rank
+pub const COMMUNITY_ROLES: &[(&str, &[&str])] = &[
+    (DEFAULT_COMMUNITY_ROLE, &[]),
+    ("organizer", &[permissions::COMMUNITIES_INVITE]),
+    (
+        "admin",
+        &[
+            permissions::COMMUNITIES_INVITE,
+            permissions::COMMUNITIES_MANAGE_ROLES,
+        ],
+    ),
+];
+
+/// Role given to community members unless stated otherwise
+pub const DEFAULT_COMMUNITY_ROLE: &str = "member";
+
+/// Global role (`global.role_assignments.role`) that holds every permission
+pub const PLATFORM_ADMIN_ROLE: &str = "platform_admin";
+
+const ALL_PERMISSIONS: &str = "*";
+
+/// Roles held by a user and the permissions they resolve to
+#[derive(Debug, Clone, Default)]
+pub struct UserRoles {
+    pub roles: Vec<String>,
+    permissions: HashSet<String>,
+}
+
+impl UserRoles {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.contains(ALL_PERMISSIONS) || self.permissions.contains(permission)
+    }
+
+    /// Check whether any of the roles grants a permission
+    pub fn grants_permissions(&self) -> bool {
+        !self.permissions.is_empty()
+    }
+
+    pub fn is_platform_admin(&self) -> bool {
+        self.roles.iter().any(|role| role == PLATFORM_ADMIN_ROLE)
+    }
+
+    fn add_territory_role(&mut self, role: &str) {
+        if let Some((_, granted)) = TERRITORY_ROLES.iter().find(|(name, _)| *name == role) {
+            self.permissions
+                .extend(granted.iter().map(|permission| permission.to_string()));
+        }
+        self.roles.push(role.to_string());
+    }
+
+    /// Global roles grant the permissions listed in their `permissions` JSONB,
+    /// either as an array of names or as an object of `"name": true` entries
+    fn add_global_role(&mut self, role: &str, granted: &serde_json::Value) {
+        if role == PLATFORM_ADMIN_ROLE {
+            self.permissions.insert(ALL_PERMISSIONS.to_string());
+        }
+
+        match granted {
+            serde_json::Value::Array(names) => self.permissions.extend(
+                names
+                    .iter()
+                    .filter_map(|name| name.as_str())
+                    .map(str::to_string),
+            ),
+            serde_json::Value::Object(entries) => self.permissions.extend(
+                entries
+                    .iter()
+                    .filter(|(_, enabled)| enabled.as_bool() == Some(true))
+                    .map(|(name, _)| name.clone()),
+            ),
+            _ => {}
+        }
+        self.roles.push(role.to_string());
+    }
+}
+
+/// Check whether `role` is a known territory role
+pub fn is_territory_role(role: &str) -> bool {
+    TERRITORY_ROLES.iter().any(|(name, _)| *name == role)
+}
+
+/// Check whether `role` is a known community role
+pub fn is_community_role(role: &str) -> bool {
+    COMMUNITY_ROLES.iter().any(|(name, _)| *name == role)
+}
+
+/// Rank of a community role (higher outranks lower), `None` for unknown roles
+pub fn community_role_rank(role: &str) -> Option<usize> {
+    COMMUNITY_ROLES.iter().position(|(name, _)| *name == role)
+}
+
+/// Check whether a user holds `permission` in a community
+///
+/// Territory-wide and global roles (`roles`) apply to every community; otherwise
+/// the user's role in `territory.community_members` decides.
+pub async fn has_community_permission(
+    pool: &PgPool,
+    schema_name: &str,
+    roles: &UserRoles,
+    user_id: Uuid,
+    community_id: Uuid,
+    permission: &str,
+) -> Result<bool, AppError> {
+    if roles.has_permission(permission) {
+        return Ok(true);
+    }
+
+    let community_role = community_role_of(pool, schema_name, user_id, community_id).await?;
+
+    Ok(community_role
+        .and_then(|role| COMMUNITY_ROLES.iter().find(|(name, _)| *name == role))
+        .is_some_and(|(_, granted)| granted.contains(&permission)))
+}
+
+/// Resolve a user's roles in `territory_code` plus their global roles
+///
+/// `identity_id` is the global identity ID (`global.user_identities.id`).
+pub async fn resolve_user_roles(
+    pool: &PgPool,
+    identity_id: Uuid,
+    territory_code: &str,
+) -> Result<UserRoles, AppError> {
+    let assignments: Vec<(String, bool, serde_json::Value)> = sqlx::query_as(
+        r#"
+        SELECT role, false AS is_global, '{}'::jsonb AS permissions
+        FROM global.territory_managers
+        WHERE user_id = $1 AND territory_code = $2
+        UNION ALL
+        SELECT role, true AS is_global, COALESCE(permissions, '{}'::jsonb)
+        FROM global.role_assignments
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(identity_id)
+    .bind(territory_code)
+    .fetch_all(pool)
+    .await?;
+
+    let mut roles = UserRoles::default();
+    for (role, is_global, permissions) in assignments {
+        if is_global {
+            roles.add_global_role(&role, &permissions);
+        } else {
+            roles.add_territory_role(&role);
+        }
+    }
+
+    Ok(roles)
+}
+
+/// List the territory roles granted in a territory
+pub async fn list_territory_roles(
+    pool: &PgPool,
+    territory_code: &str,
+) -> Result<Vec<RoleGrant>, AppError> {
+    let grants = sqlx::query_as::<_, RoleGrant>(
+        r#"
+        SELECT
+            ui.username, tm.role, tm.granted_at, granter.username AS granted_by
+        FROM global.territory_managers tm
+        JOIN global.user_identities ui ON ui.id = tm.user_id
+        LEFT JOIN global.user_identities granter ON granter.id = tm.granted_by
+        WHERE tm.territory_code = $1
+        ORDER BY ui.username, tm.role
+        "#,
+    )
+    .bind(territory_code)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(grants)
+}
+
+/// Grant a territory role to a user of that territory
+///
+/// `granted_by` is the global identity ID of the granting admin.
+pub async fn grant_territory_role(
+    pool: &PgPool,
+    territory_code: &str,
+    username: &str,
+    role: &str,
+    granted_by: Uuid,
+) -> Result<RoleGrant, AppError> {
+    if !is_territory_role(role) {
+        return Err(AppError::Validation(format!("Unknown role: {}", role)));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let identity_id = find_territory_identity(&mut tx, territory_code, username).await?;
+
+    let grant = sqlx::query_as::<_, RoleGrant>(
+        r#"
+        WITH granted AS (
+            INSERT INTO global.territory_managers (user_id, territory_code, role, granted_by)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, territory_code, role) DO NOTHING
+            RETURNING role, granted_at
+        )
+        SELECT
+            (SELECT username FROM global.user_identities WHERE id = $1) AS username,
+            role, granted_at,
+            (SELECT username FROM global.user_identities WHERE id = $4) AS granted_by
+        FROM granted
+        "#,
+    )
+    .bind(identity_id)
+    .bind(territory_code)
+    .bind(role)
+    .bind(granted_by)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::Validation(format!("User already has the {} role", role)))?;
+
+    record_role_change(
+        &mut tx,
+        granted_by,
+        territory_code,
+        "role.granted",
+        identity_id,
+        role,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(grant)
+}
+
+/// Revoke a territory role from a user of that territory
+pub async fn revoke_territory_role(
+    pool: &PgPool,
+    territory_code: &str,
+    username: &str,
+    role: &str,
+    revoked_by: Uuid,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    let identity_id = find_territory_identity(&mut tx, territory_code, username).await?;
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM global.territory_managers
+        WHERE user_id = $1 AND territory_code = $2 AND role = $3
+        "#,
+    )
+    .bind(identity_id)
+    .bind(territory_code)
+    .bind(role)
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Role assignment not found".to_string()));
+    }
+
+    record_role_change(
+        &mut tx,
+        revoked_by,
+        territory_code,
+        "role.revoked",
+        identity_id,
+        role,
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn find_territory_identity(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    territory_code: &str,
+    username: &str,
+) -> Result<Uuid, AppError> {
+    sqlx::query_scalar(
+        r#"
+        SELECT id FROM global.user_identities
+        WHERE LOWER(username) = LOWER($1) AND territory_code = $2
+        "#,
+    )
+    .bind(username)
+    .bind(territory_code)
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("User not found in this territory".to_string()))
+}
+
+async fn record_role_change(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    actor_id: Uuid,
+    territory_code: &str,
+    action: &str,
+    identity_id: Uuid,
+    role: &str,
+) -> Result<(), AppError> {
+    record_audit(
+        &mut **tx,
+        &AuditEntry::new(action)
+            .actor(actor_id)
+            .territory(territory_code)
+            .resource("user_identity", identity_id)
+            .changes(serde_json::json!({ "role": role })),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_territory_roles_grant_permissions() {
+        let mut roles = UserRoles::default();
+        assert!(!roles.has_permission(permissions::INVITATIONS_CREATE_GROUP));
+
+        roles.add_territory_role("moderator");
+        assert!(roles.has_permission(permissions::INVITATIONS_CREATE_GROUP));
+        assert!(!roles.has_permission(permissions::ROLES_MANAGE));
+
+        // Unknown roles are kept but grant nothing
+        roles.add_territory_role("legacy_role");
+        assert_eq!(roles.roles, vec!["moderator", "legacy_role"]);
+        assert!(!roles.has_permission(permissions::ROLES_MANAGE));
+    }
+
+    #[test]
+    fn test_community_roles() {
+        assert!(is_community_role(DEFAULT_COMMUNITY_ROLE));
+        assert!(is_community_role("organizer"));
+        assert!(!is_community_role("territory_admin"));
+    }
+
+    #[test]
+    fn test_community_role_rank() {
+        assert!(community_role_rank("admin") > community_role_rank("organizer"));
+        assert!(community_role_rank("organizer") > community_role_rank(DEFAULT_COMMUNITY_ROLE));
+        assert_eq!(community_role_rank("owner"), None);
+    }
+
+    #[test]
+    fn test_global_role_permissions() {
+        let mut roles = UserRoles::default();
+        roles.add_global_role(
+            "devops",
+            &serde_json::json!({ "roles.manage": true, "invitations.create_group": false }),
+        );
+        assert!(roles.has_permission(permissions::ROLES_MANAGE));
+        assert!(!roles.has_permission(permissions::INVITATIONS_CREATE_GROUP));
+
+        let mut roles = UserRoles::default();
+        roles.add_global_role("support", &serde_json::json!(["invitations.create_group"]));
+        assert!(roles.has_permission(permissions::INVITATIONS_CREATE_GROUP));
+
+        let mut roles = UserRoles::default();
+        roles.add_global_role(PLATFORM_ADMIN_ROLE, &serde_json::json!({}));
+        assert!(roles.has_permission("anything.at_all"));
+    }
+}
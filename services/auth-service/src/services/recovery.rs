@@ -0,0 +1,221 @@
+use crate::services::{generate_backup_codes, hash_backup_code};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use shared_lib::error::AppError;
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Lifetime of an emailed password reset token
+const PASSWORD_RESET_TTL_SECS: i64 = 3600;
+
+/// Generate a password reset token (format: pwr_ + 32 hex characters)
+pub fn generate_password_reset_token() -> String {
+    let random_bytes: [u8; 16] = rand::random();
+    format!("pwr_{}", hex::encode(random_bytes))
+}
+
+/// Hash a password reset token for storage
+fn hash_reset_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Issue a fresh set of recovery codes, replacing any previous ones
+///
+/// Recovery codes use the same format and hashing as 2FA backup codes.
+pub async fn issue_recovery_codes<'a, A>(
+    db: A,
+    schema_name: &str,
+    user_id: Uuid,
+) -> Result<Vec<String>, AppError>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    sqlx::query(&format!(
+        "DELETE FROM {}.user_recovery_codes WHERE user_id = $1",
+        schema_name
+    ))
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let recovery_codes = generate_backup_codes();
+    for code in &recovery_codes {
+        sqlx::query(&format!(
+            "INSERT INTO {}.user_recovery_codes (user_id, code_hash) VALUES ($1, $2)",
+            schema_name
+        ))
+        .bind(user_id)
+        .bind(hash_backup_code(code))
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(recovery_codes)
+}
+
+/// Number of unused recovery codes a user has left
+pub async fn remaining_recovery_codes(
+    pool: &PgPool,
+    schema_name: &str,
+    user_id: Uuid,
+) -> Result<i64, AppError> {
+    let remaining: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM {}.user_recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+        schema_name
+    ))
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(remaining)
+}
+
+/// Replace a user's password hash, in the caller's transaction
+///
+/// The caller revokes the user's sessions and access tokens in the same
+/// transaction, so the new password never takes effect without them.
+pub async fn update_password(
+    tx: &mut Transaction<'_, Postgres>,
+    schema_name: &str,
+    user_id: Uuid,
+    password_hash: &str,
+) -> Result<(), AppError> {
+    set_password_hash(tx, schema_name, user_id, password_hash).await
+}
+
+/// Consume a recovery code and set a new password, in the caller's transaction
+pub async fn recover_with_code(
+    tx: &mut Transaction<'_, Postgres>,
+    schema_name: &str,
+    user_id: Uuid,
+    recovery_code: &str,
+    password_hash: &str,
+) -> Result<(), AppError> {
+    let consumed = sqlx::query(&format!(
+        r#"
+        UPDATE {}.user_recovery_codes
+        SET used_at = NOW()
+        WHERE id = (
+            SELECT id FROM {}.user_recovery_codes
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            LIMIT 1
+            FOR UPDATE
+        )
+        "#,
+        schema_name, schema_name
+    ))
+    .bind(user_id)
+    .bind(hash_backup_code(recovery_code))
+    .execute(&mut **tx)
+    .await?;
+
+    if consumed.rows_affected() == 0 {
+        return Err(AppError::Unauthorized("Invalid recovery code".to_string()));
+    }
+
+    set_password_hash(tx, schema_name, user_id, password_hash).await
+}
+
+/// Issue a password reset token for a user, invalidating earlier ones
+///
+/// Only the hash is stored; the returned token is meant for the user's inbox.
+pub async fn create_password_reset_token(
+    pool: &PgPool,
+    schema_name: &str,
+    user_id: Uuid,
+) -> Result<String, AppError> {
+    let token = generate_password_reset_token();
+    let expires_at = Utc::now() + Duration::seconds(PASSWORD_RESET_TTL_SECS);
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(&format!(
+        "UPDATE {}.password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        schema_name
+    ))
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(&format!(
+        "INSERT INTO {}.password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(hash_reset_token(&token))
+    .bind(expires_at)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(token)
+}
+
+/// Consume a password reset token and set a new password, in the caller's transaction
+///
+/// Returns the ID of the user whose password was reset.
+pub async fn reset_password_with_token(
+    tx: &mut Transaction<'_, Postgres>,
+    schema_name: &str,
+    token: &str,
+    password_hash: &str,
+) -> Result<Uuid, AppError> {
+    let user_id: Uuid = sqlx::query_scalar(&format!(
+        r#"
+        UPDATE {}.password_reset_tokens
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+        schema_name
+    ))
+    .bind(hash_reset_token(token))
+    .fetch_optional(&mut **tx)
+    .await?
+    .ok_or_else(|| AppError::Unauthorized("Invalid or expired reset token".to_string()))?;
+
+    set_password_hash(tx, schema_name, user_id, password_hash).await?;
+
+    Ok(user_id)
+}
+
+async fn set_password_hash(
+    tx: &mut Transaction<'_, Postgres>,
+    schema_name: &str,
+    user_id: Uuid,
+    password_hash: &str,
+) -> Result<(), AppError> {
+    let result = sqlx::query(&format!(
+        "UPDATE {}.users SET password_hash = $2 WHERE id = $1 AND is_active = true",
+        schema_name
+    ))
+    .bind(user_id)
+    .bind(password_hash)
+    .execute(&mut **tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("User not found or inactive".to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_password_reset_token() {
+        let token = generate_password_reset_token();
+
+        assert!(token.starts_with("pwr_"));
+        assert_eq!(token.len(), 36);
+        assert_ne!(token, generate_password_reset_token());
+    }
+}
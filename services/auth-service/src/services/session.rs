@@ -0,0 +1,540 @@
+use crate::{
+    models::{ForcedLogout, SessionInfo},
+    services::{
+        revoke_access_tokens_issued_before_now, revoke_all_personal_access_tokens, TokenService,
+    },
+    utils::ClientInfo,
+};
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use shared_lib::{error::AppError, record_audit, AuditEntry};
+use sqlx::{FromRow, PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Hash a refresh token for storage (only the hash is persisted)
+pub fn hash_refresh_token(refresh_token: &str) -> String {
+    format!("{:x}", Sha256::digest(refresh_token.as_bytes()))
+}
+
+/// Session started by [`create_session`]
+#[derive(Debug)]
+pub struct NewSession {
+    pub session_id: Uuid, // Session family ID
+    pub refresh_token: String,
+}
+
+/// Start a new session family for a user and return its refresh token
+///
+/// `user_id` is the global identity ID (`global.user_identities.id`).
+pub async fn create_session<'e, E>(
+    executor: E,
+    token_service: &TokenService,
+    user_id: Uuid,
+    client: &ClientInfo,
+    mfa_verified: bool,
+) -> Result<NewSession, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let session_id = Uuid::new_v4();
+    let refresh_token = token_service.generate_refresh_token();
+    let now = Utc::now();
+    let family_expires_at = now + Duration::seconds(token_service.get_refresh_family_ttl());
+    let expires_at = refresh_expiry(token_service, now, family_expires_at);
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.sessions
+            (user_id, token_hash, family_id, expires_at, family_expires_at, ip_address, user_agent,
+             mfa_verified)
+        VALUES ($1, $2, $3, $4, $5, $6::inet, $7, $8)
+        "#,
+    )
+    .bind(user_id)
+    .bind(hash_refresh_token(&refresh_token))
+    .bind(session_id)
+    .bind(expires_at)
+    .bind(family_expires_at)
+    .bind(&client.ip_address)
+    .bind(&client.user_agent)
+    .bind(mfa_verified)
+    .execute(executor)
+    .await?;
+
+    Ok(NewSession {
+        session_id,
+        refresh_token,
+    })
+}
+
+/// Session rotated by [`rotate_session`]
+#[derive(Debug)]
+pub struct RotatedSession {
+    pub session_id: Uuid, // Session family ID
+    pub territory_user_id: Uuid,
+    pub public_key_hash: String,
+    pub refresh_token: String,
+    pub mfa_verified: bool, // Carried over from login
+}
+
+#[derive(FromRow)]
+struct SessionRecord {
+    id: Uuid,
+    user_id: Uuid,
+    family_id: Uuid,
+    expires_at: DateTime<Utc>,
+    family_expires_at: DateTime<Utc>,
+    rotated_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+    mfa_verified: bool,
+    territory_code: String,
+    territory_user_id: Uuid,
+    public_key_hash: String,
+}
+
+/// Exchange a refresh token for a new one in the same family
+///
+/// This checks:
+/// 1. Token exists, belongs to `territory_code` and has not been revoked
+/// 2. Token has not been rotated before - a replayed token revokes the whole family
+/// 3. Token and family have not expired
+/// 4. The member (in `schema_name`) is still active - otherwise the family is revoked
+///
+/// The old session is kept (marked as rotated) so later replays can be detected.
+pub async fn rotate_session(
+    pool: &PgPool,
+    token_service: &TokenService,
+    schema_name: &str,
+    refresh_token: &str,
+    territory_code: &str,
+    client: &ClientInfo,
+) -> Result<RotatedSession, AppError> {
+    let mut tx = pool.begin().await?;
+
+    // Lock the session so concurrent refreshes of the same token are serialized
+    let session = sqlx::query_as::<_, SessionRecord>(
+        r#"
+        SELECT
+            s.id, s.user_id, s.family_id, s.expires_at, s.family_expires_at,
+            s.rotated_at, s.revoked_at, s.mfa_verified,
+            ui.territory_code, ui.territory_user_id, ui.public_key_hash
+        FROM global.sessions s
+        JOIN global.user_identities ui ON ui.id = s.user_id
+        WHERE s.token_hash = $1
+        FOR UPDATE OF s
+        "#,
+    )
+    .bind(hash_refresh_token(refresh_token))
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::Unauthorized("Invalid refresh token".to_string()))?;
+
+    if session.revoked_at.is_some() {
+        return Err(AppError::Unauthorized("Invalid refresh token".to_string()));
+    }
+
+    if session.territory_code != territory_code {
+        return Err(AppError::Unauthorized("Invalid session".to_string()));
+    }
+
+    // A rotated-out token is being replayed: either the client or an attacker
+    // holds a stolen copy, and we cannot tell which. Revoke the whole family.
+    if session.rotated_at.is_some() {
+        revoke_family(&mut tx, session.family_id, "reuse_detected").await?;
+
+        record_audit(
+            &mut *tx,
+            &AuditEntry::new("session.refresh_token_reused")
+                .actor(session.user_id)
+                .territory(&session.territory_code)
+                .resource("session_family", session.family_id)
+                .client(client),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        tracing::warn!(
+            "Refresh token reuse detected for user {} - revoked session family {}",
+            session.user_id,
+            session.family_id
+        );
+
+        return Err(AppError::Unauthorized(
+            "Refresh token reuse detected".to_string(),
+        ));
+    }
+
+    let now = Utc::now();
+    if session.expires_at < now || session.family_expires_at < now {
+        revoke_family(&mut tx, session.family_id, "expired").await?;
+        tx.commit().await?;
+
+        return Err(AppError::Unauthorized("Refresh token expired".to_string()));
+    }
+
+    // A deactivated member's token must not be exchanged. The row stays locked
+    // until commit, so a deactivation cannot slip in before the new token exists.
+    let is_active: Option<bool> = sqlx::query_scalar(&format!(
+        "SELECT is_active FROM {}.users WHERE id = $1 FOR SHARE",
+        schema_name
+    ))
+    .bind(session.territory_user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if is_active != Some(true) {
+        revoke_family(&mut tx, session.family_id, "user_inactive").await?;
+        tx.commit().await?;
+
+        return Err(AppError::Unauthorized(
+            "User not found or inactive".to_string(),
+        ));
+    }
+
+    sqlx::query("UPDATE global.sessions SET rotated_at = $2 WHERE id = $1")
+        .bind(session.id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    let new_refresh_token = token_service.generate_refresh_token();
+    let expires_at = refresh_expiry(token_service, now, session.family_expires_at);
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.sessions
+            (user_id, token_hash, family_id, parent_id, expires_at, family_expires_at,
+             ip_address, user_agent, mfa_verified)
+        VALUES ($1, $2, $3, $4, $5, $6, $7::inet, $8, $9)
+        "#,
+    )
+    .bind(session.user_id)
+    .bind(hash_refresh_token(&new_refresh_token))
+    .bind(session.family_id)
+    .bind(session.id)
+    .bind(expires_at)
+    .bind(session.family_expires_at)
+    .bind(&client.ip_address)
+    .bind(&client.user_agent)
+    .bind(session.mfa_verified)
+    .execute(&mut *tx)
+    .await?;
+
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("auth.refresh")
+            .actor(session.user_id)
+            .territory(&session.territory_code)
+            .resource("session_family", session.family_id)
+            .client(client),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(RotatedSession {
+        session_id: session.family_id,
+        territory_user_id: session.territory_user_id,
+        public_key_hash: session.public_key_hash,
+        refresh_token: new_refresh_token,
+        mfa_verified: session.mfa_verified,
+    })
+}
+
+/// End the session family of a live refresh token (logout)
+pub async fn end_session(
+    pool: &PgPool,
+    refresh_token: &str,
+    client: &ClientInfo,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    // Every row of the family belongs to the same identity
+    let ended: Option<(Uuid, Uuid, String)> = sqlx::query_as(
+        r#"
+        WITH ended AS (
+            DELETE FROM global.sessions
+            WHERE family_id = (
+                SELECT family_id FROM global.sessions
+                WHERE token_hash = $1 AND rotated_at IS NULL AND revoked_at IS NULL
+            )
+            RETURNING user_id, family_id
+        )
+        SELECT DISTINCT e.user_id, e.family_id, ui.territory_code
+        FROM ended e
+        JOIN global.user_identities ui ON ui.id = e.user_id
+        "#,
+    )
+    .bind(hash_refresh_token(refresh_token))
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let (user_id, family_id, territory_code) =
+        ended.ok_or_else(|| AppError::NotFound("Refresh token not found".to_string()))?;
+
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("auth.logout")
+            .actor(user_id)
+            .territory(&territory_code)
+            .resource("session_family", family_id)
+            .client(client),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// List a user's active sessions, newest activity first
+///
+/// `current_session_id` marks the session of the requesting access token.
+pub async fn list_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    current_session_id: Option<Uuid>,
+) -> Result<Vec<SessionInfo>, AppError> {
+    let sessions = sqlx::query_as::<_, SessionInfo>(
+        r#"
+        SELECT
+            s.family_id AS id,
+            host(s.ip_address) AS ip_address,
+            s.user_agent,
+            (SELECT MIN(f.created_at) FROM global.sessions f WHERE f.family_id = s.family_id) AS created_at,
+            s.created_at AS last_active_at,
+            s.expires_at,
+            COALESCE(s.family_id = $2, false) AS current
+        FROM global.sessions s
+        WHERE s.user_id = $1
+          AND s.rotated_at IS NULL
+          AND s.revoked_at IS NULL
+          AND s.expires_at > NOW()
+          AND s.family_expires_at > NOW()
+        ORDER BY s.created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(current_session_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+/// Revoke one of a user's sessions (remote logout)
+///
+/// Audited as `session.revoked` in the same transaction.
+pub async fn revoke_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    territory_code: &str,
+    session_id: Uuid,
+    client: &ClientInfo,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE global.sessions
+        SET revoked_at = NOW(), revoked_reason = 'user_revoked'
+        WHERE family_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    }
+
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("session.revoked")
+            .actor(user_id)
+            .territory(territory_code)
+            .resource("session_family", session_id)
+            .client(client),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Revoke all of a user's sessions except `keep_session_id` (all of them if `None`)
+///
+/// Returns the number of live sessions revoked.
+pub async fn revoke_other_sessions<'e, E>(
+    executor: E,
+    user_id: Uuid,
+    keep_session_id: Option<Uuid>,
+    reason: &str,
+) -> Result<i64, AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let revoked: i64 = sqlx::query_scalar(
+        r#"
+        WITH revoked AS (
+            UPDATE global.sessions
+            SET revoked_at = NOW(), revoked_reason = $3
+            WHERE user_id = $1 AND family_id IS DISTINCT FROM $2 AND revoked_at IS NULL
+            RETURNING family_id, rotated_at, expires_at
+        )
+        SELECT COUNT(*) FROM revoked WHERE rotated_at IS NULL AND expires_at > NOW()
+        "#,
+    )
+    .bind(user_id)
+    .bind(keep_session_id)
+    .bind(reason)
+    .fetch_one(executor)
+    .await?;
+
+    Ok(revoked)
+}
+
+/// Sign a member out everywhere (moderator action)
+///
+/// In one transaction: revokes all of the member's sessions, the access
+/// tokens issued to them so far and their personal access tokens, and records
+/// the operation in `global.audit_log`.
+pub async fn force_logout(
+    pool: &PgPool,
+    schema_name: &str,
+    territory_code: &str,
+    username: &str,
+    reason: &str,
+    actor_identity_id: Uuid,
+) -> Result<ForcedLogout, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let identity_id: Uuid = sqlx::query_scalar(&format!(
+        r#"
+        SELECT ui.id
+        FROM {}.users u
+        JOIN global.user_identities ui
+            ON ui.territory_code = $1 AND ui.territory_user_id = u.id
+        WHERE LOWER(u.username) = LOWER($2)
+        "#,
+        schema_name
+    ))
+    .bind(territory_code)
+    .bind(username)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("User not found in this territory".to_string()))?;
+
+    let sessions_revoked =
+        revoke_other_sessions(&mut *tx, identity_id, None, "force_logout").await?;
+    revoke_access_tokens_issued_before_now(&mut *tx, &[identity_id], "force_logout").await?;
+    let personal_access_tokens_revoked =
+        revoke_all_personal_access_tokens(&mut *tx, identity_id, "force_logout").await?;
+
+    record_audit(
+        &mut *tx,
+        &AuditEntry::new("auth.force_logout")
+            .actor(actor_identity_id)
+            .territory(territory_code)
+            .resource("user", identity_id)
+            .changes(serde_json::json!({
+                "reason": reason,
+                "sessions_revoked": sessions_revoked,
+                "personal_access_tokens_revoked": personal_access_tokens_revoked,
+            })),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(ForcedLogout {
+        sessions_revoked,
+        personal_access_tokens_revoked,
+    })
+}
+
+/// Check whether a session family still has a live refresh token
+pub async fn is_session_active(pool: &PgPool, session_id: Uuid) -> Result<bool, AppError> {
+    let active: bool = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM global.sessions
+            WHERE family_id = $1
+              AND rotated_at IS NULL
+              AND revoked_at IS NULL
+              AND expires_at > NOW()
+              AND family_expires_at > NOW()
+        )
+        "#,
+    )
+    .bind(session_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(active)
+}
+
+async fn revoke_family(
+    tx: &mut Transaction<'_, Postgres>,
+    family_id: Uuid,
+    reason: &str,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        UPDATE global.sessions
+        SET revoked_at = NOW(), revoked_reason = $2
+        WHERE family_id = $1 AND revoked_at IS NULL
+        "#,
+    )
+    .bind(family_id)
+    .bind(reason)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Sliding refresh token expiry, capped by the family's absolute lifetime
+fn refresh_expiry(
+    token_service: &TokenService,
+    now: DateTime<Utc>,
+    family_expires_at: DateTime<Utc>,
+) -> DateTime<Utc> {
+    (now + Duration::seconds(token_service.get_refresh_token_ttl())).min(family_expires_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_refresh_token_is_stable() {
+        let hash = hash_refresh_token("token");
+
+        assert_eq!(hash, hash_refresh_token("token"));
+        assert_ne!(hash, hash_refresh_token("other"));
+        assert_eq!(hash.len(), 64);
+    }
+
+    #[test]
+    fn test_refresh_expiry_capped_by_family() {
+        let service = TokenService::new("test_secret", 900, 604800);
+        let now = Utc::now();
+
+        let family_expires_at = now + Duration::days(30);
+        assert_eq!(
+            refresh_expiry(&service, now, family_expires_at),
+            now + Duration::days(7)
+        );
+
+        let family_expires_at = now + Duration::days(2);
+        assert_eq!(
+            refresh_expiry(&service, now, family_expires_at),
+            family_expires_at
+        );
+    }
+}
@@ -1,28 +1,147 @@
-use crate::models::Claims;
+use super::TokenRevocations;
+use crate::models::{
+    invitation::InvitationClaims, AuditCheckpointClaims, Claims, ClientAccessClaims, IdTokenClaims,
+    MfaChallengeClaims,
+};
 use anyhow::Result;
-use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+        EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType,
+        PublicKeyUse,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use p256::{
+    elliptic_curve::sec1::ToEncodedPoint,
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
 use uuid::Uuid;
 
+/// Default absolute lifetime of a refresh token family (30 days)
+const DEFAULT_REFRESH_FAMILY_TTL: i64 = 2_592_000;
+
+/// Audience of MFA challenge tokens (access tokens carry no audience)
+const MFA_CHALLENGE_AUDIENCE: &str = "unityplan:mfa-challenge";
+
+/// Audience of signed invitation tokens
+const INVITATION_AUDIENCE: &str = "unityplan:invitation";
+
+/// Audience of signed audit log checkpoints
+const AUDIT_CHECKPOINT_AUDIENCE: &str = "unityplan:audit-checkpoint";
+
+/// Audience of access tokens issued to OIDC relying parties (userinfo only)
+const CLIENT_ACCESS_AUDIENCE: &str = "unityplan:userinfo";
+
+/// Lifetime of an MFA challenge token (5 minutes)
+const MFA_CHALLENGE_TTL: i64 = 300;
+
+/// Key used to verify access tokens
+struct VerificationKey {
+    kid: Option<String>, // None only for the legacy HS256 shared secret
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    jwk: Option<Jwk>, // Shared secrets are never published
+}
+
 /// Token service for JWT generation and validation
+///
+/// Tokens are signed either with an HS256 shared secret (development) or with an
+/// Ed25519 (EdDSA) / P-256 (ES256) private key. Asymmetric tokens carry a `kid`
+/// header, and the public keys are published via [`TokenService::jwks`] so other
+/// services can verify tokens without being able to mint them.
+///
+/// During a key rotation, retired public keys are kept as verification keys until
+/// the tokens they signed have expired.
 pub struct TokenService {
+    algorithm: Algorithm,
+    signing_kid: Option<String>,
     encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
-    access_token_ttl: i64, // seconds
-    #[allow(dead_code)] // Used for future refresh token implementation
-    refresh_token_ttl: i64, // seconds
+    // Current signing key first, followed by retired keys
+    verification_keys: Vec<VerificationKey>,
+    access_token_ttl: i64,                      // seconds
+    refresh_token_ttl: i64,                     // seconds, sliding (renewed on every rotation)
+    refresh_family_ttl: i64,                    // seconds, absolute lifetime of a login session
+    revocations: Option<Arc<TokenRevocations>>, // Revoked access tokens, checked on validation
 }
 
 impl TokenService {
+    /// Create a token service signing with an HS256 shared secret
     pub fn new(secret: &str, access_token_ttl: i64, refresh_token_ttl: i64) -> Self {
         Self {
+            algorithm: Algorithm::HS256,
+            signing_kid: None,
             encoding_key: EncodingKey::from_secret(secret.as_bytes()),
-            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            verification_keys: vec![VerificationKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+                jwk: None,
+            }],
             access_token_ttl,
             refresh_token_ttl,
+            refresh_family_ttl: DEFAULT_REFRESH_FAMILY_TTL,
+            revocations: None,
         }
     }
 
+    /// Create a token service signing with an Ed25519 or P-256 private key
+    ///
+    /// The key must be a PKCS#8 PEM (`openssl genpkey -algorithm ed25519`). The
+    /// algorithm is detected from the key and the `kid` is its RFC 7638 thumbprint.
+    pub fn from_private_key_pem(
+        private_key_pem: &str,
+        access_token_ttl: i64,
+        refresh_token_ttl: i64,
+    ) -> Result<Self> {
+        let (algorithm, encoding_key, jwk) = parse_private_key(private_key_pem)?;
+        let key = verification_key_from_jwk(jwk)?;
+
+        Ok(Self {
+            algorithm,
+            signing_kid: key.kid.clone(),
+            encoding_key,
+            verification_keys: vec![key],
+            access_token_ttl,
+            refresh_token_ttl,
+            refresh_family_ttl: DEFAULT_REFRESH_FAMILY_TTL,
+            revocations: None,
+        })
+    }
+
+    /// Set the absolute lifetime of a refresh token family
+    ///
+    /// Rotation never extends a session past this point; the user has to log in again.
+    pub fn with_refresh_family_ttl(mut self, refresh_family_ttl: i64) -> Self {
+        self.refresh_family_ttl = refresh_family_ttl;
+        self
+    }
+
+    /// Reject access tokens revoked by ID or by a per-user cut-off
+    pub fn with_revocations(mut self, revocations: Arc<TokenRevocations>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// Accept tokens signed by a retired key (public key PEM) during rotation
+    ///
+    /// Returns the key's `kid`. Adding a key that is already known is a no-op.
+    pub fn add_verification_key(&mut self, public_key_pem: &str) -> Result<String> {
+        let key = verification_key_from_jwk(parse_public_key(public_key_pem)?)?;
+        let kid = key.kid.clone().unwrap_or_default();
+
+        if self.find_key(Some(&kid)).is_none() {
+            self.verification_keys.push(key);
+        }
+
+        Ok(kid)
+    }
+
     /// Generate access token (short-lived, 15 minutes default)
     pub fn generate_access_token(
         &self,
@@ -30,21 +149,116 @@ impl TokenService {
         territory_code: &str,
         user_id: Uuid,
         username: &str,
+        session_id: Option<Uuid>,
+        mfa_verified: bool,
     ) -> Result<String> {
-        let now = Utc::now().timestamp();
+        let claims = self.access_claims(
+            public_key_hash,
+            territory_code,
+            user_id,
+            username,
+            session_id,
+            mfa_verified,
+        );
+
+        encode(&self.header(), &claims, &self.encoding_key)
+            .map_err(|e| anyhow::anyhow!("Failed to generate access token: {}", e))
+    }
+
+    /// Claims of a new access token, valid from now for the access token TTL
+    pub fn access_claims(
+        &self,
+        public_key_hash: &str,
+        territory_code: &str,
+        user_id: Uuid,
+        username: &str,
+        session_id: Option<Uuid>,
+        mfa_verified: bool,
+    ) -> Claims {
+        let issued_at = Utc::now();
+        let now = issued_at.timestamp();
         let exp = now + self.access_token_ttl;
 
-        let claims = Claims {
+        Claims {
             sub: public_key_hash.to_string(),
             territory_code: territory_code.to_string(),
             user_id: user_id.to_string(),
             username: username.to_string(),
             iat: now,
+            iat_ms: Some(issued_at.timestamp_millis()),
             exp,
+            sid: session_id.map(|id| id.to_string()),
+            amr: if mfa_verified {
+                vec!["pwd".to_string(), "otp".to_string()]
+            } else {
+                vec!["pwd".to_string()]
+            },
+            jti: Some(Uuid::new_v4().to_string()),
+        }
+    }
+
+    /// Sign an access token for an OIDC relying party
+    ///
+    /// Its audience keeps it out of [`Self::validate_token`]; only the userinfo
+    /// endpoint accepts it.
+    pub fn sign_client_access_token(
+        &self,
+        claims: Claims,
+        client_id: &str,
+        scope: &str,
+    ) -> Result<String> {
+        let claims = ClientAccessClaims {
+            claims,
+            aud: CLIENT_ACCESS_AUDIENCE.to_string(),
+            client_id: client_id.to_string(),
+            scope: scope.to_string(),
         };
 
-        encode(&Header::default(), &claims, &self.encoding_key)
-            .map_err(|e| anyhow::anyhow!("Failed to generate access token: {}", e))
+        encode(&self.header(), &claims, &self.encoding_key)
+            .map_err(|e| anyhow::anyhow!("Failed to generate client access token: {}", e))
+    }
+
+    /// Validate and decode an access token issued to an OIDC relying party
+    pub fn validate_client_access_token(&self, token: &str) -> Result<ClientAccessClaims> {
+        let key = self.verification_key_for(token)?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_audience(&[CLIENT_ACCESS_AUDIENCE]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+
+        let token_data = decode::<ClientAccessClaims>(token, &key.decoding_key, &validation)
+            .map_err(|e| anyhow::anyhow!("Invalid client access token: {}", e))?;
+
+        Ok(token_data.claims)
+    }
+
+    /// Sign an OIDC ID token for a relying party
+    ///
+    /// Relying parties verify it with [`Self::jwks`], so deployments acting as an
+    /// OpenID provider need an asymmetric signing key.
+    pub fn sign_id_token(&self, claims: &IdTokenClaims) -> Result<String> {
+        encode(&self.header(), claims, &self.encoding_key)
+            .map_err(|e| anyhow::anyhow!("Failed to sign ID token: {}", e))
+    }
+
+    /// Generate MFA challenge token (issued after the password step of a 2FA login)
+    pub fn generate_mfa_challenge_token(
+        &self,
+        user_id: Uuid,
+        territory_code: &str,
+    ) -> Result<String> {
+        let now = Utc::now().timestamp();
+
+        let claims = MfaChallengeClaims {
+            sub: user_id.to_string(),
+            territory_code: territory_code.to_string(),
+            aud: MFA_CHALLENGE_AUDIENCE.to_string(),
+            iat: now,
+            exp: now + MFA_CHALLENGE_TTL,
+        };
+
+        encode(&self.header(), &claims, &self.encoding_key)
+            .map_err(|e| anyhow::anyhow!("Failed to generate MFA challenge token: {}", e))
     }
 
     /// Generate refresh token (random string)
@@ -54,21 +268,282 @@ impl TokenService {
 
     /// Validate and decode access token
     pub fn validate_token(&self, token: &str) -> Result<Claims> {
-        let token_data = decode::<Claims>(token, &self.decoding_key, &Validation::default())
-            .map_err(|e| anyhow::anyhow!("Invalid token: {}", e))?;
+        let key = self.verification_key_for(token)?;
+
+        let token_data =
+            decode::<Claims>(token, &key.decoding_key, &Validation::new(key.algorithm))
+                .map_err(|e| anyhow::anyhow!("Invalid token: {}", e))?;
+
+        if self
+            .revocations
+            .as_ref()
+            .is_some_and(|revocations| revocations.is_revoked(&token_data.claims))
+        {
+            return Err(anyhow::anyhow!("Token has been revoked"));
+        }
+
+        Ok(token_data.claims)
+    }
+
+    /// Validate and decode MFA challenge token
+    pub fn validate_mfa_challenge_token(&self, token: &str) -> Result<MfaChallengeClaims> {
+        let key = self.verification_key_for(token)?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_audience(&[MFA_CHALLENGE_AUDIENCE]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+
+        let token_data = decode::<MfaChallengeClaims>(token, &key.decoding_key, &validation)
+            .map_err(|e| anyhow::anyhow!("Invalid MFA token: {}", e))?;
+
+        Ok(token_data.claims)
+    }
+
+    /// Sign an invitation so it can be checked without a database round trip
+    ///
+    /// `nonce` is the stored invitation token; the signed token expires with it.
+    pub fn sign_invitation(
+        &self,
+        territory_code: &str,
+        token_type: &str,
+        expires_at: DateTime<Utc>,
+        nonce: &str,
+    ) -> Result<String> {
+        let claims = InvitationClaims {
+            aud: INVITATION_AUDIENCE.to_string(),
+            territory_code: territory_code.to_lowercase(),
+            token_type: token_type.to_string(),
+            exp: expires_at.timestamp(),
+            nonce: nonce.to_string(),
+        };
+
+        encode(&self.header(), &claims, &self.encoding_key)
+            .map_err(|e| anyhow::anyhow!("Failed to sign invitation: {}", e))
+    }
+
+    /// Check the signature, audience and expiry of a signed invitation token
+    pub fn verify_signed_invitation(&self, token: &str) -> Result<InvitationClaims> {
+        let key = self.verification_key_for(token)?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_audience(&[INVITATION_AUDIENCE]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+
+        let token_data = decode::<InvitationClaims>(token, &key.decoding_key, &validation)
+            .map_err(|e| anyhow::anyhow!("Invalid invitation token: {}", e))?;
+
+        Ok(token_data.claims)
+    }
+
+    /// Sign the head of an audit log hash chain
+    ///
+    /// Checkpoints never expire: they vouch for the chain for as long as the
+    /// signing key is kept as a verification key.
+    pub fn sign_audit_checkpoint(&self, chain_id: &str, seq: i64, hash: &str) -> Result<String> {
+        let claims = AuditCheckpointClaims {
+            aud: AUDIT_CHECKPOINT_AUDIENCE.to_string(),
+            chain: chain_id.to_string(),
+            seq,
+            hash: hash.to_string(),
+            iat: Utc::now().timestamp(),
+        };
+
+        encode(&self.header(), &claims, &self.encoding_key)
+            .map_err(|e| anyhow::anyhow!("Failed to sign audit checkpoint: {}", e))
+    }
+
+    /// Check the signature and audience of an audit checkpoint
+    pub fn verify_audit_checkpoint(&self, signature: &str) -> Result<AuditCheckpointClaims> {
+        let key = self.verification_key_for(signature)?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_audience(&[AUDIT_CHECKPOINT_AUDIENCE]);
+        validation.set_required_spec_claims(&["aud"]);
+        validation.validate_exp = false;
+
+        let token_data = decode::<AuditCheckpointClaims>(signature, &key.decoding_key, &validation)
+            .map_err(|e| anyhow::anyhow!("Invalid audit checkpoint: {}", e))?;
 
         Ok(token_data.claims)
     }
 
+    /// Public verification keys as a JWK Set (empty when using a shared secret)
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .verification_keys
+                .iter()
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+
+    /// Algorithm newly issued tokens are signed with
+    pub fn signing_algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Key ID stamped on newly issued tokens (None for HS256)
+    pub fn signing_key_id(&self) -> Option<&str> {
+        self.signing_kid.as_deref()
+    }
+
     /// Get access token TTL in seconds
     pub fn get_access_token_ttl(&self) -> i64 {
         self.access_token_ttl
     }
+
+    /// Get MFA challenge token TTL in seconds
+    pub fn get_mfa_challenge_ttl(&self) -> i64 {
+        MFA_CHALLENGE_TTL
+    }
+
+    /// Get refresh token TTL in seconds
+    pub fn get_refresh_token_ttl(&self) -> i64 {
+        self.refresh_token_ttl
+    }
+
+    /// Get refresh token family lifetime in seconds
+    pub fn get_refresh_family_ttl(&self) -> i64 {
+        self.refresh_family_ttl
+    }
+
+    fn header(&self) -> Header {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.signing_kid.clone();
+        header
+    }
+
+    fn verification_key_for(&self, token: &str) -> Result<&VerificationKey> {
+        let header = decode_header(token).map_err(|e| anyhow::anyhow!("Invalid token: {}", e))?;
+
+        self.find_key(header.kid.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Invalid token: unknown signing key"))
+    }
+
+    fn find_key(&self, kid: Option<&str>) -> Option<&VerificationKey> {
+        self.verification_keys
+            .iter()
+            .find(|key| key.kid.as_deref() == kid)
+    }
+}
+
+/// Parse a PKCS#8 private key into its algorithm, encoding key and public JWK
+fn parse_private_key(pem: &str) -> Result<(Algorithm, EncodingKey, Jwk)> {
+    if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+        let encoding_key = EncodingKey::from_ed_pem(pem.as_bytes())?;
+        return Ok((
+            Algorithm::EdDSA,
+            encoding_key,
+            ed25519_jwk(&key.verifying_key()),
+        ));
+    }
+
+    if let Ok(key) = p256::SecretKey::from_pkcs8_pem(pem) {
+        let encoding_key = EncodingKey::from_ec_pem(pem.as_bytes())?;
+        return Ok((Algorithm::ES256, encoding_key, p256_jwk(&key.public_key())));
+    }
+
+    anyhow::bail!("Unsupported signing key: expected an Ed25519 or P-256 PKCS#8 PEM")
+}
+
+/// Parse a SubjectPublicKeyInfo PEM into a public JWK
+fn parse_public_key(pem: &str) -> Result<Jwk> {
+    if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+        return Ok(ed25519_jwk(&key));
+    }
+
+    if let Ok(key) = p256::PublicKey::from_public_key_pem(pem) {
+        return Ok(p256_jwk(&key));
+    }
+
+    anyhow::bail!("Unsupported verification key: expected an Ed25519 or P-256 public key PEM")
+}
+
+fn ed25519_jwk(key: &ed25519_dalek::VerifyingKey) -> Jwk {
+    let x = URL_SAFE_NO_PAD.encode(key.as_bytes());
+    let thumbprint = jwk_thumbprint(&format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, x));
+
+    Jwk {
+        common: signing_key_parameters(thumbprint, KeyAlgorithm::EdDSA),
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x,
+        }),
+    }
+}
+
+fn p256_jwk(key: &p256::PublicKey) -> Jwk {
+    let point = key.to_encoded_point(false);
+    // Uncompressed points always carry both coordinates
+    let x = URL_SAFE_NO_PAD.encode(point.x().expect("uncompressed point"));
+    let y = URL_SAFE_NO_PAD.encode(point.y().expect("uncompressed point"));
+    let thumbprint = jwk_thumbprint(&format!(
+        r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+        x, y
+    ));
+
+    Jwk {
+        common: signing_key_parameters(thumbprint, KeyAlgorithm::ES256),
+        algorithm: AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+            key_type: EllipticCurveKeyType::EC,
+            curve: EllipticCurve::P256,
+            x,
+            y,
+        }),
+    }
+}
+
+fn signing_key_parameters(kid: String, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid),
+        ..Default::default()
+    }
+}
+
+/// RFC 7638 thumbprint over the canonical (sorted, whitespace-free) required members
+fn jwk_thumbprint(canonical_json: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical_json.as_bytes()))
+}
+
+fn verification_key_from_jwk(jwk: Jwk) -> Result<VerificationKey> {
+    let algorithm = match &jwk.algorithm {
+        AlgorithmParameters::OctetKeyPair(_) => Algorithm::EdDSA,
+        _ => Algorithm::ES256,
+    };
+
+    Ok(VerificationKey {
+        kid: jwk.common.key_id.clone(),
+        algorithm,
+        decoding_key: DecodingKey::from_jwk(&jwk)?,
+        jwk: Some(jwk),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey, EncodePublicKey};
+
+    fn ed25519_pem() -> (String, String) {
+        let key = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+        (
+            key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string(),
+            key.verifying_key()
+                .to_public_key_pem(LineEnding::LF)
+                .unwrap(),
+        )
+    }
+
+    fn p256_pem() -> String {
+        p256::SecretKey::random(&mut rand::rngs::OsRng)
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap()
+            .to_string()
+    }
 
     #[test]
     fn test_generate_and_validate_token() {
@@ -79,7 +554,14 @@ mod tests {
         let username = "testuser";
 
         let token = service
-            .generate_access_token(public_key_hash, territory_code, user_id, username)
+            .generate_access_token(
+                public_key_hash,
+                territory_code,
+                user_id,
+                username,
+                None,
+                false,
+            )
             .unwrap();
 
         let claims = service.validate_token(&token).unwrap();
@@ -111,4 +593,219 @@ mod tests {
         assert!(Uuid::parse_str(&token1).is_ok());
         assert!(Uuid::parse_str(&token2).is_ok());
     }
+
+    #[test]
+    fn test_eddsa_token_carries_kid() {
+        let (private_pem, _) = ed25519_pem();
+        let service = TokenService::from_private_key_pem(&private_pem, 900, 604800).unwrap();
+
+        let token = service
+            .generate_access_token("hash", "dk", Uuid::new_v4(), "testuser", None, false)
+            .unwrap();
+        let header = decode_header(&token).unwrap();
+
+        assert_eq!(header.alg, Algorithm::EdDSA);
+        assert_eq!(header.kid.as_deref(), service.signing_key_id());
+        assert!(service.validate_token(&token).is_ok());
+    }
+
+    #[test]
+    fn test_es256_token_roundtrip() {
+        let service = TokenService::from_private_key_pem(&p256_pem(), 900, 604800).unwrap();
+
+        let token = service
+            .generate_access_token("hash", "dk", Uuid::new_v4(), "testuser", None, false)
+            .unwrap();
+
+        assert_eq!(decode_header(&token).unwrap().alg, Algorithm::ES256);
+        assert_eq!(service.validate_token(&token).unwrap().username, "testuser");
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_tokens_valid() {
+        let (old_private, old_public) = ed25519_pem();
+        let old_service = TokenService::from_private_key_pem(&old_private, 900, 604800).unwrap();
+        let old_token = old_service
+            .generate_access_token("hash", "dk", Uuid::new_v4(), "testuser", None, false)
+            .unwrap();
+
+        let mut new_service = TokenService::from_private_key_pem(&p256_pem(), 900, 604800).unwrap();
+        assert!(new_service.validate_token(&old_token).is_err());
+
+        let kid = new_service.add_verification_key(&old_public).unwrap();
+        assert_eq!(Some(kid.as_str()), old_service.signing_key_id());
+        assert!(new_service.validate_token(&old_token).is_ok());
+        assert_eq!(new_service.jwks().keys.len(), 2);
+    }
+
+    #[test]
+    fn test_token_from_other_key_is_rejected() {
+        let service = TokenService::from_private_key_pem(&ed25519_pem().0, 900, 604800).unwrap();
+        let hs256 = TokenService::new("test_secret", 900, 604800);
+
+        let foreign = TokenService::from_private_key_pem(&ed25519_pem().0, 900, 604800)
+            .unwrap()
+            .generate_access_token("hash", "dk", Uuid::new_v4(), "testuser", None, false)
+            .unwrap();
+        let unsigned_kid = hs256
+            .generate_access_token("hash", "dk", Uuid::new_v4(), "testuser", None, false)
+            .unwrap();
+
+        assert!(service.validate_token(&foreign).is_err());
+        assert!(service.validate_token(&unsigned_kid).is_err());
+    }
+
+    #[test]
+    fn test_jwks_publishes_public_keys_only() {
+        let (private_pem, public_pem) = ed25519_pem();
+        let service = TokenService::from_private_key_pem(&private_pem, 900, 604800).unwrap();
+
+        let jwks = serde_json::to_value(service.jwks()).unwrap();
+        let key = &jwks["keys"][0];
+
+        assert_eq!(key["kty"], "OKP");
+        assert_eq!(key["crv"], "Ed25519");
+        assert_eq!(key["alg"], "EdDSA");
+        assert_eq!(key["use"], "sig");
+        assert_eq!(key["kid"], service.signing_key_id().unwrap());
+        assert!(key.get("d").is_none());
+
+        // kid is stable: the public key alone derives the same thumbprint
+        let mut verifier = TokenService::new("unused", 900, 604800);
+        let kid = verifier.add_verification_key(&public_pem).unwrap();
+        assert_eq!(Some(kid.as_str()), service.signing_key_id());
+
+        assert!(TokenService::new("test_secret", 900, 604800)
+            .jwks()
+            .keys
+            .is_empty());
+    }
+
+    #[test]
+    fn test_mfa_challenge_token_is_not_an_access_token() {
+        let service = TokenService::new("test_secret", 900, 604800);
+        let user_id = Uuid::new_v4();
+
+        let challenge = service.generate_mfa_challenge_token(user_id, "dk").unwrap();
+        let claims = service.validate_mfa_challenge_token(&challenge).unwrap();
+        assert_eq!(claims.sub, user_id.to_string());
+        assert_eq!(claims.territory_code, "dk");
+
+        // Neither token type is accepted in place of the other
+        assert!(service.validate_token(&challenge).is_err());
+        let access = service
+            .generate_access_token("hash", "dk", user_id, "testuser", None, false)
+            .unwrap();
+        assert!(service.validate_mfa_challenge_token(&access).is_err());
+    }
+
+    #[test]
+    fn test_signed_invitation_round_trip() {
+        let (private_pem, _) = ed25519_pem();
+        let service = TokenService::from_private_key_pem(&private_pem, 900, 604800).unwrap();
+        let expires_at = Utc::now() + chrono::Duration::days(7);
+
+        let token = service
+            .sign_invitation("DK", "group", expires_at, "inv_0123")
+            .unwrap();
+
+        let claims = service.verify_signed_invitation(&token).unwrap();
+        assert_eq!(claims.territory_code, "dk");
+        assert_eq!(claims.token_type, "group");
+        assert_eq!(claims.nonce, "inv_0123");
+
+        // Neither an access token nor an expired invitation passes as one
+        let access_token = service
+            .generate_access_token("hash", "DK", Uuid::new_v4(), "user", None, false)
+            .unwrap();
+        assert!(service.verify_signed_invitation(&access_token).is_err());
+        assert!(service.validate_token(&token).is_err());
+
+        let expired = service
+            .sign_invitation(
+                "DK",
+                "group",
+                Utc::now() - chrono::Duration::days(1),
+                "inv_0123",
+            )
+            .unwrap();
+        assert!(service.verify_signed_invitation(&expired).is_err());
+    }
+
+    #[test]
+    fn test_audit_checkpoint_round_trip() {
+        let (private_pem, _) = ed25519_pem();
+        let service = TokenService::from_private_key_pem(&private_pem, 900, 604800).unwrap();
+        let hash = "ab".repeat(32);
+
+        let signature = service.sign_audit_checkpoint("dk", 42, &hash).unwrap();
+
+        let claims = service.verify_audit_checkpoint(&signature).unwrap();
+        assert_eq!(claims.chain, "dk");
+        assert_eq!(claims.seq, 42);
+        assert_eq!(claims.hash, hash);
+
+        // Checkpoints and access tokens are not interchangeable
+        assert!(service.validate_token(&signature).is_err());
+        let access_token = service
+            .generate_access_token("hash", "DK", Uuid::new_v4(), "user", None, false)
+            .unwrap();
+        assert!(service.verify_audit_checkpoint(&access_token).is_err());
+
+        // Nor is a checkpoint signed by another key
+        let (other_pem, _) = ed25519_pem();
+        let other = TokenService::from_private_key_pem(&other_pem, 900, 604800).unwrap();
+        let forged = other.sign_audit_checkpoint("dk", 42, &hash).unwrap();
+        assert!(service.verify_audit_checkpoint(&forged).is_err());
+    }
+
+    #[test]
+    fn test_access_token_records_mfa() {
+        let service = TokenService::new("test_secret", 900, 604800);
+
+        let token = service
+            .generate_access_token("hash", "dk", Uuid::new_v4(), "testuser", None, true)
+            .unwrap();
+
+        assert_eq!(service.validate_token(&token).unwrap().amr, ["pwd", "otp"]);
+    }
+
+    #[test]
+    fn test_oidc_tokens_are_not_access_tokens() {
+        let (private_pem, _) = ed25519_pem();
+        let service = TokenService::from_private_key_pem(&private_pem, 900, 604800).unwrap();
+        let claims = service.access_claims("hash", "dk", Uuid::new_v4(), "user", None, false);
+
+        let client_token = service
+            .sign_client_access_token(claims.clone(), "forgejo", "openid profile")
+            .unwrap();
+        let decoded = service.validate_client_access_token(&client_token).unwrap();
+        assert_eq!(decoded.claims.sub, "hash");
+        assert_eq!(decoded.client_id, "forgejo");
+        assert_eq!(decoded.scope, "openid profile");
+
+        let id_token = service
+            .sign_id_token(&IdTokenClaims::from_claims(
+                &claims,
+                "https://dk.unityplan.test/api/auth",
+                "forgejo",
+                Some("n-0S6_WzA2Mj".to_string()),
+            ))
+            .unwrap();
+
+        // Relying parties cannot call the API with their tokens, and the
+        // userinfo endpoint only takes client access tokens
+        assert!(service.validate_token(&client_token).is_err());
+        assert!(service.validate_token(&id_token).is_err());
+        assert!(service.validate_client_access_token(&id_token).is_err());
+        let access_token = service
+            .generate_access_token("hash", "dk", Uuid::new_v4(), "user", None, false)
+            .unwrap();
+        assert!(service.validate_client_access_token(&access_token).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unsupported_key() {
+        assert!(TokenService::from_private_key_pem("not a key", 900, 604800).is_err());
+    }
 }
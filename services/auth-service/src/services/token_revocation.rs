@@ -0,0 +1,260 @@
+use super::auth_state::{read, write};
+use crate::models::Claims;
+use chrono::{DateTime, Utc};
+use shared_lib::error::AppError;
+use sqlx::{PgExecutor, PgPool};
+use std::{collections::HashMap, sync::RwLock};
+use uuid::Uuid;
+
+/// Revoke one access token (logout)
+///
+/// Every auth-service instance learns about it through the `auth_state`
+/// notification.
+pub async fn revoke_access_token<'e, E>(
+    executor: E,
+    claims: &Claims,
+    reason: &str,
+) -> Result<(), AppError>
+where
+    E: PgExecutor<'e>,
+{
+    let jti = claims
+        .jti
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(|_| AppError::Validation("Invalid token ID".to_string()))?
+        .ok_or_else(|| AppError::Validation("Token has no ID".to_string()))?;
+
+    let expires_at = DateTime::<Utc>::from_timestamp(claims.exp, 0)
+        .ok_or_else(|| AppError::Validation("Invalid token expiry".to_string()))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.revoked_access_tokens (jti, user_id, reason, expires_at)
+        SELECT $1, id, $3, $4 FROM global.user_identities WHERE public_key_hash = $2
+        ON CONFLICT (jti) DO NOTHING
+        "#,
+    )
+    .bind(jti)
+    .bind(&claims.sub)
+    .bind(reason)
+    .bind(expires_at)
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Revoke every access token issued to these users until now
+///
+/// `identity_ids` are global identity IDs (`global.user_identities.id`).
+/// The cut-off is taken from the clock that stamps `iat_ms` on access tokens,
+/// and rejects tokens issued up to and including its millisecond.
+pub async fn revoke_access_tokens_issued_before_now<'e, E>(
+    executor: E,
+    identity_ids: &[Uuid],
+    reason: &str,
+) -> Result<(), AppError>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO global.access_token_cutoffs (user_id, not_before, reason)
+        SELECT id, $3, $2
+        FROM unnest($1::uuid[]) AS id
+        ON CONFLICT (user_id) DO UPDATE
+        SET not_before = GREATEST(global.access_token_cutoffs.not_before, EXCLUDED.not_before),
+            reason = EXCLUDED.reason,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(identity_ids)
+    .bind(reason)
+    .bind(Utc::now())
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// In-memory copy of the access token revocations that can still matter
+///
+/// Consulted by [`super::TokenService::validate_token`]. Loaded with
+/// [`Self::reload`] and kept current by an [`super::AuthStateListener`].
+pub struct TokenRevocations {
+    access_token_ttl: i64,                 // seconds
+    tokens: RwLock<HashMap<String, i64>>,  // jti -> exp
+    cutoffs: RwLock<HashMap<String, i64>>, // sub (public_key_hash) -> not_before, in ms
+}
+
+impl TokenRevocations {
+    /// `access_token_ttl` is the longest lifetime of an access token; older
+    /// cut-offs cannot reject anything
+    pub fn new(access_token_ttl: i64) -> Self {
+        Self {
+            access_token_ttl,
+            tokens: RwLock::new(HashMap::new()),
+            cutoffs: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether an access token was revoked by ID, or issued before its
+    /// user's cut-off
+    ///
+    /// Tokens without `iat_ms` only know their second, so every one issued in
+    /// the cut-off's second is rejected.
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        let revoked_by_id = claims
+            .jti
+            .as_ref()
+            .is_some_and(|jti| read(&self.tokens).contains_key(jti));
+
+        revoked_by_id
+            || read(&self.cutoffs)
+                .get(&claims.sub)
+                .is_some_and(|not_before| issued_at_ms(claims) <= *not_before)
+    }
+
+    pub fn revoke_token(&self, jti: &str, expires_at: i64) {
+        self.purge_expired();
+        write(&self.tokens).insert(jti.to_string(), expires_at);
+    }
+
+    /// `not_before` is in milliseconds
+    pub fn revoke_issued_before(&self, sub: &str, not_before: i64) {
+        self.purge_expired();
+        let mut cutoffs = write(&self.cutoffs);
+        let cutoff = cutoffs.entry(sub.to_string()).or_insert(not_before);
+        *cutoff = (*cutoff).max(not_before);
+    }
+
+    /// Replace the in-memory copy with the revocations in the database
+    pub async fn reload(&self, pool: &PgPool) -> Result<(), AppError> {
+        let tokens: Vec<(Uuid, i64)> = sqlx::query_as(
+            r#"
+            SELECT jti, FLOOR(EXTRACT(EPOCH FROM expires_at))::bigint
+            FROM global.revoked_access_tokens
+            WHERE expires_at > NOW()
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let cutoffs: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+            SELECT ui.public_key_hash, FLOOR(EXTRACT(EPOCH FROM c.not_before) * 1000)::bigint
+            FROM global.access_token_cutoffs c
+            JOIN global.user_identities ui ON ui.id = c.user_id
+            WHERE c.not_before > NOW() - make_interval(secs => $1)
+            "#,
+        )
+        .bind(self.access_token_ttl as f64)
+        .fetch_all(pool)
+        .await?;
+
+        *write(&self.tokens) = tokens
+            .into_iter()
+            .map(|(jti, exp)| (jti.to_string(), exp))
+            .collect();
+        *write(&self.cutoffs) = cutoffs.into_iter().collect();
+
+        Ok(())
+    }
+
+    /// Drop revocations of tokens that have expired anyway
+    fn purge_expired(&self) {
+        let now = Utc::now();
+        write(&self.tokens).retain(|_, exp| *exp > now.timestamp());
+        write(&self.cutoffs).retain(|_, not_before| {
+            *not_before + self.access_token_ttl * 1000 > now.timestamp_millis()
+        });
+    }
+}
+
+/// When an access token was issued, in milliseconds
+fn issued_at_ms(claims: &Claims) -> i64 {
+    claims.iat_ms.unwrap_or(claims.iat * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(sub: &str, iat_ms: i64) -> Claims {
+        let iat = iat_ms.div_euclid(1000);
+        Claims {
+            sub: sub.to_string(),
+            territory_code: "dk".to_string(),
+            user_id: Uuid::new_v4().to_string(),
+            username: "alice".to_string(),
+            exp: iat + 900,
+            iat,
+            iat_ms: Some(iat_ms),
+            sid: None,
+            amr: vec!["pwd".to_string()],
+            jti: Some(Uuid::new_v4().to_string()),
+        }
+    }
+
+    #[test]
+    fn test_revoked_token_id_is_rejected() {
+        let revocations = TokenRevocations::new(900);
+        let now = Utc::now().timestamp_millis();
+        let revoked = claims("alice", now);
+        let other = claims("alice", now);
+
+        revocations.revoke_token(revoked.jti.as_deref().unwrap(), revoked.exp);
+
+        assert!(revocations.is_revoked(&revoked));
+        assert!(!revocations.is_revoked(&other));
+    }
+
+    #[test]
+    fn test_cutoff_rejects_tokens_issued_before_it() {
+        let revocations = TokenRevocations::new(900);
+        // Half a second in, so tokens from the same second fall on both sides
+        let now = Utc::now().timestamp() * 1000 + 500;
+
+        revocations.revoke_issued_before("alice", now);
+
+        assert!(revocations.is_revoked(&claims("alice", now - 1000)));
+        assert!(revocations.is_revoked(&claims("alice", now - 1)));
+        assert!(revocations.is_revoked(&claims("alice", now)));
+        assert!(!revocations.is_revoked(&claims("alice", now + 1)));
+        assert!(!revocations.is_revoked(&claims("bob", now - 1)));
+
+        // An earlier cut-off never moves it back
+        revocations.revoke_issued_before("alice", now - 60_000);
+        assert!(revocations.is_revoked(&claims("alice", now - 1)));
+    }
+
+    #[test]
+    fn test_cutoff_rejects_tokens_without_millis_from_its_second() {
+        let revocations = TokenRevocations::new(900);
+        let now = Utc::now().timestamp() * 1000 + 500;
+        let second = |iat: i64| Claims {
+            iat_ms: None,
+            ..claims("alice", iat * 1000)
+        };
+
+        revocations.revoke_issued_before("alice", now);
+
+        assert!(revocations.is_revoked(&second(now / 1000)));
+        assert!(!revocations.is_revoked(&second(now / 1000 + 1)));
+    }
+
+    #[test]
+    fn test_expired_revocations_are_purged() {
+        let revocations = TokenRevocations::new(900);
+        let now = Utc::now();
+
+        revocations.revoke_token("expired", now.timestamp() - 1);
+        revocations.revoke_issued_before("alice", now.timestamp_millis() - 900_001);
+        revocations.purge_expired();
+
+        assert!(read(&revocations.tokens).is_empty());
+        assert!(read(&revocations.cutoffs).is_empty());
+    }
+}
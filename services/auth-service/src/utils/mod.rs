@@ -2,4 +2,5 @@ pub mod crypto;
 pub mod validation;
 
 // pub use crypto::* - unused, comment out
+pub use shared_lib::ClientInfo;
 // pub use validation::* - unused, comment out
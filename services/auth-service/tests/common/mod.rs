@@ -1,5 +1,13 @@
-use auth_service::services::TokenService;
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::StatusCode,
+    test,
+};
+use auth_service::services::{EmailService, TokenService};
 use chrono::{Duration, Utc};
+use serde_json::json;
+use shared_lib::{DomainEvent, EventEnvelope, InMemoryMailer, SchemaLayout, TerritoryResolver};
 use sqlx::PgPool;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -8,6 +16,13 @@ use uuid::Uuid;
 const TERRITORY_SCHEMA: &str = "territory"; // For single-territory pods (default)
                                             // For multi-territory pods, use: "territory_dk", "territory_no", etc.
 
+/// Guards `territory.settings` entries that change what managers may do
+///
+/// Settings are shared by every test in the binary. Tests that switch on
+/// `require_mfa_for_managers` hold `write()`; tests that use manager
+/// permissions without 2FA hold `read()`.
+pub static MANAGER_SETTINGS: tokio::sync::RwLock<()> = tokio::sync::RwLock::const_new(());
+
 /// TestContext tracks all data created during a test and ensures precise cleanup.
 ///
 /// CRITICAL TESTING RULE:
@@ -19,8 +34,12 @@ const TERRITORY_SCHEMA: &str = "territory"; // For single-territory pods (defaul
 pub struct TestContext {
     pub pool: PgPool,
     pub token_service: Arc<TokenService>,
+    pub territories: Arc<TerritoryResolver>,
+    pub email_service: Arc<EmailService>,
+    pub mailer: InMemoryMailer, // Emails sent through email_service
     created_users: Vec<Uuid>,
     created_invitations: Vec<Uuid>,
+    created_communities: Vec<Uuid>,
 }
 
 impl TestContext {
@@ -38,14 +57,44 @@ impl TestContext {
 
         setup_test_data(&pool).await;
 
+        let mailer = InMemoryMailer::new();
+
         Self {
             pool,
             token_service: create_token_service(),
+            territories: create_territory_resolver(),
+            email_service: Arc::new(EmailService::new(
+                Arc::new(mailer.clone()),
+                "http://localhost:8000",
+            )),
+            mailer,
             created_users: Vec::new(),
             created_invitations: Vec::new(),
+            created_communities: Vec::new(),
         }
     }
 
+    /// Events about a territory user waiting in (or relayed from) the outbox, oldest first
+    pub async fn outbox_events<E: DomainEvent>(&self, user_id: Uuid) -> Vec<EventEnvelope<E>> {
+        let payloads: Vec<serde_json::Value> = sqlx::query_scalar(
+            r#"
+            SELECT payload FROM global.event_outbox
+            WHERE event_type = $1 AND payload->'payload'->>'user_id' = $2
+            ORDER BY created_at
+            "#,
+        )
+        .bind(E::EVENT_TYPE)
+        .bind(user_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .expect("Failed to load outbox events");
+
+        payloads
+            .into_iter()
+            .map(|payload| serde_json::from_value(payload).expect("Invalid event in outbox"))
+            .collect()
+    }
+
     /// Create a test user and track it for cleanup
     pub async fn create_user(&mut self) -> (Uuid, String, String, Option<String>) {
         let (user_id, username, password, email) =
@@ -54,6 +103,11 @@ impl TestContext {
         (user_id, username, password, email)
     }
 
+    /// Track a user created through the API (e.g. by registering) for cleanup
+    pub fn track_user(&mut self, user_id: Uuid) {
+        self.created_users.push(user_id);
+    }
+
     /// Create a simple test invitation (wrapper for convenience)
     pub async fn create_invitation(&mut self) -> String {
         let (inv_id, token) =
@@ -88,6 +142,17 @@ impl TestContext {
         (inv_id, token)
     }
 
+    /// Create a group invitation with a use limit and track its ID
+    pub async fn create_limited_invitation(&mut self, max_uses: i32) -> String {
+        let (inv_id, token) =
+            create_limited_invitation_with_id(&self.pool, TERRITORY_SCHEMA, max_uses).await;
+
+        // Track this invitation for precise cleanup
+        self.created_invitations.push(inv_id);
+
+        token
+    }
+
     /// Create an expired invitation and track its ID
     pub async fn create_expired_invitation(&mut self) -> String {
         let (inv_id, token) = create_expired_invitation_with_id(&self.pool, TERRITORY_SCHEMA).await;
@@ -118,6 +183,25 @@ impl TestContext {
         token
     }
 
+    /// Create an active community and track its ID
+    pub async fn create_community(&mut self) -> Uuid {
+        let code = format!("TEST-{}", &Uuid::new_v4().to_string()[..8]);
+
+        let community_id: Uuid = sqlx::query_scalar(&format!(
+            "INSERT INTO {}.communities (code, name) VALUES ($1, 'Test Community') RETURNING id",
+            TERRITORY_SCHEMA
+        ))
+        .bind(&code)
+        .fetch_one(&self.pool)
+        .await
+        .expect("Failed to create test community");
+
+        // Track this community for precise cleanup
+        self.created_communities.push(community_id);
+
+        community_id
+    }
+
     /// Cleanup ONLY the data this test created (precise deletion by ID)
     pub async fn cleanup(self) {
         // 1. Delete invitation uses for tracked users
@@ -144,7 +228,18 @@ impl TestContext {
             .ok();
         }
 
-        // 3. Delete tracked users by exact ID (cascades to user_identities)
+        // 3. Delete outbox events about tracked users
+        for user_id in &self.created_users {
+            sqlx::query(
+                "DELETE FROM global.event_outbox WHERE payload->'payload'->>'user_id' = $1",
+            )
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+            .ok();
+        }
+
+        // 4. Delete tracked users by exact ID (cascades to user_identities)
         for user_id in &self.created_users {
             sqlx::query(&format!(
                 "DELETE FROM {}.users WHERE id = $1",
@@ -156,7 +251,19 @@ impl TestContext {
             .ok();
         }
 
-        // 4. Clean up any orphaned global identities
+        // 5. Delete tracked communities by exact ID (cascades to members and their invitations)
+        for community_id in &self.created_communities {
+            sqlx::query(&format!(
+                "DELETE FROM {}.communities WHERE id = $1",
+                TERRITORY_SCHEMA
+            ))
+            .bind(community_id)
+            .execute(&self.pool)
+            .await
+            .ok();
+        }
+
+        // 6. Clean up any orphaned global identities
         sqlx::query(&format!("DELETE FROM global.user_identities WHERE LOWER(territory_code) = 'dk' AND territory_user_id NOT IN (SELECT id FROM {}.users)", TERRITORY_SCHEMA))
             .execute(&self.pool)
             .await
@@ -164,6 +271,64 @@ impl TestContext {
     }
 }
 
+/// Log in to "dk" through `/api/auth/login`, which must succeed, and return the response body
+///
+/// `ip` becomes the peer address, so tests that count failures per IP can keep
+/// their own counter; without it the attempt has no IP counter at all.
+pub async fn login<S, B>(
+    app: &S,
+    username: &str,
+    password: &str,
+    user_agent: Option<&str>,
+    ip: Option<&str>,
+) -> serde_json::Value
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let (status, body) = try_login(app, username, password, user_agent, ip).await;
+    assert!(status.is_success(), "Login failed with {}", status);
+    body
+}
+
+/// Like [`login`], but return the status too (with a `Null` body for errors)
+pub async fn try_login<S, B>(
+    app: &S,
+    username: &str,
+    password: &str,
+    user_agent: Option<&str>,
+    ip: Option<&str>,
+) -> (StatusCode, serde_json::Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let mut req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }));
+    if let Some(user_agent) = user_agent {
+        req = req.insert_header(("User-Agent", user_agent));
+    }
+    if let Some(ip) = ip {
+        req = req.peer_addr(format!("{}:52100", ip).parse().unwrap());
+    }
+
+    let resp = test::call_service(app, req.to_request()).await;
+    let status = resp.status();
+
+    let body = if status.is_success() {
+        test::read_body_json(resp).await
+    } else {
+        serde_json::Value::Null
+    };
+
+    (status, body)
+}
+
 // ============================================================================
 // Internal helper functions used by TestContext
 // ============================================================================
@@ -176,6 +341,13 @@ fn create_token_service() -> Arc<TokenService> {
     ))
 }
 
+fn create_territory_resolver() -> Arc<TerritoryResolver> {
+    Arc::new(
+        TerritoryResolver::new("dk", SchemaLayout::Single, ["dk"])
+            .expect("Failed to create territory resolver"),
+    )
+}
+
 async fn setup_test_data(pool: &PgPool) {
     // Ensure Denmark territory exists
     sqlx::query(
@@ -232,6 +404,35 @@ async fn create_test_invitation_with_id_for_email(
     (id, token)
 }
 
+/// Create a group invitation token with `max_uses` and return its UUID
+async fn create_limited_invitation_with_id(
+    pool: &PgPool,
+    schema: &str,
+    max_uses: i32,
+) -> (Uuid, String) {
+    let unique_id = Uuid::new_v4().to_string().replace("-", "");
+    let token = format!("test_limited_{}", unique_id);
+    let expires_at = Utc::now() + Duration::days(7);
+
+    let id: Uuid = sqlx::query_scalar(&format!(
+        r#"
+        INSERT INTO {}.invitation_tokens 
+        (token, token_type, max_uses, current_uses, expires_at, is_active)
+        VALUES ($1, 'group', $2, 0, $3, true)
+        RETURNING id
+        "#,
+        schema
+    ))
+    .bind(&token)
+    .bind(max_uses)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await
+    .expect("Failed to create limited invitation");
+
+    (id, token)
+}
+
 /// Create a test invitation token with optional user_id and return its UUID
 async fn create_test_invitation_with_id_and_user(
     pool: &PgPool,
@@ -294,13 +495,13 @@ async fn create_test_user_with_id(
     schema: &str,
 ) -> (Uuid, String, String, Option<String>) {
     // Generate unique username (must be globally unique)
-    let username = format!("testuser_{}", Uuid::new_v4().to_string()[..8].to_string());
+    let username = format!("testuser_{}", &Uuid::new_v4().to_string()[..8]);
 
     // Email is optional (50% chance for testing both scenarios)
     let email = if rand::random::<bool>() {
         Some(format!(
             "testuser_{}@test.dk",
-            Uuid::new_v4().to_string()[..8].to_string()
+            &Uuid::new_v4().to_string()[..8]
         ))
     } else {
         None
@@ -384,3 +585,12 @@ async fn create_revoked_invitation_with_id(pool: &PgPool, schema: &str) -> (Uuid
 
     (id, token)
 }
+
+/// Extract the token starting with `prefix` from an emailed link
+pub fn token_from_link(body: &str, prefix: &str) -> String {
+    let start = body.find(prefix).expect("Email should contain a token");
+    body[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect()
+}
@@ -0,0 +1,338 @@
+use actix_web::{test, web, App};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::common::*;
+
+#[actix_web::test]
+async fn test_audit_log_records_and_lists_auth_events() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+
+    let (admin_id, admin_username, admin_password, _email) = ctx.create_user().await;
+    let (member_id, member_username, member_password, _email) = ctx.create_user().await;
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, 'territory_admin' FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(admin_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let member_identity: Uuid = sqlx::query_scalar(
+        "SELECT id FROM global.user_identities WHERE territory_code = 'dk' AND territory_user_id = $1",
+    )
+    .bind(member_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/logout",
+                        web::post().to(auth_service::handlers::auth::logout),
+                    ),
+            )
+            .service(
+                web::scope("/api/audit")
+                    .wrap(auth_service::middleware::RequirePermission(
+                        auth_service::services::permissions::AUDIT_READ,
+                    ))
+                    .wrap(auth_service::middleware::JwtAuth)
+                    .route(
+                        "",
+                        web::get().to(auth_service::handlers::audit::list_audit_entries),
+                    ),
+            ),
+    )
+    .await;
+
+    let login = |username: &str, password: &str| {
+        test::TestRequest::post()
+            .uri("/api/auth/login")
+            .peer_addr("203.0.113.9:52100".parse().unwrap())
+            .set_json(json!({
+                "username": username,
+                "password": password,
+                "territory_code": "dk"
+            }))
+            .to_request()
+    };
+
+    // A failed login, a successful one and a logout
+    let resp = test::call_service(&app, login(&member_username, "WrongPassword123!")).await;
+    assert_eq!(resp.status(), 401);
+
+    let member: serde_json::Value =
+        test::call_and_read_body_json(&app, login(&member_username, &member_password)).await;
+    let member_token = member["access_token"].as_str().unwrap().to_string();
+
+    let get = |query: &str, access_token: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/audit?{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .to_request()
+    };
+
+    // Members without the permission are turned away by the middleware
+    match test::try_call_service(&app, get("", &member_token)).await {
+        Ok(resp) => assert_eq!(resp.status(), 403),
+        Err(err) => assert_eq!(err.as_response_error().status_code(), 403),
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/logout")
+        .set_json(json!({ "refresh_token": member["refresh_token"] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let admin: serde_json::Value =
+        test::call_and_read_body_json(&app, login(&admin_username, &admin_password)).await;
+    let admin_token = admin["access_token"].as_str().unwrap().to_string();
+
+    // Newest first, filtered by actor and action prefix
+    let page: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        get(
+            &format!("user_id={}&action=auth.*", member_identity),
+            &admin_token,
+        ),
+    )
+    .await;
+    let actions: Vec<&str> = page["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["auth.logout", "auth.login", "auth.login_failed"]);
+    assert_eq!(page["total"], 3);
+    assert_eq!(page["entries"][2]["changes"]["reason"], "invalid_password");
+    assert_eq!(page["entries"][1]["ip_address"], "203.0.113.9");
+    assert_eq!(page["entries"][1]["territory_code"], "dk");
+
+    let page: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        get(
+            &format!("user_id={}&action=auth.login&per_page=1", member_identity),
+            &admin_token,
+        ),
+    )
+    .await;
+    assert_eq!(page["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(page["total"], 1);
+
+    let resp = test::call_service(&app, get("per_page=0", &admin_token)).await;
+    assert_eq!(resp.status(), 400);
+
+    // Forget the failed attempt so repeated runs never lock the address out
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'ip' AND key = '203.0.113.9'")
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_audit_chain_detects_tampering() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+
+    // A territory of its own, so tampering never touches the shared "dk" chain
+    let territory = format!("audit-{}", &Uuid::new_v4().simple().to_string()[..8]);
+    sqlx::query(
+        r#"
+        INSERT INTO global.territories (code, name, type, is_active)
+        VALUES ($1, 'Audit Test', 'country', true)
+        "#,
+    )
+    .bind(&territory)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    for step in 1..=3 {
+        let entry = shared_lib::AuditEntry::new("test.step")
+            .territory(&territory)
+            .changes(json!({ "step": step }));
+        shared_lib::record_audit(&ctx.pool, &entry).await.unwrap();
+    }
+
+    let checkpoints =
+        auth_service::services::create_audit_checkpoints(&ctx.pool, &ctx.token_service)
+            .await
+            .unwrap();
+    assert!(checkpoints
+        .iter()
+        .any(|checkpoint| checkpoint.chain_id == territory && checkpoint.seq == 3));
+
+    let report =
+        auth_service::services::verify_audit_log(&ctx.pool, &ctx.token_service, &territory)
+            .await
+            .unwrap();
+    assert!(report.valid, "{:?}", report.broken_link);
+    assert_eq!(report.entries_checked, 3);
+    assert_eq!(report.head_seq, 3);
+    assert_eq!(report.checkpoints_checked, 1);
+
+    // Editing an entry breaks its own hash
+    sqlx::query(
+        r#"UPDATE global.audit_log SET changes = '{"step": 20}' WHERE chain_id = $1 AND seq = 2"#,
+    )
+    .bind(&territory)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let report =
+        auth_service::services::verify_audit_log(&ctx.pool, &ctx.token_service, &territory)
+            .await
+            .unwrap();
+    assert!(!report.valid);
+    assert_eq!(report.entries_checked, 1);
+    let broken = report.broken_link.unwrap();
+    assert_eq!(broken.seq, 2);
+    assert!(broken.entry_id.is_some());
+    assert_eq!(broken.reason, "Entry content does not match its hash");
+
+    // Cutting entries off the end is caught by the signed checkpoint
+    sqlx::query(
+        r#"UPDATE global.audit_log SET changes = '{"step": 2}' WHERE chain_id = $1 AND seq = 2"#,
+    )
+    .bind(&territory)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+    sqlx::query("DELETE FROM global.audit_log WHERE chain_id = $1 AND seq = 3")
+        .bind(&territory)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    let report =
+        auth_service::services::verify_audit_log(&ctx.pool, &ctx.token_service, &territory)
+            .await
+            .unwrap();
+    assert!(!report.valid);
+    assert_eq!(report.entries_checked, 2);
+    assert_eq!(report.broken_link.unwrap().seq, 3);
+
+    // A checkpoint that was not signed by us is rejected
+    sqlx::query("DELETE FROM global.audit_checkpoints WHERE chain_id = $1")
+        .bind(&territory)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        r#"
+        INSERT INTO global.audit_checkpoints (chain_id, seq, hash, signature)
+        SELECT chain_id, seq, hash, 'forged' FROM global.audit_log
+        WHERE chain_id = $1 AND seq = 1
+        "#,
+    )
+    .bind(&territory)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let report =
+        auth_service::services::verify_audit_log(&ctx.pool, &ctx.token_service, &territory)
+            .await
+            .unwrap();
+    assert!(!report.valid);
+    let broken = report.broken_link.unwrap();
+    assert_eq!(broken.seq, 1);
+    assert_eq!(broken.reason, "Checkpoint signature is invalid");
+
+    // Territory admins verify their own territory through the API
+    let (admin_id, admin_username, admin_password, _email) = ctx.create_user().await;
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, 'territory_admin' FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(admin_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            )
+            .service(
+                web::scope("/api/audit")
+                    .wrap(auth_service::middleware::RequirePermission(
+                        auth_service::services::permissions::AUDIT_READ,
+                    ))
+                    .wrap(auth_service::middleware::JwtAuth)
+                    .route(
+                        "/verify",
+                        web::get().to(auth_service::handlers::audit::verify_audit_entries),
+                    ),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": admin_username,
+            "password": admin_password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let admin: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+    // Other territories' chains are out of reach
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/audit/verify?territory_code={}", territory))
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", admin["access_token"].as_str().unwrap()),
+        ))
+        .to_request();
+    let report: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(report["chain_id"], "dk");
+    assert_eq!(report["valid"], true, "{}", report);
+    assert!(report["entries_checked"].as_i64().unwrap() >= 1);
+
+    for table in ["audit_checkpoints", "audit_log"] {
+        sqlx::query(&format!("DELETE FROM global.{} WHERE chain_id = $1", table))
+            .bind(&territory)
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+    }
+    sqlx::query("DELETE FROM global.territories WHERE code = $1")
+        .bind(&territory)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
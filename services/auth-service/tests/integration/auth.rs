@@ -1,5 +1,6 @@
 use actix_web::{test, web, App};
 use serde_json::json;
+use uuid::Uuid;
 
 use crate::common::*;
 
@@ -10,7 +11,9 @@ async fn test_register_success() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
             .route(
                 "/api/auth/register",
                 web::post().to(auth_service::handlers::auth::register),
@@ -60,6 +63,104 @@ async fn test_register_success() {
 
     assert_eq!(uses, 1, "Invitation should be marked as used");
 
+    // Downstream services hear about the new member and the used invitation
+    // once the outbox relay runs
+    let user_id: Uuid = sqlx::query_scalar("SELECT id FROM territory.users WHERE username = $1")
+        .bind(format!("newuser_{}", unique_id))
+        .fetch_one(&ctx.pool)
+        .await
+        .expect("Should find the registered user");
+    ctx.track_user(user_id);
+    let registered = ctx
+        .outbox_events::<shared_lib::UserRegistered>(user_id)
+        .await;
+    assert_eq!(registered.len(), 1);
+    assert_eq!(registered[0].territory, "dk");
+    assert_eq!(
+        registered[0].payload.username,
+        format!("newuser_{}", unique_id)
+    );
+    assert_eq!(registered[0].subject(), "territory.dk.user.registered");
+
+    let redeemed = ctx
+        .outbox_events::<shared_lib::InvitationRedeemed>(user_id)
+        .await;
+    assert_eq!(redeemed.len(), 1);
+    assert_eq!(
+        redeemed[0].payload.invitation_id,
+        registered[0].payload.invitation_id
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_concurrent_registrations_cannot_overuse_invitation() {
+    let mut ctx = TestContext::new().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .route(
+                "/api/auth/register",
+                web::post().to(auth_service::handlers::auth::register),
+            ),
+    )
+    .await;
+
+    // One use left for two people
+    let invitation_token = ctx.create_limited_invitation(1).await;
+
+    let register = || {
+        let unique_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+        test::TestRequest::post()
+            .uri("/api/auth/register")
+            .set_json(json!({
+                "username": format!("racer_{}", unique_id),
+                "password": "StrongPassword123!",
+                "full_name": "Test User",
+                "territory_code": "dk",
+                "invitation_token": invitation_token
+            }))
+            .to_request()
+    };
+
+    let (first, second) = futures_util::future::join(
+        test::call_service(&app, register()),
+        test::call_service(&app, register()),
+    )
+    .await;
+
+    let mut statuses = vec![first.status().as_u16(), second.status().as_u16()];
+    for resp in [first, second] {
+        if resp.status() == 201 {
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            ctx.track_user(body["user"]["id"].as_str().unwrap().parse().unwrap());
+        }
+    }
+    statuses.sort();
+    assert_eq!(
+        statuses,
+        vec![201, 400],
+        "Only one registration may use the invitation"
+    );
+
+    let (uses, recorded): (i32, i64) = sqlx::query_as(
+        r#"
+        SELECT t.current_uses, (SELECT COUNT(*) FROM territory.invitation_uses u WHERE u.token_id = t.id)
+        FROM territory.invitation_tokens t
+        WHERE t.token = $1
+        "#,
+    )
+    .bind(&invitation_token)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!((uses, recorded), (1, 1));
+
     ctx.cleanup().await;
 }
 
@@ -70,7 +171,9 @@ async fn test_register_invalid_invitation() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
             .route(
                 "/api/auth/register",
                 web::post().to(auth_service::handlers::auth::register),
@@ -111,7 +214,9 @@ async fn test_register_expired_invitation() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
             .route(
                 "/api/auth/register",
                 web::post().to(auth_service::handlers::auth::register),
@@ -151,11 +256,12 @@ async fn test_register_expired_invitation() {
 async fn test_login_success() {
     let mut ctx = TestContext::new().await;
 
-    let (_user_id, username, password, _email) = ctx.create_user().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
 
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/login",
@@ -190,6 +296,11 @@ async fn test_login_success() {
         "Should return refresh token"
     );
 
+    let logins = ctx.outbox_events::<shared_lib::UserLoggedIn>(user_id).await;
+    assert_eq!(logins.len(), 1);
+    assert_eq!(logins[0].payload.user_id, user_id);
+    assert!(!logins[0].payload.mfa_verified);
+
     ctx.cleanup().await;
 }
 
@@ -202,6 +313,7 @@ async fn test_login_wrong_password() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/login",
@@ -234,6 +346,7 @@ async fn test_login_nonexistent_user() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/login",
@@ -242,8 +355,9 @@ async fn test_login_nonexistent_user() {
     )
     .await;
 
+    // Unique per run, so repeated runs never lock the username out
     let login_req = json!({
-        "username": "nonexistent_user",
+        "username": format!("nonexistent_{}", &Uuid::new_v4().simple().to_string()[..8]),
         "password": "Password123!",
         "territory_code": "dk"
     });
@@ -268,6 +382,7 @@ async fn test_refresh_token_success() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/login",
@@ -297,7 +412,7 @@ async fn test_refresh_token_success() {
 
     let req = test::TestRequest::post()
         .uri("/api/auth/refresh")
-        .set_json(&json!({
+        .set_json(json!({
             "refresh_token": refresh_token,
             "territory_code": "dk"
         }))
@@ -324,6 +439,7 @@ async fn test_refresh_token_invalid() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/refresh",
@@ -334,7 +450,7 @@ async fn test_refresh_token_invalid() {
 
     let req = test::TestRequest::post()
         .uri("/api/auth/refresh")
-        .set_json(&json!({
+        .set_json(json!({
             "refresh_token": "invalid.token.here",
             "territory_code": "dk"
         }))
@@ -359,6 +475,7 @@ async fn test_logout() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/login",
@@ -388,7 +505,7 @@ async fn test_logout() {
 
     let req = test::TestRequest::post()
         .uri("/api/auth/logout")
-        .set_json(&json!({ "refresh_token": refresh_token }))
+        .set_json(json!({ "refresh_token": refresh_token }))
         .to_request();
 
     let resp = test::call_service(&app, req).await;
@@ -407,6 +524,7 @@ async fn test_me_endpoint() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/login",
@@ -476,6 +594,7 @@ async fn test_me_endpoint_unauthenticated() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .service(
                 web::scope("")
@@ -507,3 +626,321 @@ async fn test_me_endpoint_unauthenticated() {
 
     ctx.cleanup().await;
 }
+
+#[actix_web::test]
+async fn test_login_territory_not_served_by_pod() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    // Resolver for a pod that serves a different territory than the user's
+    let other_pod =
+        shared_lib::TerritoryResolver::new("no", shared_lib::SchemaLayout::Single, ["no"]).unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::new(other_pod))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            ),
+    )
+    .await;
+
+    let login_req = json!({
+        "username": username,
+        "password": password,
+        "territory_code": "dk"
+    });
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(&login_req)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.status(),
+        400,
+        "Login should fail for a territory this pod does not serve"
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_jwks_verifies_issued_tokens() {
+    use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey};
+    use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    // Sign with an Ed25519 key instead of the shared test secret
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+    let token_service = auth_service::services::TokenService::from_private_key_pem(
+        &signing_key.to_pkcs8_pem(LineEnding::LF).unwrap(),
+        900,
+        604800,
+    )
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::new(token_service))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            )
+            .route(
+                "/api/auth/.well-known/jwks.json",
+                web::get().to(auth_service::handlers::well_known::jwks),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let access_token = body["access_token"].as_str().unwrap();
+
+    let req = test::TestRequest::get()
+        .uri("/api/auth/.well-known/jwks.json")
+        .to_request();
+    let jwks: JwkSet = test::call_and_read_body_json(&app, req).await;
+
+    // A verifier holding only the JWKS can check the token
+    let kid = decode_header(access_token).unwrap().kid.unwrap();
+    let jwk = jwks.find(&kid).expect("Signing key should be published");
+    let claims = decode::<auth_service::models::Claims>(
+        access_token,
+        &DecodingKey::from_jwk(jwk).unwrap(),
+        &Validation::new(jsonwebtoken::Algorithm::EdDSA),
+    )
+    .expect("Token should verify against the published key");
+
+    assert_eq!(claims.claims.username, username.to_lowercase());
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_refresh_token_reuse_revokes_family() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            )
+            .route(
+                "/api/auth/refresh",
+                web::post().to(auth_service::handlers::auth::refresh),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let original_token = body["refresh_token"].as_str().unwrap().to_string();
+
+    let refresh = |token: &str| {
+        test::TestRequest::post()
+            .uri("/api/auth/refresh")
+            .set_json(json!({
+                "refresh_token": token,
+                "territory_code": "dk"
+            }))
+            .to_request()
+    };
+
+    // Legitimate rotation
+    let resp = test::call_service(&app, refresh(&original_token)).await;
+    assert_eq!(resp.status(), 200, "First refresh should succeed");
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let rotated_token = body["refresh_token"].as_str().unwrap().to_string();
+    assert_ne!(rotated_token, original_token, "Refresh token should rotate");
+
+    // Replaying the rotated-out token is detected...
+    let resp = test::call_service(&app, refresh(&original_token)).await;
+    assert_eq!(
+        resp.status(),
+        401,
+        "Replayed refresh token should be rejected"
+    );
+
+    // ...and revokes the newer token in the same family
+    let resp = test::call_service(&app, refresh(&rotated_token)).await;
+    assert_eq!(
+        resp.status(),
+        401,
+        "Whole session family should be revoked after reuse"
+    );
+
+    let reuse_events: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM global.audit_log
+        WHERE action = 'session.refresh_token_reused'
+          AND resource_id = (
+              SELECT family_id::text FROM global.sessions WHERE token_hash = $1
+          )
+        "#,
+    )
+    .bind(auth_service::services::hash_refresh_token(&original_token))
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(reuse_events, 1, "Reuse should be recorded in the audit log");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_refresh_token_of_deactivated_user_ends_family() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            )
+            .route(
+                "/api/auth/refresh",
+                web::post().to(auth_service::handlers::auth::refresh),
+            ),
+    )
+    .await;
+
+    let tokens = login(&app, &username, &password, None, None).await;
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+
+    let set_active = |is_active: bool| {
+        sqlx::query("UPDATE territory.users SET is_active = $2 WHERE id = $1")
+            .bind(user_id)
+            .bind(is_active)
+            .execute(&ctx.pool)
+    };
+    let refresh = || {
+        test::TestRequest::post()
+            .uri("/api/auth/refresh")
+            .set_json(json!({
+                "refresh_token": refresh_token,
+                "territory_code": "dk"
+            }))
+            .to_request()
+    };
+
+    set_active(false).await.unwrap();
+    let resp = test::call_service(&app, refresh()).await;
+    assert_eq!(resp.status(), 401, "Deactivated user should not refresh");
+
+    // The token was not exchanged; its family is revoked instead
+    let family: Vec<(Option<chrono::DateTime<chrono::Utc>>, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT rotated_at, revoked_reason FROM global.sessions
+        WHERE family_id = (SELECT family_id FROM global.sessions WHERE token_hash = $1)
+        "#,
+    )
+    .bind(auth_service::services::hash_refresh_token(refresh_token))
+    .fetch_all(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(family, vec![(None, Some("user_inactive".to_string()))]);
+
+    // Reactivation does not bring the session back
+    set_active(true).await.unwrap();
+    let resp = test::call_service(&app, refresh()).await;
+    assert_eq!(resp.status(), 401, "Revoked family should stay revoked");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_refresh_token_family_lifetime() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            )
+            .route(
+                "/api/auth/refresh",
+                web::post().to(auth_service::handlers::auth::refresh),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let refresh_token = body["refresh_token"].as_str().unwrap();
+
+    // Refresh token itself is still valid, but the family has reached its absolute lifetime
+    sqlx::query(
+        "UPDATE global.sessions SET family_expires_at = NOW() - INTERVAL '1 minute' WHERE token_hash = $1",
+    )
+    .bind(auth_service::services::hash_refresh_token(refresh_token))
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/refresh")
+        .set_json(json!({
+            "refresh_token": refresh_token,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(
+        resp.status(),
+        401,
+        "Refresh should fail once the session family has expired"
+    );
+
+    ctx.cleanup().await;
+}
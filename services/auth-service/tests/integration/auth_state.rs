@@ -0,0 +1,255 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::StatusCode,
+    test, web, App,
+};
+use auth_service::services::{schemas_without_member_triggers, AuthStateCache, AuthStateListener};
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+
+use crate::common::*;
+
+/// Client IP of this file's logins, so they have their own IP throttle counter
+const CLIENT_IP: &str = "198.51.100.61";
+
+/// Longest wait for a NOTIFY to reach the listener
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+macro_rules! cached_app {
+    ($ctx:expr, $cache:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new($ctx.pool.clone()))
+                .app_data(web::Data::from($ctx.territories.clone()))
+                .app_data(web::Data::from($ctx.token_service.clone()))
+                .app_data(web::Data::from($cache.clone()))
+                .service(
+                    web::scope("/api/auth")
+                        .route(
+                            "/login",
+                            web::post().to(auth_service::handlers::auth::login),
+                        )
+                        .service(
+                            web::scope("/sessions")
+                                .wrap(auth_service::middleware::JwtAuth)
+                                .route(
+                                    "",
+                                    web::get().to(auth_service::handlers::session::get_sessions),
+                                )
+                                .route(
+                                    "/{id}",
+                                    web::delete()
+                                        .to(auth_service::handlers::session::delete_session),
+                                ),
+                        ),
+                ),
+        )
+        .await
+    };
+}
+
+async fn list_sessions<S, B>(app: &S, access_token: &str) -> StatusCode
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .to_request();
+
+    match app.call(req).await {
+        Ok(resp) => resp.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+/// Poll until `access_token` is rejected, or give up after `NOTIFY_TIMEOUT`
+async fn rejected_after_notify<S, B>(app: &S, access_token: &str) -> bool
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let started = std::time::Instant::now();
+    while started.elapsed() < NOTIFY_TIMEOUT {
+        if list_sessions(app, access_token).await == StatusCode::UNAUTHORIZED {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+async fn set_active(ctx: &TestContext, user_id: Uuid, is_active: bool) {
+    sqlx::query("UPDATE territory.users SET is_active = $1 WHERE id = $2")
+        .bind(is_active)
+        .bind(user_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn test_cache_hits_do_not_read_the_database() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    // No listener: only the TTL would ever drop an entry
+    let cache = Arc::new(AuthStateCache::new(Duration::from_secs(60)));
+    let app = cached_app!(ctx, cache);
+
+    let tokens = login(&app, &username, &password, Some("Laptop"), Some(CLIENT_IP)).await;
+    let token = tokens["access_token"].as_str().unwrap();
+    assert_eq!(list_sessions(&app, token).await, StatusCode::OK);
+
+    // The deactivation goes unseen until the entry is dropped
+    set_active(&ctx, user_id, false).await;
+    assert_eq!(list_sessions(&app, token).await, StatusCode::OK);
+
+    cache.clear();
+    assert_eq!(list_sessions(&app, token).await, StatusCode::UNAUTHORIZED);
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_schemas_without_member_triggers_are_not_cached() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    // territory.users has the triggers; a schema that never got them does not
+    let missing = schemas_without_member_triggers(
+        &ctx.pool,
+        &["territory".to_string(), "territory_zz".to_string()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(missing, vec!["territory_zz".to_string()]);
+
+    // No listener either, yet the deactivation is seen straight away
+    let cache = Arc::new(
+        AuthStateCache::new(Duration::from_secs(60)).uncached_schemas(["territory".to_string()]),
+    );
+    let app = cached_app!(ctx, cache);
+
+    let tokens = login(&app, &username, &password, Some("Laptop"), Some(CLIENT_IP)).await;
+    let token = tokens["access_token"].as_str().unwrap();
+    assert_eq!(list_sessions(&app, token).await, StatusCode::OK);
+
+    set_active(&ctx, user_id, false).await;
+    assert_eq!(list_sessions(&app, token).await, StatusCode::UNAUTHORIZED);
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_notifications_invalidate_revoked_sessions_and_deactivated_users() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    let cache = Arc::new(AuthStateCache::new(Duration::from_secs(60)));
+    let listener = AuthStateListener::connect(&ctx.pool)
+        .await
+        .expect("Failed to listen for auth state changes");
+    tokio::spawn(listener.cache(cache.clone()).run());
+    let app = cached_app!(ctx, cache);
+
+    let home = login(&app, &username, &password, Some("Home"), Some(CLIENT_IP)).await;
+    let home_token = home["access_token"].as_str().unwrap();
+    let centre = login(
+        &app,
+        &username,
+        &password,
+        Some("Community centre"),
+        Some(CLIENT_IP),
+    )
+    .await;
+    let centre_token = centre["access_token"].as_str().unwrap();
+    assert_eq!(list_sessions(&app, home_token).await, StatusCode::OK);
+    assert_eq!(list_sessions(&app, centre_token).await, StatusCode::OK);
+
+    // Remote logout of the community centre from home
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", home_token)))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    let centre_session = sessions
+        .iter()
+        .find(|s| s["user_agent"] == "Community centre")
+        .expect("Community centre session should be listed");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/api/auth/sessions/{}",
+            centre_session["id"].as_str().unwrap()
+        ))
+        .insert_header(("Authorization", format!("Bearer {}", home_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    assert!(
+        rejected_after_notify(&app, centre_token).await,
+        "Revoked session should be dropped from the cache"
+    );
+    assert_eq!(list_sessions(&app, home_token).await, StatusCode::OK);
+
+    // Deactivation locks out the remaining session
+    set_active(&ctx, user_id, false).await;
+    assert!(
+        rejected_after_notify(&app, home_token).await,
+        "Deactivated user should be dropped from the cache"
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_notifications_invalidate_changed_roles() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+    let (user_id, _username, _password, _email) = ctx.create_user().await;
+
+    let cache = Arc::new(AuthStateCache::new(Duration::from_secs(60)));
+    let listener = AuthStateListener::connect(&ctx.pool)
+        .await
+        .expect("Failed to listen for auth state changes");
+    tokio::spawn(listener.cache(cache.clone()).run());
+
+    let schema = ctx.territories.schema_for("dk").unwrap().to_string();
+    let member = cache
+        .member_state(&ctx.pool, &schema, "dk", user_id)
+        .await
+        .unwrap()
+        .expect("Active member should be found");
+    assert!(member.roles.roles.is_empty());
+
+    sqlx::query(
+        "INSERT INTO global.territory_managers (user_id, territory_code, role) VALUES ($1, 'dk', 'moderator')",
+    )
+    .bind(member.identity_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let started = std::time::Instant::now();
+    let mut roles = Vec::new();
+    while started.elapsed() < NOTIFY_TIMEOUT {
+        roles = cache
+            .member_state(&ctx.pool, &schema, "dk", user_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .roles
+            .roles;
+        if !roles.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(roles, vec!["moderator".to_string()]);
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,267 @@
+use actix_web::{test, web, App};
+use serde_json::json;
+
+use crate::common::*;
+
+#[actix_web::test]
+async fn test_register_sends_verification_email() {
+    let mut ctx = TestContext::new().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/register",
+                        web::post().to(auth_service::handlers::auth::register),
+                    )
+                    .route(
+                        "/verify-email/{token}",
+                        web::get().to(auth_service::handlers::email::verify_email),
+                    ),
+            ),
+    )
+    .await;
+
+    let invitation_token = ctx.create_invitation().await;
+
+    let unique_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+    let email = format!("verify_{}@test.dk", unique_id);
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "email": email,
+            "username": format!("verify_{}", unique_id),
+            "password": "StrongPassword123!",
+            "full_name": "Test User",
+            "territory_code": "dk",
+            "invitation_token": invitation_token
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let user_id: uuid::Uuid = body["user"]["id"].as_str().unwrap().parse().unwrap();
+    ctx.track_user(user_id);
+    assert_eq!(body["user"]["is_verified"], false);
+
+    let sent = ctx
+        .mailer
+        .last_sent_to(&email)
+        .expect("Verification email should be sent");
+    assert!(sent.body.contains("/api/auth/verify-email/"));
+    let token = token_from_link(&sent.body, "evt_");
+
+    let verify = || {
+        test::TestRequest::get()
+            .uri(&format!(
+                "/api/auth/verify-email/{}?territory_code=dk",
+                token
+            ))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, verify()).await;
+    assert_eq!(resp.status(), 200, "Emailed link should verify the address");
+
+    let is_verified: bool =
+        sqlx::query_scalar("SELECT is_verified FROM territory.users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert!(is_verified);
+
+    // Links are single use
+    let resp = test::call_service(&app, verify()).await;
+    assert_eq!(resp.status(), 400);
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_change_email_applies_after_verification() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, old_email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/verify-email/{token}",
+                        web::get().to(auth_service::handlers::email::verify_email),
+                    )
+                    .service(
+                        web::scope("")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/email",
+                                web::put().to(auth_service::handlers::email::change_email),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    let new_email = format!("changed_{}@test.dk", &uuid::Uuid::new_v4().to_string()[..8]);
+    let change = |current_password: &str| {
+        test::TestRequest::put()
+            .uri("/api/auth/email")
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .set_json(json!({
+                "email": new_email,
+                "current_password": current_password
+            }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, change("WrongPassword123!")).await;
+    assert_eq!(resp.status(), 401, "Current password is required");
+    assert!(ctx.mailer.last_sent_to(&new_email).is_none());
+
+    let resp = test::call_service(&app, change(&password)).await;
+    assert_eq!(resp.status(), 202);
+
+    // The old address stays until the new one is confirmed
+    let current_email = || async {
+        sqlx::query_scalar::<_, Option<String>>("SELECT email FROM territory.users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(current_email().await, old_email);
+
+    let sent = ctx
+        .mailer
+        .last_sent_to(&new_email)
+        .expect("Verification email should go to the new address");
+    let token = token_from_link(&sent.body, "evt_");
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/auth/verify-email/{}?territory_code=dk",
+            token
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    assert_eq!(current_email().await, Some(new_email.clone()));
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_invitations_require_verified_email_when_configured() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    sqlx::query("UPDATE territory.users SET is_verified = false WHERE id = $1")
+        .bind(user_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::post()
+                                    .to(auth_service::handlers::invitation::create_invitation),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    let set_required_for = |features: serde_json::Value| {
+        let pool = ctx.pool.clone();
+        async move {
+            sqlx::query(
+                "UPDATE territory.settings SET value = $1 WHERE key = 'verified_email_required_for'",
+            )
+            .bind(features)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+    };
+
+    set_required_for(json!(["invitations"])).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/invitations")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(json!({
+            "token_type": "single_use",
+            "email": format!("invited_{}@test.dk", &uuid::Uuid::new_v4().to_string()[..8]),
+            "max_uses": 1,
+            "expires_in_days": 7,
+            "purpose": "Test invitation"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    set_required_for(json!([])).await;
+
+    assert_eq!(
+        resp.status(),
+        403,
+        "Unverified user should not create invitations"
+    );
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,228 @@
+use actix_web::{test, web, App};
+use auth_service::services::{handle_introspection_message, hash_client_secret};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::common::*;
+
+/// Register a service client and return (client_id, client_secret)
+async fn create_service_client(ctx: &TestContext) -> (String, String) {
+    let client_id = format!("test-service-{}", &Uuid::new_v4().simple().to_string()[..8]);
+    let client_secret = Uuid::new_v4().simple().to_string();
+
+    sqlx::query(
+        "INSERT INTO global.service_clients (client_id, name, secret_hash) VALUES ($1, $2, $3)",
+    )
+    .bind(&client_id)
+    .bind("Test service")
+    .bind(hash_client_secret(&client_secret))
+    .execute(&ctx.pool)
+    .await
+    .expect("Failed to create service client");
+
+    (client_id, client_secret)
+}
+
+async fn public_key_hash_of(ctx: &TestContext, user_id: Uuid) -> String {
+    sqlx::query_scalar(
+        r#"
+        SELECT public_key_hash FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .expect("User should have a global identity")
+}
+
+fn basic_auth(client_id: &str, client_secret: &str) -> String {
+    format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", client_id, client_secret))
+    )
+}
+
+#[actix_web::test]
+async fn test_introspect_access_token() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
+    let (client_id, client_secret) = create_service_client(&ctx).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/logout",
+                        web::post().to(auth_service::handlers::auth::logout),
+                    )
+                    .route(
+                        "/introspect",
+                        web::post().to(auth_service::handlers::introspect),
+                    ),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let login: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let access_token = login["access_token"].as_str().unwrap().to_string();
+
+    let introspect = |authorization: Option<String>, token: &str| {
+        let mut req = test::TestRequest::post()
+            .uri("/api/auth/introspect")
+            .set_form([("token", token), ("token_type_hint", "access_token")]);
+        if let Some(authorization) = authorization {
+            req = req.insert_header(("Authorization", authorization));
+        }
+        req.to_request()
+    };
+
+    // Only registered service clients may ask
+    let resp = test::call_service(&app, introspect(None, &access_token)).await;
+    assert_eq!(resp.status(), 401);
+    assert!(resp.headers().contains_key("www-authenticate"));
+
+    let resp = test::call_service(
+        &app,
+        introspect(Some(basic_auth(&client_id, "wrong-secret")), &access_token),
+    )
+    .await;
+    assert_eq!(resp.status(), 401);
+
+    let credentials = basic_auth(&client_id, &client_secret);
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, introspect(Some(credentials.clone()), &access_token))
+            .await;
+
+    let public_key_hash = public_key_hash_of(&ctx, user_id).await;
+    assert_eq!(body["active"], true);
+    assert_eq!(body["token_type"], "access_token");
+    assert_eq!(body["sub"], public_key_hash);
+    assert_eq!(body["territory_code"], "dk");
+    assert_eq!(body["user_id"], user_id.to_string());
+    assert_eq!(body["username"], username);
+    assert_eq!(body["roles"], json!([]));
+    assert!(body["exp"].as_i64().unwrap() > chrono::Utc::now().timestamp());
+
+    let last_used: Option<chrono::DateTime<chrono::Utc>> =
+        sqlx::query_scalar("SELECT last_used_at FROM global.service_clients WHERE client_id = $1")
+            .bind(&client_id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert!(last_used.is_some(), "Client use should be recorded");
+
+    // Pods ask over NATS with the same answer
+    let response = handle_introspection_message(
+        &ctx.pool,
+        &ctx.token_service,
+        &ctx.territories,
+        json!({ "token": access_token }).to_string().as_bytes(),
+    )
+    .await;
+    assert!(response.active);
+    assert_eq!(response.user_id, Some(user_id));
+
+    // Garbage is simply inactive
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, introspect(Some(credentials.clone()), "not-a-token"))
+            .await;
+    assert_eq!(body, json!({ "active": false }));
+
+    // Logging out revokes the session the token is bound to
+    let req = test::TestRequest::post()
+        .uri("/api/auth/logout")
+        .set_json(json!({ "refresh_token": login["refresh_token"] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, introspect(Some(credentials.clone()), &access_token))
+            .await;
+    assert_eq!(body, json!({ "active": false }));
+
+    let response =
+        handle_introspection_message(&ctx.pool, &ctx.token_service, &ctx.territories, b"not json")
+            .await;
+    assert!(!response.active);
+
+    sqlx::query("DELETE FROM global.service_clients WHERE client_id = $1")
+        .bind(&client_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_introspect_checks_identity_and_deactivation() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, _password, _email) = ctx.create_user().await;
+
+    let token = ctx
+        .token_service
+        .generate_access_token("unknown-key", "dk", user_id, &username, None, false)
+        .unwrap();
+
+    // The subject must match the user's global identity
+    let response = handle_introspection_message(
+        &ctx.pool,
+        &ctx.token_service,
+        &ctx.territories,
+        json!({ "token": token }).to_string().as_bytes(),
+    )
+    .await;
+    assert!(!response.active);
+
+    let public_key_hash = public_key_hash_of(&ctx, user_id).await;
+    let token = ctx
+        .token_service
+        .generate_access_token(&public_key_hash, "dk", user_id, &username, None, false)
+        .unwrap();
+    let request = json!({ "token": token }).to_string();
+
+    let response = handle_introspection_message(
+        &ctx.pool,
+        &ctx.token_service,
+        &ctx.territories,
+        request.as_bytes(),
+    )
+    .await;
+    assert!(response.active);
+    assert_eq!(response.sid, None);
+
+    sqlx::query("UPDATE territory.users SET is_active = false WHERE id = $1")
+        .bind(user_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    let response = handle_introspection_message(
+        &ctx.pool,
+        &ctx.token_service,
+        &ctx.territories,
+        request.as_bytes(),
+    )
+    .await;
+    assert!(!response.active);
+
+    ctx.cleanup().await;
+}
@@ -12,6 +12,7 @@ async fn test_create_invitation_authenticated() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .service(
                 web::scope("/api/auth")
@@ -81,6 +82,10 @@ async fn test_create_invitation_authenticated() {
 
     let body: serde_json::Value = test::read_body_json(resp).await;
     assert!(body["token"].is_string(), "Should return invitation token");
+    assert!(
+        body["signed_token"].is_string(),
+        "Should return the signed form of the token"
+    );
 
     ctx.cleanup().await;
 }
@@ -92,6 +97,7 @@ async fn test_create_invitation_unauthenticated() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/invitations",
@@ -128,6 +134,7 @@ async fn test_list_user_invitations() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .service(
                 web::scope("/api/auth")
@@ -193,6 +200,7 @@ async fn test_revoke_invitation() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .service(
                 web::scope("/api/auth")
@@ -264,6 +272,7 @@ async fn test_validate_invitation_maxed_out() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/invitations/validate/{token}",
@@ -294,6 +303,7 @@ async fn test_validate_invitation_revoked() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/api/auth/invitations/validate/{token}",
@@ -327,7 +337,9 @@ async fn test_invitation_email_mismatch() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
             .route(
                 "/api/auth/register",
                 web::post().to(auth_service::handlers::auth::register),
@@ -356,3 +368,821 @@ async fn test_invitation_email_mismatch() {
 
     ctx.cleanup().await;
 }
+
+#[actix_web::test]
+async fn test_community_invitation_adds_member_on_registration() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+    let community_id = ctx.create_community().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/register",
+                        web::post().to(auth_service::handlers::auth::register),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::post()
+                                    .to(auth_service::handlers::invitation::create_invitation),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    let create = |community_id: Option<uuid::Uuid>, role: Option<&str>| {
+        let unique_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+        test::TestRequest::post()
+            .uri("/api/auth/invitations")
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .set_json(json!({
+                "token_type": "single_use",
+                "email": format!("neighbour_{}@test.dk", unique_id),
+                "max_uses": 1,
+                "expires_in_days": 7,
+                "community_id": community_id,
+                "role": role
+            }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, create(Some(community_id), None)).await;
+    assert_eq!(
+        resp.status(),
+        403,
+        "Non-members cannot invite into a community"
+    );
+
+    let resp = test::call_service(&app, create(None, Some("organizer"))).await;
+    assert_eq!(resp.status(), 400, "Roles need a community");
+
+    // Organizers may invite members, but not hand out roles
+    sqlx::query(
+        "INSERT INTO territory.community_members (user_id, community_id, role) VALUES ($1, $2, 'organizer')",
+    )
+    .bind(user_id)
+    .bind(community_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let resp = test::call_service(&app, create(Some(community_id), Some("admin"))).await;
+    assert_eq!(resp.status(), 403, "Organizers cannot hand out admin roles");
+
+    let resp = test::call_service(&app, create(Some(community_id), Some("chieftain"))).await;
+    assert_eq!(resp.status(), 400, "Unknown community roles are rejected");
+
+    let resp = test::call_service(&app, create(Some(community_id), None)).await;
+    assert_eq!(resp.status(), 201);
+    let invitation: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(invitation["community_id"], community_id.to_string());
+    assert_eq!(invitation["role"], "member");
+
+    // Redeeming the invitation makes the newcomer a member
+    let unique_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "email": invitation["email"],
+            "username": format!("neighbour_{}", unique_id),
+            "password": "StrongPassword123!",
+            "territory_code": "dk",
+            "invitation_token": invitation["token"]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let new_user_id: uuid::Uuid = body["user"]["id"].as_str().unwrap().parse().unwrap();
+    ctx.track_user(new_user_id);
+
+    let role: String = sqlx::query_scalar(
+        "SELECT role FROM territory.community_members WHERE user_id = $1 AND community_id = $2",
+    )
+    .bind(new_user_id)
+    .bind(community_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .expect("Newcomer should be a community member");
+    assert_eq!(role, "member");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_username_targeted_invitation() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/register",
+                        web::post().to(auth_service::handlers::auth::register),
+                    )
+                    .route(
+                        "/invitations/validate/{token}",
+                        web::get().to(auth_service::handlers::invitation::validate_invitation),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::post()
+                                    .to(auth_service::handlers::invitation::create_invitation),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    // No email needed - the username is the target
+    let invited_username = format!("friend_{}", &uuid::Uuid::new_v4().to_string()[..8]);
+    let req = test::TestRequest::post()
+        .uri("/api/auth/invitations")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(json!({
+            "token_type": "single_use",
+            "username": invited_username,
+            "max_uses": 1,
+            "expires_in_days": 7
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let invitation: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(invitation["username"], invited_username.as_str());
+    let invitation_token = invitation["token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/auth/invitations/validate/{}?territory_code=dk&username=someone_else",
+            invitation_token
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400, "Validation should check the username");
+
+    let register = |username: &str| {
+        test::TestRequest::post()
+            .uri("/api/auth/register")
+            .set_json(json!({
+                "username": username,
+                "password": "StrongPassword123!",
+                "territory_code": "dk",
+                "invitation_token": invitation_token
+            }))
+            .to_request()
+    };
+
+    let other_username = format!("other_{}", &uuid::Uuid::new_v4().to_string()[..8]);
+    let resp = test::call_service(&app, register(&other_username)).await;
+    assert_eq!(resp.status(), 400, "Only the invited username may register");
+
+    let resp = test::call_service(&app, register(&invited_username.to_uppercase())).await;
+    assert_eq!(resp.status(), 201, "Usernames match case-insensitively");
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    ctx.track_user(body["user"]["id"].as_str().unwrap().parse().unwrap());
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_existing_member_accepts_community_invitation() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+
+    let (admin_id, admin_username, admin_password, _email) = ctx.create_user().await;
+    let (member_id, member_username, member_password, _email) = ctx.create_user().await;
+    let (_other_id, other_username, other_password, _email) = ctx.create_user().await;
+    let community_id = ctx.create_community().await;
+
+    // Territory admins may invite into any community with any role
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, 'territory_admin' FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(admin_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::post()
+                                    .to(auth_service::handlers::invitation::create_invitation),
+                            )
+                            .route(
+                                "/{token}/accept",
+                                web::post()
+                                    .to(auth_service::handlers::invitation::accept_invitation),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let login = |username: &str, password: &str| {
+        test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({
+                "username": username,
+                "password": password,
+                "territory_code": "dk"
+            }))
+            .to_request()
+    };
+    let mut tokens = Vec::new();
+    for (username, password) in [
+        (&admin_username, &admin_password),
+        (&member_username, &member_password),
+        (&other_username, &other_password),
+    ] {
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, login(username, password)).await;
+        tokens.push(body["access_token"].as_str().unwrap().to_string());
+    }
+    let (admin_token, member_token, other_token) = (&tokens[0], &tokens[1], &tokens[2]);
+
+    let create = |body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/auth/invitations")
+            .insert_header(("Authorization", format!("Bearer {}", admin_token)))
+            .set_json(body)
+            .to_request()
+    };
+    let accept = |invitation_token: &str, access_token: &str| {
+        test::TestRequest::post()
+            .uri(&format!(
+                "/api/auth/invitations/{}/accept",
+                invitation_token
+            ))
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .to_request()
+    };
+
+    let invitation: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        create(json!({
+            "token_type": "single_use",
+            "username": member_username,
+            "max_uses": 1,
+            "expires_in_days": 7,
+            "community_id": community_id,
+            "role": "organizer"
+        })),
+    )
+    .await;
+    let invitation_token = invitation["token"].as_str().unwrap();
+
+    let resp = test::call_service(&app, accept(invitation_token, other_token)).await;
+    assert_eq!(resp.status(), 400, "Invitation is for another member");
+
+    let resp = test::call_service(&app, accept(invitation_token, member_token)).await;
+    assert_eq!(resp.status(), 200);
+
+    let role: String = sqlx::query_scalar(
+        "SELECT role FROM territory.community_members WHERE user_id = $1 AND community_id = $2",
+    )
+    .bind(member_id)
+    .bind(community_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .expect("Member should have joined the community");
+    assert_eq!(role, "organizer");
+
+    let redeemed = ctx
+        .outbox_events::<shared_lib::InvitationRedeemed>(member_id)
+        .await;
+    assert_eq!(redeemed.len(), 1);
+    assert_eq!(redeemed[0].payload.user_id, member_id);
+    assert_eq!(redeemed[0].payload.community_id, Some(community_id));
+
+    let resp = test::call_service(&app, accept(invitation_token, member_token)).await;
+    assert_eq!(resp.status(), 400, "Invitation is used up");
+
+    // Invitations promote, but never demote
+    let invite_as = |role: &str| {
+        create(json!({
+            "token_type": "single_use",
+            "username": member_username,
+            "max_uses": 1,
+            "expires_in_days": 7,
+            "community_id": community_id,
+            "role": role
+        }))
+    };
+    let community_role = || {
+        sqlx::query_scalar::<_, String>(
+            "SELECT role FROM territory.community_members WHERE user_id = $1 AND community_id = $2",
+        )
+        .bind(member_id)
+        .bind(community_id)
+        .fetch_one(&ctx.pool)
+    };
+
+    let promotion: serde_json::Value =
+        test::call_and_read_body_json(&app, invite_as("admin")).await;
+    let resp = test::call_service(
+        &app,
+        accept(promotion["token"].as_str().unwrap(), member_token),
+    )
+    .await;
+    assert_eq!(resp.status(), 200, "Invitation with a higher role promotes");
+    assert_eq!(community_role().await.unwrap(), "admin");
+
+    for role in ["organizer", "admin"] {
+        let invitation: serde_json::Value =
+            test::call_and_read_body_json(&app, invite_as(role)).await;
+        let resp = test::call_service(
+            &app,
+            accept(invitation["token"].as_str().unwrap(), member_token),
+        )
+        .await;
+        assert_eq!(
+            resp.status(),
+            409,
+            "{} invitation must not replace admin",
+            role
+        );
+    }
+    assert_eq!(community_role().await.unwrap(), "admin");
+
+    // Sign-up invitations cannot be accepted by existing members
+    let signup: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        create(json!({
+            "token_type": "single_use",
+            "username": member_username,
+            "max_uses": 1,
+            "expires_in_days": 7
+        })),
+    )
+    .await;
+    let resp = test::call_service(
+        &app,
+        accept(signup["token"].as_str().unwrap(), member_token),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_invitation_qr_and_flyer() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+    let (_other_id, other_username, other_password, _email) = ctx.create_user().await;
+
+    let (invitation_id, invitation_token) = ctx.create_invitation_with_user(user_id).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::new(
+                auth_service::services::InvitationCardService::new("https://dk.unityplan.org"),
+            ))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/{id}/qr",
+                                web::get()
+                                    .to(auth_service::handlers::invitation::get_invitation_qr),
+                            )
+                            .route(
+                                "/{id}/flyer",
+                                web::get()
+                                    .to(auth_service::handlers::invitation::get_invitation_flyer),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let mut access_tokens = Vec::new();
+    for (username, password) in [(&username, &password), (&other_username, &other_password)] {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({
+                "username": username,
+                "password": password,
+                "territory_code": "dk"
+            }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        access_tokens.push(body["access_token"].as_str().unwrap().to_string());
+    }
+
+    let get = |path: &str, access_token: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/auth/invitations/{}/{}", invitation_id, path))
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, get("qr", &access_tokens[0])).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "image/svg+xml");
+    let body = test::read_body(resp).await;
+    assert!(body.windows(4).any(|window| window == b"<svg"));
+
+    let resp = test::call_service(&app, get("qr?format=png", &access_tokens[0])).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
+    let body = test::read_body(resp).await;
+    assert!(body.starts_with(b"\x89PNG"));
+
+    let resp = test::call_service(&app, get("qr?format=gif", &access_tokens[0])).await;
+    assert_eq!(resp.status(), 400, "Unknown formats are rejected");
+
+    // The flyer links the signed token and spells out the short one for manual entry
+    let resp = test::call_service(&app, get("flyer", &access_tokens[0])).await;
+    assert_eq!(resp.status(), 200);
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    let link_prefix = "https://dk.unityplan.org/register?territory_code=dk&amp;invitation_token=";
+    let link_start = body
+        .find(link_prefix)
+        .expect("Flyer should contain the link")
+        + link_prefix.len();
+    let link_token: String = body[link_start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .collect();
+    let claims = ctx
+        .token_service
+        .verify_signed_invitation(&link_token)
+        .expect("Flyer link should carry a signed invitation");
+    assert_eq!(claims.nonce, invitation_token);
+    assert!(body.contains(&format!(">{}</text>", invitation_token)));
+
+    let resp = test::call_service(&app, get("qr", &access_tokens[1])).await;
+    assert_eq!(
+        resp.status(),
+        404,
+        "Only the creator can print an invitation"
+    );
+
+    sqlx::query("UPDATE territory.invitation_tokens SET is_active = false WHERE id = $1")
+        .bind(invitation_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    let resp = test::call_service(&app, get("flyer", &access_tokens[0])).await;
+    assert_eq!(resp.status(), 400, "Revoked invitations are not printed");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_invitation_batch() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+
+    let (organiser_id, organiser_username, organiser_password, _email) = ctx.create_user().await;
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    // Batches outside a community need invitations.create_group
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, 'moderator' FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(organiser_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::new(
+                auth_service::services::InvitationCardService::new("https://dk.unityplan.org"),
+            ))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/batch",
+                                web::post().to(
+                                    auth_service::handlers::invitation::create_invitation_batch,
+                                ),
+                            )
+                            .route(
+                                "/batch/{batch_id}",
+                                web::get()
+                                    .to(auth_service::handlers::invitation::get_invitation_batch),
+                            )
+                            .route(
+                                "/batch/{batch_id}",
+                                web::delete().to(
+                                    auth_service::handlers::invitation::revoke_invitation_batch,
+                                ),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let mut access_tokens = Vec::new();
+    for (username, password) in [
+        (&organiser_username, &organiser_password),
+        (&username, &password),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({
+                "username": username,
+                "password": password,
+                "territory_code": "dk"
+            }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        access_tokens.push(body["access_token"].as_str().unwrap().to_string());
+    }
+    let (organiser_token, user_token) = (&access_tokens[0], &access_tokens[1]);
+
+    let create = |access_token: &str, query: &str, body: serde_json::Value| {
+        test::TestRequest::post()
+            .uri(&format!("/api/auth/invitations/batch{}", query))
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .set_json(body)
+            .to_request()
+    };
+    let class = json!({
+        "emails": ["pupil_a@test.dk", "pupil_b@test.dk"],
+        "usernames": ["pupil_c"],
+        "count": 2,
+        "expires_in_days": 14
+    });
+
+    let resp = test::call_service(&app, create(user_token, "", class.clone())).await;
+    assert_eq!(resp.status(), 403, "Plain users cannot create batches");
+
+    let resp = test::call_service(&app, create(organiser_token, "", json!({ "count": 0 }))).await;
+    assert_eq!(resp.status(), 400, "Empty batches are rejected");
+
+    let resp = test::call_service(&app, create(organiser_token, "", class.clone())).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let batch_id = body["batch_id"].as_str().unwrap().to_string();
+    let invitations = body["invitations"].as_array().unwrap();
+    assert_eq!(invitations.len(), 5);
+    assert!(invitations.iter().all(|invitation| {
+        invitation["token_type"] == "single_use"
+            && invitation["max_uses"] == 1
+            && invitation["batch_id"] == batch_id.as_str()
+    }));
+    assert_eq!(invitations[0]["email"], "pupil_a@test.dk");
+    assert_eq!(invitations[2]["username"], "pupil_c");
+
+    // The batch report is available as CSV for mail merges
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/auth/invitations/batch/{}?format=csv",
+            batch_id
+        ))
+        .insert_header(("Authorization", format!("Bearer {}", organiser_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let csv = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 6, "Header plus one row per invitation");
+    assert!(lines[0].starts_with("token,email,username,registration_link"));
+    let row = lines
+        .iter()
+        .find(|line| line.contains("pupil_a@test.dk"))
+        .expect("Batch report should list every recipient");
+    assert!(row.contains("https://dk.unityplan.org/register?territory_code=dk&invitation_token="));
+
+    // Batches only count towards their creator's quota
+    let set_quota = |quota: i64| {
+        let pool = ctx.pool.clone();
+        async move {
+            sqlx::query(
+                "UPDATE territory.settings SET value = $1 WHERE key = 'invitation_quota_per_creator'",
+            )
+            .bind(json!(quota))
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+    };
+
+    set_quota(6).await;
+    let resp = test::call_service(&app, create(organiser_token, "", json!({ "count": 2 }))).await;
+    set_quota(200).await;
+    assert_eq!(resp.status(), 403, "Quota of 6 would be exceeded");
+
+    let revoke = || {
+        test::TestRequest::delete()
+            .uri(&format!("/api/auth/invitations/batch/{}", batch_id))
+            .insert_header(("Authorization", format!("Bearer {}", organiser_token)))
+            .to_request()
+    };
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/auth/invitations/batch/{}", batch_id))
+        .insert_header(("Authorization", format!("Bearer {}", user_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404, "Only the creator can revoke a batch");
+
+    let body: serde_json::Value = test::call_and_read_body_json(&app, revoke()).await;
+    assert_eq!(body["revoked"], 5);
+
+    let active: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM territory.invitation_tokens WHERE batch_id = $1 AND is_active",
+    )
+    .bind(uuid::Uuid::parse_str(&batch_id).unwrap())
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(active, 0);
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_signed_invitation_token() {
+    let mut ctx = TestContext::new().await;
+
+    let invitation_token = ctx.create_limited_invitation(2).await;
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(7);
+    let signed = ctx
+        .token_service
+        .sign_invitation("dk", "group", expires_at, &invitation_token)
+        .unwrap();
+    let foreign = ctx
+        .token_service
+        .sign_invitation("no", "group", expires_at, &invitation_token)
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/register",
+                        web::post().to(auth_service::handlers::auth::register),
+                    )
+                    .route(
+                        "/invitations/validate/{token}",
+                        web::get().to(auth_service::handlers::invitation::validate_invitation),
+                    ),
+            ),
+    )
+    .await;
+
+    // Signed tokens name their own territory
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/auth/invitations/validate/{}", signed))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["signed"], true);
+
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/api/auth/invitations/validate/{}?territory_code=dk",
+            foreign
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.status(),
+        400,
+        "Tokens from other territories are rejected"
+    );
+
+    let tampered = format!("{}x", signed);
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/auth/invitations/validate/{}", tampered))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400, "Tampered tokens are rejected");
+
+    // Registering with the signed token redeems the stored invitation
+    let unique_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "email": format!("signed_{}@test.dk", unique_id),
+            "username": format!("signed_{}", unique_id),
+            "password": "StrongPassword123!",
+            "territory_code": "dk",
+            "invitation_token": signed
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    ctx.track_user(body["user"]["id"].as_str().unwrap().parse().unwrap());
+
+    let current_uses: i32 =
+        sqlx::query_scalar("SELECT current_uses FROM territory.invitation_tokens WHERE token = $1")
+            .bind(&invitation_token)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert_eq!(current_uses, 1);
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,268 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    test, web, App,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::common::*;
+
+/// Create a group invitation handed out by `user_id`
+///
+/// Deleted together with its creator during cleanup.
+async fn invitation_from(pool: &PgPool, user_id: Uuid) -> String {
+    let token = format!("test_invite_{}", Uuid::new_v4().simple());
+
+    sqlx::query(
+        r#"
+        INSERT INTO territory.invitation_tokens
+        (token, token_type, max_uses, current_uses, expires_at, is_active, created_by_user_id)
+        VALUES ($1, 'group', 5, 0, NOW() + INTERVAL '7 days', true, $2)
+        "#,
+    )
+    .bind(&token)
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .unwrap();
+
+    token
+}
+
+/// Register a new user with an invitation and return their ID and username
+async fn register<S, B>(app: &S, invitation_token: &str) -> (Uuid, String)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let username = format!("lineage_{}", &Uuid::new_v4().to_string()[..8]);
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "username": username,
+            "password": "StrongPassword123!",
+            "territory_code": "dk",
+            "invitation_token": invitation_token
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(app, req).await;
+
+    (
+        body["user"]["id"].as_str().unwrap().parse().unwrap(),
+        username,
+    )
+}
+
+#[actix_web::test]
+async fn test_lineage_tree_and_subtree_deactivation() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+
+    let (moderator_id, moderator_username, moderator_password, _email) = ctx.create_user().await;
+    let (root_id, root_username, root_password, _email) = ctx.create_user().await;
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, 'moderator' FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(moderator_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/register",
+                        web::post().to(auth_service::handlers::auth::register),
+                    )
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/lineage")
+                            .wrap(auth_service::middleware::RequirePermission(
+                                auth_service::services::permissions::USERS_MODERATE,
+                            ))
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/{username}",
+                                web::get().to(auth_service::handlers::lineage::get_lineage),
+                            )
+                            .route(
+                                "/{username}/deactivate",
+                                web::post().to(auth_service::handlers::lineage::deactivate_lineage),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    // root -> child -> grandchild
+    let (child_id, child_username) =
+        register(&app, &invitation_from(&ctx.pool, root_id).await).await;
+    ctx.track_user(child_id);
+    let (grandchild_id, grandchild_username) =
+        register(&app, &invitation_from(&ctx.pool, child_id).await).await;
+    ctx.track_user(grandchild_id);
+
+    let invited_by: Option<Uuid> =
+        sqlx::query_scalar("SELECT invited_by_user_id FROM territory.users WHERE id = $1")
+            .bind(child_id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        invited_by,
+        Some(root_id),
+        "Registration records the inviter"
+    );
+
+    let mut access_tokens = Vec::new();
+    for (username, password) in [
+        (&moderator_username, &moderator_password),
+        (&root_username, &root_password),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({
+                "username": username,
+                "password": password,
+                "territory_code": "dk"
+            }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        access_tokens.push(body["access_token"].as_str().unwrap().to_string());
+    }
+    let (moderator_token, root_token) = (&access_tokens[0], &access_tokens[1]);
+
+    let lineage = |uri: String, access_token: &str| {
+        test::TestRequest::get()
+            .uri(&uri)
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .to_request()
+    };
+
+    // Members without users.moderate are refused by the guard
+    match test::try_call_service(
+        &app,
+        lineage(format!("/api/auth/lineage/{}", root_username), root_token),
+    )
+    .await
+    {
+        Ok(resp) => assert_eq!(resp.status(), 403),
+        Err(err) => assert_eq!(err.as_response_error().status_code(), 403),
+    }
+
+    let body: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        lineage(
+            format!("/api/auth/lineage/{}", root_username),
+            moderator_token,
+        ),
+    )
+    .await;
+    assert_eq!(body["descendant_count"], 2);
+    assert_eq!(body["max_depth"], 2);
+    assert_eq!(body["descendants"][0]["username"], child_username.as_str());
+    assert_eq!(
+        body["descendants"][1]["username"],
+        grandchild_username.as_str()
+    );
+    assert_eq!(
+        body["descendants"][1]["invited_by"],
+        child_username.as_str()
+    );
+
+    let body: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        lineage(
+            format!("/api/auth/lineage/{}?max_depth=1", root_username),
+            moderator_token,
+        ),
+    )
+    .await;
+    assert_eq!(body["descendant_count"], 1, "Depth limit stops the walk");
+
+    // Who brought the grandchild in?
+    let body: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        lineage(
+            format!("/api/auth/lineage/{}", grandchild_username),
+            moderator_token,
+        ),
+    )
+    .await;
+    let ancestors: Vec<&str> = body["ancestors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|ancestor| ancestor["username"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        ancestors,
+        vec![child_username.as_str(), root_username.as_str()]
+    );
+
+    let deactivate = |username: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/api/auth/lineage/{}/deactivate", username))
+            .insert_header(("Authorization", format!("Bearer {}", moderator_token)))
+            .set_json(json!({ "reason": "Spam wave" }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, deactivate(&moderator_username)).await;
+    assert_eq!(
+        resp.status(),
+        400,
+        "Moderators cannot deactivate their own tree"
+    );
+
+    let resp = test::call_service(&app, deactivate(&child_username)).await;
+    assert_eq!(resp.status(), 200);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["users_deactivated"], 2);
+    assert_eq!(
+        body["tokens_revoked"], 1,
+        "The child's outstanding invitation"
+    );
+    assert_eq!(body["sessions_revoked"], 2, "Sessions from registration");
+
+    let active: Vec<(Uuid, bool)> =
+        sqlx::query_as("SELECT id, is_active FROM territory.users WHERE id = ANY($1)")
+            .bind(vec![root_id, child_id, grandchild_id])
+            .fetch_all(&ctx.pool)
+            .await
+            .unwrap();
+    for (user_id, is_active) in active {
+        assert_eq!(
+            is_active,
+            user_id == root_id,
+            "Only the subtree is deactivated"
+        );
+    }
+
+    let audited: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM global.audit_log WHERE action = 'lineage.deactivated' AND resource_id = $1",
+    )
+    .bind(child_id.to_string())
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(audited, 1);
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,513 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    test, web, App,
+};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha1::Sha1;
+
+use crate::common::*;
+
+/// Compute the TOTP code `steps_ahead` time steps from now (RFC 6238, SHA-1, 6 digits)
+///
+/// Enrolment consumes the current step, so later logins use the next one.
+fn totp_now(secret: &str, steps_ahead: i64) -> String {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+    let step = chrono::Utc::now().timestamp() / 30 + steps_ahead;
+
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).unwrap();
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    format!("{:06}", binary % 1_000_000)
+}
+
+/// Enrol and confirm TOTP, returning (secret, backup_codes)
+async fn enrol_totp<S, B>(app: &S, access_token: &str) -> (String, Vec<String>)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/api/auth/mfa/totp/enrol")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(app, req).await;
+
+    let secret = body["secret"].as_str().unwrap().to_string();
+    assert!(body["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/mfa/totp/confirm")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(json!({ "code": totp_now(&secret, 0) }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(app, req).await;
+
+    let backup_codes = body["backup_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+
+    (secret, backup_codes)
+}
+
+#[actix_web::test]
+async fn test_totp_login_requires_second_step() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/login/mfa",
+                        web::post().to(auth_service::handlers::auth::login_mfa),
+                    )
+                    .service(
+                        web::scope("/mfa")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/totp/enrol",
+                                web::post().to(auth_service::handlers::mfa::enrol_totp),
+                            )
+                            .route(
+                                "/totp/confirm",
+                                web::post().to(auth_service::handlers::mfa::confirm_totp),
+                            ),
+                    )
+                    .service(
+                        web::scope("")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route("/me", web::get().to(auth_service::handlers::auth::me)),
+                    ),
+            ),
+    )
+    .await;
+
+    let body = login(&app, &username, &password, None, None).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    let (secret, backup_codes) = enrol_totp(&app, &access_token).await;
+    assert_eq!(backup_codes.len(), 10);
+
+    // Password alone now only yields a challenge
+    let body = login(&app, &username, &password, None, None).await;
+    assert_eq!(body["mfa_required"], true);
+    assert!(body["access_token"].is_null(), "No tokens before 2FA");
+    let mfa_token = body["mfa_token"].as_str().unwrap().to_string();
+
+    // The challenge token is not an access token
+    let req = test::TestRequest::get()
+        .uri("/api/auth/me")
+        .insert_header(("Authorization", format!("Bearer {}", mfa_token)))
+        .to_request();
+    let resp = test::try_call_service(&app, req).await;
+    match resp {
+        Ok(resp) => assert_eq!(resp.status(), 401, "MFA token must not grant access"),
+        Err(e) => assert_eq!(e.as_response_error().status_code(), 401),
+    }
+
+    // Wrong code is rejected
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login/mfa")
+        .set_json(json!({ "mfa_token": mfa_token, "code": "000000-x" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401, "Invalid code should be rejected");
+
+    // Correct code completes the login
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login/mfa")
+        .set_json(json!({ "mfa_token": mfa_token, "code": totp_now(&secret, 1) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200, "Valid code should complete login");
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert!(body["access_token"].is_string());
+    assert!(body["refresh_token"].is_string());
+
+    let claims = ctx
+        .token_service
+        .validate_token(body["access_token"].as_str().unwrap())
+        .unwrap();
+    assert_eq!(claims.amr, vec!["pwd", "otp"]);
+
+    // The same code cannot be replayed
+    let body = login(&app, &username, &password, None, None).await;
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login/mfa")
+        .set_json(json!({ "mfa_token": body["mfa_token"], "code": totp_now(&secret, 1) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401, "Replayed code should be rejected");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_backup_code_is_single_use() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/login/mfa",
+                        web::post().to(auth_service::handlers::auth::login_mfa),
+                    )
+                    .service(
+                        web::scope("/mfa")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/totp/enrol",
+                                web::post().to(auth_service::handlers::mfa::enrol_totp),
+                            )
+                            .route(
+                                "/totp/confirm",
+                                web::post().to(auth_service::handlers::mfa::confirm_totp),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let body = login(&app, &username, &password, None, None).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    let (_secret, backup_codes) = enrol_totp(&app, &access_token).await;
+
+    // First use of a backup code succeeds
+    let body = login(&app, &username, &password, None, None).await;
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login/mfa")
+        .set_json(json!({ "mfa_token": body["mfa_token"], "code": backup_codes[0] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200, "Backup code should complete login");
+
+    // Second use of the same code fails
+    let body = login(&app, &username, &password, None, None).await;
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login/mfa")
+        .set_json(json!({ "mfa_token": body["mfa_token"], "code": backup_codes[0] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401, "Backup code should only work once");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_disabling_totp_is_throttled() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/mfa")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/totp/enrol",
+                                web::post().to(auth_service::handlers::mfa::enrol_totp),
+                            )
+                            .route(
+                                "/totp/confirm",
+                                web::post().to(auth_service::handlers::mfa::confirm_totp),
+                            )
+                            .route(
+                                "/totp",
+                                web::delete().to(auth_service::handlers::mfa::disable_totp),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let body = login(&app, &username, &password, None, None).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+    let (secret, _backup_codes) = enrol_totp(&app, &access_token).await;
+
+    let disable = |code: String| {
+        let req = test::TestRequest::delete()
+            .uri("/api/auth/mfa/totp")
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .set_json(json!({ "code": code }))
+            .to_request();
+        let call = app.call(req);
+        async move {
+            match call.await {
+                Ok(resp) => resp.status(),
+                Err(e) => e.as_response_error().status_code(),
+            }
+        }
+    };
+
+    // A stolen session cannot guess its way past the second factor
+    for _ in 0..6 {
+        assert_eq!(disable(totp_now(&secret, 5)).await, 401, "Wrong code");
+    }
+    assert_eq!(
+        disable(totp_now(&secret, 1)).await,
+        429,
+        "Guessing should lock the account out"
+    );
+
+    let enabled: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM territory.user_mfa WHERE user_id = $1 AND enabled_at IS NOT NULL)",
+    )
+    .bind(user_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert!(enabled, "2FA should still be enabled");
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'username' AND key = $1")
+        .bind(format!("dk:{}", username.to_lowercase()))
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_territory_can_require_mfa_for_managers() {
+    let _settings = MANAGER_SETTINGS.write().await;
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    // Make the user a territory manager
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, 'territory_admin' FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "UPDATE territory.settings SET value = 'true'::jsonb WHERE key = 'require_mfa_for_managers'",
+    )
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/login/mfa",
+                        web::post().to(auth_service::handlers::auth::login_mfa),
+                    )
+                    .service(
+                        web::scope("/mfa")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/totp/enrol",
+                                web::post().to(auth_service::handlers::mfa::enrol_totp),
+                            )
+                            .route(
+                                "/totp/confirm",
+                                web::post().to(auth_service::handlers::mfa::confirm_totp),
+                            )
+                            .route(
+                                "/totp",
+                                web::delete().to(auth_service::handlers::mfa::disable_totp),
+                            ),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::post()
+                                    .to(auth_service::handlers::invitation::create_invitation),
+                            ),
+                    )
+                    .service(
+                        web::scope("/roles")
+                            .wrap(auth_service::middleware::RequirePermission(
+                                auth_service::services::permissions::ROLES_MANAGE,
+                            ))
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route("", web::get().to(auth_service::handlers::role::list_roles)),
+                    ),
+            ),
+    )
+    .await;
+
+    let create_req = json!({
+        "token_type": "group",
+        "max_uses": 5,
+        "expires_in_days": 7,
+        "purpose": "Test invitation"
+    });
+
+    // Password-only session cannot use invitation rights
+    let body = login(&app, &username, &password, None, None).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/invitations")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(&create_req)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403, "Manager without 2FA should be refused");
+
+    // Every permission-guarded route refuses it, not just invitations
+    let roles_status = |access_token: String| {
+        let req = test::TestRequest::get()
+            .uri("/api/auth/roles")
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .to_request();
+        let call = app.call(req);
+        async move {
+            match call.await {
+                Ok(resp) => resp.status(),
+                Err(e) => e.as_response_error().status_code(),
+            }
+        }
+    };
+    assert_eq!(
+        roles_status(access_token.clone()).await,
+        403,
+        "Manager without 2FA should not manage roles"
+    );
+
+    // After enrolling and signing in with a code, it works
+    let (secret, _backup_codes) = enrol_totp(&app, &access_token).await;
+
+    let body = login(&app, &username, &password, None, None).await;
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login/mfa")
+        .set_json(json!({ "mfa_token": body["mfa_token"], "code": totp_now(&secret, 1) }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let access_token = body["access_token"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/invitations")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(&create_req)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201, "Manager with 2FA should be allowed");
+    assert_eq!(roles_status(access_token.clone()).await, 200);
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    sqlx::query("DELETE FROM territory.invitation_tokens WHERE token = $1")
+        .bind(body["token"].as_str().unwrap())
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    // Managers cannot switch 2FA off while the territory requires it
+    let req = test::TestRequest::delete()
+        .uri("/api/auth/mfa/totp")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(json!({ "code": totp_now(&secret, 1) }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 403, "Required 2FA cannot be disabled");
+
+    // Global roles that grant permissions count as manager roles too
+    let (global_user_id, _, _, _) = ctx.create_user().await;
+    let (member_id, _, _, _) = ctx.create_user().await;
+    sqlx::query(
+        r#"
+        INSERT INTO global.role_assignments (user_id, role, permissions)
+        SELECT id, 'auditor', '["audit.read"]'::jsonb FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(global_user_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    for (territory_user_id, expected) in [(global_user_id, true), (member_id, false)] {
+        let identity_id: uuid::Uuid = sqlx::query_scalar(
+            "SELECT id FROM global.user_identities WHERE territory_code = 'dk' AND territory_user_id = $1",
+        )
+        .bind(territory_user_id)
+        .fetch_one(&ctx.pool)
+        .await
+        .unwrap();
+
+        let required =
+            auth_service::services::is_mfa_required(&ctx.pool, "territory", identity_id, "dk")
+                .await
+                .unwrap();
+        assert_eq!(required, expected);
+    }
+
+    sqlx::query(
+        "UPDATE territory.settings SET value = 'false'::jsonb WHERE key = 'require_mfa_for_managers'",
+    )
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    ctx.cleanup().await;
+}
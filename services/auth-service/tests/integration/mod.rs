@@ -1,3 +1,17 @@
 // Integration test modules
+pub mod audit;
 pub mod auth;
+pub mod auth_state;
+pub mod email;
+pub mod introspection;
 pub mod invitation;
+pub mod lineage;
+pub mod mfa;
+pub mod oidc;
+pub mod outbox;
+pub mod password;
+pub mod personal_access_token;
+pub mod revocation;
+pub mod role;
+pub mod session;
+pub mod throttle;
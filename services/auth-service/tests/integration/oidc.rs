@@ -0,0 +1,508 @@
+use actix_web::{test, web, App};
+use auth_service::services::{hash_client_secret, OidcProvider, TokenService};
+use base64::{engine::general_purpose::STANDARD, engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use url::Url;
+use uuid::Uuid;
+
+use crate::common::*;
+
+const ISSUER: &str = "https://dk.unityplan.test/api/auth";
+const REDIRECT_URI: &str = "https://rp.example.test/callback";
+
+/// A relying party as a deployment would configure it (Forgejo, Matrix, ...)
+struct TestClient {
+    client_id: String,
+    client_secret: Option<String>, // None for public clients
+}
+
+impl TestClient {
+    /// Register a client in the test territory
+    async fn register(ctx: &TestContext, confidential: bool) -> Self {
+        let client_id = format!("test-rp-{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let client_secret = confidential.then(|| Uuid::new_v4().simple().to_string());
+
+        sqlx::query(
+            r#"
+            INSERT INTO global.oidc_clients
+                (client_id, territory_code, name, secret_hash, redirect_uris)
+            VALUES ($1, 'dk', 'Test Forge', $2, $3)
+            "#,
+        )
+        .bind(&client_id)
+        .bind(client_secret.as_deref().map(hash_client_secret))
+        .bind(vec![REDIRECT_URI.to_string()])
+        .execute(&ctx.pool)
+        .await
+        .expect("Failed to register OIDC client");
+
+        Self {
+            client_id,
+            client_secret,
+        }
+    }
+
+    /// Authorization request query string, with a fresh PKCE verifier
+    fn authorization_request(&self, scope: &str, state: &str) -> (String, String) {
+        let code_verifier = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", REDIRECT_URI)
+            .append_pair("scope", scope)
+            .append_pair("state", state)
+            .append_pair("nonce", "n-0S6_WzA2Mj")
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256")
+            .finish();
+
+        (query, code_verifier)
+    }
+
+    /// Token request for `code`; confidential clients use HTTP Basic
+    fn token_request(&self, code: &str, code_verifier: &str) -> test::TestRequest {
+        let req = test::TestRequest::post().uri("/api/auth/oidc/token");
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", REDIRECT_URI),
+            ("code_verifier", code_verifier),
+            ("client_id", &self.client_id),
+        ];
+
+        match &self.client_secret {
+            Some(secret) => req
+                .insert_header((
+                    "Authorization",
+                    format!(
+                        "Basic {}",
+                        STANDARD.encode(format!("{}:{}", self.client_id, secret))
+                    ),
+                ))
+                .set_form(form),
+            None => req.set_form(form),
+        }
+    }
+
+    async fn unregister(self, ctx: &TestContext) {
+        sqlx::query("DELETE FROM global.oidc_clients WHERE client_id = $1")
+            .bind(&self.client_id)
+            .execute(&ctx.pool)
+            .await
+            .expect("Failed to remove OIDC client");
+    }
+}
+
+/// Query parameters of the redirect the front-end is told to follow
+fn redirect_params(redirect_to: &str) -> HashMap<String, String> {
+    let url = Url::parse(redirect_to).expect("redirect_to should be a URL");
+    assert!(
+        redirect_to.starts_with(REDIRECT_URI),
+        "Must redirect to the registered URI"
+    );
+    url.query_pairs().into_owned().collect()
+}
+
+/// Body of POST /api/auth/oidc/authorize, as the front-end sends it
+fn authorization_decision(query: &str, approved: bool) -> serde_json::Value {
+    let mut decision: serde_json::Map<String, serde_json::Value> =
+        url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .map(|(name, value)| (name, json!(value)))
+            .collect();
+    decision.insert("approved".to_string(), json!(approved));
+    decision.into()
+}
+
+/// Ed25519 token service, so relying parties can verify ID tokens with the JWKS
+fn signing_token_service() -> TokenService {
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+    TokenService::from_private_key_pem(
+        &signing_key.to_pkcs8_pem(LineEnding::LF).unwrap(),
+        900,
+        604800,
+    )
+    .unwrap()
+}
+
+macro_rules! oidc_app {
+    ($ctx:expr, $token_service:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new($ctx.pool.clone()))
+                .app_data(web::Data::from($ctx.territories.clone()))
+                .app_data(web::Data::new($token_service))
+                .app_data(web::Data::new(OidcProvider::new(
+                    ISSUER,
+                    "https://dk.unityplan.test/oidc/authorize",
+                )))
+                .service(
+                    web::scope("/api/auth")
+                        .route(
+                            "/login",
+                            web::post().to(auth_service::handlers::auth::login),
+                        )
+                        .route(
+                            "/.well-known/jwks.json",
+                            web::get().to(auth_service::handlers::jwks),
+                        )
+                        .route(
+                            "/.well-known/openid-configuration",
+                            web::get().to(auth_service::handlers::openid_configuration),
+                        )
+                        .route(
+                            "/oidc/token",
+                            web::post().to(auth_service::handlers::oidc_token),
+                        )
+                        .route(
+                            "/oidc/userinfo",
+                            web::get().to(auth_service::handlers::oidc_userinfo),
+                        )
+                        .service(
+                            web::scope("/oidc/authorize")
+                                .wrap(auth_service::middleware::JwtAuth)
+                                .route("", web::get().to(auth_service::handlers::oidc_consent))
+                                .route("", web::post().to(auth_service::handlers::oidc_authorize)),
+                        ),
+                ),
+        )
+        .await
+    };
+}
+
+#[actix_web::test]
+async fn test_authorization_code_flow_end_to_end() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
+    let email = format!("{}@test.dk", username);
+    sqlx::query("UPDATE territory.users SET email = $2, avatar_url = $3 WHERE id = $1")
+        .bind(user_id)
+        .bind(&email)
+        .bind("https://cdn.example.test/avatar.png")
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    let client = TestClient::register(&ctx, true).await;
+
+    let app = oidc_app!(ctx, signing_token_service());
+
+    // The relying party discovers the provider
+    let req = test::TestRequest::get()
+        .uri("/api/auth/.well-known/openid-configuration")
+        .to_request();
+    let discovery: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(discovery["issuer"], ISSUER);
+    assert_eq!(
+        discovery["token_endpoint"],
+        format!("{}/oidc/token", ISSUER)
+    );
+    assert_eq!(
+        discovery["jwks_uri"],
+        format!("{}/.well-known/jwks.json", ISSUER)
+    );
+    assert_eq!(
+        discovery["id_token_signing_alg_values_supported"],
+        json!(["EdDSA"])
+    );
+    assert_eq!(
+        discovery["code_challenge_methods_supported"],
+        json!(["S256"])
+    );
+
+    // The member, logged in to the front-end, sees the consent screen
+    let tokens = login(&app, &username, &password, None, None).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let (query, code_verifier) =
+        client.authorization_request("openid profile email offline_access", "xyz");
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/auth/oidc/authorize?{}", query))
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .to_request();
+    let screen: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(screen["client"]["name"], "Test Forge");
+    assert_eq!(screen["consent_required"], true);
+    let scopes: Vec<&str> = screen["scopes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|scope| scope["scope"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        scopes,
+        ["openid", "profile", "email"],
+        "Unsupported scopes are dropped"
+    );
+
+    // ... and approves
+    let decision = authorization_decision(&query, true);
+    let req = test::TestRequest::post()
+        .uri("/api/auth/oidc/authorize")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(&decision)
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let params = redirect_params(body["redirect_to"].as_str().unwrap());
+    assert_eq!(params["state"], "xyz");
+    let code = params["code"].clone();
+
+    // The relying party redeems the code
+    let req = client.token_request(&code, &code_verifier).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "no-store");
+    let tokens: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(tokens["token_type"], "Bearer");
+    assert_eq!(tokens["scope"], "openid profile email");
+
+    // ... verifies the ID token with the published keys
+    let req = test::TestRequest::get()
+        .uri("/api/auth/.well-known/jwks.json")
+        .to_request();
+    let jwks: JwkSet = test::call_and_read_body_json(&app, req).await;
+    let id_token = tokens["id_token"].as_str().unwrap();
+    let kid = decode_header(id_token).unwrap().kid.unwrap();
+    let jwk = jwks.find(&kid).expect("ID token key should be published");
+    let mut validation = Validation::new(jsonwebtoken::Algorithm::EdDSA);
+    validation.set_audience(&[&client.client_id]);
+    validation.set_issuer(&[ISSUER]);
+    let id_claims =
+        decode::<serde_json::Value>(id_token, &DecodingKey::from_jwk(jwk).unwrap(), &validation)
+            .expect("ID token should verify")
+            .claims;
+
+    let public_key_hash: String = sqlx::query_scalar(
+        r#"
+        SELECT public_key_hash FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(id_claims["sub"], public_key_hash);
+    assert_eq!(id_claims["nonce"], "n-0S6_WzA2Mj");
+    assert_eq!(id_claims["amr"], json!(["pwd"]));
+    assert!(
+        id_claims.get("username").is_none(),
+        "Profile stays out of the ID token"
+    );
+
+    // ... and asks who signed in
+    let client_token = tokens["access_token"].as_str().unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/auth/oidc/userinfo")
+        .insert_header(("Authorization", format!("Bearer {}", client_token)))
+        .to_request();
+    let info: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(info["sub"], public_key_hash);
+    assert_eq!(info["preferred_username"], username);
+    assert_eq!(info["name"], "Test User");
+    assert_eq!(info["picture"], "https://cdn.example.test/avatar.png");
+    assert_eq!(info["email"], email);
+    assert_eq!(info["email_verified"], true);
+
+    // Codes are single use
+    let req = client.token_request(&code, &code_verifier).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let error: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(error["error"], "invalid_grant");
+
+    // The relying party's token does not open the API, and member tokens do
+    // not open userinfo
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/auth/oidc/authorize?{}", query))
+        .insert_header(("Authorization", format!("Bearer {}", client_token)))
+        .to_request();
+    let resp = test::try_call_service(&app, req).await;
+    assert!(resp.is_err() || resp.unwrap().status() == 401);
+    let req = test::TestRequest::get()
+        .uri("/api/auth/oidc/userinfo")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+    assert!(resp.headers().contains_key("www-authenticate"));
+
+    // Consent is remembered
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/auth/oidc/authorize?{}", query))
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .to_request();
+    let screen: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(screen["consent_required"], false);
+
+    client.unregister(&ctx).await;
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_authorization_request_checks_and_privacy() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
+    // A private profile only shares the username
+    sqlx::query("UPDATE territory.users SET profile_visibility = 'private' WHERE id = $1")
+        .bind(user_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    let public_client = TestClient::register(&ctx, false).await;
+    let confidential_client = TestClient::register(&ctx, true).await;
+
+    let app = oidc_app!(ctx, signing_token_service());
+    let tokens = login(&app, &username, &password, None, None).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let bearer = format!("Bearer {}", access_token);
+
+    let authorize = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/auth/oidc/authorize?{}", query))
+            .insert_header(("Authorization", bearer.clone()))
+            .to_request()
+    };
+
+    // Unregistered redirect URIs are never redirected to
+    let (query, _) = public_client.authorization_request("openid", "s1");
+    let query = query.replace("rp.example.test", "evil.example.test");
+    let resp = test::call_service(&app, authorize(&query)).await;
+    assert_eq!(resp.status(), 400);
+    let error: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(error["error"], "invalid_request");
+    assert!(error.get("redirect_to").is_none());
+
+    // Other errors go back to the client
+    let (query, _) = public_client.authorization_request("openid", "s2");
+    let query = query.replace("code_challenge_method=S256", "code_challenge_method=plain");
+    let resp = test::call_service(&app, authorize(&query)).await;
+    assert_eq!(resp.status(), 400);
+    let error: serde_json::Value = test::read_body_json(resp).await;
+    let params = redirect_params(error["redirect_to"].as_str().unwrap());
+    assert_eq!(params["error"], "invalid_request");
+    assert_eq!(params["state"], "s2");
+
+    let (query, _) = public_client.authorization_request("profile", "s3");
+    let resp = test::call_service(&app, authorize(&query)).await;
+    let error: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(error["error"], "invalid_scope");
+
+    // Declining sends the member back with access_denied
+    let (query, _) = public_client.authorization_request("openid profile", "s4");
+    let decision = authorization_decision(&query, false);
+    let req = test::TestRequest::post()
+        .uri("/api/auth/oidc/authorize")
+        .insert_header(("Authorization", bearer.clone()))
+        .set_json(&decision)
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let params = redirect_params(body["redirect_to"].as_str().unwrap());
+    assert_eq!(params["error"], "access_denied");
+    assert!(!params.contains_key("code"));
+
+    // Approving for a public client: PKCE alone authenticates the exchange
+    let (query, code_verifier) = public_client.authorization_request("openid profile", "s5");
+    let decision = authorization_decision(&query, true);
+    let req = test::TestRequest::post()
+        .uri("/api/auth/oidc/authorize")
+        .insert_header(("Authorization", bearer.clone()))
+        .set_json(&decision)
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let code = redirect_params(body["redirect_to"].as_str().unwrap())["code"].clone();
+
+    // A wrong verifier spends the code
+    let wrong_verifier = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let resp = test::call_service(
+        &app,
+        public_client
+            .token_request(&code, &wrong_verifier)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 400);
+    let resp = test::call_service(
+        &app,
+        public_client
+            .token_request(&code, &code_verifier)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(
+        resp.status(),
+        400,
+        "Code must not survive a failed exchange"
+    );
+
+    // Confidential clients must authenticate
+    let (query, code_verifier) = confidential_client.authorization_request("openid profile", "s6");
+    let decision = authorization_decision(&query, true);
+    let req = test::TestRequest::post()
+        .uri("/api/auth/oidc/authorize")
+        .insert_header(("Authorization", bearer.clone()))
+        .set_json(&decision)
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let code = redirect_params(body["redirect_to"].as_str().unwrap())["code"].clone();
+
+    let unauthenticated = TestClient {
+        client_id: confidential_client.client_id.clone(),
+        client_secret: None,
+    };
+    let resp = test::call_service(
+        &app,
+        unauthenticated
+            .token_request(&code, &code_verifier)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 401);
+    let error: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(error["error"], "invalid_client");
+
+    let tokens: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        confidential_client
+            .token_request(&code, &code_verifier)
+            .to_request(),
+    )
+    .await;
+
+    // The private profile keeps everything but the username to itself
+    let req = test::TestRequest::get()
+        .uri("/api/auth/oidc/userinfo")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", tokens["access_token"].as_str().unwrap()),
+        ))
+        .to_request();
+    let info: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(info["preferred_username"], username);
+    assert!(info.get("name").is_none());
+    assert!(info.get("email").is_none(), "email scope was not granted");
+
+    // Deactivating the client cuts off its tokens
+    sqlx::query("UPDATE global.oidc_clients SET is_active = false WHERE client_id = $1")
+        .bind(&confidential_client.client_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri("/api/auth/oidc/userinfo")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", tokens["access_token"].as_str().unwrap()),
+        ))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401);
+
+    public_client.unregister(&ctx).await;
+    confidential_client.unregister(&ctx).await;
+    ctx.cleanup().await;
+}
@@ -0,0 +1,218 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use shared_lib::{
+    enqueue_event, AppError, EventEnvelope, EventPublisher, InMemoryEventPublisher, OutboxMetrics,
+    OutboxRelay, UserLoggedIn,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::common::*;
+
+/// Rejects every event of one territory and accepts the rest
+struct FailingPublisher {
+    subject_prefix: String,
+}
+
+#[async_trait]
+impl EventPublisher for FailingPublisher {
+    async fn publish_bytes(
+        &self,
+        _message_id: Uuid,
+        subject: &str,
+        _payload: Vec<u8>,
+    ) -> shared_lib::Result<()> {
+        if subject.starts_with(&self.subject_prefix) {
+            return Err(AppError::Nats("no responders".to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn login_event() -> UserLoggedIn {
+    UserLoggedIn {
+        user_id: Uuid::new_v4(),
+        public_key_hash: "outbox-test".to_string(),
+        session_id: Uuid::new_v4(),
+        mfa_verified: false,
+    }
+}
+
+/// Run the relay until `event_id` has been handled (other tests may have events pending)
+async fn relay_until_attempted(relay: &OutboxRelay, ctx: &TestContext, event_id: Uuid) {
+    for _ in 0..50 {
+        relay.relay_pending().await.expect("Relay pass failed");
+
+        let attempts: i32 =
+            sqlx::query_scalar("SELECT attempts FROM global.event_outbox WHERE id = $1")
+                .bind(event_id)
+                .fetch_one(&ctx.pool)
+                .await
+                .expect("Event should be in the outbox");
+        if attempts > 0 {
+            return;
+        }
+    }
+    panic!("Relay never got to event {}", event_id);
+}
+
+#[actix_web::test]
+async fn test_outbox_relay_publishes_committed_events() {
+    let ctx = TestContext::new().await;
+    let territory = format!("outbox-{}", &Uuid::new_v4().simple().to_string()[..8]);
+
+    // Events of a rolled back change are never published
+    let mut tx = ctx.pool.begin().await.unwrap();
+    let rolled_back = enqueue_event(&mut *tx, &territory, login_event())
+        .await
+        .unwrap();
+    tx.rollback().await.unwrap();
+
+    let mut tx = ctx.pool.begin().await.unwrap();
+    let envelope = enqueue_event(&mut *tx, &territory, login_event())
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+
+    let publisher = InMemoryEventPublisher::new();
+    let metrics = OutboxMetrics::register(&prometheus::Registry::new()).unwrap();
+    let relay =
+        OutboxRelay::new(ctx.pool.clone(), Arc::new(publisher.clone())).metrics(metrics.clone());
+    relay_until_attempted(&relay, &ctx, envelope.id).await;
+
+    // Published once, with the envelope id as the deduplication id
+    let published: Vec<_> = publisher
+        .published()
+        .into_iter()
+        .filter(|event| {
+            event
+                .subject
+                .starts_with(&format!("territory.{}.", territory))
+        })
+        .collect();
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].id, envelope.id);
+    assert_eq!(
+        published[0].subject,
+        format!("territory.{}.user.login", territory)
+    );
+    assert_eq!(
+        EventEnvelope::<UserLoggedIn>::from_slice(&published[0].payload).unwrap(),
+        envelope
+    );
+    assert!(publisher
+        .published()
+        .iter()
+        .all(|event| event.id != rolled_back.id));
+    assert!(metrics.published("user.login") >= 1);
+
+    let published_at: Option<DateTime<Utc>> =
+        sqlx::query_scalar("SELECT published_at FROM global.event_outbox WHERE id = $1")
+            .bind(envelope.id)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert!(
+        published_at.is_some(),
+        "Event should be marked as published"
+    );
+
+    // Published events are not picked up again
+    let before = publisher.published().len();
+    relay.relay_pending().await.unwrap();
+    assert!(publisher
+        .published()
+        .iter()
+        .skip(before)
+        .all(|event| event.id != envelope.id));
+
+    sqlx::query("DELETE FROM global.event_outbox WHERE territory_code = $1")
+        .bind(&territory)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_outbox_relay_retries_failed_events_with_backoff() {
+    let ctx = TestContext::new().await;
+    let territory = format!("outbox-{}", &Uuid::new_v4().simple().to_string()[..8]);
+
+    let envelope = enqueue_event(&ctx.pool, &territory, login_event())
+        .await
+        .unwrap();
+
+    let metrics = OutboxMetrics::register(&prometheus::Registry::new()).unwrap();
+    let relay = OutboxRelay::new(
+        ctx.pool.clone(),
+        Arc::new(FailingPublisher {
+            subject_prefix: format!("territory.{}.", territory),
+        }),
+    )
+    .metrics(metrics.clone());
+    relay_until_attempted(&relay, &ctx, envelope.id).await;
+
+    let (published_at, attempts, last_error, next_attempt_at): (
+        Option<DateTime<Utc>>,
+        i32,
+        Option<String>,
+        DateTime<Utc>,
+    ) = sqlx::query_as(
+        r#"
+        SELECT published_at, attempts, last_error, next_attempt_at
+        FROM global.event_outbox WHERE id = $1
+        "#,
+    )
+    .bind(envelope.id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert!(published_at.is_none(), "Failed event stays pending");
+    assert_eq!(attempts, 1);
+    assert!(last_error.unwrap().contains("no responders"));
+    assert!(next_attempt_at > Utc::now(), "Retry should be backed off");
+    assert_eq!(metrics.failed("user.login"), 1);
+
+    // Not retried before the backoff has passed
+    relay.relay_pending().await.unwrap();
+    assert_eq!(metrics.failed("user.login"), 1);
+
+    // Once due, a working publisher delivers it
+    sqlx::query("UPDATE global.event_outbox SET next_attempt_at = NOW() WHERE id = $1")
+        .bind(envelope.id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    let publisher = InMemoryEventPublisher::new();
+    let relay = OutboxRelay::new(ctx.pool.clone(), Arc::new(publisher.clone()));
+    for _ in 0..50 {
+        relay.relay_pending().await.unwrap();
+        if publisher
+            .published()
+            .iter()
+            .any(|event| event.id == envelope.id)
+        {
+            break;
+        }
+    }
+
+    let (published_at, attempts, last_error): (Option<DateTime<Utc>>, i32, Option<String>) =
+        sqlx::query_as(
+            "SELECT published_at, attempts, last_error FROM global.event_outbox WHERE id = $1",
+        )
+        .bind(envelope.id)
+        .fetch_one(&ctx.pool)
+        .await
+        .unwrap();
+    assert!(published_at.is_some(), "Retried event should be published");
+    assert_eq!(attempts, 2);
+    assert!(last_error.is_none());
+
+    sqlx::query("DELETE FROM global.event_outbox WHERE territory_code = $1")
+        .bind(&territory)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    ctx.cleanup().await;
+}
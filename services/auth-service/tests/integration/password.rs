@@ -0,0 +1,298 @@
+use actix_web::{test, web, App};
+use serde_json::json;
+
+use crate::common::*;
+
+#[actix_web::test]
+async fn test_change_password_revokes_other_sessions() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/refresh",
+                        web::post().to(auth_service::handlers::auth::refresh),
+                    )
+                    .service(
+                        web::scope("")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/password",
+                                web::post().to(auth_service::handlers::password::change_password),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let (_, laptop) = try_login(&app, &username, &password, None, None).await;
+    let (_, phone) = try_login(&app, &username, &password, None, None).await;
+
+    // Wrong current password is refused
+    let req = test::TestRequest::post()
+        .uri("/api/auth/password")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", laptop["access_token"].as_str().unwrap()),
+        ))
+        .set_json(json!({
+            "current_password": "NotMyPassword123!",
+            "new_password": "BrandNewPassword456!"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401, "Wrong current password should fail");
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/password")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", laptop["access_token"].as_str().unwrap()),
+        ))
+        .set_json(json!({
+            "current_password": password,
+            "new_password": "BrandNewPassword456!"
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body["revoked_sessions"], 1,
+        "The phone should be signed out"
+    );
+
+    // Only the new password works
+    let (status, _) = try_login(&app, &username, &password, None, None).await;
+    assert_eq!(status, 401, "Old password should no longer work");
+    let (status, _) = try_login(&app, &username, "BrandNewPassword456!", None, None).await;
+    assert_eq!(status, 200, "New password should work");
+
+    // The phone's session is gone, the laptop's survives
+    let req = test::TestRequest::post()
+        .uri("/api/auth/refresh")
+        .set_json(json!({ "refresh_token": phone["refresh_token"], "territory_code": "dk" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401, "Other session should be revoked");
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/refresh")
+        .set_json(json!({ "refresh_token": laptop["refresh_token"], "territory_code": "dk" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200, "Current session should survive");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_recovery_codes_reset_password() {
+    let mut ctx = TestContext::new().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/register",
+                        web::post().to(auth_service::handlers::auth::register),
+                    )
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/password/recover",
+                        web::post().to(auth_service::handlers::password::recover_account),
+                    ),
+            ),
+    )
+    .await;
+
+    // Register without an email - recovery codes are the only way back in
+    let invitation_token = ctx.create_invitation().await;
+    let username = format!("recover_{}", &uuid::Uuid::new_v4().to_string()[..8]);
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({
+            "username": username,
+            "password": "OriginalPassword123!",
+            "territory_code": "dk",
+            "invitation_token": invitation_token
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    ctx.track_user(body["user"]["id"].as_str().unwrap().parse().unwrap());
+
+    let recovery_codes = body["recovery_codes"].as_array().unwrap().clone();
+    assert_eq!(recovery_codes.len(), 10, "Registration should issue codes");
+
+    let stored: Vec<String> = sqlx::query_scalar(
+        "SELECT code_hash FROM territory.user_recovery_codes WHERE user_id = $1",
+    )
+    .bind(
+        body["user"]["id"]
+            .as_str()
+            .unwrap()
+            .parse::<uuid::Uuid>()
+            .unwrap(),
+    )
+    .fetch_all(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(stored.len(), 10);
+    assert!(
+        !stored.contains(&recovery_codes[0].as_str().unwrap().to_string()),
+        "Codes must be stored hashed"
+    );
+
+    let recover = |code: &serde_json::Value| {
+        test::TestRequest::post()
+            .uri("/api/auth/password/recover")
+            .set_json(json!({
+                "username": username,
+                "territory_code": "DK", // Codes are case-insensitive
+                "recovery_code": code,
+                "new_password": "RecoveredPassword456!"
+            }))
+            .to_request()
+    };
+
+    let body: serde_json::Value =
+        test::call_and_read_body_json(&app, recover(&recovery_codes[0])).await;
+    assert_eq!(body["remaining_recovery_codes"], 9);
+
+    let (status, _) = try_login(&app, &username, "RecoveredPassword456!", None, None).await;
+    assert_eq!(status, 200, "New password should work after recovery");
+
+    // Each code works once
+    let resp = test::call_service(&app, recover(&recovery_codes[0])).await;
+    assert_eq!(resp.status(), 401, "Used recovery code should be rejected");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_reset_password_with_email_token() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    // Reset links only go to verified addresses
+    let email = format!("reset_{}@test.dk", &uuid::Uuid::new_v4().to_string()[..8]);
+    sqlx::query("UPDATE territory.users SET email = $2, is_verified = true WHERE id = $1")
+        .bind(user_id)
+        .bind(&email)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::from(ctx.email_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/password/reset-request",
+                        web::post().to(auth_service::handlers::password::request_password_reset),
+                    )
+                    .route(
+                        "/password/reset",
+                        web::post().to(auth_service::handlers::password::reset_password),
+                    ),
+            ),
+    )
+    .await;
+
+    // Known and unknown addresses get the same answer
+    for address in [email.as_str(), "nobody@test.dk"] {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/password/reset-request")
+            .set_json(json!({ "email": address, "territory_code": "dk" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 202);
+    }
+
+    let issued: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM territory.password_reset_tokens WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        issued, 1,
+        "A reset token should be issued for the verified email"
+    );
+
+    // Take the token from the emailed link
+    let sent = ctx
+        .mailer
+        .last_sent_to(&email)
+        .expect("Reset email should be sent");
+    let token = token_from_link(&sent.body, "pwr_");
+    assert!(ctx.mailer.last_sent_to("nobody@test.dk").is_none());
+
+    // A session opened with the old password
+    login(&app, &username, &password, None, None).await;
+
+    let reset = || {
+        test::TestRequest::post()
+            .uri("/api/auth/password/reset")
+            .set_json(json!({
+                "token": token,
+                "territory_code": "DK", // Codes are case-insensitive
+                "new_password": "ResetPassword789!"
+            }))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, reset()).await;
+    assert_eq!(resp.status(), 200, "Reset token should set the password");
+
+    let live_sessions: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM global.sessions s
+        JOIN global.user_identities ui ON ui.id = s.user_id
+        WHERE ui.territory_user_id = $1 AND s.revoked_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(live_sessions, 0, "The reset should sign out every session");
+
+    let (status, _) = try_login(&app, &username, &password, None, None).await;
+    assert_eq!(status, 401, "Old password should no longer work");
+    let (status, _) = try_login(&app, &username, "ResetPassword789!", None, None).await;
+    assert_eq!(status, 200, "New password should work");
+
+    let resp = test::call_service(&app, reset()).await;
+    assert_eq!(resp.status(), 401, "Reset token should only work once");
+
+    ctx.cleanup().await;
+}
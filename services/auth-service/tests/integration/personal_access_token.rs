@@ -0,0 +1,336 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::StatusCode,
+    test, web, App,
+};
+use auth_service::{
+    middleware::{JwtAuth, ScopedAuth},
+    services::scopes,
+};
+use serde_json::json;
+
+use crate::common::*;
+
+/// Client IP of this file's logins, so they have their own IP throttle counter
+const CLIENT_IP: &str = "198.51.100.97";
+
+macro_rules! token_app {
+    ($ctx:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new($ctx.pool.clone()))
+                .app_data(web::Data::from($ctx.territories.clone()))
+                .app_data(web::Data::from($ctx.token_service.clone()))
+                .service(
+                    web::scope("/api/auth")
+                        .route(
+                            "/login",
+                            web::post().to(auth_service::handlers::auth::login),
+                        )
+                        .service(
+                            web::scope("/tokens")
+                                .wrap(JwtAuth)
+                                .route(
+                                    "",
+                                    web::post().to(
+                                        auth_service::handlers::personal_access_token::create_personal_token,
+                                    ),
+                                )
+                                .route(
+                                    "",
+                                    web::get().to(
+                                        auth_service::handlers::personal_access_token::list_personal_tokens,
+                                    ),
+                                )
+                                .route(
+                                    "/{id}",
+                                    web::delete().to(
+                                        auth_service::handlers::personal_access_token::revoke_personal_token,
+                                    ),
+                                ),
+                        )
+                        .service(
+                            web::scope("/sessions").wrap(JwtAuth).route(
+                                "",
+                                web::get().to(auth_service::handlers::session::get_sessions),
+                            ),
+                        )
+                        .route(
+                            "/me",
+                            web::get()
+                                .to(auth_service::handlers::auth::me)
+                                .wrap(ScopedAuth(scopes::PROFILE_READ)),
+                        )
+                        .service(
+                            web::scope("/invitations")
+                                .route(
+                                    "",
+                                    web::post()
+                                        .to(auth_service::handlers::invitation::create_invitation)
+                                        .wrap(ScopedAuth(scopes::INVITATIONS_WRITE)),
+                                )
+                                .route(
+                                    "",
+                                    web::get()
+                                        .to(auth_service::handlers::invitation::list_invitations)
+                                        .wrap(ScopedAuth(scopes::INVITATIONS_READ)),
+                                ),
+                        ),
+                ),
+        )
+        .await
+    };
+}
+
+async fn create_token<S, B>(
+    app: &S,
+    access_token: &str,
+    body: serde_json::Value,
+) -> (StatusCode, serde_json::Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/api/auth/tokens")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .set_json(body)
+        .to_request();
+
+    match app.call(req).await {
+        Ok(resp) => {
+            let status = resp.status();
+            let body = test::read_body(resp).await;
+            (
+                status,
+                serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+            )
+        }
+        Err(e) => (e.as_response_error().status_code(), serde_json::Value::Null),
+    }
+}
+
+/// Status of a request made with `token`
+async fn status_with<S, B>(app: &S, req: test::TestRequest, token: &str) -> StatusCode
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = req
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+
+    match app.call(req).await {
+        Ok(resp) => resp.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+#[actix_web::test]
+async fn test_personal_access_token_lifecycle() {
+    let mut ctx = TestContext::new().await;
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+    let app = token_app!(ctx);
+
+    let tokens = login(&app, &username, &password, None, Some(CLIENT_IP)).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let (status, created) = create_token(
+        &app,
+        access_token,
+        json!({
+            "name": "Invitation report script",
+            "scopes": ["profile:read", "invitations:read"],
+            "expires_in_days": 30
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let pat = created["token"].as_str().unwrap().to_string();
+    assert!(pat.starts_with("upat_"));
+    assert_eq!(
+        created["scopes"],
+        json!(["invitations:read", "profile:read"])
+    );
+
+    // Scoped routes accept it
+    let req = test::TestRequest::get()
+        .uri("/api/auth/me")
+        .insert_header(("Authorization", format!("Bearer {}", pat)))
+        .to_request();
+    let me: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(me["username"], username.as_str());
+
+    let list_invitations = test::TestRequest::get().uri("/api/auth/invitations");
+    assert_eq!(
+        status_with(&app, list_invitations, &pat).await,
+        StatusCode::OK
+    );
+
+    // Routes needing a scope it lacks refuse it
+    let create_invitation = test::TestRequest::post()
+        .uri("/api/auth/invitations")
+        .set_json(json!({ "token_type": "group", "max_uses": 5 }));
+    assert_eq!(
+        status_with(&app, create_invitation, &pat).await,
+        StatusCode::FORBIDDEN
+    );
+
+    // Routes that take no scope never accept it, token management included
+    let sessions = test::TestRequest::get().uri("/api/auth/sessions");
+    assert_eq!(
+        status_with(&app, sessions, &pat).await,
+        StatusCode::UNAUTHORIZED
+    );
+    let (status, _) = create_token(
+        &app,
+        &pat,
+        json!({ "name": "Escalation", "scopes": ["invitations:write"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // The owner sees the token, its use, but not the token itself
+    let req = test::TestRequest::get()
+        .uri("/api/auth/tokens")
+        .insert_header(("Authorization", format!("Bearer {}", access_token)))
+        .to_request();
+    let tokens: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0]["name"], "Invitation report script");
+    assert!(tokens[0]["last_used_at"].is_string());
+    assert!(tokens[0].get("token").is_none());
+
+    // Revocation takes effect immediately
+    let revoke = test::TestRequest::delete().uri(&format!(
+        "/api/auth/tokens/{}",
+        created["id"].as_str().unwrap()
+    ));
+    assert_eq!(
+        status_with(&app, revoke, access_token).await,
+        StatusCode::OK
+    );
+
+    let me = test::TestRequest::get().uri("/api/auth/me");
+    assert_eq!(status_with(&app, me, &pat).await, StatusCode::UNAUTHORIZED);
+
+    let revoke_again = test::TestRequest::delete().uri(&format!(
+        "/api/auth/tokens/{}",
+        created["id"].as_str().unwrap()
+    ));
+    assert_eq!(
+        status_with(&app, revoke_again, access_token).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let actions: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT action FROM global.audit_log
+        WHERE resource_type = 'personal_access_token' AND resource_id = $1
+        ORDER BY created_at
+        "#,
+    )
+    .bind(created["id"].as_str().unwrap())
+    .fetch_all(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        actions,
+        [
+            "personal_access_token.created",
+            "personal_access_token.revoked"
+        ]
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_personal_access_token_request_validation() {
+    let mut ctx = TestContext::new().await;
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+    let app = token_app!(ctx);
+
+    let tokens = login(&app, &username, &password, None, Some(CLIENT_IP)).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    for body in [
+        json!({ "name": "Too powerful", "scopes": ["admin:all"] }),
+        json!({ "name": "No scopes", "scopes": [] }),
+        json!({ "name": "", "scopes": ["profile:read"] }),
+        json!({ "name": "Forever", "scopes": ["profile:read"], "expires_in_days": 3650 }),
+    ] {
+        let (status, _) = create_token(&app, access_token, body.clone()).await;
+        assert_eq!(
+            status,
+            StatusCode::BAD_REQUEST,
+            "{} should be rejected",
+            body
+        );
+    }
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_deactivation_and_forced_logout_stop_personal_access_tokens() {
+    let mut ctx = TestContext::new().await;
+    let (user_id, username, password, _email) = ctx.create_user().await;
+    let app = token_app!(ctx);
+
+    let tokens = login(&app, &username, &password, None, Some(CLIENT_IP)).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let (_, created) = create_token(
+        &app,
+        access_token,
+        json!({ "name": "Profile backup", "scopes": ["profile:read"] }),
+    )
+    .await;
+    let pat = created["token"].as_str().unwrap().to_string();
+
+    // Deactivated members cannot use their tokens
+    sqlx::query("UPDATE territory.users SET is_active = $1 WHERE id = $2")
+        .bind(false)
+        .bind(user_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    let me = test::TestRequest::get().uri("/api/auth/me");
+    assert_eq!(status_with(&app, me, &pat).await, StatusCode::UNAUTHORIZED);
+
+    sqlx::query("UPDATE territory.users SET is_active = $1 WHERE id = $2")
+        .bind(true)
+        .bind(user_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    let me = test::TestRequest::get().uri("/api/auth/me");
+    assert_eq!(status_with(&app, me, &pat).await, StatusCode::OK);
+
+    // A forced logout revokes them for good
+    let identity_id: uuid::Uuid = sqlx::query_scalar(
+        "SELECT id FROM global.user_identities WHERE territory_code = 'dk' AND territory_user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    let schema = ctx.territories.schema_for("dk").unwrap().to_string();
+    let outcome = auth_service::services::force_logout(
+        &ctx.pool,
+        &schema,
+        "dk",
+        &username,
+        "Token leaked in a public repository",
+        identity_id,
+    )
+    .await
+    .unwrap();
+    assert_eq!(outcome.personal_access_tokens_revoked, 1);
+
+    let me = test::TestRequest::get().uri("/api/auth/me");
+    assert_eq!(status_with(&app, me, &pat).await, StatusCode::UNAUTHORIZED);
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,288 @@
+use actix_web::{dev::Service, test, web, App};
+use auth_service::services::{AuthStateListener, TokenRevocations, TokenService};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+
+use crate::common::*;
+
+/// Client IP of this file's logins, so they have their own IP throttle counter
+const CLIENT_IP: &str = "198.51.100.84";
+
+/// Longest wait for a NOTIFY to reach the listener
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Token service that checks revocations, kept current by a listener
+async fn revocation_checking_token_service(ctx: &TestContext) -> Arc<TokenService> {
+    let listener = AuthStateListener::connect(&ctx.pool)
+        .await
+        .expect("Failed to listen for auth state changes");
+    let revocations = Arc::new(TokenRevocations::new(900));
+    revocations.reload(&ctx.pool).await.unwrap();
+    tokio::spawn(listener.revocations(revocations.clone()).run());
+
+    Arc::new(
+        TokenService::new("test_secret_key_for_jwt_tokens_12345", 900, 604800)
+            .with_revocations(revocations),
+    )
+}
+
+/// Poll until `access_token` no longer validates, or give up after `NOTIFY_TIMEOUT`
+async fn revoked_after_notify(token_service: &TokenService, access_token: &str) -> bool {
+    let started = std::time::Instant::now();
+    while started.elapsed() < NOTIFY_TIMEOUT {
+        if token_service.validate_token(access_token).is_err() {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    false
+}
+
+#[actix_web::test]
+async fn test_logout_revokes_the_access_token() {
+    let mut ctx = TestContext::new().await;
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+    let token_service = revocation_checking_token_service(&ctx).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/logout",
+                        web::post().to(auth_service::handlers::auth::logout),
+                    ),
+            ),
+    )
+    .await;
+
+    let laptop = login(&app, &username, &password, None, Some(CLIENT_IP)).await;
+    let phone = login(&app, &username, &password, None, Some(CLIENT_IP)).await;
+    let laptop_token = laptop["access_token"].as_str().unwrap();
+    let phone_token = phone["access_token"].as_str().unwrap();
+
+    let claims = token_service.validate_token(laptop_token).unwrap();
+    assert!(claims.jti.is_some(), "Access tokens should carry a jti");
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/logout")
+        .insert_header(("Authorization", format!("Bearer {}", laptop_token)))
+        .set_json(json!({ "refresh_token": laptop["refresh_token"] }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200, "Logout should succeed");
+
+    assert!(
+        revoked_after_notify(&token_service, laptop_token).await,
+        "Logged out access token should be revoked"
+    );
+    assert!(
+        token_service.validate_token(phone_token).is_ok(),
+        "Other devices keep their access tokens"
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_password_change_revokes_earlier_access_tokens() {
+    let mut ctx = TestContext::new().await;
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+    let token_service = revocation_checking_token_service(&ctx).await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/password",
+                                web::post().to(auth_service::handlers::password::change_password),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    // Issued moments before the change, usually in the same second
+    let phone = login(&app, &username, &password, None, Some(CLIENT_IP)).await;
+    let phone_token = phone["access_token"].as_str().unwrap();
+    let laptop = login(&app, &username, &password, None, Some(CLIENT_IP)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/password")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", laptop["access_token"].as_str().unwrap()),
+        ))
+        .set_json(json!({
+            "current_password": password,
+            "new_password": "BrandNewPassword456!"
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let replacement = body["access_token"]
+        .as_str()
+        .expect("Response should carry a new access token");
+
+    assert!(
+        revoked_after_notify(&token_service, phone_token).await,
+        "Access tokens issued before the change should be revoked"
+    );
+    assert!(
+        token_service.validate_token(replacement).is_ok(),
+        "The replacement access token should work"
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_moderator_force_logout() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+    let (moderator_id, moderator_username, moderator_password, _email) = ctx.create_user().await;
+    let (_member_id, member_username, member_password, _email) = ctx.create_user().await;
+    let token_service = revocation_checking_token_service(&ctx).await;
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, 'moderator' FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(moderator_id)
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/refresh",
+                        web::post().to(auth_service::handlers::auth::refresh),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(auth_service::middleware::RequirePermission(
+                                auth_service::services::permissions::USERS_MODERATE,
+                            ))
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "/{username}/logout",
+                                web::post().to(auth_service::handlers::session::force_logout_user),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let member = login(
+        &app,
+        &member_username,
+        &member_password,
+        None,
+        Some(CLIENT_IP),
+    )
+    .await;
+    let member_token = member["access_token"].as_str().unwrap();
+    let moderator = login(
+        &app,
+        &moderator_username,
+        &moderator_password,
+        None,
+        Some(CLIENT_IP),
+    )
+    .await;
+
+    // Members cannot sign each other out
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/auth/users/{}/logout", moderator_username))
+        .insert_header(("Authorization", format!("Bearer {}", member_token)))
+        .set_json(json!({ "reason": "Revenge" }))
+        .to_request();
+    let resp = app.call(req).await;
+    let status = match resp {
+        Ok(resp) => resp.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    assert_eq!(status, 403, "Members should not be able to force a logout");
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/auth/users/{}/logout", member_username))
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", moderator["access_token"].as_str().unwrap()),
+        ))
+        .set_json(json!({ "reason": "Compromised account reported" }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["sessions_revoked"], 1);
+
+    // Access token and session are both gone
+    assert!(
+        revoked_after_notify(&token_service, member_token).await,
+        "The member's access token should be revoked"
+    );
+    let req = test::TestRequest::post()
+        .uri("/api/auth/refresh")
+        .set_json(json!({ "refresh_token": member["refresh_token"], "territory_code": "dk" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401, "The member's session should be revoked");
+
+    let actions: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT action FROM global.audit_log
+        WHERE action = 'auth.force_logout' AND resource_id = (
+            SELECT id::text FROM global.user_identities
+            WHERE territory_code = 'dk' AND username = $1
+        )
+        "#,
+    )
+    .bind(&member_username)
+    .fetch_all(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(actions, ["auth.force_logout"]);
+
+    // Unknown users are reported
+    let req = test::TestRequest::post()
+        .uri("/api/auth/users/nobody_here/logout")
+        .insert_header((
+            "Authorization",
+            format!("Bearer {}", moderator["access_token"].as_str().unwrap()),
+        ))
+        .set_json(json!({ "reason": "Typo" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,202 @@
+use actix_web::{test, web, App};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::common::*;
+
+/// Give a territory user a territory role directly in the database
+async fn assign_role(pool: &PgPool, user_id: uuid::Uuid, role: &str) {
+    sqlx::query(
+        r#"
+        INSERT INTO global.territory_managers (user_id, territory_code, role)
+        SELECT id, territory_code, $2 FROM global.user_identities
+        WHERE territory_code = 'dk' AND territory_user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(role)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn test_group_invitations_require_permission() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::post()
+                                    .to(auth_service::handlers::invitation::create_invitation),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let tokens = login(&app, &username, &password, None, None).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let create = |token_type: &str| {
+        let unique_id = uuid::Uuid::new_v4().to_string()[..8].to_string();
+        let body = match token_type {
+            "group" => json!({
+                "token_type": "group",
+                "max_uses": 1000,
+                "expires_in_days": 7
+            }),
+            _ => json!({
+                "token_type": "single_use",
+                "email": format!("invited_{}@test.dk", unique_id),
+                "max_uses": 1,
+                "expires_in_days": 7
+            }),
+        };
+
+        test::TestRequest::post()
+            .uri("/api/auth/invitations")
+            .insert_header(("Authorization", format!("Bearer {}", access_token)))
+            .set_json(body)
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, create("group")).await;
+    assert_eq!(
+        resp.status(),
+        403,
+        "Plain users cannot create group invitations"
+    );
+
+    let resp = test::call_service(&app, create("single_use")).await;
+    assert_eq!(
+        resp.status(),
+        201,
+        "Plain users can still invite one person"
+    );
+
+    // Roles are resolved on every request, so the same token now works
+    assign_role(&ctx.pool, user_id, "moderator").await;
+
+    let resp = test::call_service(&app, create("group")).await;
+    assert_eq!(
+        resp.status(),
+        201,
+        "Moderators can create group invitations"
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_admin_grants_and_revokes_roles() {
+    let _settings = MANAGER_SETTINGS.read().await;
+    let mut ctx = TestContext::new().await;
+
+    let (admin_id, admin_username, admin_password, _email) = ctx.create_user().await;
+    let (_member_id, member_username, member_password, _email) = ctx.create_user().await;
+
+    assign_role(&ctx.pool, admin_id, "territory_admin").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/roles")
+                            .wrap(auth_service::middleware::RequirePermission(
+                                auth_service::services::permissions::ROLES_MANAGE,
+                            ))
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route("", web::get().to(auth_service::handlers::role::list_roles))
+                            .route("", web::post().to(auth_service::handlers::role::grant_role))
+                            .route(
+                                "/{username}/{role}",
+                                web::delete().to(auth_service::handlers::role::revoke_role),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let admin = login(&app, &admin_username, &admin_password, None, None).await;
+    let admin_token = admin["access_token"].as_str().unwrap();
+    let member = login(&app, &member_username, &member_password, None, None).await;
+    let member_token = member["access_token"].as_str().unwrap();
+
+    let grant = |token: &str, role: &str| {
+        test::TestRequest::post()
+            .uri("/api/auth/roles")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({ "username": member_username, "role": role }))
+            .to_request()
+    };
+
+    // Members without roles.manage are refused by the guard
+    match test::try_call_service(&app, grant(member_token, "moderator")).await {
+        Ok(resp) => assert_eq!(resp.status(), 403),
+        Err(err) => assert_eq!(err.as_response_error().status_code(), 403),
+    }
+
+    let resp = test::call_service(&app, grant(admin_token, "superuser")).await;
+    assert_eq!(resp.status(), 400, "Unknown roles are rejected");
+
+    let resp = test::call_service(&app, grant(admin_token, "moderator")).await;
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["username"], member_username.as_str());
+    assert_eq!(body["granted_by"], admin_username.as_str());
+
+    let resp = test::call_service(&app, grant(admin_token, "moderator")).await;
+    assert_eq!(resp.status(), 409, "Granting twice is a conflict");
+
+    let req = test::TestRequest::get()
+        .uri("/api/auth/roles")
+        .insert_header(("Authorization", format!("Bearer {}", admin_token)))
+        .to_request();
+    let grants: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(grants
+        .iter()
+        .any(|g| g["username"] == member_username.as_str() && g["role"] == "moderator"));
+
+    let revoke = |username: &str| {
+        test::TestRequest::delete()
+            .uri(&format!("/api/auth/roles/{}/moderator", username))
+            .insert_header(("Authorization", format!("Bearer {}", admin_token)))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, revoke(&member_username)).await;
+    assert_eq!(resp.status(), 200);
+
+    let resp = test::call_service(&app, revoke(&member_username)).await;
+    assert_eq!(resp.status(), 404, "Role is already gone");
+
+    let resp = test::call_service(&app, revoke(&admin_username)).await;
+    assert_eq!(resp.status(), 400, "Admins cannot revoke their own roles");
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,317 @@
+use actix_web::{test, web, App};
+use serde_json::json;
+
+use crate::common::*;
+
+/// Client IP of this file's logins, so they have their own IP throttle counter
+const CLIENT_IP: &str = "198.51.100.23";
+
+#[actix_web::test]
+async fn test_list_sessions_marks_current() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/sessions")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::get().to(auth_service::handlers::session::get_sessions),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let laptop = login(&app, &username, &password, Some("Laptop"), Some(CLIENT_IP)).await;
+    let laptop_token = laptop["access_token"].as_str().unwrap();
+    login(&app, &username, &password, Some("Phone"), Some(CLIENT_IP)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", laptop_token)))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(sessions.len(), 2, "Both devices should be listed");
+
+    let current: Vec<_> = sessions.iter().filter(|s| s["current"] == true).collect();
+    assert_eq!(current.len(), 1, "Exactly one session should be current");
+    assert_eq!(current[0]["user_agent"], "Laptop");
+    assert_eq!(current[0]["ip_address"], "198.51.100.23");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_revoke_session_logs_out_device() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .route(
+                        "/refresh",
+                        web::post().to(auth_service::handlers::auth::refresh),
+                    )
+                    .service(
+                        web::scope("/sessions")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::get().to(auth_service::handlers::session::get_sessions),
+                            )
+                            .route(
+                                "/{id}",
+                                web::delete().to(auth_service::handlers::session::delete_session),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let home = login(&app, &username, &password, Some("Home"), Some(CLIENT_IP)).await;
+    let home_token = home["access_token"].as_str().unwrap();
+    let centre = login(
+        &app,
+        &username,
+        &password,
+        Some("Community centre"),
+        Some(CLIENT_IP),
+    )
+    .await;
+    let centre_token = centre["access_token"].as_str().unwrap();
+
+    // Find the community centre session from the home device
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", home_token)))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    let centre_session = sessions
+        .iter()
+        .find(|s| s["user_agent"] == "Community centre")
+        .expect("Community centre session should be listed");
+
+    let req = test::TestRequest::delete()
+        .uri(&format!(
+            "/api/auth/sessions/{}",
+            centre_session["id"].as_str().unwrap()
+        ))
+        .insert_header(("Authorization", format!("Bearer {}", home_token)))
+        .insert_header(("User-Agent", "Home"))
+        .peer_addr(format!("{}:52100", CLIENT_IP).parse().unwrap())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200, "Revoking a session should succeed");
+
+    // Audited with who revoked which session, and from where
+    let audit: (String, String, String, Option<String>) = sqlx::query_as(
+        r#"
+        SELECT ui.username, a.territory_code, host(a.ip_address), a.user_agent
+        FROM global.audit_log a
+        JOIN global.user_identities ui ON ui.id = a.user_id
+        WHERE a.action = 'session.revoked' AND a.resource_type = 'session_family'
+          AND a.resource_id = $1
+        "#,
+    )
+    .bind(centre_session["id"].as_str().unwrap())
+    .fetch_one(&ctx.pool)
+    .await
+    .expect("Revoking a session should be audited");
+    assert_eq!(
+        audit,
+        (
+            username.clone(),
+            "dk".to_string(),
+            CLIENT_IP.to_string(),
+            Some("Home".to_string())
+        )
+    );
+
+    // The revoked device can neither use its access token...
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", centre_token)))
+        .to_request();
+    let resp = test::try_call_service(&app, req).await;
+    match resp {
+        Ok(resp) => assert_eq!(resp.status(), 401, "Revoked access token should fail"),
+        Err(e) => assert_eq!(e.as_response_error().status_code(), 401),
+    }
+
+    // ...nor refresh it
+    let req = test::TestRequest::post()
+        .uri("/api/auth/refresh")
+        .set_json(json!({
+            "refresh_token": centre["refresh_token"],
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 401, "Revoked refresh token should fail");
+
+    // The home device is unaffected
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", home_token)))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(sessions.len(), 1, "Only the home session should remain");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_revoke_other_users_session_not_found() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+    let (_other_id, other_username, other_password, _other_email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/sessions")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::get().to(auth_service::handlers::session::get_sessions),
+                            )
+                            .route(
+                                "/{id}",
+                                web::delete().to(auth_service::handlers::session::delete_session),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let body = login(&app, &username, &password, Some("Laptop"), Some(CLIENT_IP)).await;
+    let token = body["access_token"].as_str().unwrap();
+    let other = login(
+        &app,
+        &other_username,
+        &other_password,
+        Some("Phone"),
+        Some(CLIENT_IP),
+    )
+    .await;
+    let other_token = other["access_token"].as_str().unwrap();
+
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", other_token)))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    let other_session_id = sessions[0]["id"].as_str().unwrap();
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/auth/sessions/{}", other_session_id))
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(
+        resp.status(),
+        404,
+        "Users must not be able to revoke other users' sessions"
+    );
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_revoke_other_sessions() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .service(
+                web::scope("/api/auth")
+                    .route(
+                        "/login",
+                        web::post().to(auth_service::handlers::auth::login),
+                    )
+                    .service(
+                        web::scope("/sessions")
+                            .wrap(auth_service::middleware::JwtAuth)
+                            .route(
+                                "",
+                                web::get().to(auth_service::handlers::session::get_sessions),
+                            )
+                            .route(
+                                "/revoke-others",
+                                web::post().to(auth_service::handlers::session::revoke_others),
+                            ),
+                    ),
+            ),
+    )
+    .await;
+
+    let body = login(&app, &username, &password, Some("Laptop"), Some(CLIENT_IP)).await;
+    let token = body["access_token"].as_str().unwrap();
+    login(&app, &username, &password, Some("Phone"), Some(CLIENT_IP)).await;
+    login(
+        &app,
+        &username,
+        &password,
+        Some("Library PC"),
+        Some(CLIENT_IP),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/sessions/revoke-others")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["revoked"], 2, "Both other sessions should be revoked");
+
+    let req = test::TestRequest::get()
+        .uri("/api/auth/sessions")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .to_request();
+    let sessions: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(sessions.len(), 1, "Only the current session should remain");
+    assert_eq!(sessions[0]["current"], true);
+
+    ctx.cleanup().await;
+}
@@ -0,0 +1,420 @@
+use actix_web::{test, web, App};
+use serde_json::json;
+use shared_lib::TrustedProxies;
+use std::net::SocketAddr;
+
+use crate::common::*;
+
+#[actix_web::test]
+async fn test_repeated_failures_lock_out_username() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            ),
+    )
+    .await;
+
+    // Free attempts and the first locking failure are plain 401s
+    for _ in 0..6 {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({
+                "username": username,
+                "password": "WrongPassword123!",
+                "territory_code": "dk"
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401, "Wrong password should fail");
+    }
+
+    // Now even the right password is refused until the lockout passes
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 429, "Locked username should be throttled");
+
+    let retry_after: i64 = resp
+        .headers()
+        .get("Retry-After")
+        .expect("429 should carry Retry-After")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after >= 1);
+
+    // Once the lockout has passed, a successful login resets the counter
+    tokio::time::sleep(std::time::Duration::from_secs(retry_after as u64)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({
+            "username": username,
+            "password": password,
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200, "Login should succeed after lockout");
+
+    let remaining: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM global.login_attempts WHERE scope = 'username' AND key = $1",
+    )
+    .bind(format!("dk:{}", username.to_lowercase()))
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining, 0, "Successful login should clear the counter");
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_parallel_guesses_cannot_outrun_the_lockout() {
+    let mut ctx = TestContext::new().await;
+
+    let (_user_id, username, _password, _email) = ctx.create_user().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            ),
+    )
+    .await;
+
+    // A guesser deep into backoff whose last lockout has just passed: the next
+    // failure locks the username for minutes
+    sqlx::query(
+        "INSERT INTO global.login_attempts (scope, key, failure_count) VALUES ('username', $1, 14)",
+    )
+    .bind(format!("dk:{}", username.to_lowercase()))
+    .execute(&ctx.pool)
+    .await
+    .unwrap();
+
+    // All guesses are in flight before any password has been checked
+    let guesses = (0..20).map(|_| {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({
+                "username": username,
+                "password": "WrongPassword123!",
+                "territory_code": "dk"
+            }))
+            .to_request();
+        test::call_service(&app, req)
+    });
+    let statuses: Vec<u16> = futures_util::future::join_all(guesses)
+        .await
+        .iter()
+        .map(|resp| resp.status().as_u16())
+        .collect();
+
+    let checked = statuses.iter().filter(|&&status| status == 401).count();
+    let throttled = statuses.iter().filter(|&&status| status == 429).count();
+    assert_eq!(checked, 1, "Only one guess should be checked");
+    assert_eq!(throttled, 19, "The rest should be throttled");
+
+    // Only the guess that started the lockout is audited, not the refused ones
+    let reasons: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT changes->>'reason' FROM global.audit_log
+        WHERE action = 'auth.login_failed' AND changes->>'username' = $1
+        ORDER BY seq
+        "#,
+    )
+    .bind(&username)
+    .fetch_all(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(reasons, vec!["invalid_password", "locked_out"]);
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'username' AND key = $1")
+        .bind(format!("dk:{}", username.to_lowercase()))
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_inactive_accounts_look_like_wrong_passwords() {
+    let mut ctx = TestContext::new().await;
+
+    let (user_id, username, password, _email) = ctx.create_user().await;
+    sqlx::query("UPDATE territory.users SET is_active = false WHERE id = $1")
+        .bind(user_id)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            ),
+    )
+    .await;
+
+    let mut bodies = Vec::new();
+    for attempt in [password.as_str(), "WrongPassword123!"] {
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .set_json(json!({
+                "username": username,
+                "password": attempt,
+                "territory_code": "dk"
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401, "Inactive account should not log in");
+        bodies.push(test::read_body(resp).await);
+    }
+    assert_eq!(
+        bodies[0], bodies[1],
+        "Right and wrong passwords should get the same response"
+    );
+
+    let failures: i32 = sqlx::query_scalar(
+        "SELECT failure_count FROM global.login_attempts WHERE scope = 'username' AND key = $1",
+    )
+    .bind(format!("dk:{}", username.to_lowercase()))
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(failures, 2, "Both attempts should count as failures");
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'username' AND key = $1")
+        .bind(format!("dk:{}", username.to_lowercase()))
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_unknown_usernames_count_against_ip() {
+    let ctx = TestContext::new().await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            ),
+    )
+    .await;
+
+    // Unique documentation-range IP so parallel tests do not share the counter
+    let ip = format!("2001:db8::{:x}", rand::random::<u16>().max(1));
+    let peer = SocketAddr::new(ip.parse().unwrap(), 52100);
+
+    // Guessing a different username each time still trips the IP counter
+    let mut guessed = Vec::new();
+    for _ in 0..21 {
+        let username = format!("nobody_{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        guessed.push(format!("dk:{}", username));
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .peer_addr(peer)
+            .set_json(json!({
+                "username": username,
+                "password": "WrongPassword123!",
+                "territory_code": "dk"
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401, "Unknown user should fail");
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .peer_addr(peer)
+        .set_json(json!({
+            "username": "someone_else",
+            "password": "WrongPassword123!",
+            "territory_code": "dk"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 429, "Locked IP should be throttled");
+    assert!(resp.headers().contains_key("Retry-After"));
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'ip' AND key = $1")
+        .bind(&ip)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'username' AND key = ANY($1)")
+        .bind(&guessed)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_spoofed_forwarded_for_does_not_reset_ip_counter() {
+    let ctx = TestContext::new().await;
+
+    let trusted_proxies: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
+            .app_data(web::Data::from(ctx.token_service.clone()))
+            .app_data(web::Data::new(trusted_proxies))
+            .route(
+                "/api/auth/login",
+                web::post().to(auth_service::handlers::auth::login),
+            ),
+    )
+    .await;
+
+    let ip = format!("2001:db8::1:{:x}", rand::random::<u16>().max(1));
+    let peer = SocketAddr::new(ip.parse().unwrap(), 52100);
+
+    // The peer is not a trusted proxy, so a fresh X-Forwarded-For on every
+    // attempt must not give it a fresh counter
+    let mut guessed = Vec::new();
+    let mut spoofed = Vec::new();
+    for attempt in 0..22u16 {
+        let username = format!("nobody_{}", &uuid::Uuid::new_v4().to_string()[..8]);
+        guessed.push(format!("dk:{}", username));
+        spoofed.push(format!("2001:db8:ffff::{:x}", attempt + 1));
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth/login")
+            .peer_addr(peer)
+            .insert_header(("X-Forwarded-For", spoofed.last().unwrap().as_str()))
+            .set_json(json!({
+                "username": username,
+                "password": "WrongPassword123!",
+                "territory_code": "dk"
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        let expected = if attempt < 21 { 401 } else { 429 };
+        assert_eq!(resp.status(), expected, "Attempt {}", attempt + 1);
+    }
+
+    let spoofed_counters: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM global.login_attempts WHERE scope = 'ip' AND key = ANY($1)",
+    )
+    .bind(&spoofed)
+    .fetch_one(&ctx.pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        spoofed_counters, 0,
+        "Spoofed addresses should not be counted"
+    );
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'ip' AND key = $1")
+        .bind(&ip)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'username' AND key = ANY($1)")
+        .bind(&guessed)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
+
+#[actix_web::test]
+async fn test_stale_counters_are_pruned() {
+    let ctx = TestContext::new().await;
+
+    // (last failure, locked until) relative to now, and whether the counter survives
+    let counters = [
+        ("2 hours", None, false),
+        ("2 hours", Some("-1 minute"), false),
+        ("2 hours", Some("10 minutes"), true),
+        ("1 minute", None, true),
+    ];
+    let keys: Vec<String> = counters
+        .iter()
+        .map(|_| format!("dk:stale_{}", &uuid::Uuid::new_v4().to_string()[..8]))
+        .collect();
+
+    for (key, (last_failure, locked_until, _)) in keys.iter().zip(&counters) {
+        sqlx::query(
+            r#"
+            INSERT INTO global.login_attempts (scope, key, failure_count, last_failure_at, locked_until)
+            VALUES ('username', $1, 10, NOW() - $2::interval, NOW() + $3::interval)
+            "#,
+        )
+        .bind(key)
+        .bind(last_failure)
+        .bind(locked_until)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    }
+
+    let pruned = auth_service::services::prune_login_attempts(&ctx.pool)
+        .await
+        .unwrap();
+    assert!(pruned >= 2, "Both stale counters should be pruned");
+
+    for (key, (last_failure, locked_until, survives)) in keys.iter().zip(&counters) {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM global.login_attempts WHERE scope = 'username' AND key = $1)",
+        )
+        .bind(key)
+        .fetch_one(&ctx.pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            exists, *survives,
+            "Counter last failed {} ago, locked until {:?}",
+            last_failure, locked_until
+        );
+    }
+
+    sqlx::query("DELETE FROM global.login_attempts WHERE scope = 'username' AND key = ANY($1)")
+        .bind(&keys)
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+    ctx.cleanup().await;
+}
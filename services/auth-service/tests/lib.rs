@@ -23,6 +23,7 @@ async fn test_health_endpoint() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(ctx.pool.clone()))
+            .app_data(web::Data::from(ctx.territories.clone()))
             .app_data(web::Data::from(ctx.token_service.clone()))
             .route(
                 "/health",
@@ -0,0 +1,13 @@
+-- Rollback refresh token families
+DROP INDEX IF EXISTS global.idx_global_sessions_family;
+
+-- Rotated and revoked sessions are no longer distinguishable from live ones
+DELETE FROM global.sessions WHERE rotated_at IS NOT NULL OR revoked_at IS NOT NULL;
+
+ALTER TABLE global.sessions
+    DROP COLUMN IF EXISTS revoked_reason,
+    DROP COLUMN IF EXISTS revoked_at,
+    DROP COLUMN IF EXISTS rotated_at,
+    DROP COLUMN IF EXISTS family_expires_at,
+    DROP COLUMN IF EXISTS parent_id,
+    DROP COLUMN IF EXISTS family_id;
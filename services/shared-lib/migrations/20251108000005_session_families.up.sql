@@ -0,0 +1,25 @@
+-- Refresh token families
+--
+-- Every login starts a family. Rotating a refresh token marks the old session as
+-- rotated and inserts a child session in the same family. Presenting a rotated
+-- token again means it was copied, so the whole family is revoked.
+
+ALTER TABLE global.sessions
+    ADD COLUMN family_id UUID,
+    ADD COLUMN parent_id UUID REFERENCES global.sessions(id) ON DELETE SET NULL,
+    ADD COLUMN family_expires_at TIMESTAMPTZ,
+    ADD COLUMN rotated_at TIMESTAMPTZ,
+    ADD COLUMN revoked_at TIMESTAMPTZ,
+    ADD COLUMN revoked_reason VARCHAR(50);
+
+-- Existing sessions become single-member families
+UPDATE global.sessions
+SET family_id = id,
+    family_expires_at = expires_at
+WHERE family_id IS NULL;
+
+ALTER TABLE global.sessions
+    ALTER COLUMN family_id SET NOT NULL,
+    ALTER COLUMN family_expires_at SET NOT NULL;
+
+CREATE INDEX idx_global_sessions_family ON global.sessions(family_id);
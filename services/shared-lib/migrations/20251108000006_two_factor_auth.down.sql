@@ -0,0 +1,9 @@
+-- Rollback TOTP two-factor authentication
+ALTER TABLE global.sessions DROP COLUMN IF EXISTS mfa_verified;
+
+DELETE FROM territory.settings WHERE key = 'require_mfa_for_managers';
+
+DROP TRIGGER IF EXISTS update_territory_user_mfa_updated_at ON territory.user_mfa;
+
+DROP TABLE IF EXISTS territory.user_mfa_backup_codes;
+DROP TABLE IF EXISTS territory.user_mfa;
@@ -0,0 +1,37 @@
+-- TOTP two-factor authentication (RFC 6238)
+
+-- One TOTP authenticator per user. The row is created at enrolment and only
+-- becomes active (enabled_at) once the user has confirmed a code.
+CREATE TABLE territory.user_mfa (
+    user_id UUID PRIMARY KEY REFERENCES territory.users(id) ON DELETE CASCADE,
+    totp_secret VARCHAR(64) NOT NULL, -- Base32, as shown to the authenticator app
+    enabled_at TIMESTAMPTZ, -- NULL while enrolment is pending
+    last_used_step BIGINT, -- Last accepted TOTP time step (prevents code replay)
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- One-time backup codes (SHA-256 hashes only)
+CREATE TABLE territory.user_mfa_backup_codes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES territory.users(id) ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_user_mfa_backup_codes_user ON territory.user_mfa_backup_codes(user_id);
+
+CREATE TRIGGER update_territory_user_mfa_updated_at
+    BEFORE UPDATE ON territory.user_mfa
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Territories can require 2FA for users holding territory_managers roles
+INSERT INTO territory.settings (key, value)
+VALUES ('require_mfa_for_managers', 'false'::jsonb)
+ON CONFLICT (key) DO NOTHING;
+
+-- Whether a session was established with a second factor (carried into refreshed tokens)
+ALTER TABLE global.sessions
+    ADD COLUMN mfa_verified BOOLEAN NOT NULL DEFAULT FALSE;
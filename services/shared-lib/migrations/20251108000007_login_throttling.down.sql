@@ -0,0 +1,2 @@
+-- Rollback login throttling
+DROP TABLE IF EXISTS global.login_attempts;
@@ -0,0 +1,16 @@
+-- Login throttling
+--
+-- Failed login counters shared by all auth-service replicas. Counters are kept
+-- per territory username and per client IP; once a counter passes its free
+-- attempts, each further failure locks the key for an exponentially growing
+-- period (capped). Counters older than the failure window start over.
+
+CREATE TABLE global.login_attempts (
+    scope VARCHAR(20) NOT NULL,              -- 'username' or 'ip'
+    key VARCHAR(255) NOT NULL,               -- 'territory:username' or IP address
+    failure_count INTEGER NOT NULL DEFAULT 0,
+    last_failure_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMPTZ,
+    PRIMARY KEY (scope, key),
+    CONSTRAINT valid_login_attempt_scope CHECK (scope IN ('username', 'ip'))
+);
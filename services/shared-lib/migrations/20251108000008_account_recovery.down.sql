@@ -0,0 +1,3 @@
+-- Rollback account recovery
+DROP TABLE IF EXISTS territory.password_reset_tokens;
+DROP TABLE IF EXISTS territory.user_recovery_codes;
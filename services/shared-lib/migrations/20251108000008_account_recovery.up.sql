@@ -0,0 +1,28 @@
+-- Account recovery without email
+--
+-- Email is optional, so every account gets one-time recovery codes at
+-- registration. A recovery code can be redeemed for a password reset. Accounts
+-- with a verified email can also reset through a short-lived emailed token.
+
+-- One-time recovery codes (SHA-256 hashes only)
+CREATE TABLE territory.user_recovery_codes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES territory.users(id) ON DELETE CASCADE,
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_user_recovery_codes_user ON territory.user_recovery_codes(user_id);
+
+-- Password reset tokens sent by email (SHA-256 hashes only)
+CREATE TABLE territory.password_reset_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES territory.users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_password_reset_tokens_user ON territory.password_reset_tokens(user_id);
@@ -0,0 +1,4 @@
+-- Rollback email verification
+DELETE FROM territory.settings WHERE key = 'verified_email_required_for';
+
+DROP TABLE IF EXISTS territory.email_verification_tokens;
@@ -0,0 +1,22 @@
+-- Email verification
+--
+-- A verification token is issued for the address given at registration and
+-- for every email change. The address is kept on the token: an email change
+-- only reaches territory.users once the new address has been verified.
+
+CREATE TABLE territory.email_verification_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES territory.users(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE, -- SHA-256 of the emailed token
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_email_verification_tokens_user ON territory.email_verification_tokens(user_id);
+
+-- Features a territory reserves for users with a verified email (e.g. ["invitations"])
+INSERT INTO territory.settings (key, value)
+VALUES ('verified_email_required_for', '[]'::jsonb)
+ON CONFLICT (key) DO NOTHING;
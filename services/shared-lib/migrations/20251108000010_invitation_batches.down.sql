@@ -0,0 +1,6 @@
+-- Rollback invitation batches
+DELETE FROM territory.settings WHERE key = 'invitation_quota_per_creator';
+
+DROP INDEX IF EXISTS territory.idx_invitation_tokens_batch;
+
+ALTER TABLE territory.invitation_tokens DROP COLUMN IF EXISTS batch_id;
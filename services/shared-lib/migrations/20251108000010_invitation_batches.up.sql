@@ -0,0 +1,15 @@
+-- Invitation batches
+--
+-- Organisers can create many single-use invitations at once (e.g. for a
+-- school class). Tokens of one batch share a batch_id so the batch can be
+-- reported on and revoked as a unit.
+
+ALTER TABLE territory.invitation_tokens ADD COLUMN batch_id UUID;
+
+CREATE INDEX idx_invitation_tokens_batch ON territory.invitation_tokens(batch_id)
+WHERE batch_id IS NOT NULL;
+
+-- Maximum number of usable (active, unexpired, not used up) invitations per creator
+INSERT INTO territory.settings (key, value)
+VALUES ('invitation_quota_per_creator', '200'::jsonb)
+ON CONFLICT (key) DO NOTHING;
@@ -0,0 +1,5 @@
+-- Rollback invitation lineage
+--
+-- The backfilled inviters are kept: they are derived from invitation_tokens
+-- and registration populates the column from now on.
+SELECT 1;
@@ -0,0 +1,13 @@
+-- Invitation lineage
+--
+-- territory.users.invited_by_user_id records who brought a user in, so
+-- moderators can walk the invitation tree. Registration fills it from the
+-- invitation's creator; this backfills users registered before that.
+
+UPDATE territory.users u
+SET invited_by_user_id = t.created_by_user_id
+FROM territory.invitation_tokens t
+WHERE u.invitation_by_token_id = t.id
+  AND u.invited_by_user_id IS NULL
+  AND t.created_by_user_id IS NOT NULL
+  AND t.created_by_user_id <> u.id;
@@ -0,0 +1,18 @@
+-- Rollback tamper-evident audit log
+DROP TABLE IF EXISTS global.audit_checkpoints;
+
+UPDATE global.audit_log a SET user_id = NULL
+WHERE user_id IS NOT NULL
+  AND NOT EXISTS (SELECT 1 FROM global.user_identities ui WHERE ui.id = a.user_id);
+
+ALTER TABLE global.audit_log
+    ADD CONSTRAINT audit_log_user_id_fkey FOREIGN KEY (user_id)
+    REFERENCES global.user_identities(id) ON DELETE SET NULL;
+
+DROP INDEX IF EXISTS global.idx_global_audit_log_chain;
+
+ALTER TABLE global.audit_log
+    DROP COLUMN IF EXISTS hash,
+    DROP COLUMN IF EXISTS prev_hash,
+    DROP COLUMN IF EXISTS seq,
+    DROP COLUMN IF EXISTS chain_id;
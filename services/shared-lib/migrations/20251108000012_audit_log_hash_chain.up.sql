@@ -0,0 +1,32 @@
+-- Tamper-evident audit log
+--
+-- Every audit entry is chained to the previous entry of its chain (one chain
+-- per territory, plus "global" for entries without a territory):
+-- hash = SHA-256 over the entry's content and prev_hash, computed by the
+-- writer in shared-lib. Editing or deleting an entry breaks the chain from that
+-- entry on. Signed checkpoints of the chain heads also reveal entries removed
+-- from the end of a chain. Entries written before this migration stay unchained.
+
+ALTER TABLE global.audit_log
+    ADD COLUMN chain_id VARCHAR(100),
+    ADD COLUMN seq BIGINT,
+    ADD COLUMN prev_hash VARCHAR(64),
+    ADD COLUMN hash VARCHAR(64);
+
+CREATE UNIQUE INDEX idx_global_audit_log_chain ON global.audit_log(chain_id, seq)
+WHERE seq IS NOT NULL;
+
+-- Deleting an identity must not rewrite hashed entries, so the actor's ID is
+-- kept as a plain value
+ALTER TABLE global.audit_log DROP CONSTRAINT audit_log_user_id_fkey;
+
+-- Signed chain heads
+CREATE TABLE global.audit_checkpoints (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    chain_id VARCHAR(100) NOT NULL,
+    seq BIGINT NOT NULL,
+    hash VARCHAR(64) NOT NULL,
+    signature TEXT NOT NULL, -- JWS over chain_id, seq and hash (auth-service signing key)
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (chain_id, seq)
+);
@@ -0,0 +1,2 @@
+-- Rollback transactional outbox
+DROP TABLE IF EXISTS global.event_outbox;
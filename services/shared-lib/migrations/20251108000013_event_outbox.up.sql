@@ -0,0 +1,24 @@
+-- Transactional outbox for domain events
+--
+-- Services write events here in the same transaction as the change they
+-- describe, so an event exists exactly when its change was committed. A relay
+-- in each service publishes pending rows to NATS JetStream, retrying failures
+-- with backoff. The row ID is the envelope ID and is sent as Nats-Msg-Id, so
+-- JetStream drops an event the relay delivers twice.
+
+CREATE TABLE global.event_outbox (
+    id UUID PRIMARY KEY, -- Envelope ID
+    subject VARCHAR(255) NOT NULL,
+    event_type VARCHAR(100) NOT NULL,
+    territory_code VARCHAR(100) NOT NULL,
+    payload JSONB NOT NULL, -- Complete envelope, as published
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    published_at TIMESTAMPTZ,
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- The relay only ever looks at pending events
+CREATE INDEX idx_global_event_outbox_pending ON global.event_outbox(next_attempt_at)
+WHERE published_at IS NULL;
@@ -0,0 +1,2 @@
+-- Rollback service clients
+DROP TABLE IF EXISTS global.service_clients;
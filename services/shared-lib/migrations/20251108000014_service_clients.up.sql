@@ -0,0 +1,15 @@
+-- Service clients
+--
+-- Internal services (user-service, future badge and notification services)
+-- authenticate as a service client to introspect access tokens instead of
+-- sharing the JWT signing secret. Only a SHA-256 hash of the client secret is
+-- stored, like global.sessions.token_hash.
+
+CREATE TABLE global.service_clients (
+    client_id VARCHAR(100) PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    secret_hash VARCHAR(64) NOT NULL, -- SHA-256 (hex) of the client secret
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ
+);
@@ -0,0 +1,4 @@
+-- Rollback OpenID Connect provider
+DROP TABLE IF EXISTS global.oidc_consents;
+DROP TABLE IF EXISTS global.oidc_authorization_codes;
+DROP TABLE IF EXISTS global.oidc_clients;
@@ -0,0 +1,48 @@
+-- OpenID Connect provider
+--
+-- Relying parties (Forgejo, Matrix, ...) are registered per territory and sign
+-- members of that territory in with the authorization code flow. PKCE (S256) is
+-- required for every client; confidential clients also authenticate with a
+-- secret. Only SHA-256 hashes of client secrets and authorization codes are
+-- stored, like global.service_clients and global.sessions.
+
+CREATE TABLE global.oidc_clients (
+    client_id VARCHAR(100) PRIMARY KEY,
+    territory_code VARCHAR(100) NOT NULL REFERENCES global.territories(code) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL, -- Shown on the consent screen
+    secret_hash VARCHAR(64), -- SHA-256 (hex) of the client secret; NULL for public clients
+    redirect_uris TEXT[] NOT NULL, -- Exact match only
+    allowed_scopes TEXT[] NOT NULL DEFAULT ARRAY['openid', 'profile', 'email'],
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_global_oidc_clients_territory ON global.oidc_clients(territory_code);
+
+-- Single-use authorization codes, redeemed at the token endpoint
+CREATE TABLE global.oidc_authorization_codes (
+    code_hash VARCHAR(64) PRIMARY KEY, -- SHA-256 (hex) of the code
+    client_id VARCHAR(100) NOT NULL REFERENCES global.oidc_clients(client_id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES global.user_identities(id) ON DELETE CASCADE,
+    session_id UUID, -- Session family the member was logged in with
+    redirect_uri TEXT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    nonce TEXT,
+    code_challenge VARCHAR(128) NOT NULL, -- PKCE, S256 only
+    mfa_verified BOOLEAN NOT NULL DEFAULT false,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_global_oidc_authorization_codes_expires ON global.oidc_authorization_codes(expires_at);
+
+-- Scopes a member agreed to share with a client; the consent screen is skipped
+-- while a request asks for no more than this
+CREATE TABLE global.oidc_consents (
+    user_id UUID NOT NULL REFERENCES global.user_identities(id) ON DELETE CASCADE,
+    client_id VARCHAR(100) NOT NULL REFERENCES global.oidc_clients(client_id) ON DELETE CASCADE,
+    scopes TEXT[] NOT NULL,
+    granted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, client_id)
+);
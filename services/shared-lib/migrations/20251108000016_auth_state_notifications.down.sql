@@ -0,0 +1,10 @@
+-- Rollback auth state notifications
+DROP TRIGGER IF EXISTS trg_notify_global_roles ON global.role_assignments;
+DROP TRIGGER IF EXISTS trg_notify_territory_roles ON global.territory_managers;
+DROP TRIGGER IF EXISTS trg_notify_member_deleted ON territory.users;
+DROP TRIGGER IF EXISTS trg_notify_member_changed ON territory.users;
+DROP TRIGGER IF EXISTS trg_notify_session_deleted ON global.sessions;
+DROP TRIGGER IF EXISTS trg_notify_session_revoked ON global.sessions;
+DROP FUNCTION IF EXISTS notify_identity_roles();
+DROP FUNCTION IF EXISTS notify_member_state();
+DROP FUNCTION IF EXISTS notify_session_state();
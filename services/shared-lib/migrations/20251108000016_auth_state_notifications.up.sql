@@ -0,0 +1,99 @@
+-- Auth state notifications
+--
+-- auth-service trusts the signed claims of access tokens and keeps the state
+-- that can change during a token's lifetime (session revocation, account
+-- deactivation, email verification, roles) in an in-memory cache. These
+-- triggers tell every instance to drop a cached entry, via NOTIFY on the
+-- auth_state channel. Payloads are JSON:
+--
+--   {"kind": "session", "id": <session family_id>}
+--   {"kind": "member", "schema": <territory schema>, "id": <territory user id>}
+--   {"kind": "identity", "id": <global.user_identities.id>}
+--
+-- Notifications are sent on commit, so a rolled back revocation never drops
+-- anything.
+
+CREATE OR REPLACE FUNCTION notify_session_state()
+RETURNS TRIGGER AS $$
+DECLARE
+    v_family_id UUID;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        v_family_id := OLD.family_id;
+    ELSE
+        v_family_id := NEW.family_id;
+    END IF;
+
+    PERFORM pg_notify(
+        'auth_state',
+        json_build_object('kind', 'session', 'id', v_family_id)::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Revocation (logout everywhere, reuse detection, remote logout) and logout
+CREATE TRIGGER trg_notify_session_revoked
+    AFTER UPDATE OF revoked_at ON global.sessions
+    FOR EACH ROW
+    WHEN (OLD.revoked_at IS DISTINCT FROM NEW.revoked_at)
+    EXECUTE FUNCTION notify_session_state();
+
+CREATE TRIGGER trg_notify_session_deleted
+    AFTER DELETE ON global.sessions
+    FOR EACH ROW
+    EXECUTE FUNCTION notify_session_state();
+
+-- Works for every territory schema (TG_TABLE_SCHEMA names the schema)
+CREATE OR REPLACE FUNCTION notify_member_state()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify(
+        'auth_state',
+        json_build_object('kind', 'member', 'schema', TG_TABLE_SCHEMA, 'id', OLD.id)::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_notify_member_changed
+    AFTER UPDATE OF is_active, is_verified ON territory.users
+    FOR EACH ROW
+    WHEN (OLD.is_active IS DISTINCT FROM NEW.is_active
+          OR OLD.is_verified IS DISTINCT FROM NEW.is_verified)
+    EXECUTE FUNCTION notify_member_state();
+
+CREATE TRIGGER trg_notify_member_deleted
+    AFTER DELETE ON territory.users
+    FOR EACH ROW
+    EXECUTE FUNCTION notify_member_state();
+
+-- Territory and global role grants
+CREATE OR REPLACE FUNCTION notify_identity_roles()
+RETURNS TRIGGER AS $$
+DECLARE
+    v_user_id UUID;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        v_user_id := OLD.user_id;
+    ELSE
+        v_user_id := NEW.user_id;
+    END IF;
+
+    PERFORM pg_notify(
+        'auth_state',
+        json_build_object('kind', 'identity', 'id', v_user_id)::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_notify_territory_roles
+    AFTER INSERT OR UPDATE OR DELETE ON global.territory_managers
+    FOR EACH ROW
+    EXECUTE FUNCTION notify_identity_roles();
+
+CREATE TRIGGER trg_notify_global_roles
+    AFTER INSERT OR UPDATE OR DELETE ON global.role_assignments
+    FOR EACH ROW
+    EXECUTE FUNCTION notify_identity_roles();
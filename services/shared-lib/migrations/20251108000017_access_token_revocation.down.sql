@@ -0,0 +1,5 @@
+-- Rollback access token revocation
+DROP TABLE IF EXISTS global.access_token_cutoffs;
+DROP TABLE IF EXISTS global.revoked_access_tokens;
+DROP FUNCTION IF EXISTS notify_access_token_cutoff();
+DROP FUNCTION IF EXISTS notify_access_token_revoked();
@@ -0,0 +1,73 @@
+-- Access token revocation
+--
+-- Access tokens carry a jti (UUID). Ending a refresh session does not stop
+-- the access tokens issued for it, so logout revokes the presented token by
+-- jti, and password changes, suspension and forced logout reject every access
+-- token a user was issued before a point in time. auth-service keeps both in
+-- memory and checks them when validating a token; the triggers below tell
+-- every instance about new entries on the auth_state channel.
+--
+-- Entries only matter while the tokens they cover can still be valid: rows of
+-- global.revoked_access_tokens can be deleted after expires_at, and cut-offs
+-- once not_before is older than ACCESS_TOKEN_TTL.
+
+CREATE TABLE global.revoked_access_tokens (
+    jti UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES global.user_identities(id) ON DELETE CASCADE,
+    reason VARCHAR(50) NOT NULL,           -- 'logout', ...
+    expires_at TIMESTAMPTZ NOT NULL,       -- exp of the token
+    revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_revoked_access_tokens_expires ON global.revoked_access_tokens(expires_at);
+
+-- One cut-off per user; a later revocation moves it forward
+CREATE TABLE global.access_token_cutoffs (
+    user_id UUID PRIMARY KEY REFERENCES global.user_identities(id) ON DELETE CASCADE,
+    not_before TIMESTAMPTZ NOT NULL,       -- Tokens issued (iat) earlier are rejected
+    reason VARCHAR(50) NOT NULL,           -- 'password_changed', 'lineage_deactivated', 'force_logout'
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_access_token_cutoffs_not_before ON global.access_token_cutoffs(not_before);
+
+CREATE OR REPLACE FUNCTION notify_access_token_revoked()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify(
+        'auth_state',
+        json_build_object(
+            'kind', 'access_token',
+            'jti', NEW.jti,
+            'expires_at', FLOOR(EXTRACT(EPOCH FROM NEW.expires_at))::bigint
+        )::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_notify_access_token_revoked
+    AFTER INSERT ON global.revoked_access_tokens
+    FOR EACH ROW
+    EXECUTE FUNCTION notify_access_token_revoked();
+
+-- Tokens identify users by public_key_hash (sub)
+CREATE OR REPLACE FUNCTION notify_access_token_cutoff()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify(
+        'auth_state',
+        json_build_object(
+            'kind', 'access_token_cutoff',
+            'sub', (SELECT public_key_hash FROM global.user_identities WHERE id = NEW.user_id),
+            'not_before', FLOOR(EXTRACT(EPOCH FROM NEW.not_before))::bigint
+        )::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_notify_access_token_cutoff
+    AFTER INSERT OR UPDATE OF not_before ON global.access_token_cutoffs
+    FOR EACH ROW
+    EXECUTE FUNCTION notify_access_token_cutoff();
@@ -0,0 +1,2 @@
+-- Rollback personal access tokens
+DROP TABLE IF EXISTS global.personal_access_tokens;
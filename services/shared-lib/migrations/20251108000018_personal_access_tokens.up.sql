@@ -0,0 +1,23 @@
+-- Personal access tokens
+--
+-- Members create long-lived tokens for scripts and integrations instead of
+-- scripting the password login. A token carries the scopes it was created
+-- with (e.g. 'profile:read', 'invitations:write') and only works on routes
+-- that accept one of them. Only a SHA-256 hash of the token is stored, like
+-- global.sessions.token_hash; the token itself is shown once at creation.
+
+CREATE TABLE global.personal_access_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES global.user_identities(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,            -- Chosen by the member, e.g. 'Invitation sync script'
+    token_hash VARCHAR(64) NOT NULL UNIQUE, -- SHA-256 (hex) of the token
+    scopes TEXT[] NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    last_used_ip INET,
+    revoked_at TIMESTAMPTZ,
+    revoked_reason VARCHAR(50)             -- 'user_revoked', 'force_logout'
+);
+
+CREATE INDEX idx_personal_access_tokens_user ON global.personal_access_tokens(user_id);
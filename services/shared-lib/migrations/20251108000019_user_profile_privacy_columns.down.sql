@@ -0,0 +1,4 @@
+-- Rollback user profile privacy columns
+ALTER TABLE territory.user_profiles
+    DROP COLUMN IF EXISTS show_real_name,
+    DROP COLUMN IF EXISTS website_url;
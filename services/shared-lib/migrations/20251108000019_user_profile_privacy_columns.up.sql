@@ -0,0 +1,10 @@
+-- Profile columns read by the user-service profile API and the OIDC userinfo endpoint
+--
+-- user_profiles was created without the website and real-name privacy
+-- columns both services read. ADD COLUMN IF NOT EXISTS keeps databases that
+-- already have them (created by hand) as they are. Profile visibility is
+-- territory.users.profile_visibility.
+
+ALTER TABLE territory.user_profiles
+    ADD COLUMN IF NOT EXISTS website_url TEXT,
+    ADD COLUMN IF NOT EXISTS show_real_name BOOLEAN DEFAULT true;
@@ -0,0 +1,17 @@
+-- Rollback member state triggers per territory schema
+-- (territory.users keeps the triggers of migration 20251108000016)
+DO $$
+DECLARE
+    v_schema TEXT;
+BEGIN
+    FOR v_schema IN
+        SELECT table_schema FROM information_schema.tables
+        WHERE table_name = 'users' AND table_schema LIKE 'territory\_%'
+    LOOP
+        EXECUTE format('DROP TRIGGER IF EXISTS trg_notify_member_deleted ON %I.users', v_schema);
+        EXECUTE format('DROP TRIGGER IF EXISTS trg_notify_member_changed ON %I.users', v_schema);
+    END LOOP;
+END;
+$$;
+
+DROP FUNCTION IF EXISTS install_member_state_triggers(TEXT);
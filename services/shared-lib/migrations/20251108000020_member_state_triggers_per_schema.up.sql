@@ -0,0 +1,54 @@
+-- Member state triggers for every territory schema
+--
+-- Migration 20251108000016 created the member triggers on territory.users
+-- only, so pods with the multi-schema layout (territory_<code>) never
+-- announced deactivations. notify_member_state() already reads the schema
+-- from TG_TABLE_SCHEMA; install_member_state_triggers() puts its triggers on
+-- the users table of any schema.
+--
+-- Schemas created after this migration must call it once they have a users table:
+--
+--   SELECT install_member_state_triggers('territory_de');
+--
+-- auth-service checks the schemas it serves at startup and does not cache
+-- members of a schema without these triggers.
+
+CREATE OR REPLACE FUNCTION install_member_state_triggers(p_schema TEXT)
+RETURNS VOID AS $$
+BEGIN
+    EXECUTE format('DROP TRIGGER IF EXISTS trg_notify_member_changed ON %I.users', p_schema);
+    EXECUTE format(
+        'CREATE TRIGGER trg_notify_member_changed
+            AFTER UPDATE OF is_active, is_verified ON %I.users
+            FOR EACH ROW
+            WHEN (OLD.is_active IS DISTINCT FROM NEW.is_active
+                  OR OLD.is_verified IS DISTINCT FROM NEW.is_verified)
+            EXECUTE FUNCTION notify_member_state()',
+        p_schema
+    );
+
+    EXECUTE format('DROP TRIGGER IF EXISTS trg_notify_member_deleted ON %I.users', p_schema);
+    EXECUTE format(
+        'CREATE TRIGGER trg_notify_member_deleted
+            AFTER DELETE ON %I.users
+            FOR EACH ROW
+            EXECUTE FUNCTION notify_member_state()',
+        p_schema
+    );
+END;
+$$ LANGUAGE plpgsql;
+
+-- Territory schemas that already exist
+DO $$
+DECLARE
+    v_schema TEXT;
+BEGIN
+    FOR v_schema IN
+        SELECT table_schema FROM information_schema.tables
+        WHERE table_name = 'users'
+          AND (table_schema = 'territory' OR table_schema LIKE 'territory\_%')
+    LOOP
+        PERFORM install_member_state_triggers(v_schema);
+    END LOOP;
+END;
+$$;
@@ -0,0 +1,16 @@
+-- Rollback access token cut-offs in milliseconds
+
+CREATE OR REPLACE FUNCTION notify_access_token_cutoff()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify(
+        'auth_state',
+        json_build_object(
+            'kind', 'access_token_cutoff',
+            'sub', (SELECT public_key_hash FROM global.user_identities WHERE id = NEW.user_id),
+            'not_before', FLOOR(EXTRACT(EPOCH FROM NEW.not_before))::bigint
+        )::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,22 @@
+-- Access token cut-offs in milliseconds
+--
+-- Cut-offs used to be truncated to the second and only rejected tokens with
+-- an earlier iat, so tokens issued in the same second as a password change or
+-- forced logout stayed valid. Access tokens now carry iat_ms, and
+-- auth-service writes not_before at full precision; the notification carries
+-- it in milliseconds (not_before_ms) so every instance compares the same way.
+
+CREATE OR REPLACE FUNCTION notify_access_token_cutoff()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify(
+        'auth_state',
+        json_build_object(
+            'kind', 'access_token_cutoff',
+            'sub', (SELECT public_key_hash FROM global.user_identities WHERE id = NEW.user_id),
+            'not_before_ms', FLOOR(EXTRACT(EPOCH FROM NEW.not_before) * 1000)::bigint
+        )::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,3 @@
+-- Rollback index for pruning login throttle counters
+
+DROP INDEX IF EXISTS global.idx_login_attempts_last_failure;
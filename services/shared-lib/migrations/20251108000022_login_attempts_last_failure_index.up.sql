@@ -0,0 +1,7 @@
+-- Index for pruning login throttle counters
+--
+-- auth-service periodically deletes counters whose last failure is older than
+-- the failure window and whose lockout has passed; without pruning,
+-- global.login_attempts keeps a row for every username and IP ever tried.
+
+CREATE INDEX idx_login_attempts_last_failure ON global.login_attempts(last_failure_at);
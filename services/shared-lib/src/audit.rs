@@ -0,0 +1,434 @@
+use crate::error::Result;
+use crate::request::ClientInfo;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Acquire, FromRow, PgPool, Postgres};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// `prev_hash` of the first entry of every chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Chain of entries written without a territory
+pub const GLOBAL_AUDIT_CHAIN: &str = "global";
+
+/// Entries loaded per query while verifying a chain
+const VERIFY_BATCH_SIZE: i64 = 1000;
+
+/// One row of `global.audit_log`
+///
+/// Built with [`AuditEntry::new`] and the chained setters, then written with
+/// [`record_audit`]:
+///
+/// ```ignore
+/// let entry = AuditEntry::new("invitation.revoked")
+///     .actor(identity_id)
+///     .territory("dk")
+///     .resource("invitation", token_id)
+///     .client(&client);
+/// record_audit(&mut *tx, &entry).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AuditEntry {
+    pub action: String,                 // Dotted event name, e.g. "auth.login"
+    pub user_id: Option<Uuid>,          // Actor: global.user_identities.id
+    pub territory_code: Option<String>, // Territory the event happened in
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub changes: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(action: &str) -> Self {
+        Self {
+            action: action.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Global identity that performed the action
+    pub fn actor(mut self, user_id: Uuid) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+
+    pub fn territory(mut self, territory_code: &str) -> Self {
+        self.territory_code = Some(territory_code.to_lowercase());
+        self
+    }
+
+    /// What the action was performed on
+    pub fn resource(mut self, resource_type: &str, resource_id: impl ToString) -> Self {
+        self.resource_type = Some(resource_type.to_string());
+        self.resource_id = Some(resource_id.to_string());
+        self
+    }
+
+    /// Details of the action; never put secrets (passwords, tokens) in here
+    pub fn changes(mut self, changes: serde_json::Value) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    /// IP address and user agent of the request that triggered the action
+    pub fn client(mut self, client: &ClientInfo) -> Self {
+        self.ip_address = client.ip_address.clone();
+        self.user_agent = client.user_agent.clone();
+        self
+    }
+
+    /// Hash chain the entry belongs to
+    pub fn chain_id(&self) -> &str {
+        self.territory_code.as_deref().unwrap_or(GLOBAL_AUDIT_CHAIN)
+    }
+}
+
+/// Append an entry to `global.audit_log`
+///
+/// The entry is linked to the head of its chain (see [`AuditRecord`]). Writers
+/// of the same chain are serialized until the surrounding transaction ends, so
+/// pass the transaction of the audited change where there is one: the entry is
+/// then only kept if the change is.
+pub async fn record_audit<'a, A>(conn: A, entry: &AuditEntry) -> Result<()>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tx = conn.begin().await?;
+    let chain_id = entry.chain_id();
+
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(format!("audit_log:{}", chain_id))
+        .execute(&mut *tx)
+        .await?;
+
+    let head: Option<(i64, String)> = sqlx::query_as(
+        r#"
+        SELECT seq, hash FROM global.audit_log
+        WHERE chain_id = $1 AND seq IS NOT NULL
+        ORDER BY seq DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(chain_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let (seq, prev_hash) = match head {
+        Some((seq, hash)) => (seq + 1, hash),
+        None => (1, GENESIS_HASH.to_string()),
+    };
+
+    // PostgreSQL keeps microseconds; hash exactly what will be stored
+    let now = Utc::now();
+    let created_at = DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now);
+
+    let mut record = AuditRecord {
+        id: Uuid::new_v4(),
+        chain_id: chain_id.to_string(),
+        seq,
+        prev_hash,
+        hash: String::new(),
+        user_id: entry.user_id,
+        action: entry.action.clone(),
+        resource_type: entry.resource_type.clone(),
+        resource_id: entry.resource_id.clone(),
+        changes: entry.changes.clone(),
+        ip_address: entry.ip_address.clone(),
+        user_agent: entry.user_agent.clone(),
+        created_at: Some(created_at),
+    };
+    record.hash = record.compute_hash();
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.audit_log
+            (id, user_id, territory_code, action, resource_type, resource_id, changes,
+             ip_address, user_agent, created_at, chain_id, seq, prev_hash, hash)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8::inet, $9, $10, $11, $12, $13, $14)
+        "#,
+    )
+    .bind(record.id)
+    .bind(record.user_id)
+    .bind(&entry.territory_code)
+    .bind(&record.action)
+    .bind(&record.resource_type)
+    .bind(&record.resource_id)
+    .bind(&record.changes)
+    .bind(&record.ip_address)
+    .bind(&record.user_agent)
+    .bind(record.created_at)
+    .bind(&record.chain_id)
+    .bind(record.seq)
+    .bind(&record.prev_hash)
+    .bind(&record.hash)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Chained audit log entry as stored
+///
+/// `hash` is the SHA-256 of the canonical JSON array of every other field
+/// (see [`AuditRecord::compute_hash`]). `territory_code` is not hashed: it may
+/// be cleared when a territory is deleted, and `chain_id` already records it.
+#[derive(Debug, Clone, FromRow)]
+pub struct AuditRecord {
+    pub id: Uuid,
+    pub chain_id: String,
+    pub seq: i64, // 1-based position in the chain
+    pub prev_hash: String,
+    pub hash: String,
+    pub user_id: Option<Uuid>,
+    pub action: String,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub changes: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl AuditRecord {
+    /// Hash of the entry's content and its predecessor's hash (hex SHA-256)
+    pub fn compute_hash(&self) -> String {
+        let content = serde_json::json!([
+            self.chain_id,
+            self.seq,
+            self.prev_hash,
+            self.id,
+            self.user_id,
+            self.action,
+            self.resource_type,
+            self.resource_id,
+            self.changes,
+            self.ip_address,
+            self.user_agent,
+            self.created_at
+                .map(|created_at| created_at.to_rfc3339_opts(SecondsFormat::Micros, true)),
+        ]);
+
+        hex::encode(Sha256::digest(content.to_string().as_bytes()))
+    }
+}
+
+/// Chain head vouched for by a signed checkpoint
+#[derive(Debug, Clone)]
+pub struct ChainCheckpoint {
+    pub seq: i64,
+    pub hash: String,
+}
+
+/// Outcome of [`verify_audit_chain`]
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditChainReport {
+    pub chain_id: String,
+    pub valid: bool,
+    pub entries_checked: i64,
+    pub head_seq: i64, // 0 for an empty chain
+    pub head_hash: String,
+    pub checkpoints_checked: usize,
+    pub broken_link: Option<AuditChainBreak>, // First problem found
+}
+
+/// Where and why a chain stopped verifying
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditChainBreak {
+    pub seq: i64,
+    pub entry_id: Option<Uuid>, // None if the entry is missing
+    pub reason: String,
+}
+
+/// Walk an audit chain from its first entry and report the first broken link
+///
+/// Checks that sequence numbers have no gaps, that every entry links to its
+/// predecessor's hash and still matches its own hash, and that the chain
+/// passes through every checkpoint. Checkpoint signatures must be checked by
+/// the caller.
+pub async fn verify_audit_chain(
+    pool: &PgPool,
+    chain_id: &str,
+    checkpoints: &[ChainCheckpoint],
+) -> Result<AuditChainReport> {
+    let expected_at: HashMap<i64, &str> = checkpoints
+        .iter()
+        .map(|checkpoint| (checkpoint.seq, checkpoint.hash.as_str()))
+        .collect();
+
+    let mut report = AuditChainReport {
+        chain_id: chain_id.to_string(),
+        valid: true,
+        entries_checked: 0,
+        head_seq: 0,
+        head_hash: GENESIS_HASH.to_string(),
+        checkpoints_checked: 0,
+        broken_link: None,
+    };
+
+    'walk: loop {
+        let records = sqlx::query_as::<_, AuditRecord>(
+            r#"
+            SELECT
+                id, chain_id, seq, prev_hash, hash, user_id, action, resource_type,
+                resource_id, changes, host(ip_address) AS ip_address, user_agent, created_at
+            FROM global.audit_log
+            WHERE chain_id = $1 AND seq > $2
+            ORDER BY seq
+            LIMIT $3
+            "#,
+        )
+        .bind(chain_id)
+        .bind(report.head_seq)
+        .bind(VERIFY_BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        if records.is_empty() {
+            break;
+        }
+
+        for record in records {
+            let expected_seq = report.head_seq + 1;
+            let problem = if record.seq != expected_seq {
+                report.broken_link = Some(AuditChainBreak {
+                    seq: expected_seq,
+                    entry_id: None,
+                    reason: format!("Entry {} is missing", expected_seq),
+                });
+                break 'walk;
+            } else if record.prev_hash != report.head_hash {
+                Some("Entry does not link to the previous entry's hash")
+            } else if record.compute_hash() != record.hash {
+                Some("Entry content does not match its hash")
+            } else if expected_at
+                .get(&record.seq)
+                .is_some_and(|hash| *hash != record.hash)
+            {
+                Some("Entry does not match the signed checkpoint")
+            } else {
+                None
+            };
+
+            if let Some(reason) = problem {
+                report.broken_link = Some(AuditChainBreak {
+                    seq: record.seq,
+                    entry_id: Some(record.id),
+                    reason: reason.to_string(),
+                });
+                break 'walk;
+            }
+
+            if expected_at.contains_key(&record.seq) {
+                report.checkpoints_checked += 1;
+            }
+            report.entries_checked += 1;
+            report.head_seq = record.seq;
+            report.head_hash = record.hash;
+        }
+    }
+
+    // A checkpoint past the end means entries were cut off the chain
+    if report.broken_link.is_none() {
+        if let Some(checkpoint) = checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.seq > report.head_seq)
+            .min_by_key(|checkpoint| checkpoint.seq)
+        {
+            report.broken_link = Some(AuditChainBreak {
+                seq: report.head_seq + 1,
+                entry_id: None,
+                reason: format!(
+                    "Entries {} to {} are missing (signed checkpoint at {})",
+                    report.head_seq + 1,
+                    checkpoint.seq,
+                    checkpoint.seq
+                ),
+            });
+        }
+    }
+
+    report.valid = report.broken_link.is_none();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> AuditRecord {
+        AuditRecord {
+            id: Uuid::nil(),
+            chain_id: "dk".to_string(),
+            seq: 1,
+            prev_hash: GENESIS_HASH.to_string(),
+            hash: String::new(),
+            user_id: None,
+            action: "auth.login".to_string(),
+            resource_type: Some("session_family".to_string()),
+            resource_id: Some("42".to_string()),
+            changes: Some(serde_json::json!({ "mfa_verified": false })),
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: None,
+            created_at: DateTime::from_timestamp(1_700_000_000, 123_456_000),
+        }
+    }
+
+    #[test]
+    fn test_audit_entry_builder() {
+        let user_id = Uuid::new_v4();
+        let client = ClientInfo {
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: Some("curl/8.0".to_string()),
+        };
+
+        let entry = AuditEntry::new("invitation.revoked")
+            .actor(user_id)
+            .territory("DK")
+            .resource("invitation", 42)
+            .client(&client);
+
+        assert_eq!(entry.action, "invitation.revoked");
+        assert_eq!(entry.user_id, Some(user_id));
+        assert_eq!(entry.territory_code.as_deref(), Some("dk"));
+        assert_eq!(entry.chain_id(), "dk");
+        assert_eq!(entry.resource_type.as_deref(), Some("invitation"));
+        assert_eq!(entry.resource_id.as_deref(), Some("42"));
+        assert_eq!(entry.ip_address.as_deref(), Some("203.0.113.7"));
+        assert!(entry.changes.is_none());
+
+        assert_eq!(
+            AuditEntry::new("system.start").chain_id(),
+            GLOBAL_AUDIT_CHAIN
+        );
+    }
+
+    #[test]
+    fn test_record_hash_covers_content_and_link() {
+        let original = record();
+        let hash = original.compute_hash();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, record().compute_hash(), "Hash must be deterministic");
+
+        let mut edited = record();
+        edited.changes = Some(serde_json::json!({ "mfa_verified": true }));
+        assert_ne!(edited.compute_hash(), hash);
+
+        let mut relinked = record();
+        relinked.prev_hash = "f".repeat(64);
+        assert_ne!(relinked.compute_hash(), hash);
+
+        let mut stored_hash = record();
+        stored_hash.hash = "anything".to_string();
+        assert_eq!(
+            stored_hash.compute_hash(),
+            hash,
+            "The stored hash is not hashed"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::territory::SchemaLayout;
 use serde::Deserialize;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -14,6 +15,8 @@ pub struct ServerConfig {
     pub port: u16,
     pub pod_id: String,
     pub territory: String,
+    #[serde(default)]
+    pub schema_layout: SchemaLayout,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -87,6 +90,7 @@ mod tests {
         assert_eq!(config.server.host, "0.0.0.0");
         assert_eq!(config.server.port, 8080);
         assert_eq!(config.server.pod_id, "dk");
+        assert_eq!(config.server.schema_layout, SchemaLayout::Single);
         assert_eq!(config.database.max_connections, 20);
     }
 }
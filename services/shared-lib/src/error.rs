@@ -13,6 +13,9 @@ pub enum AppError {
     #[error("NATS error: {0}")]
     Nats(String),
 
+    #[error("Mail error: {0}")]
+    Mail(String),
+
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
 
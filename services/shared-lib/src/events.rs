@@ -0,0 +1,371 @@
+use crate::error::{AppError, Result};
+use crate::nats::NatsClient;
+use async_nats::jetstream;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Payload of a domain event
+pub trait DomainEvent: Serialize + DeserializeOwned + Send + Sync {
+    /// Dotted event name, also the end of the subject (e.g. "user.registered")
+    const EVENT_TYPE: &'static str;
+
+    /// Payload schema version
+    const VERSION: u32;
+}
+
+/// Metadata wrapped around every published event
+///
+/// Events are published on territory-scoped subjects, `territory.{code}.{type}`
+/// (e.g. `territory.dk.user.registered`), so consumers can subscribe per
+/// territory (`territory.dk.>`), per event type (`territory.*.user.registered`)
+/// or to everything (`territory.>`).
+///
+/// Payloads are versioned per event type. Adding an optional field keeps the
+/// version; renaming or removing a field, or changing its meaning, needs a new
+/// version.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope<P> {
+    pub id: Uuid, // Unique per event; consumers use it to drop duplicates
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub version: u32,
+    pub territory: String,
+    pub occurred_at: DateTime<Utc>,
+    pub payload: P,
+}
+
+impl<E: DomainEvent> EventEnvelope<E> {
+    pub fn new(territory_code: &str, payload: E) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type: E::EVENT_TYPE.to_string(),
+            version: E::VERSION,
+            territory: territory_code.to_lowercase(),
+            occurred_at: Utc::now(),
+            payload,
+        }
+    }
+
+    /// Decode a message, refusing other event types and versions
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let envelope: EventEnvelope<serde_json::Value> = serde_json::from_slice(bytes)?;
+        if envelope.event_type != E::EVENT_TYPE || envelope.version != E::VERSION {
+            return Err(AppError::Validation(format!(
+                "Expected {} v{}, got {} v{}",
+                E::EVENT_TYPE,
+                E::VERSION,
+                envelope.event_type,
+                envelope.version
+            )));
+        }
+
+        Ok(Self {
+            id: envelope.id,
+            event_type: envelope.event_type,
+            version: envelope.version,
+            territory: envelope.territory,
+            occurred_at: envelope.occurred_at,
+            payload: serde_json::from_value(envelope.payload)?,
+        })
+    }
+}
+
+impl<P> EventEnvelope<P> {
+    /// Subject the event is published on
+    pub fn subject(&self) -> String {
+        event_subject(&self.territory, &self.event_type)
+    }
+}
+
+/// Subject of an event type in a territory
+pub fn event_subject(territory_code: &str, event_type: &str) -> String {
+    format!("territory.{}.{}", territory_code.to_lowercase(), event_type)
+}
+
+/// A new account was registered with an invitation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserRegistered {
+    pub user_id: Uuid,           // Territory user
+    pub public_key_hash: String, // Global identity, the `sub` of access tokens
+    pub username: String,
+    pub invitation_id: Uuid,
+    pub invited_by: Option<Uuid>, // Territory user who created the invitation
+}
+
+impl DomainEvent for UserRegistered {
+    const EVENT_TYPE: &'static str = "user.registered";
+    const VERSION: u32 = 1;
+}
+
+/// A login completed (after the second factor, if enabled)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserLoggedIn {
+    pub user_id: Uuid,
+    pub public_key_hash: String,
+    pub session_id: Uuid, // Session family started by the login
+    pub mfa_verified: bool,
+}
+
+impl DomainEvent for UserLoggedIn {
+    const EVENT_TYPE: &'static str = "user.login";
+    const VERSION: u32 = 1;
+}
+
+/// An invitation was used, to register or to join its community
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvitationRedeemed {
+    pub invitation_id: Uuid,
+    pub user_id: Uuid,            // Who used it
+    pub invited_by: Option<Uuid>, // Who created it
+    pub community_id: Option<Uuid>,
+}
+
+impl DomainEvent for InvitationRedeemed {
+    const EVENT_TYPE: &'static str = "invitation.redeemed";
+    const VERSION: u32 = 1;
+}
+
+/// Profile fields were changed or cleared
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileUpdated {
+    pub user_id: Uuid,
+    pub fields: Vec<String>, // Names only, never values
+    pub cleared: bool,       // The whole profile was reset
+}
+
+impl DomainEvent for ProfileUpdated {
+    const EVENT_TYPE: &'static str = "user.profile_updated";
+    const VERSION: u32 = 1;
+}
+
+/// A user started following another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserFollowed {
+    pub follower_id: Uuid,
+    pub following_id: Uuid,
+}
+
+impl DomainEvent for UserFollowed {
+    const EVENT_TYPE: &'static str = "user.followed";
+    const VERSION: u32 = 1;
+}
+
+/// A user blocked another (the reason stays private)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserBlocked {
+    pub blocker_id: Uuid,
+    pub blocked_id: Uuid,
+}
+
+impl DomainEvent for UserBlocked {
+    const EVENT_TYPE: &'static str = "user.blocked";
+    const VERSION: u32 = 1;
+}
+
+/// Delivers encoded events
+///
+/// Services hold an `Arc<dyn EventPublisher>` so the transport can be chosen at
+/// startup: [`JetStreamPublisher`] in deployments, [`LogEventPublisher`] without
+/// NATS and [`InMemoryEventPublisher`] in tests. Events normally reach it through
+/// the outbox relay (see [`crate::outbox`]); [`publish_event`] publishes directly.
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    /// Publish one event; `message_id` is the envelope id, used for deduplication
+    async fn publish_bytes(&self, message_id: Uuid, subject: &str, payload: Vec<u8>) -> Result<()>;
+}
+
+/// Wrap an event in an envelope and publish it on its territory's subject
+pub async fn publish_event<E: DomainEvent>(
+    publisher: &dyn EventPublisher,
+    territory_code: &str,
+    event: E,
+) -> Result<EventEnvelope<E>> {
+    let envelope = EventEnvelope::new(territory_code, event);
+    publisher
+        .publish_bytes(
+            envelope.id,
+            &envelope.subject(),
+            serde_json::to_vec(&envelope)?,
+        )
+        .await?;
+
+    Ok(envelope)
+}
+
+/// Core NATS publishing: fire and forget, nothing is stored
+#[async_trait]
+impl EventPublisher for NatsClient {
+    async fn publish_bytes(
+        &self,
+        _message_id: Uuid,
+        subject: &str,
+        payload: Vec<u8>,
+    ) -> Result<()> {
+        self.publish(subject, payload).await
+    }
+}
+
+/// Publishes to JetStream and waits until a stream has stored the event
+///
+/// The event id is sent as `Nats-Msg-Id`, so the stream drops an event the relay
+/// delivers twice within its duplicate window. Publishing fails when no stream
+/// covers the subject (e.g. `territory.dk.>`).
+#[derive(Clone)]
+pub struct JetStreamPublisher {
+    context: jetstream::Context,
+}
+
+impl JetStreamPublisher {
+    pub fn new(nats: &NatsClient) -> Self {
+        Self {
+            context: jetstream::new(nats.client().clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for JetStreamPublisher {
+    async fn publish_bytes(&self, message_id: Uuid, subject: &str, payload: Vec<u8>) -> Result<()> {
+        let publish = jetstream::context::Publish::build()
+            .message_id(message_id.to_string())
+            .payload(payload.into());
+
+        self.context
+            .send_publish(subject.to_string(), publish)
+            .await
+            .map_err(|e| AppError::Nats(e.to_string()))?
+            .await
+            .map_err(|e| AppError::Nats(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Logs events instead of publishing them, for running without NATS
+#[derive(Debug, Clone, Default)]
+pub struct LogEventPublisher;
+
+#[async_trait]
+impl EventPublisher for LogEventPublisher {
+    async fn publish_bytes(&self, message_id: Uuid, subject: &str, payload: Vec<u8>) -> Result<()> {
+        tracing::debug!(
+            "Event {} on {} (not published): {}",
+            message_id,
+            subject,
+            String::from_utf8_lossy(&payload)
+        );
+        Ok(())
+    }
+}
+
+/// An event captured by [`InMemoryEventPublisher`]
+#[derive(Debug, Clone)]
+pub struct PublishedEvent {
+    pub id: Uuid,
+    pub subject: String,
+    pub payload: Vec<u8>,
+}
+
+/// Keeps published events in memory so tests can inspect them
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryEventPublisher {
+    published: Arc<Mutex<Vec<PublishedEvent>>>,
+}
+
+impl InMemoryEventPublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All events published so far, oldest first
+    pub fn published(&self) -> Vec<PublishedEvent> {
+        self.published
+            .lock()
+            .expect("event publisher lock poisoned")
+            .clone()
+    }
+
+    /// Published events of one type, decoded
+    pub fn events<E: DomainEvent>(&self) -> Vec<EventEnvelope<E>> {
+        self.published()
+            .iter()
+            .filter_map(|event| EventEnvelope::<E>::from_slice(&event.payload).ok())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl EventPublisher for InMemoryEventPublisher {
+    async fn publish_bytes(&self, message_id: Uuid, subject: &str, payload: Vec<u8>) -> Result<()> {
+        self.published
+            .lock()
+            .expect("event publisher lock poisoned")
+            .push(PublishedEvent {
+                id: message_id,
+                subject: subject.to_string(),
+                payload,
+            });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_event_wraps_payload_in_envelope() {
+        let publisher = InMemoryEventPublisher::new();
+        let event = UserFollowed {
+            follower_id: Uuid::new_v4(),
+            following_id: Uuid::new_v4(),
+        };
+
+        let envelope = publish_event(&publisher, "DK", event.clone())
+            .await
+            .unwrap();
+        assert_eq!(envelope.subject(), "territory.dk.user.followed");
+
+        let published = publisher.published();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].id, envelope.id);
+        assert_eq!(published[0].subject, "territory.dk.user.followed");
+
+        let json: serde_json::Value = serde_json::from_slice(&published[0].payload).unwrap();
+        assert_eq!(json["type"], "user.followed");
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["territory"], "dk");
+        assert_eq!(json["id"], envelope.id.to_string());
+        assert_eq!(
+            json["payload"]["follower_id"],
+            event.follower_id.to_string()
+        );
+
+        let decoded = publisher.events::<UserFollowed>();
+        assert_eq!(decoded, vec![envelope]);
+        assert!(publisher.events::<UserBlocked>().is_empty());
+    }
+
+    #[test]
+    fn test_envelope_rejects_other_versions() {
+        let envelope = EventEnvelope::new(
+            "dk",
+            UserBlocked {
+                blocker_id: Uuid::new_v4(),
+                blocked_id: Uuid::new_v4(),
+            },
+        );
+        let mut json = serde_json::to_value(&envelope).unwrap();
+        let bytes = serde_json::to_vec(&json).unwrap();
+        assert_eq!(
+            EventEnvelope::<UserBlocked>::from_slice(&bytes).unwrap(),
+            envelope
+        );
+
+        json["version"] = serde_json::json!(2);
+        let bytes = serde_json::to_vec(&json).unwrap();
+        assert!(EventEnvelope::<UserBlocked>::from_slice(&bytes).is_err());
+    }
+}
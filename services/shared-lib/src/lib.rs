@@ -1,13 +1,32 @@
+pub mod audit;
 pub mod config;
 pub mod database;
 pub mod error;
+pub mod events;
+pub mod mailer;
+pub mod metrics;
 pub mod nats;
+pub mod outbox;
+pub mod request;
+pub mod territory;
 
 // Re-export commonly used types
+pub use audit::{
+    record_audit, verify_audit_chain, AuditChainBreak, AuditChainReport, AuditEntry, ChainCheckpoint,
+};
 pub use config::AppConfig;
 pub use database::Database;
 pub use error::{AppError, Result};
+pub use events::{
+    publish_event, DomainEvent, EventEnvelope, EventPublisher, InMemoryEventPublisher,
+    InvitationRedeemed, JetStreamPublisher, LogEventPublisher, ProfileUpdated, UserBlocked,
+    UserFollowed, UserLoggedIn, UserRegistered,
+};
+pub use mailer::{Email, FileMailer, InMemoryMailer, Mailer, SmtpConfig, SmtpMailer};
 pub use nats::NatsClient;
+pub use outbox::{enqueue_event, OutboxMetrics, OutboxRelay};
+pub use request::{ClientInfo, TrustedProxies};
+pub use territory::{SchemaLayout, TerritoryResolver};
 
 /// Version information embedded at build time
 pub mod version {
@@ -0,0 +1,245 @@
+use crate::error::{AppError, Result};
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// A plain-text email
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers emails
+///
+/// Services hold an `Arc<dyn Mailer>` so the transport can be chosen at startup:
+/// [`SmtpMailer`] in deployments (MailHog in the dev stack), [`FileMailer`] for
+/// running without an SMTP server and [`InMemoryMailer`] in tests.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<()>;
+}
+
+/// SMTP connection settings
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from_email: String,
+    pub from_name: String,
+    pub starttls: bool, // false for MailHog, which speaks plain SMTP
+}
+
+impl SmtpConfig {
+    /// Read `SMTP_*` variables; `None` when `SMTP_HOST` is unset
+    pub fn from_env() -> Option<Self> {
+        let host = std::env::var("SMTP_HOST").ok().filter(|h| !h.is_empty())?;
+        let non_empty = |key: &str| std::env::var(key).ok().filter(|v| !v.is_empty());
+
+        Some(Self {
+            host,
+            port: non_empty("SMTP_PORT")
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(1025),
+            username: non_empty("SMTP_USERNAME"),
+            password: non_empty("SMTP_PASSWORD"),
+            from_email: non_empty("SMTP_FROM_EMAIL")
+                .unwrap_or_else(|| "noreply@unityplan.local".to_string()),
+            from_name: non_empty("SMTP_FROM_NAME")
+                .unwrap_or_else(|| "UnityPlan Platform".to_string()),
+            starttls: non_empty("SMTP_STARTTLS").is_some_and(|v| v == "true"),
+        })
+    }
+}
+
+/// Sends email through an SMTP server
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &SmtpConfig) -> Result<Self> {
+        let mut builder = if config.starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                .map_err(|e| AppError::Mail(e.to_string()))?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+        };
+
+        builder = builder.port(config.port);
+
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let from = format!("{} <{}>", config.from_name, config.from_email)
+            .parse()
+            .map_err(|e| AppError::Mail(format!("Invalid sender address: {}", e)))?;
+
+        tracing::info!("SMTP mailer configured for {}:{}", config.host, config.port);
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<()> {
+        let to: Mailbox = email
+            .to
+            .parse()
+            .map_err(|e| AppError::Mail(format!("Invalid recipient address: {}", e)))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| AppError::Mail(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::Mail(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Writes each email to a JSON file in a directory instead of sending it
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| AppError::Mail(e.to_string()))?;
+
+        tracing::info!("File mailer writing to {}", dir.display());
+
+        Ok(Self { dir })
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<()> {
+        let path = self.dir.join(format!(
+            "{}-{}.json",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3f"),
+            uuid::Uuid::new_v4()
+        ));
+
+        let contents = serde_json::to_vec_pretty(&email)?;
+        tokio::fs::write(&path, contents)
+            .await
+            .map_err(|e| AppError::Mail(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Keeps sent emails in memory so tests can inspect them
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryMailer {
+    sent: Arc<Mutex<Vec<Email>>>,
+}
+
+impl InMemoryMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All emails sent so far, oldest first
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().expect("mailer lock poisoned").clone()
+    }
+
+    /// The most recent email sent to `to`
+    pub fn last_sent_to(&self, to: &str) -> Option<Email> {
+        self.sent()
+            .into_iter()
+            .rev()
+            .find(|email| email.to.eq_ignore_ascii_case(to))
+    }
+}
+
+#[async_trait]
+impl Mailer for InMemoryMailer {
+    async fn send(&self, email: Email) -> Result<()> {
+        self.sent.lock().expect("mailer lock poisoned").push(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(to: &str, subject: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body: "Hello".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_mailer_records_emails() {
+        let mailer = InMemoryMailer::new();
+
+        mailer.send(email("a@test.dk", "First")).await.unwrap();
+        mailer.send(email("b@test.dk", "Second")).await.unwrap();
+        mailer.send(email("A@test.dk", "Third")).await.unwrap();
+
+        assert_eq!(mailer.sent().len(), 3);
+        assert_eq!(mailer.last_sent_to("a@test.dk").unwrap().subject, "Third");
+        assert!(mailer.last_sent_to("c@test.dk").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_mailer_writes_json() {
+        let dir = std::env::temp_dir().join(format!("mailer-test-{}", uuid::Uuid::new_v4()));
+        let mailer = FileMailer::new(&dir).unwrap();
+
+        mailer.send(email("a@test.dk", "Subject")).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let contents = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        let written: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(written["to"], "a@test.dk");
+        assert_eq!(written["subject"], "Subject");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_smtp_mailer_rejects_invalid_sender() {
+        let config = SmtpConfig {
+            host: "localhost".to_string(),
+            port: 1025,
+            username: None,
+            password: None,
+            from_email: "not an address".to_string(),
+            from_name: "UnityPlan".to_string(),
+            starttls: false,
+        };
+
+        assert!(SmtpMailer::new(&config).is_err());
+    }
+}
@@ -0,0 +1,17 @@
+use actix_web::HttpResponse;
+use prometheus::{Encoder, TextEncoder};
+
+/// Prometheus metrics of this process (default registry)
+/// GET /metrics
+pub async fn metrics() -> HttpResponse {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        tracing::error!("Failed to encode metrics: {}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer)
+}
@@ -54,6 +54,18 @@ impl NatsClient {
             .await
             .map_err(|e| AppError::Nats(e.to_string()))
     }
+
+    /// Subscribe as part of a queue group; each message goes to one member
+    pub async fn queue_subscribe(
+        &self,
+        subject: &str,
+        queue_group: &str,
+    ) -> Result<async_nats::Subscriber> {
+        self.client
+            .queue_subscribe(subject.to_string(), queue_group.to_string())
+            .await
+            .map_err(|e| AppError::Nats(e.to_string()))
+    }
 }
 
 #[cfg(test)]
@@ -68,6 +80,6 @@ mod tests {
             "unityplan-global".to_string()
         ).await.unwrap();
 
-        assert!(client.publish("test.subject", b"test message").await.is_ok());
+        assert!(client.publish("test.subject", "test message").await.is_ok());
     }
 }
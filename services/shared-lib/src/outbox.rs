@@ -0,0 +1,326 @@
+use crate::error::{AppError, Result};
+use crate::events::{DomainEvent, EventEnvelope, EventPublisher};
+use chrono::{DateTime, Duration, Utc};
+use prometheus::{IntCounterVec, IntGauge, Opts, Registry};
+use sqlx::{FromRow, PgExecutor, PgPool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Events published per relay pass
+pub const DEFAULT_RELAY_BATCH_SIZE: i64 = 100;
+
+/// Wait before the first retry of a failed event; doubles with every attempt
+const RETRY_BASE_DELAY_SECS: i64 = 5;
+
+/// Longest wait between two attempts
+const RETRY_MAX_DELAY_SECS: i64 = 600;
+
+/// Write an event to `global.event_outbox`
+///
+/// Pass the transaction of the change the event describes, so the event is
+/// stored exactly when the change is committed. [`OutboxRelay`] publishes it
+/// afterwards:
+///
+/// ```ignore
+/// let mut tx = pool.begin().await?;
+/// // ... the change itself ...
+/// enqueue_event(&mut *tx, "dk", UserFollowed { follower_id, following_id }).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn enqueue_event<'e, X, E>(
+    executor: X,
+    territory_code: &str,
+    event: E,
+) -> Result<EventEnvelope<E>>
+where
+    X: PgExecutor<'e>,
+    E: DomainEvent,
+{
+    let envelope = EventEnvelope::new(territory_code, event);
+
+    sqlx::query(
+        r#"
+        INSERT INTO global.event_outbox
+            (id, subject, event_type, territory_code, payload, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(envelope.id)
+    .bind(envelope.subject())
+    .bind(&envelope.event_type)
+    .bind(&envelope.territory)
+    .bind(serde_json::to_value(&envelope)?)
+    .bind(envelope.occurred_at)
+    .execute(executor)
+    .await?;
+
+    Ok(envelope)
+}
+
+/// Delay before the next attempt after `attempts` failed ones
+pub fn retry_delay(attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    let secs = RETRY_BASE_DELAY_SECS.saturating_mul(1 << doublings);
+    Duration::seconds(secs.min(RETRY_MAX_DELAY_SECS))
+}
+
+/// Prometheus metrics of an [`OutboxRelay`]
+#[derive(Clone)]
+pub struct OutboxMetrics {
+    published: IntCounterVec,
+    failed: IntCounterVec,
+    pending: IntGauge,
+}
+
+impl OutboxMetrics {
+    /// Create the metrics and register them in `registry`
+    pub fn register(registry: &Registry) -> Result<Self> {
+        let metrics = Self {
+            published: IntCounterVec::new(
+                Opts::new(
+                    "outbox_events_published_total",
+                    "Outbox events delivered to the event publisher",
+                ),
+                &["event_type"],
+            )
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+            failed: IntCounterVec::new(
+                Opts::new(
+                    "outbox_publish_failures_total",
+                    "Failed attempts to deliver outbox events",
+                ),
+                &["event_type"],
+            )
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+            pending: IntGauge::new("outbox_pending_events", "Outbox events not yet delivered")
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+        };
+
+        registry
+            .register(Box::new(metrics.published.clone()))
+            .and_then(|_| registry.register(Box::new(metrics.failed.clone())))
+            .and_then(|_| registry.register(Box::new(metrics.pending.clone())))
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+
+        Ok(metrics)
+    }
+
+    /// Register in the process-wide registry, served at `/metrics`
+    pub fn register_default() -> Result<Self> {
+        Self::register(prometheus::default_registry())
+    }
+
+    pub fn published(&self, event_type: &str) -> u64 {
+        self.published.with_label_values(&[event_type]).get()
+    }
+
+    pub fn failed(&self, event_type: &str) -> u64 {
+        self.failed.with_label_values(&[event_type]).get()
+    }
+
+    pub fn pending(&self) -> i64 {
+        self.pending.get()
+    }
+}
+
+/// Result of one relay pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelayOutcome {
+    pub published: usize,
+    pub failed: usize, // Scheduled for another attempt
+}
+
+#[derive(Debug, FromRow)]
+struct PendingEvent {
+    id: Uuid,
+    subject: String,
+    event_type: String,
+    payload: serde_json::Value,
+    attempts: i32,
+}
+
+/// Publishes pending outbox events
+///
+/// Delivery is at least once: an event is marked as published only after the
+/// publisher accepted it, so a relay that stops in between publishes it again.
+/// The envelope id goes along as the message id (`Nats-Msg-Id` on JetStream) for
+/// deduplication. Failed events are retried with exponential backoff, and they
+/// do not hold back the events behind them.
+///
+/// Rows are claimed with `FOR UPDATE SKIP LOCKED`, so every service instance
+/// can run a relay.
+#[derive(Clone)]
+pub struct OutboxRelay {
+    pool: PgPool,
+    publisher: Arc<dyn EventPublisher>,
+    batch_size: i64,
+    metrics: Option<OutboxMetrics>,
+}
+
+impl OutboxRelay {
+    pub fn new(pool: PgPool, publisher: Arc<dyn EventPublisher>) -> Self {
+        Self {
+            pool,
+            publisher,
+            batch_size: DEFAULT_RELAY_BATCH_SIZE,
+            metrics: None,
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn metrics(mut self, metrics: OutboxMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Publish one batch of due events, oldest first
+    pub async fn relay_pending(&self) -> Result<RelayOutcome> {
+        let mut tx = self.pool.begin().await?;
+
+        let events = sqlx::query_as::<_, PendingEvent>(
+            r#"
+            SELECT id, subject, event_type, payload, attempts
+            FROM global.event_outbox
+            WHERE published_at IS NULL AND next_attempt_at <= NOW()
+            ORDER BY created_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+        )
+        .bind(self.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut outcome = RelayOutcome::default();
+        for event in events {
+            let payload = serde_json::to_vec(&event.payload)?;
+            match self
+                .publisher
+                .publish_bytes(event.id, &event.subject, payload)
+                .await
+            {
+                Ok(()) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE global.event_outbox
+                        SET published_at = NOW(), attempts = attempts + 1, last_error = NULL
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(event.id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                    outcome.published += 1;
+                    if let Some(metrics) = &self.metrics {
+                        metrics
+                            .published
+                            .with_label_values(&[&event.event_type])
+                            .inc();
+                    }
+                }
+                Err(e) => {
+                    let attempts = event.attempts + 1;
+                    let next_attempt_at: DateTime<Utc> = Utc::now() + retry_delay(attempts);
+                    tracing::warn!(
+                        "Failed to publish event {} on {} (attempt {}): {}",
+                        event.id,
+                        event.subject,
+                        attempts,
+                        e
+                    );
+
+                    sqlx::query(
+                        r#"
+                        UPDATE global.event_outbox
+                        SET attempts = $2, last_error = $3, next_attempt_at = $4
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(event.id)
+                    .bind(attempts)
+                    .bind(e.to_string())
+                    .bind(next_attempt_at)
+                    .execute(&mut *tx)
+                    .await?;
+
+                    outcome.failed += 1;
+                    if let Some(metrics) = &self.metrics {
+                        metrics.failed.with_label_values(&[&event.event_type]).inc();
+                    }
+                }
+            }
+        }
+
+        tx.commit().await?;
+
+        if let Some(metrics) = &self.metrics {
+            let pending: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM global.event_outbox WHERE published_at IS NULL",
+            )
+            .fetch_one(&self.pool)
+            .await?;
+            metrics.pending.set(pending);
+        }
+
+        Ok(outcome)
+    }
+
+    /// Relay due events every `interval`, forever
+    ///
+    /// Full batches are followed by the next one right away, so a backlog drains
+    /// without waiting for the next tick.
+    pub async fn run(self, interval: std::time::Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            loop {
+                match self.relay_pending().await {
+                    Ok(outcome)
+                        if (outcome.published + outcome.failed) as i64 >= self.batch_size =>
+                    {
+                        continue
+                    }
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("Outbox relay failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_backs_off_exponentially() {
+        assert_eq!(retry_delay(1), Duration::seconds(5));
+        assert_eq!(retry_delay(2), Duration::seconds(10));
+        assert_eq!(retry_delay(3), Duration::seconds(20));
+        assert_eq!(retry_delay(8), Duration::seconds(600));
+        assert_eq!(retry_delay(i32::MAX), Duration::seconds(600));
+        assert_eq!(retry_delay(0), Duration::seconds(5));
+    }
+
+    #[test]
+    fn test_metrics_register_once_per_registry() {
+        let registry = Registry::new();
+        let metrics = OutboxMetrics::register(&registry).unwrap();
+        metrics
+            .published
+            .with_label_values(&["user.followed"])
+            .inc();
+
+        assert_eq!(metrics.published("user.followed"), 1);
+        assert_eq!(metrics.failed("user.followed"), 0);
+        assert!(OutboxMetrics::register(&registry).is_err());
+    }
+}
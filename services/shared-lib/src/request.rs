@@ -0,0 +1,285 @@
+use crate::error::{AppError, Result};
+use actix_web::{http::header, web, HttpRequest};
+use std::net::{IpAddr, SocketAddr};
+
+/// Client details recorded with sessions and audit log entries
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// Extract the client IP and user agent from a request
+    ///
+    /// The IP is the peer address, unless the peer is one of the
+    /// [`TrustedProxies`] registered as app data: then it is the last address
+    /// in `Forwarded`/`X-Forwarded-For` that is not a trusted proxy. Clients
+    /// can send these headers themselves, so they are ignored otherwise. Only
+    /// values that parse as an IP address are kept, so they can be stored in
+    /// an INET column.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let trusted_proxies = req
+            .app_data::<web::Data<TrustedProxies>>()
+            .map(|proxies| proxies.get_ref());
+
+        let ip_address = client_ip(req, trusted_proxies).map(|ip| ip.to_string());
+
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(512).collect());
+
+        Self {
+            ip_address,
+            user_agent,
+        }
+    }
+}
+
+/// Reverse proxies whose forwarding headers are trusted (`TRUSTED_PROXIES`)
+///
+/// Parsed from a comma-separated list of addresses and CIDR ranges, e.g.
+/// `10.0.0.0/8, 2001:db8::1`. Register as `web::Data<TrustedProxies>`; without
+/// it, no proxy is trusted.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<IpNetwork>);
+
+impl TrustedProxies {
+    /// Check whether `ip` belongs to a trusted proxy
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|network| network.contains(ip))
+    }
+}
+
+impl std::str::FromStr for TrustedProxies {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                IpNetwork::parse(entry).ok_or_else(|| {
+                    AppError::Validation(format!("Invalid trusted proxy '{}'", entry))
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Self)
+    }
+}
+
+/// Address range in CIDR notation (a single address without a prefix length)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr.parse::<IpAddr>().ok()?, prefix_len.parse().ok()?),
+            None => {
+                let addr = s.parse::<IpAddr>().ok()?;
+                (addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        };
+
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        (prefix_len <= max_prefix_len).then_some(Self { addr, prefix_len })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let whole_bytes = usize::from(prefix_len / 8);
+    let remaining_bits = prefix_len % 8;
+
+    if network[..whole_bytes] != ip[..whole_bytes] {
+        return false;
+    }
+
+    let mask = 0xffu8
+        .checked_shl(u32::from(8 - remaining_bits))
+        .unwrap_or(0);
+    remaining_bits == 0 || network[whole_bytes] & mask == ip[whole_bytes] & mask
+}
+
+/// Client IP of a request
+///
+/// Starts at the peer address and, while that is a trusted proxy, steps back
+/// through the forwarded addresses from right to left (each proxy appends the
+/// address it received the request from).
+fn client_ip(req: &HttpRequest, trusted_proxies: Option<&TrustedProxies>) -> Option<IpAddr> {
+    let mut client = req.peer_addr()?.ip().to_canonical();
+
+    let Some(trusted_proxies) = trusted_proxies else {
+        return Some(client);
+    };
+
+    for hop in forwarded_addresses(req).iter().rev() {
+        if !trusted_proxies.contains(client) {
+            break;
+        }
+        match parse_ip(hop) {
+            Some(ip) => client = ip.to_canonical(),
+            None => break,
+        }
+    }
+
+    Some(client)
+}
+
+/// Forwarded client addresses, nearest last: `for=` of `Forwarded` (RFC 7239),
+/// or `X-Forwarded-For` when there is no `Forwarded` header
+fn forwarded_addresses(req: &HttpRequest) -> Vec<String> {
+    let header_values = |name| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded: Vec<String> = header_values(header::FORWARDED)
+        .into_iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.eq_ignore_ascii_case("for")
+                    .then(|| value.trim_matches('"').to_string())
+            })
+        })
+        .collect();
+
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    header_values(header::X_FORWARDED_FOR)
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|a| a.ip()))
+        .or_else(|| {
+            addr.strip_prefix('[')
+                .and_then(|addr| addr.strip_suffix(']'))
+                .and_then(|addr| addr.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn trusted(proxies: &str) -> web::Data<TrustedProxies> {
+        web::Data::new(proxies.parse().unwrap())
+    }
+
+    #[test]
+    fn test_client_info_from_request() {
+        let req = TestRequest::default()
+            .peer_addr("203.0.113.7:52100".parse().unwrap())
+            .insert_header(("User-Agent", "Mozilla/5.0 (X11; Linux x86_64)"))
+            .to_http_request();
+
+        let client = ClientInfo::from_request(&req);
+
+        assert_eq!(client.ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(
+            client.user_agent.as_deref(),
+            Some("Mozilla/5.0 (X11; Linux x86_64)")
+        );
+    }
+
+    #[test]
+    fn test_forwarded_for_is_ignored_from_untrusted_peers() {
+        let spoofed = |proxies: Option<web::Data<TrustedProxies>>| {
+            let mut req = TestRequest::default()
+                .peer_addr("203.0.113.7:52100".parse().unwrap())
+                .insert_header(("X-Forwarded-For", "198.51.100.1"));
+            if let Some(proxies) = proxies {
+                req = req.app_data(proxies);
+            }
+            ClientInfo::from_request(&req.to_http_request()).ip_address
+        };
+
+        assert_eq!(spoofed(None).as_deref(), Some("203.0.113.7"));
+        assert_eq!(
+            spoofed(Some(trusted("10.0.0.0/8"))).as_deref(),
+            Some("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn test_forwarded_for_from_trusted_proxies() {
+        // The client prepended a fake address; the proxies appended the real ones
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.2:443".parse().unwrap())
+            .app_data(trusted("10.0.0.0/8, 2001:db8::1"))
+            .insert_header(("X-Forwarded-For", "192.0.2.66, 203.0.113.7, 10.0.0.9"))
+            .to_http_request();
+        assert_eq!(
+            ClientInfo::from_request(&req).ip_address.as_deref(),
+            Some("203.0.113.7")
+        );
+
+        let req = TestRequest::default()
+            .peer_addr("[2001:db8::1]:443".parse().unwrap())
+            .app_data(trusted("10.0.0.0/8, 2001:db8::1"))
+            .insert_header((
+                "Forwarded",
+                r#"for=192.0.2.66, for="[2001:db8::7]:4711";proto=https"#,
+            ))
+            .to_http_request();
+        assert_eq!(
+            ClientInfo::from_request(&req).ip_address.as_deref(),
+            Some("2001:db8::7")
+        );
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let proxies: TrustedProxies = "10.0.0.0/8, 192.168.1.1, 2001:db8::/32".parse().unwrap();
+
+        assert!(proxies.contains("10.200.3.4".parse().unwrap()));
+        assert!(proxies.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(proxies.contains("192.168.1.1".parse().unwrap()));
+        assert!(!proxies.contains("192.168.1.2".parse().unwrap()));
+        assert!(proxies.contains("2001:db8:ffff::1".parse().unwrap()));
+        assert!(!proxies.contains("2001:db9::1".parse().unwrap()));
+
+        assert!("".parse::<TrustedProxies>().unwrap().0.is_empty());
+        assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+        assert!("proxy.internal".parse::<TrustedProxies>().is_err());
+    }
+
+    #[test]
+    fn test_parse_ip() {
+        assert_eq!(parse_ip("192.0.2.1"), "192.0.2.1".parse().ok());
+        assert_eq!(parse_ip("192.0.2.1:8080"), "192.0.2.1".parse().ok());
+        assert_eq!(parse_ip("[2001:db8::1]:443"), "2001:db8::1".parse().ok());
+        assert_eq!(parse_ip("unknown"), None);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::error::{AppError, Result};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Schema name used by single-territory pods
+pub const SINGLE_TERRITORY_SCHEMA: &str = "territory";
+
+/// Maximum identifier length accepted by PostgreSQL (NAMEDATALEN - 1)
+const MAX_IDENTIFIER_LEN: usize = 63;
+
+/// How territory data is laid out in a pod database
+///
+/// - `Single`: one territory per pod, data lives in the generic `territory` schema
+/// - `Multi`: several territories per pod, each in its own `territory_XX` schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaLayout {
+    #[default]
+    Single,
+    Multi,
+}
+
+impl std::str::FromStr for SchemaLayout {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "single" => Ok(Self::Single),
+            "multi" => Ok(Self::Multi),
+            other => Err(AppError::Validation(format!(
+                "Unknown schema layout '{}' (expected 'single' or 'multi')",
+                other
+            ))),
+        }
+    }
+}
+
+/// Validate a schema identifier before it is interpolated into SQL
+///
+/// Only lowercase ASCII letters, digits and underscores are accepted, the first
+/// character must be a letter or underscore, and the length must fit PostgreSQL's
+/// identifier limit. This is stricter than PostgreSQL itself on purpose.
+pub fn validate_schema_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+
+    let valid_start = matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_');
+    let valid_rest = chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+
+    if !valid_start || !valid_rest || name.len() > MAX_IDENTIFIER_LEN {
+        return Err(AppError::Validation(format!(
+            "Invalid schema identifier: '{}'",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Resolves territory codes to the database schema holding their data
+///
+/// Built once at startup from the territories assigned to this pod
+/// (`global.territories.pod_id`). Only territories served by the pod resolve;
+/// every schema name is validated when the resolver is built, so the values it
+/// hands out are safe to interpolate into `format!`-built queries.
+#[derive(Debug, Clone)]
+pub struct TerritoryResolver {
+    pod_id: String,
+    layout: SchemaLayout,
+    schemas: HashMap<String, String>, // lowercase territory code -> schema name
+}
+
+impl TerritoryResolver {
+    /// Build a resolver for an explicit set of territory codes
+    pub fn new<I, S>(pod_id: &str, layout: SchemaLayout, territory_codes: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let codes: Vec<String> = territory_codes
+            .into_iter()
+            .map(|code| code.as_ref().trim().to_lowercase())
+            .collect();
+
+        if layout == SchemaLayout::Single && codes.len() > 1 {
+            return Err(AppError::Config(config::ConfigError::Message(format!(
+                "Pod '{}' serves {} territories but uses the single schema layout",
+                pod_id,
+                codes.len()
+            ))));
+        }
+
+        let mut schemas = HashMap::with_capacity(codes.len());
+        for code in codes {
+            let schema = match layout {
+                SchemaLayout::Single => SINGLE_TERRITORY_SCHEMA.to_string(),
+                SchemaLayout::Multi => format!("territory_{}", code.replace('-', "_")),
+            };
+            validate_schema_name(&schema)?;
+            schemas.insert(code, schema);
+        }
+
+        Ok(Self {
+            pod_id: pod_id.to_string(),
+            layout,
+            schemas,
+        })
+    }
+
+    /// Load the territories served by `pod_id` from `global.territories`
+    pub async fn load(pool: &PgPool, pod_id: &str, layout: SchemaLayout) -> Result<Self> {
+        let codes: Vec<String> = sqlx::query_scalar(
+            "SELECT code FROM global.territories WHERE pod_id = $1 AND is_active = true ORDER BY code",
+        )
+        .bind(pod_id)
+        .fetch_all(pool)
+        .await?;
+
+        if codes.is_empty() {
+            tracing::warn!("No active territories assigned to pod '{}'", pod_id);
+        }
+
+        let resolver = Self::new(pod_id, layout, &codes)?;
+
+        tracing::info!(
+            "Territory resolver loaded for pod '{}' ({:?} layout): {:?}",
+            pod_id,
+            layout,
+            codes
+        );
+
+        Ok(resolver)
+    }
+
+    /// Get the schema name for a territory served by this pod
+    pub fn schema_for(&self, territory_code: &str) -> Result<&str> {
+        self.schemas
+            .get(&territory_code.trim().to_lowercase())
+            .map(String::as_str)
+            .ok_or_else(|| {
+                AppError::NotFound(format!(
+                    "Territory '{}' is not served by pod '{}'",
+                    territory_code, self.pod_id
+                ))
+            })
+    }
+
+    /// The only territory served by this pod, if it serves exactly one
+    pub fn default_territory(&self) -> Option<&str> {
+        if self.schemas.len() == 1 {
+            self.schemas.keys().next().map(String::as_str)
+        } else {
+            None
+        }
+    }
+
+    /// Territory codes served by this pod
+    pub fn territories(&self) -> impl Iterator<Item = &str> {
+        self.schemas.keys().map(String::as_str)
+    }
+
+    pub fn pod_id(&self) -> &str {
+        &self.pod_id
+    }
+
+    pub fn layout(&self) -> SchemaLayout {
+        self.layout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_layout_uses_generic_schema() {
+        let resolver = TerritoryResolver::new("dk", SchemaLayout::Single, ["dk"]).unwrap();
+
+        assert_eq!(resolver.schema_for("dk").unwrap(), "territory");
+        assert_eq!(resolver.schema_for("DK").unwrap(), "territory");
+        assert_eq!(resolver.default_territory(), Some("dk"));
+    }
+
+    #[test]
+    fn test_single_layout_rejects_multiple_territories() {
+        assert!(TerritoryResolver::new("eu", SchemaLayout::Single, ["de", "fr"]).is_err());
+    }
+
+    #[test]
+    fn test_multi_layout_routes_per_territory() {
+        let resolver =
+            TerritoryResolver::new("eu", SchemaLayout::Multi, ["DE", "fr", "es"]).unwrap();
+
+        assert_eq!(resolver.schema_for("de").unwrap(), "territory_de");
+        assert_eq!(resolver.schema_for("FR").unwrap(), "territory_fr");
+        assert_eq!(resolver.schema_for("es").unwrap(), "territory_es");
+        assert_eq!(resolver.default_territory(), None);
+    }
+
+    #[test]
+    fn test_unknown_territory_is_rejected() {
+        let resolver = TerritoryResolver::new("eu", SchemaLayout::Multi, ["de"]).unwrap();
+
+        assert!(matches!(
+            resolver.schema_for("dk"),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_multi_layout_rejects_unsafe_codes() {
+        assert!(TerritoryResolver::new("eu", SchemaLayout::Multi, ["de; DROP TABLE x"]).is_err());
+        assert!(TerritoryResolver::new("eu", SchemaLayout::Multi, ["de\"x"]).is_err());
+    }
+
+    #[test]
+    fn test_first_nation_codes_are_normalised() {
+        let resolver = TerritoryResolver::new("ca", SchemaLayout::Multi, ["HAIDA-FN-CA"]).unwrap();
+
+        assert_eq!(
+            resolver.schema_for("haida-fn-ca").unwrap(),
+            "territory_haida_fn_ca"
+        );
+    }
+
+    #[test]
+    fn test_validate_schema_name() {
+        assert!(validate_schema_name("territory").is_ok());
+        assert!(validate_schema_name("territory_dk").is_ok());
+        assert!(validate_schema_name("_private").is_ok());
+
+        assert!(validate_schema_name("").is_err());
+        assert!(validate_schema_name("1territory").is_err());
+        assert!(validate_schema_name("Territory").is_err());
+        assert!(validate_schema_name("territory.users").is_err());
+        assert!(validate_schema_name("territory; --").is_err());
+        assert!(validate_schema_name(&"a".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_schema_layout_from_str() {
+        assert_eq!(
+            "single".parse::<SchemaLayout>().unwrap(),
+            SchemaLayout::Single
+        );
+        assert_eq!(
+            "Multi".parse::<SchemaLayout>().unwrap(),
+            SchemaLayout::Multi
+        );
+        assert!("sharded".parse::<SchemaLayout>().is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod territory;
+
+pub use territory::TerritorySchema;
@@ -0,0 +1,57 @@
+use actix_web::{dev::Payload, error::ErrorBadRequest, web, Error, FromRequest, HttpRequest};
+use shared_lib::TerritoryResolver;
+use std::future::{ready, Ready};
+
+/// Header carrying the caller's territory code
+pub const TERRITORY_HEADER: &str = "X-Territory-Code";
+
+/// Territory schema resolved for the current request
+///
+/// Taken from the `X-Territory-Code` header. Single-territory pods may omit the
+/// header, in which case the pod's only territory is used. The schema name comes
+/// from the pod's `TerritoryResolver`, so it is always whitelisted and safe to
+/// interpolate into queries.
+#[derive(Debug, Clone)]
+pub struct TerritorySchema {
+    pub territory_code: String,
+    pub schema: String,
+}
+
+impl TerritorySchema {
+    fn resolve(req: &HttpRequest) -> Result<Self, Error> {
+        let territories = req
+            .app_data::<web::Data<TerritoryResolver>>()
+            .ok_or_else(|| ErrorBadRequest("Territory resolver not configured"))?;
+
+        let territory_code = match req.headers().get(TERRITORY_HEADER) {
+            Some(value) => value
+                .to_str()
+                .map_err(|_| ErrorBadRequest("Invalid territory code header"))?
+                .trim()
+                .to_lowercase(),
+            None => territories
+                .default_territory()
+                .map(str::to_string)
+                .ok_or_else(|| ErrorBadRequest("X-Territory-Code header is required"))?,
+        };
+
+        let schema = territories
+            .schema_for(&territory_code)
+            .map_err(|_| ErrorBadRequest("Invalid territory code"))?
+            .to_string();
+
+        Ok(Self {
+            territory_code,
+            schema,
+        })
+    }
+}
+
+impl FromRequest for TerritorySchema {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Self::resolve(req))
+    }
+}
@@ -2,6 +2,7 @@ use actix_web::{web, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::extractors::TerritorySchema;
 use crate::models::connection::BlockUserRequest;
 use crate::services::UserService;
 
@@ -30,6 +31,7 @@ pub struct ApiResponse<T> {
 /// Follow a user
 pub async fn follow_user(
     path: web::Path<ConnectionPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id and verify it matches path.user_id
 ) -> Result<HttpResponse> {
@@ -46,7 +48,10 @@ pub async fn follow_user(
     }
 
     // Check if target user is blocked
-    match service.is_blocked(follower_id, following_id).await {
+    match service
+        .is_blocked(&territory.schema, follower_id, following_id)
+        .await
+    {
         Ok(true) => {
             return Ok(HttpResponse::Forbidden().json(ApiResponse::<()> {
                 success: false,
@@ -60,7 +65,15 @@ pub async fn follow_user(
         _ => {}
     }
 
-    match service.follow_user(follower_id, following_id).await {
+    match service
+        .follow_user(
+            &territory.schema,
+            &territory.territory_code,
+            follower_id,
+            following_id,
+        )
+        .await
+    {
         Ok(connection) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some(connection),
@@ -81,13 +94,17 @@ pub async fn follow_user(
 /// Unfollow a user
 pub async fn unfollow_user(
     path: web::Path<ConnectionPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id and verify it matches path.user_id
 ) -> Result<HttpResponse> {
     let follower_id = path.user_id;
     let following_id = path.target_id;
 
-    match service.unfollow_user(follower_id, following_id).await {
+    match service
+        .unfollow_user(&territory.schema, follower_id, following_id)
+        .await
+    {
         Ok(true) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some("Successfully unfollowed user"),
@@ -113,11 +130,12 @@ pub async fn unfollow_user(
 /// Get followers of a user
 pub async fn get_followers(
     path: web::Path<UserIdPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
 ) -> Result<HttpResponse> {
     let user_id = path.user_id;
 
-    match service.get_followers(user_id).await {
+    match service.get_followers(&territory.schema, user_id).await {
         Ok(followers) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some(followers),
@@ -138,11 +156,12 @@ pub async fn get_followers(
 /// Get users that a user is following
 pub async fn get_following(
     path: web::Path<UserIdPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
 ) -> Result<HttpResponse> {
     let user_id = path.user_id;
 
-    match service.get_following(user_id).await {
+    match service.get_following(&territory.schema, user_id).await {
         Ok(following) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some(following),
@@ -164,6 +183,7 @@ pub async fn get_following(
 pub async fn block_user(
     path: web::Path<ConnectionPath>,
     body: web::Json<BlockUserRequest>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id and verify it matches path.user_id
 ) -> Result<HttpResponse> {
@@ -180,7 +200,13 @@ pub async fn block_user(
     }
 
     match service
-        .block_user(blocker_id, blocked_id, body.reason.clone())
+        .block_user(
+            &territory.schema,
+            &territory.territory_code,
+            blocker_id,
+            blocked_id,
+            body.reason.clone(),
+        )
         .await
     {
         Ok(block) => Ok(HttpResponse::Ok().json(ApiResponse {
@@ -203,13 +229,17 @@ pub async fn block_user(
 /// Unblock a user
 pub async fn unblock_user(
     path: web::Path<ConnectionPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id and verify it matches path.user_id
 ) -> Result<HttpResponse> {
     let blocker_id = path.user_id;
     let blocked_id = path.target_id;
 
-    match service.unblock_user(blocker_id, blocked_id).await {
+    match service
+        .unblock_user(&territory.schema, blocker_id, blocked_id)
+        .await
+    {
         Ok(true) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some("Successfully unblocked user"),
@@ -235,12 +265,13 @@ pub async fn unblock_user(
 /// Get blocked users list (owner only)
 pub async fn get_blocked_users(
     path: web::Path<UserIdPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id and verify it matches path.user_id
 ) -> Result<HttpResponse> {
     let user_id = path.user_id;
 
-    match service.get_blocked_users(user_id).await {
+    match service.get_blocked_users(&territory.schema, user_id).await {
         Ok(blocks) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some(blocks),
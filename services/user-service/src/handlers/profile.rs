@@ -1,8 +1,10 @@
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
+use shared_lib::ClientInfo;
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::extractors::TerritorySchema;
 use crate::models::profile::UpdateProfileRequest;
 use crate::services::UserService;
 
@@ -31,12 +33,16 @@ pub struct ApiResponse<T> {
 pub async fn get_profile(
     path: web::Path<UserIdPath>,
     query: web::Query<ProfileQuery>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
 ) -> Result<HttpResponse> {
     let user_id = path.user_id;
     let viewer_id = query.viewer_id;
 
-    match service.get_public_profile(user_id, viewer_id).await {
+    match service
+        .get_public_profile(&territory.schema, user_id, viewer_id)
+        .await
+    {
         Ok(Some(profile)) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some(profile),
@@ -62,12 +68,13 @@ pub async fn get_profile(
 /// Get full profile (owner only)
 pub async fn get_full_profile(
     path: web::Path<UserIdPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id
 ) -> Result<HttpResponse> {
     let user_id = path.user_id;
 
-    match service.get_profile(user_id).await {
+    match service.get_profile(&territory.schema, user_id).await {
         Ok(Some(profile)) => Ok(HttpResponse::Ok().json(ApiResponse {
             success: true,
             data: Some(profile),
@@ -92,8 +99,10 @@ pub async fn get_full_profile(
 /// PUT /api/profiles/{user_id}
 /// Update user profile (owner only)
 pub async fn update_profile(
+    req: HttpRequest,
     path: web::Path<UserIdPath>,
     body: web::Json<UpdateProfileRequest>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id and verify ownership
 ) -> Result<HttpResponse> {
@@ -109,12 +118,34 @@ pub async fn update_profile(
         }));
     }
 
-    match service.update_profile(user_id, request).await {
-        Ok(profile) => Ok(HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some(profile),
-            error: None,
-        })),
+    let fields = request.changed_fields();
+
+    match service
+        .update_profile(
+            &territory.schema,
+            &territory.territory_code,
+            user_id,
+            request,
+        )
+        .await
+    {
+        Ok(profile) => {
+            record_profile_change(
+                &req,
+                &territory,
+                &service,
+                user_id,
+                "profile.updated",
+                &fields,
+            )
+            .await;
+
+            Ok(HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some(profile),
+                error: None,
+            }))
+        }
         Err(e) => {
             eprintln!("Database error: {}", e);
             Ok(HttpResponse::InternalServerError().json(ApiResponse::<()> {
@@ -129,37 +160,37 @@ pub async fn update_profile(
 /// DELETE /api/profiles/{user_id}
 /// Delete user profile (sets all fields to NULL, owner only)
 pub async fn delete_profile(
+    req: HttpRequest,
     path: web::Path<UserIdPath>,
+    territory: TerritorySchema,
     service: web::Data<UserService>,
     // TODO: Add auth middleware to extract authenticated user_id and verify ownership
 ) -> Result<HttpResponse> {
     let user_id = path.user_id;
 
-    // Create empty update request to clear all profile fields
-    let empty_request = UpdateProfileRequest {
-        about: Some(None),
-        interests: Some(vec![]),
-        skills: Some(vec![]),
-        languages: Some(vec![]),
-        location: Some(None),
-        website_url: Some(None),
-        github_url: Some(None),
-        linkedin_url: Some(None),
-        twitter_handle: Some(None),
-        theme: Some("light".to_string()),
-        metadata: Some(serde_json::json!({})),
-        profile_visibility: Some("public".to_string()),
-        show_email: Some(false),
-        show_real_name: Some(true),
-        allow_messages_from: Some("everyone".to_string()),
-    };
-
-    match service.update_profile(user_id, empty_request).await {
-        Ok(_) => Ok(HttpResponse::Ok().json(ApiResponse {
-            success: true,
-            data: Some("Profile cleared successfully"),
-            error: None,
-        })),
+    let fields = UpdateProfileRequest::cleared().changed_fields();
+
+    match service
+        .clear_profile(&territory.schema, &territory.territory_code, user_id)
+        .await
+    {
+        Ok(_) => {
+            record_profile_change(
+                &req,
+                &territory,
+                &service,
+                user_id,
+                "profile.cleared",
+                &fields,
+            )
+            .await;
+
+            Ok(HttpResponse::Ok().json(ApiResponse {
+                success: true,
+                data: Some("Profile cleared successfully"),
+                error: None,
+            }))
+        }
         Err(e) => {
             eprintln!("Database error: {}", e);
             Ok(HttpResponse::InternalServerError().json(ApiResponse::<()> {
@@ -171,6 +202,30 @@ pub async fn delete_profile(
     }
 }
 
+/// Audit a profile change; the change itself is already saved, so a failure
+/// is only logged
+async fn record_profile_change(
+    req: &HttpRequest,
+    territory: &TerritorySchema,
+    service: &UserService,
+    user_id: Uuid,
+    action: &str,
+    fields: &[&str],
+) {
+    if let Err(e) = service
+        .record_profile_change(
+            &territory.territory_code,
+            user_id,
+            action,
+            fields,
+            &ClientInfo::from_request(req),
+        )
+        .await
+    {
+        eprintln!("Failed to record audit entry: {}", e);
+    }
+}
+
 /// Configure profile routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
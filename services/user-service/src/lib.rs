@@ -1,6 +1,7 @@
 // Library interface for user-service
 // This allows tests to import from user_service::*
 
+pub mod extractors;
 pub mod handlers;
 pub mod models;
 pub mod services;
@@ -1,10 +1,16 @@
+mod extractors;
 mod handlers;
 mod models;
 mod services;
 
 use actix_web::{middleware, web, App, HttpResponse, HttpServer};
+use shared_lib::{
+    EventPublisher, JetStreamPublisher, LogEventPublisher, NatsClient, OutboxMetrics, OutboxRelay,
+    SchemaLayout, TerritoryResolver, TrustedProxies,
+};
 use sqlx::postgres::PgPoolOptions;
 use std::env;
+use std::sync::Arc;
 
 use crate::services::{StorageService, UserService};
 
@@ -21,6 +27,27 @@ async fn main() -> std::io::Result<()> {
     let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8084".to_string());
     let avatars_path = env::var("AVATARS_PATH").unwrap_or_else(|_| "./uploads/avatars".to_string());
+    let pod_id = env::var("POD_ID").unwrap_or_else(|_| "dk".to_string());
+    let nats_url = env::var("NATS_URL").ok().filter(|url| !url.is_empty());
+    let outbox_relay_interval = env::var("OUTBOX_RELAY_INTERVAL")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(1); // seconds
+    let schema_layout = env::var("TERRITORY_SCHEMA_LAYOUT")
+        .ok()
+        .map(|s| s.parse::<SchemaLayout>())
+        .transpose()
+        .expect("Invalid TERRITORY_SCHEMA_LAYOUT")
+        .unwrap_or_default();
+    // Proxies whose X-Forwarded-For/Forwarded headers give the client IP
+    let trusted_proxies = web::Data::new(
+        env::var("TRUSTED_PROXIES")
+            .ok()
+            .map(|s| s.parse::<TrustedProxies>())
+            .transpose()
+            .expect("Invalid TRUSTED_PROXIES")
+            .unwrap_or_default(),
+    );
 
     log::info!("Starting User Service...");
     log::info!("Database URL: {}", database_url);
@@ -38,10 +65,38 @@ async fn main() -> std::io::Result<()> {
     // Note: Migrations are managed in shared-lib
     // All migrations should be run via shared-lib/migrations
 
+    // Resolve which territories (and schemas) this pod serves
+    let territories = web::Data::new(
+        TerritoryResolver::load(&pool, &pod_id, schema_layout)
+            .await
+            .expect("Failed to load territories for this pod"),
+    );
+
     // Create services
-    let user_service = web::Data::new(UserService::new(pool));
+    let user_service = web::Data::new(UserService::new(pool.clone()));
     let storage_service = web::Data::new(StorageService::new(avatars_path));
 
+    // Create domain event publisher (JetStream, or the log without a NATS server)
+    let events: Arc<dyn EventPublisher> = match nats_url {
+        Some(url) => {
+            let cluster_name =
+                env::var("NATS_CLUSTER_NAME").unwrap_or_else(|_| "unityplan-global".to_string());
+            let nats = NatsClient::new(&url, cluster_name)
+                .await
+                .expect("Failed to connect to NATS");
+            Arc::new(JetStreamPublisher::new(&nats))
+        }
+        None => {
+            log::warn!("NATS_URL not set - domain events are logged, not published");
+            Arc::new(LogEventPublisher)
+        }
+    };
+
+    // Publish the domain events written to the outbox
+    let relay = OutboxRelay::new(pool, events)
+        .metrics(OutboxMetrics::register_default().expect("Failed to register outbox metrics"));
+    tokio::spawn(relay.run(std::time::Duration::from_secs(outbox_relay_interval.max(1))));
+
     // Create avatars directory if it doesn't exist
     std::fs::create_dir_all("./uploads/avatars").expect("Failed to create avatars directory");
 
@@ -54,13 +109,16 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || {
         App::new()
             // Add services to app data
+            .app_data(territories.clone())
             .app_data(user_service.clone())
             .app_data(storage_service.clone())
+            .app_data(trusted_proxies.clone())
             // Middleware
             .wrap(middleware::Logger::default())
             .wrap(middleware::Compress::default())
             // Health check
             .route("/health", web::get().to(health_check))
+            .route("/metrics", web::get().to(shared_lib::metrics::metrics))
             // API routes
             .service(
                 web::scope("/api")
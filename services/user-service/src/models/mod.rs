@@ -2,6 +2,7 @@ pub mod connection;
 pub mod privacy;
 pub mod profile;
 
-pub use connection::*;
-pub use privacy::*;
-pub use profile::*;
+// Re-exported from lib.rs with explicit paths
+// pub use connection::* - unused, comment out
+// pub use privacy::* - unused, comment out
+// pub use profile::* - unused, comment out
@@ -69,6 +69,54 @@ pub struct UpdateProfileRequest {
     pub allow_messages_from: Option<String>,
 }
 
+impl UpdateProfileRequest {
+    /// Request that resets every field to its default (empty profile)
+    pub fn cleared() -> Self {
+        Self {
+            about: Some(None),
+            interests: Some(vec![]),
+            skills: Some(vec![]),
+            languages: Some(vec![]),
+            location: Some(None),
+            website_url: Some(None),
+            github_url: Some(None),
+            linkedin_url: Some(None),
+            twitter_handle: Some(None),
+            theme: Some("light".to_string()),
+            metadata: Some(serde_json::json!({})),
+            profile_visibility: Some("public".to_string()),
+            show_email: Some(false),
+            show_real_name: Some(true),
+            allow_messages_from: Some("everyone".to_string()),
+        }
+    }
+
+    /// Names of the fields this request sets (for the audit log)
+    pub fn changed_fields(&self) -> Vec<&'static str> {
+        [
+            ("about", self.about.is_some()),
+            ("interests", self.interests.is_some()),
+            ("skills", self.skills.is_some()),
+            ("languages", self.languages.is_some()),
+            ("location", self.location.is_some()),
+            ("website_url", self.website_url.is_some()),
+            ("github_url", self.github_url.is_some()),
+            ("linkedin_url", self.linkedin_url.is_some()),
+            ("twitter_handle", self.twitter_handle.is_some()),
+            ("theme", self.theme.is_some()),
+            ("metadata", self.metadata.is_some()),
+            ("profile_visibility", self.profile_visibility.is_some()),
+            ("show_email", self.show_email.is_some()),
+            ("show_real_name", self.show_real_name.is_some()),
+            ("allow_messages_from", self.allow_messages_from.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(field, _)| field)
+        .collect()
+    }
+}
+
 fn validate_theme(theme: &str) -> Result<(), validator::ValidationError> {
     if ["light", "dark", "auto"].contains(&theme) {
         Ok(())
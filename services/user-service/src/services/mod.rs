@@ -1,5 +1,5 @@
 pub mod storage_service;
 pub mod user_service;
 
-pub use storage_service::{StorageError, StorageService};
+pub use storage_service::StorageService;
 pub use user_service::UserService;
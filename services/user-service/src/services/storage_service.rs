@@ -159,6 +159,7 @@ impl StorageService {
     }
 
     /// Check if avatar exists for a user
+    #[allow(dead_code)] // Will be used when profile responses link stored avatars
     pub async fn avatar_exists(&self, user_id: Uuid) -> bool {
         let path = self.get_avatar_path(user_id, None);
         path.exists()
@@ -187,6 +188,7 @@ pub enum StorageError {
     Io(String),
     ImageProcessing(String),
     UnsupportedFormat,
+    #[allow(dead_code)] // Upload handler currently enforces the size limit while streaming
     FileTooLarge,
 }
 
@@ -3,10 +3,18 @@ use crate::models::{
     privacy::PrivacySettings,
     profile::{FullUserProfile, PublicUserProfile, UpdateProfileRequest, UserProfile},
 };
+use shared_lib::{
+    enqueue_event, record_audit, AppError, AuditEntry, ClientInfo, ProfileUpdated, UserBlocked,
+    UserFollowed,
+};
 use sqlx::PgPool;
 use uuid::Uuid;
 
 /// User service for managing user profiles, connections, and blocks
+///
+/// Every operation takes the territory schema resolved for the caller
+/// (see `extractors::TerritorySchema`), so one instance serves all territories
+/// hosted by the pod.
 pub struct UserService {
     pool: PgPool,
 }
@@ -20,8 +28,12 @@ impl UserService {
     // ==================== Profile Operations ====================
 
     /// Get full profile for a user (includes privacy settings)
-    pub async fn get_profile(&self, user_id: Uuid) -> Result<Option<FullUserProfile>, sqlx::Error> {
-        let profile = sqlx::query_as::<_, UserProfile>(
+    pub async fn get_profile(
+        &self,
+        schema: &str,
+        user_id: Uuid,
+    ) -> Result<Option<FullUserProfile>, sqlx::Error> {
+        let profile = sqlx::query_as::<_, UserProfile>(&format!(
             r#"
             SELECT 
                 user_id,
@@ -42,16 +54,17 @@ impl UserService {
                 allow_messages_from,
                 created_at,
                 updated_at
-            FROM territory.user_profiles
+            FROM {}.user_profiles
             WHERE user_id = $1
             "#,
-        )
+            schema
+        ))
         .bind(user_id)
         .fetch_optional(&self.pool)
         .await?;
 
         if let Some(p) = profile {
-            // Get user basic info from territory.users
+            // Get user basic info from the territory users table
             let user_info = sqlx::query_as::<
                 _,
                 (
@@ -62,13 +75,14 @@ impl UserService {
                     Option<String>,
                     Option<String>,
                 ),
-            >(
+            >(&format!(
                 r#"
                 SELECT username, email, full_name, display_name, avatar_url, bio
-                FROM territory.users
+                FROM {}.users
                 WHERE id = $1
                 "#,
-            )
+                schema
+            ))
             .bind(user_id)
             .fetch_optional(&self.pool)
             .await?;
@@ -117,16 +131,17 @@ impl UserService {
     /// Get public profile for viewing by other users
     pub async fn get_public_profile(
         &self,
+        schema: &str,
         user_id: Uuid,
         viewer_id: Option<Uuid>,
     ) -> Result<Option<PublicUserProfile>, sqlx::Error> {
         // Get full profile
-        let full_profile = self.get_profile(user_id).await?;
+        let full_profile = self.get_profile(schema, user_id).await?;
 
         if let Some(profile) = full_profile {
             // Check if viewer is connected
             let is_connected = if let Some(vid) = viewer_id {
-                self.is_connected(vid, user_id).await?
+                self.is_connected(schema, vid, user_id).await?
             } else {
                 false
             };
@@ -176,16 +191,55 @@ impl UserService {
         }
     }
 
-    /// Update user profile
+    /// Update user profile and announce the changed fields (`user.profile_updated`)
     pub async fn update_profile(
         &self,
+        schema: &str,
+        territory_code: &str,
         user_id: Uuid,
         request: UpdateProfileRequest,
-    ) -> Result<UserProfile, sqlx::Error> {
+    ) -> Result<UserProfile, AppError> {
+        self.save_profile(schema, territory_code, user_id, request, false)
+            .await
+    }
+
+    /// Reset every profile field to its default
+    pub async fn clear_profile(
+        &self,
+        schema: &str,
+        territory_code: &str,
+        user_id: Uuid,
+    ) -> Result<UserProfile, AppError> {
+        self.save_profile(
+            schema,
+            territory_code,
+            user_id,
+            UpdateProfileRequest::cleared(),
+            true,
+        )
+        .await
+    }
+
+    async fn save_profile(
+        &self,
+        schema: &str,
+        territory_code: &str,
+        user_id: Uuid,
+        request: UpdateProfileRequest,
+        cleared: bool,
+    ) -> Result<UserProfile, AppError> {
+        let fields = request
+            .changed_fields()
+            .iter()
+            .map(|field| field.to_string())
+            .collect();
+
+        let mut tx = self.pool.begin().await?;
+
         // Upsert profile
-        let profile = sqlx::query_as::<_, UserProfile>(
+        let profile = sqlx::query_as::<_, UserProfile>(&format!(
             r#"
-            INSERT INTO territory.user_profiles (
+            INSERT INTO {}.user_profiles AS p (
                 user_id, about, interests, skills, languages, location,
                 website_url, github_url, linkedin_url, twitter_handle,
                 theme, metadata, profile_visibility, show_email,
@@ -193,21 +247,21 @@ impl UserService {
             )
             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
             ON CONFLICT (user_id) DO UPDATE SET
-                about = COALESCE($2, territory.user_profiles.about),
-                interests = COALESCE($3, territory.user_profiles.interests),
-                skills = COALESCE($4, territory.user_profiles.skills),
-                languages = COALESCE($5, territory.user_profiles.languages),
-                location = COALESCE($6, territory.user_profiles.location),
-                website_url = COALESCE($7, territory.user_profiles.website_url),
-                github_url = COALESCE($8, territory.user_profiles.github_url),
-                linkedin_url = COALESCE($9, territory.user_profiles.linkedin_url),
-                twitter_handle = COALESCE($10, territory.user_profiles.twitter_handle),
-                theme = COALESCE($11, territory.user_profiles.theme),
-                metadata = COALESCE($12, territory.user_profiles.metadata),
-                profile_visibility = COALESCE($13, territory.user_profiles.profile_visibility),
-                show_email = COALESCE($14, territory.user_profiles.show_email),
-                show_real_name = COALESCE($15, territory.user_profiles.show_real_name),
-                allow_messages_from = COALESCE($16, territory.user_profiles.allow_messages_from),
+                about = COALESCE($2, p.about),
+                interests = COALESCE($3, p.interests),
+                skills = COALESCE($4, p.skills),
+                languages = COALESCE($5, p.languages),
+                location = COALESCE($6, p.location),
+                website_url = COALESCE($7, p.website_url),
+                github_url = COALESCE($8, p.github_url),
+                linkedin_url = COALESCE($9, p.linkedin_url),
+                twitter_handle = COALESCE($10, p.twitter_handle),
+                theme = COALESCE($11, p.theme),
+                metadata = COALESCE($12, p.metadata),
+                profile_visibility = COALESCE($13, p.profile_visibility),
+                show_email = COALESCE($14, p.show_email),
+                show_real_name = COALESCE($15, p.show_real_name),
+                allow_messages_from = COALESCE($16, p.allow_messages_from),
                 updated_at = NOW()
             RETURNING 
                 user_id, about, interests, skills, languages, location,
@@ -215,7 +269,8 @@ impl UserService {
                 theme, metadata, profile_visibility, show_email,
                 show_real_name, allow_messages_from, created_at, updated_at
             "#,
-        )
+            schema
+        ))
         .bind(user_id)
         .bind(request.about)
         .bind(request.interests.as_deref())
@@ -232,28 +287,75 @@ impl UserService {
         .bind(request.show_email)
         .bind(request.show_real_name)
         .bind(request.allow_messages_from)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        enqueue_event(
+            &mut *tx,
+            territory_code,
+            ProfileUpdated {
+                user_id,
+                fields,
+                cleared,
+            },
+        )
+        .await?;
+
+        tx.commit().await?;
+
         Ok(profile)
     }
 
+    /// Record a profile change in `global.audit_log`
+    ///
+    /// The entry is attributed to the profile owner's global identity and only
+    /// lists the names of the changed fields, not their values.
+    pub async fn record_profile_change(
+        &self,
+        territory_code: &str,
+        user_id: Uuid,
+        action: &str,
+        fields: &[&str],
+        client: &ClientInfo,
+    ) -> Result<(), AppError> {
+        let identity_id: Option<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM global.user_identities WHERE territory_code = $1 AND territory_user_id = $2",
+        )
+        .bind(territory_code)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let mut entry = AuditEntry::new(action)
+            .territory(territory_code)
+            .resource("user_profile", user_id)
+            .changes(serde_json::json!({ "fields": fields }))
+            .client(client);
+        if let Some(identity_id) = identity_id {
+            entry = entry.actor(identity_id);
+        }
+
+        record_audit(&self.pool, &entry).await
+    }
+
     // ==================== Connection Operations ====================
 
     /// Check if user A is connected to (following) user B
     pub async fn is_connected(
         &self,
+        schema: &str,
         follower_id: Uuid,
         following_id: Uuid,
     ) -> Result<bool, sqlx::Error> {
-        let result: (bool,) = sqlx::query_as(
+        let result: (bool,) = sqlx::query_as(&format!(
             r#"
             SELECT EXISTS(
-                SELECT 1 FROM territory.user_connections
+                SELECT 1 FROM {}.user_connections
                 WHERE follower_id = $1 AND following_id = $2
             )
             "#,
-        )
+            schema
+        ))
         .bind(follower_id)
         .bind(following_id)
         .fetch_one(&self.pool)
@@ -262,76 +364,100 @@ impl UserService {
         Ok(result.0)
     }
 
-    /// Follow a user
+    /// Follow a user; a new connection is announced as `user.followed`
     pub async fn follow_user(
         &self,
+        schema: &str,
+        territory_code: &str,
         follower_id: Uuid,
         following_id: Uuid,
-    ) -> Result<UserConnection, sqlx::Error> {
+    ) -> Result<UserConnection, AppError> {
+        let mut tx = self.pool.begin().await?;
+
         // Check if either user has blocked the other
-        let block_exists = sqlx::query_scalar::<_, bool>(
+        let block_exists = sqlx::query_scalar::<_, bool>(&format!(
             r#"
             SELECT EXISTS(
-                SELECT 1 FROM territory.user_blocks
+                SELECT 1 FROM {}.user_blocks
                 WHERE (blocker_id = $1 AND blocked_id = $2)
                    OR (blocker_id = $2 AND blocked_id = $1)
             )
             "#,
-        )
+            schema
+        ))
         .bind(follower_id)
         .bind(following_id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
         if block_exists {
-            return Err(sqlx::Error::RowNotFound);
+            return Err(AppError::Database(sqlx::Error::RowNotFound));
         }
 
         // First try to insert
-        let result = sqlx::query_as::<_, UserConnection>(
+        let result = sqlx::query_as::<_, UserConnection>(&format!(
             r#"
-            INSERT INTO territory.user_connections (follower_id, following_id)
+            INSERT INTO {}.user_connections (follower_id, following_id)
             VALUES ($1, $2)
             ON CONFLICT (follower_id, following_id) DO NOTHING
             RETURNING follower_id, following_id, created_at
             "#,
-        )
+            schema
+        ))
         .bind(follower_id)
         .bind(following_id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *tx)
         .await?;
 
         // If insert was skipped (conflict), fetch existing connection
-        match result {
-            Some(conn) => Ok(conn),
+        let connection = match result {
+            Some(conn) => {
+                enqueue_event(
+                    &mut *tx,
+                    territory_code,
+                    UserFollowed {
+                        follower_id,
+                        following_id,
+                    },
+                )
+                .await?;
+                conn
+            }
             None => {
-                sqlx::query_as::<_, UserConnection>(
+                sqlx::query_as::<_, UserConnection>(&format!(
                     r#"
                     SELECT follower_id, following_id, created_at
-                    FROM territory.user_connections
+                    FROM {}.user_connections
                     WHERE follower_id = $1 AND following_id = $2
                     "#,
-                )
+                    schema
+                ))
                 .bind(follower_id)
                 .bind(following_id)
-                .fetch_one(&self.pool)
-                .await
+                .fetch_one(&mut *tx)
+                .await?
             }
-        }
+        };
+
+        tx.commit().await?;
+
+        Ok(connection)
     }
 
     /// Unfollow a user
     pub async fn unfollow_user(
         &self,
+        schema: &str,
         follower_id: Uuid,
         following_id: Uuid,
     ) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query(
+        let result = sqlx::query(&format!(
             r#"
-            DELETE FROM territory.user_connections
+            DELETE FROM {}.user_connections
             WHERE follower_id = $1 AND following_id = $2
             "#,
-        )
+            schema
+        ))
         .bind(follower_id)
         .bind(following_id)
         .execute(&self.pool)
@@ -343,9 +469,10 @@ impl UserService {
     /// Get followers of a user
     pub async fn get_followers(
         &self,
+        schema: &str,
         user_id: Uuid,
     ) -> Result<Vec<ConnectionResponse>, sqlx::Error> {
-        let followers = sqlx::query_as::<_, ConnectionResponse>(
+        let followers = sqlx::query_as::<_, ConnectionResponse>(&format!(
             r#"
             SELECT 
                 uc.follower_id as user_id,
@@ -353,12 +480,13 @@ impl UserService {
                 u.display_name,
                 u.avatar_url,
                 uc.created_at
-            FROM territory.user_connections uc
-            JOIN territory.users u ON u.id = uc.follower_id
+            FROM {}.user_connections uc
+            JOIN {}.users u ON u.id = uc.follower_id
             WHERE uc.following_id = $1
             ORDER BY uc.created_at DESC
             "#,
-        )
+            schema, schema
+        ))
         .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
@@ -369,9 +497,10 @@ impl UserService {
     /// Get users that a user is following
     pub async fn get_following(
         &self,
+        schema: &str,
         user_id: Uuid,
     ) -> Result<Vec<ConnectionResponse>, sqlx::Error> {
-        let following = sqlx::query_as::<_, ConnectionResponse>(
+        let following = sqlx::query_as::<_, ConnectionResponse>(&format!(
             r#"
             SELECT 
                 uc.following_id as user_id,
@@ -379,12 +508,13 @@ impl UserService {
                 u.display_name,
                 u.avatar_url,
                 uc.created_at
-            FROM territory.user_connections uc
-            JOIN territory.users u ON u.id = uc.following_id
+            FROM {}.user_connections uc
+            JOIN {}.users u ON u.id = uc.following_id
             WHERE uc.follower_id = $1
             ORDER BY uc.created_at DESC
             "#,
-        )
+            schema, schema
+        ))
         .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
@@ -394,49 +524,78 @@ impl UserService {
 
     // ==================== Block Operations ====================
 
-    /// Block a user
+    /// Block a user and announce it as `user.blocked` (without the reason)
     pub async fn block_user(
         &self,
+        schema: &str,
+        territory_code: &str,
         blocker_id: Uuid,
         blocked_id: Uuid,
         reason: Option<String>,
-    ) -> Result<UserBlock, sqlx::Error> {
-        // First, remove any existing connection
-        let _ = self.unfollow_user(blocker_id, blocked_id).await;
-        let _ = self.unfollow_user(blocked_id, blocker_id).await;
+    ) -> Result<UserBlock, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // First, remove any existing connection in either direction
+        sqlx::query(&format!(
+            r#"
+            DELETE FROM {}.user_connections
+            WHERE (follower_id = $1 AND following_id = $2)
+               OR (follower_id = $2 AND following_id = $1)
+            "#,
+            schema
+        ))
+        .bind(blocker_id)
+        .bind(blocked_id)
+        .execute(&mut *tx)
+        .await?;
 
         // Then create block
-        let block = sqlx::query_as::<_, UserBlock>(
+        let block = sqlx::query_as::<_, UserBlock>(&format!(
             r#"
-            INSERT INTO territory.user_blocks (blocker_id, blocked_id, reason)
+            INSERT INTO {}.user_blocks AS b (blocker_id, blocked_id, reason)
             VALUES ($1, $2, $3)
             ON CONFLICT (blocker_id, blocked_id) DO UPDATE SET
-                reason = COALESCE($3, territory.user_blocks.reason),
+                reason = COALESCE($3, b.reason),
                 created_at = NOW()
             RETURNING blocker_id, blocked_id, reason, created_at
             "#,
-        )
+            schema
+        ))
         .bind(blocker_id)
         .bind(blocked_id)
         .bind(reason)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        enqueue_event(
+            &mut *tx,
+            territory_code,
+            UserBlocked {
+                blocker_id,
+                blocked_id,
+            },
+        )
+        .await?;
+
+        tx.commit().await?;
+
         Ok(block)
     }
 
     /// Unblock a user
     pub async fn unblock_user(
         &self,
+        schema: &str,
         blocker_id: Uuid,
         blocked_id: Uuid,
     ) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query(
+        let result = sqlx::query(&format!(
             r#"
-            DELETE FROM territory.user_blocks
+            DELETE FROM {}.user_blocks
             WHERE blocker_id = $1 AND blocked_id = $2
             "#,
-        )
+            schema
+        ))
         .bind(blocker_id)
         .bind(blocked_id)
         .execute(&self.pool)
@@ -448,17 +607,19 @@ impl UserService {
     /// Check if user A has blocked user B
     pub async fn is_blocked(
         &self,
+        schema: &str,
         blocker_id: Uuid,
         blocked_id: Uuid,
     ) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query_as::<_, (bool,)>(
+        let result = sqlx::query_as::<_, (bool,)>(&format!(
             r#"
             SELECT EXISTS(
-                SELECT 1 FROM territory.user_blocks
+                SELECT 1 FROM {}.user_blocks
                 WHERE blocker_id = $1 AND blocked_id = $2
             )
             "#,
-        )
+            schema
+        ))
         .bind(blocker_id)
         .bind(blocked_id)
         .fetch_one(&self.pool)
@@ -468,15 +629,20 @@ impl UserService {
     }
 
     /// Get list of users blocked by a user
-    pub async fn get_blocked_users(&self, user_id: Uuid) -> Result<Vec<UserBlock>, sqlx::Error> {
-        let blocks = sqlx::query_as::<_, UserBlock>(
+    pub async fn get_blocked_users(
+        &self,
+        schema: &str,
+        user_id: Uuid,
+    ) -> Result<Vec<UserBlock>, sqlx::Error> {
+        let blocks = sqlx::query_as::<_, UserBlock>(&format!(
             r#"
             SELECT blocker_id, blocked_id, reason, created_at
-            FROM territory.user_blocks
+            FROM {}.user_blocks
             WHERE blocker_id = $1
             ORDER BY created_at DESC
             "#,
-        )
+            schema
+        ))
         .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
@@ -2,7 +2,10 @@ use sqlx::PgPool;
 use uuid::Uuid;
 
 // Territory schema name - matches multi-pod architecture
-const TERRITORY_SCHEMA: &str = "territory";
+pub const TERRITORY_SCHEMA: &str = "territory";
+
+// Territory the test users belong to (events are published for it)
+pub const TERRITORY_CODE: &str = "dk";
 
 /// TestContext tracks all data created during a test and ensures precise cleanup.
 ///
@@ -115,6 +118,20 @@ impl TestContext {
             .await
             .ok();
 
+            // Delete outbox events about the user
+            sqlx::query(
+                r#"
+                DELETE FROM global.event_outbox
+                WHERE $1 IN (payload->'payload'->>'user_id',
+                             payload->'payload'->>'follower_id',
+                             payload->'payload'->>'blocker_id')
+                "#,
+            )
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+            .ok();
+
             // Delete user
             sqlx::query(&format!(
                 "DELETE FROM {}.users WHERE id = $1",
@@ -1,4 +1,4 @@
-use crate::common::TestContext;
+use crate::common::{TestContext, TERRITORY_CODE, TERRITORY_SCHEMA};
 use user_service::services::UserService;
 
 #[tokio::test]
@@ -9,11 +9,11 @@ async fn test_block_user() {
     let blocker_id = ctx.create_user("blocker", "blocker@example.com").await;
     let blocked_id = ctx.create_user("blocked", "blocked@example.com").await;
     
-    service.block_user(blocker_id, blocked_id, Some("Spam".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked_id, Some("Spam".to_string())).await
         .expect("Block should succeed");
     
     // Verify block exists
-    let blocked_users = service.get_blocked_users(blocker_id).await
+    let blocked_users = service.get_blocked_users(TERRITORY_SCHEMA, blocker_id).await
         .expect("Query should succeed");
     
     assert_eq!(blocked_users.len(), 1);
@@ -31,15 +31,15 @@ async fn test_unblock_user() {
     let blocked_id = ctx.create_user("blocked", "blocked@example.com").await;
     
     // Block user
-    service.block_user(blocker_id, blocked_id, Some("Testing".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked_id, Some("Testing".to_string())).await
         .expect("Block should succeed");
     
     // Unblock
-    service.unblock_user(blocker_id, blocked_id).await
+    service.unblock_user(TERRITORY_SCHEMA, blocker_id, blocked_id).await
         .expect("Unblock should succeed");
     
     // Verify block removed
-    let blocked_users = service.get_blocked_users(blocker_id).await
+    let blocked_users = service.get_blocked_users(TERRITORY_SCHEMA, blocker_id).await
         .expect("Query should succeed");
     
     assert_eq!(blocked_users.len(), 0, "Blocked list should be empty");
@@ -56,22 +56,22 @@ async fn test_blocking_removes_connections() {
     let user2_id = ctx.create_user("user2", "user2@example.com").await;
     
     // Create mutual following
-    service.follow_user(user1_id, user2_id).await.expect("Follow should succeed");
-    service.follow_user(user2_id, user1_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, user1_id, user2_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, user2_id, user1_id).await.expect("Follow should succeed");
     
     // Verify connections exist
-    let user1_following = service.get_following(user1_id).await
+    let user1_following = service.get_following(TERRITORY_SCHEMA, user1_id).await
         .expect("Query should succeed");
     assert_eq!(user1_following.len(), 1, "Should have one following before block");
     
     // User1 blocks User2
-    service.block_user(user1_id, user2_id, Some("Not interested".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, user1_id, user2_id, Some("Not interested".to_string())).await
         .expect("Block should succeed");
     
     // Verify connections are removed
-    let user1_following_after = service.get_following(user1_id).await
+    let user1_following_after = service.get_following(TERRITORY_SCHEMA, user1_id).await
         .expect("Query should succeed");
-    let user2_following_after = service.get_following(user2_id).await
+    let user2_following_after = service.get_following(TERRITORY_SCHEMA, user2_id).await
         .expect("Query should succeed");
     
     assert_eq!(user1_following_after.len(), 0, "Blocker's following should be empty");
@@ -89,12 +89,12 @@ async fn test_get_blocked_users() {
     let blocked1_id = ctx.create_user("blocked1", "blocked1@example.com").await;
     let blocked2_id = ctx.create_user("blocked2", "blocked2@example.com").await;
     
-    service.block_user(blocker_id, blocked1_id, Some("Reason 1".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked1_id, Some("Reason 1".to_string())).await
         .expect("Block should succeed");
-    service.block_user(blocker_id, blocked2_id, Some("Reason 2".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked2_id, Some("Reason 2".to_string())).await
         .expect("Block should succeed");
     
-    let blocked_users = service.get_blocked_users(blocker_id).await
+    let blocked_users = service.get_blocked_users(TERRITORY_SCHEMA, blocker_id).await
         .expect("Query should succeed");
     
     assert_eq!(blocked_users.len(), 2);
@@ -115,15 +115,15 @@ async fn test_mutual_blocks() {
     let user2_id = ctx.create_user("user2", "user2@example.com").await;
     
     // Both users block each other
-    service.block_user(user1_id, user2_id, Some("Mutual dislike".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, user1_id, user2_id, Some("Mutual dislike".to_string())).await
         .expect("Block should succeed");
-    service.block_user(user2_id, user1_id, Some("Mutual dislike".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, user2_id, user1_id, Some("Mutual dislike".to_string())).await
         .expect("Block should succeed");
     
     // Verify both blocks exist
-    let user1_blocked = service.get_blocked_users(user1_id).await
+    let user1_blocked = service.get_blocked_users(TERRITORY_SCHEMA, user1_id).await
         .expect("Query should succeed");
-    let user2_blocked = service.get_blocked_users(user2_id).await
+    let user2_blocked = service.get_blocked_users(TERRITORY_SCHEMA, user2_id).await
         .expect("Query should succeed");
     
     assert_eq!(user1_blocked.len(), 1);
@@ -144,15 +144,15 @@ async fn test_update_block_reason() {
     let blocked_id = ctx.create_user("blocked", "blocked@example.com").await;
     
     // Initial block
-    service.block_user(blocker_id, blocked_id, Some("Initial reason".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked_id, Some("Initial reason".to_string())).await
         .expect("Block should succeed");
     
     // Update reason by blocking again (ON CONFLICT DO UPDATE)
-    service.block_user(blocker_id, blocked_id, Some("Updated reason".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked_id, Some("Updated reason".to_string())).await
         .expect("Block update should succeed");
     
     // Verify only one block exists
-    let blocked_users = service.get_blocked_users(blocker_id).await
+    let blocked_users = service.get_blocked_users(TERRITORY_SCHEMA, blocker_id).await
         .expect("Query should succeed");
     
     assert_eq!(blocked_users.len(), 1, "Should have exactly one block");
@@ -167,7 +167,7 @@ async fn test_empty_blocked_list() {
     
     let user_id = ctx.create_user("peaceful", "peaceful@example.com").await;
     
-    let blocked_users = service.get_blocked_users(user_id).await
+    let blocked_users = service.get_blocked_users(TERRITORY_SCHEMA, user_id).await
         .expect("Query should succeed");
     
     assert_eq!(blocked_users.len(), 0, "New user should have no blocks");
@@ -184,16 +184,16 @@ async fn test_block_prevents_follow() {
     let blocked_id = ctx.create_user("blocked", "blocked@example.com").await;
     
     // Block user first
-    service.block_user(blocker_id, blocked_id, Some("Don't want to interact".to_string())).await
+    service.block_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked_id, Some("Don't want to interact".to_string())).await
         .expect("Block should succeed");
     
     // Try to follow blocked user - should fail
-    let follow_result = service.follow_user(blocker_id, blocked_id).await;
+    let follow_result = service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocker_id, blocked_id).await;
     
     assert!(follow_result.is_err(), "Following a blocked user should fail");
     
     // Try reverse follow (blocked user follows blocker) - should also fail
-    let reverse_follow_result = service.follow_user(blocked_id, blocker_id).await;
+    let reverse_follow_result = service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, blocked_id, blocker_id).await;
     
     assert!(reverse_follow_result.is_err(), "Blocked user should not be able to follow blocker");
     
@@ -1,4 +1,4 @@
-use crate::common::TestContext;
+use crate::common::{TestContext, TERRITORY_CODE, TERRITORY_SCHEMA};
 use user_service::services::UserService;
 
 #[tokio::test]
@@ -9,11 +9,11 @@ async fn test_follow_user() {
     let follower_id = ctx.create_user("follower", "follower@example.com").await;
     let following_id = ctx.create_user("following", "following@example.com").await;
     
-    service.follow_user(follower_id, following_id).await
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, follower_id, following_id).await
         .expect("Follow should succeed");
     
     // Verify connection exists
-    let followers = service.get_followers(following_id).await
+    let followers = service.get_followers(TERRITORY_SCHEMA, following_id).await
         .expect("Query should succeed");
     
     assert_eq!(followers.len(), 1);
@@ -31,15 +31,15 @@ async fn test_unfollow_user() {
     let following_id = ctx.create_user("following", "following@example.com").await;
     
     // Create connection
-    service.follow_user(follower_id, following_id).await
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, follower_id, following_id).await
         .expect("Follow should succeed");
     
     // Unfollow
-    service.unfollow_user(follower_id, following_id).await
+    service.unfollow_user(TERRITORY_SCHEMA, follower_id, following_id).await
         .expect("Unfollow should succeed");
     
     // Verify connection removed
-    let followers = service.get_followers(following_id).await
+    let followers = service.get_followers(TERRITORY_SCHEMA, following_id).await
         .expect("Query should succeed");
     
     assert_eq!(followers.len(), 0, "Follower list should be empty");
@@ -56,10 +56,10 @@ async fn test_get_followers() {
     let follower1_id = ctx.create_user("follower1", "follower1@example.com").await;
     let follower2_id = ctx.create_user("follower2", "follower2@example.com").await;
     
-    service.follow_user(follower1_id, user_id).await.expect("Follow should succeed");
-    service.follow_user(follower2_id, user_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, follower1_id, user_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, follower2_id, user_id).await.expect("Follow should succeed");
     
-    let followers = service.get_followers(user_id).await
+    let followers = service.get_followers(TERRITORY_SCHEMA, user_id).await
         .expect("Query should succeed");
     
     assert_eq!(followers.len(), 2);
@@ -80,10 +80,10 @@ async fn test_get_following() {
     let following1_id = ctx.create_user("following1", "following1@example.com").await;
     let following2_id = ctx.create_user("following2", "following2@example.com").await;
     
-    service.follow_user(user_id, following1_id).await.expect("Follow should succeed");
-    service.follow_user(user_id, following2_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, following1_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, following2_id).await.expect("Follow should succeed");
     
-    let following = service.get_following(user_id).await
+    let following = service.get_following(TERRITORY_SCHEMA, user_id).await
         .expect("Query should succeed");
     
     assert_eq!(following.len(), 2);
@@ -104,15 +104,15 @@ async fn test_mutual_following() {
     let user2_id = ctx.create_user("user2", "user2@example.com").await;
     
     // User1 follows User2
-    service.follow_user(user1_id, user2_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, user1_id, user2_id).await.expect("Follow should succeed");
     
     // User2 follows User1 back
-    service.follow_user(user2_id, user1_id).await.expect("Follow should succeed");
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, user2_id, user1_id).await.expect("Follow should succeed");
     
     // Verify mutual following
-    let user1_following = service.get_following(user1_id).await
+    let user1_following = service.get_following(TERRITORY_SCHEMA, user1_id).await
         .expect("Query should succeed");
-    let user2_following = service.get_following(user2_id).await
+    let user2_following = service.get_following(TERRITORY_SCHEMA, user2_id).await
         .expect("Query should succeed");
     
     assert_eq!(user1_following.len(), 1);
@@ -133,15 +133,15 @@ async fn test_duplicate_follow() {
     let following_id = ctx.create_user("following", "following@example.com").await;
     
     // First follow
-    service.follow_user(follower_id, following_id).await
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, follower_id, following_id).await
         .expect("First follow should succeed");
     
     // Duplicate follow - should handle gracefully (ON CONFLICT DO NOTHING)
-    service.follow_user(follower_id, following_id).await
+    service.follow_user(TERRITORY_SCHEMA, TERRITORY_CODE, follower_id, following_id).await
         .expect("Duplicate follow should not error");
     
     // Should still have only one connection
-    let followers = service.get_followers(following_id).await
+    let followers = service.get_followers(TERRITORY_SCHEMA, following_id).await
         .expect("Query should succeed");
     
     assert_eq!(followers.len(), 1);
@@ -156,9 +156,9 @@ async fn test_empty_followers_and_following() {
     
     let user_id = ctx.create_user("lonely", "lonely@example.com").await;
     
-    let followers = service.get_followers(user_id).await
+    let followers = service.get_followers(TERRITORY_SCHEMA, user_id).await
         .expect("Query should succeed");
-    let following = service.get_following(user_id).await
+    let following = service.get_following(TERRITORY_SCHEMA, user_id).await
         .expect("Query should succeed");
     
     assert_eq!(followers.len(), 0, "New user should have no followers");
@@ -166,3 +166,66 @@ async fn test_empty_followers_and_following() {
     
     ctx.cleanup().await;
 }
+
+#[actix_web::test]
+async fn test_follow_and_block_enqueue_events() {
+    use actix_web::{test, web, App};
+    use shared_lib::{EventEnvelope, SchemaLayout, TerritoryResolver, UserBlocked, UserFollowed};
+    use uuid::Uuid;
+
+    let mut ctx = TestContext::new().await;
+
+    let follower_id = ctx.create_user("follower", "follower@example.com").await;
+    let following_id = ctx.create_user("following", "following@example.com").await;
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(
+                TerritoryResolver::new("dk", SchemaLayout::Single, ["dk"]).unwrap(),
+            ))
+            .app_data(web::Data::new(UserService::new(ctx.pool.clone())))
+            .service(web::scope("/api").configure(user_service::handlers::connections::configure)),
+    )
+    .await;
+
+    // Following twice only announces the new connection
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/connections/{}/follow/{}", follower_id, following_id))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/connections/{}/block/{}", following_id, follower_id))
+        .set_json(serde_json::json!({ "reason": "Not published" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // Events wait in the outbox, committed with the change, until the relay publishes them
+    let outbox = |key: &'static str, user_id: Uuid| {
+        sqlx::query_as::<_, (String, serde_json::Value)>(
+            "SELECT subject, payload FROM global.event_outbox WHERE payload->'payload'->>$1 = $2",
+        )
+        .bind(key)
+        .bind(user_id.to_string())
+        .fetch_all(&ctx.pool)
+    };
+
+    let followed = outbox("follower_id", follower_id).await.unwrap();
+    assert_eq!(followed.len(), 1);
+    assert_eq!(followed[0].0, "territory.dk.user.followed");
+    let envelope: EventEnvelope<UserFollowed> = serde_json::from_value(followed[0].1.clone()).unwrap();
+    assert_eq!(envelope.payload.follower_id, follower_id);
+    assert_eq!(envelope.payload.following_id, following_id);
+
+    let blocked = outbox("blocker_id", following_id).await.unwrap();
+    assert_eq!(blocked.len(), 1);
+    let envelope: EventEnvelope<UserBlocked> = serde_json::from_value(blocked[0].1.clone()).unwrap();
+    assert_eq!(envelope.payload.blocked_id, follower_id);
+    assert!(!blocked[0].1.to_string().contains("Not published"));
+
+    ctx.cleanup().await;
+}
@@ -1,4 +1,4 @@
-use crate::common::TestContext;
+use crate::common::{TestContext, TERRITORY_CODE, TERRITORY_SCHEMA};
 use user_service::models::profile::UpdateProfileRequest;
 use user_service::services::UserService;
 
@@ -29,7 +29,7 @@ async fn test_get_profile_nonexistent() {
     let service = UserService::new(ctx.pool.clone());
     let fake_id = uuid::Uuid::new_v4();
     
-    let result = service.get_profile(fake_id).await.expect("Query should succeed");
+    let result = service.get_profile(TERRITORY_SCHEMA, fake_id).await.expect("Query should succeed");
     
     assert!(result.is_none(), "Should return None for nonexistent user");
     
@@ -63,7 +63,7 @@ async fn test_create_and_get_profile() {
         allow_messages_from: Some("everyone".to_string()),
     };
     
-    let profile = service.update_profile(user_id, update_request).await
+    let profile = service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, update_request).await
         .expect("Profile creation should succeed");
     
     // Verify profile was created
@@ -72,7 +72,7 @@ async fn test_create_and_get_profile() {
     assert_eq!(profile.interests, Some(vec!["Rust".to_string(), "Testing".to_string()]));
     
     // Retrieve and verify
-    let retrieved = service.get_profile(user_id).await
+    let retrieved = service.get_profile(TERRITORY_SCHEMA, user_id).await
         .expect("Query should succeed")
         .expect("Profile should exist");
     
@@ -96,7 +96,7 @@ async fn test_update_profile_partial() {
         ..empty_profile_request()
     };
     
-    service.update_profile(user_id, initial_request).await
+    service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, initial_request).await
         .expect("Initial profile creation should succeed");
     
     // Partial update - only change location
@@ -105,7 +105,7 @@ async fn test_update_profile_partial() {
         ..empty_profile_request()
     };
     
-    let updated = service.update_profile(user_id, update_request).await
+    let updated = service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, update_request).await
         .expect("Profile update should succeed");
     
     assert_eq!(updated.location, Some("Aarhus".to_string()));
@@ -128,11 +128,11 @@ async fn test_public_profile_privacy_public() {
         ..empty_profile_request()
     };
     
-    service.update_profile(user_id, request).await
+    service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, request).await
         .expect("Profile creation should succeed");
     
     // Fetch as stranger
-    let public_profile = service.get_public_profile(user_id, None).await
+    let public_profile = service.get_public_profile(TERRITORY_SCHEMA, user_id, None).await
         .expect("Query should succeed")
         .expect("Profile should be visible");
     
@@ -155,11 +155,11 @@ async fn test_public_profile_privacy_private() {
         ..empty_profile_request()
     };
     
-    service.update_profile(user_id, request).await
+    service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, request).await
         .expect("Profile creation should succeed");
     
     // Fetch as stranger - should get None
-    let public_profile = service.get_public_profile(user_id, None).await
+    let public_profile = service.get_public_profile(TERRITORY_SCHEMA, user_id, None).await
         .expect("Query should succeed");
     
     assert!(public_profile.is_none(), "Private profile should not be visible to strangers");
@@ -183,7 +183,7 @@ async fn test_public_profile_privacy_connections() {
         ..empty_profile_request()
     };
     
-    service.update_profile(user_id, request).await
+    service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, request).await
         .expect("Profile creation should succeed");
     
     // Create connection (viewer follows user)
@@ -197,13 +197,13 @@ async fn test_public_profile_privacy_connections() {
     .expect("Connection creation should succeed");
     
     // Fetch as connection - should see profile
-    let viewer_result = service.get_public_profile(user_id, Some(viewer_id)).await
+    let viewer_result = service.get_public_profile(TERRITORY_SCHEMA, user_id, Some(viewer_id)).await
         .expect("Query should succeed");
     
     assert!(viewer_result.is_some(), "Connected user should see profile");
     
     // Fetch as stranger - should get None
-    let stranger_result = service.get_public_profile(user_id, Some(stranger_id)).await
+    let stranger_result = service.get_public_profile(TERRITORY_SCHEMA, user_id, Some(stranger_id)).await
         .expect("Query should succeed");
     
     assert!(stranger_result.is_none(), "Stranger should not see connections-only profile");
@@ -225,11 +225,11 @@ async fn test_profile_show_email_privacy() {
         ..empty_profile_request()
     };
     
-    service.update_profile(user_id, request).await
+    service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, request).await
         .expect("Profile creation should succeed");
     
     // Fetch public profile - email should be None
-    let public_profile = service.get_public_profile(user_id, None).await
+    let public_profile = service.get_public_profile(TERRITORY_SCHEMA, user_id, None).await
         .expect("Query should succeed")
         .expect("Profile should exist");
     
@@ -241,11 +241,11 @@ async fn test_profile_show_email_privacy() {
         ..empty_profile_request()
     };
     
-    service.update_profile(user_id, update).await
+    service.update_profile(TERRITORY_SCHEMA, TERRITORY_CODE, user_id, update).await
         .expect("Update should succeed");
     
     // Fetch again - email should be visible
-    let updated_profile = service.get_public_profile(user_id, None).await
+    let updated_profile = service.get_public_profile(TERRITORY_SCHEMA, user_id, None).await
         .expect("Query should succeed")
         .expect("Profile should exist");
     